@@ -0,0 +1,178 @@
+//! A look at a few of the frames before gifski starts, for what gifs do badly, with the options that help. Turned
+//! off with [`ConvertOptions::advice`](crate::ConvertOptions::advice).
+//!
+//! ffmpeg decodes pairs of frames next to each other, spread over the gif, small and without smoothing so grain
+//! stays grain, onto stdout as RGB. How many colors each has, and how much each differs from the one after it,
+//! is what's measured.
+
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::{
+	focus,
+	paths,
+	runner::{CommandLine, CommandRunner},
+	Progress,
+};
+
+/// The most pairs of frames looked at, which bounds the cost whatever the length of the gif.
+const PAIRS: usize = 8;
+
+/// The [`Busyness::difference`] over which the frames count as noisy. Screen recordings are well under a hundredth,
+/// footage with grain or rain is several.
+const NOISY: f64 = 0.06;
+
+/// The [`Busyness::unique_colors`] over which the frames have more colors than a palette keeps without dithering
+/// noise into them.
+const COLORFUL: f64 = 0.75;
+
+/// How busy a handful of the frames are, which gifs compress worst.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct Busyness {
+	/// How many frames were looked at.
+	pub sampled: usize,
+	/// The colors in a frame, as a share of its pixels, on average. Near 1 when grain makes hardly two pixels alike.
+	pub unique_colors: f64,
+	/// How much a frame differs from the next, from 0 for not at all to 1 for black to white, on average.
+	pub difference: f64,
+}
+
+/// The index of the first frame of each pair looked at, at most [`PAIRS`] of them spread over `count` frames.
+fn pair_starts(count: usize) -> Vec<usize> {
+	let pairs = PAIRS.min(count.saturating_sub(1));
+	(0..pairs).map(|k| k * (count - 1) / pairs).collect()
+}
+
+/// `ffmpeg -i frame0001.png -i frame0002.png ... -filter_complex concat,scale,format=rgb24 -f rawvideo -`, the
+/// `frames` one after another at `size`, nearest neighbour so noise isn't smoothed out.
+pub(crate) fn sample_command(frames: &[&Path], size: (u32, u32)) -> CommandLine {
+	let mut command = CommandLine::new("ffmpeg").arg("-hide_banner");
+	for frame in frames {
+		command = command.arg("-i").arg(paths::for_tool(frame));
+	}
+	command
+		.arg("-filter_complex").arg(format!("concat=n={}:v=1:a=0,scale={}:{}:flags=neighbor,format=rgb24", frames.len(), size.0, size.1))
+		.args(["-f", "rawvideo", "-"])
+}
+
+/// Measures the RGB `pixels` of pairs of frames of `frame_bytes` each, `None` if they aren't whole pairs.
+#[allow(clippy::cast_precision_loss)]
+fn measure(pixels: &[u8], frame_bytes: usize) -> Option<Busyness> {
+	if frame_bytes == 0 || pixels.is_empty() || !pixels.len().is_multiple_of(frame_bytes * 2) { return None; }
+	let frames = pixels.chunks_exact(frame_bytes).collect::<Vec<_>>();
+	let unique_colors = frames.iter().map(|frame| {
+		let mut colors = frame.chunks_exact(3).map(|p| u32::from_be_bytes([0, p[0], p[1], p[2]])).collect::<Vec<_>>();
+		let pixels = colors.len().max(1);
+		colors.sort_unstable();
+		colors.dedup();
+		colors.len() as f64 / pixels as f64
+	}).sum::<f64>() / frames.len() as f64;
+	let difference = frames.chunks_exact(2).map(|pair| {
+		let total = pair[0].iter().zip(pair[1]).map(|(a, b)| u64::from(a.abs_diff(*b))).sum::<u64>();
+		total as f64 / (frame_bytes as f64 * 255.0)
+	}).sum::<f64>() / (frames.len() / 2) as f64;
+	Some(Busyness { sampled: frames.len(), unique_colors, difference })
+}
+
+/// What would help with frames as busy as `busyness`, leaving out what's already done: `fps` at or under 15, or
+/// quantized beforehand.
+pub(crate) fn advice(busyness: &Busyness, fps: f32, pre_quantized: bool) -> Vec<String> {
+	let mut advice = Vec::new();
+	if busyness.difference > NOISY {
+		let options = [
+			(!pre_quantized).then_some("--pre-quantize 64"),
+			(fps > 15.0).then_some("--fps 15"),
+			Some("--also-mp4 for a video of it too"),
+		].into_iter().flatten().collect::<Vec<_>>().join(", ");
+		advice.push(format!(
+			"High inter-frame noise detected, each frame is {:.1}% off the next on average, which makes a big gif. Consider {options}",
+			busyness.difference * 100.0,
+		));
+	}
+	if busyness.unique_colors > COLORFUL && !pre_quantized {
+		advice.push(format!(
+			"{:.0}% of the pixels have a color of their own, more than a gif's 256 show without dithering. Consider --pre-quantize 128 or a smaller --width",
+			busyness.unique_colors * 100.0,
+		));
+	}
+	advice
+}
+
+/// Measures how busy `frames` of `size` are, and says what would help if they're busy. `None` when there are too
+/// few frames, or ffmpeg couldn't decode them, which only means there's no advice.
+pub(crate) fn analyze(
+	runner: &dyn CommandRunner,
+	frames: &[PathBuf],
+	size: (u32, u32),
+	(fps, pre_quantized): (f32, bool),
+	progress: &mut dyn FnMut(Progress),
+) -> Option<Busyness> {
+	let sampled = pair_starts(frames.len()).into_iter()
+		.flat_map(|i| [frames[i].as_path(), frames[i + 1].as_path()])
+		.collect::<Vec<_>>();
+	if sampled.is_empty() { return None; }
+	let analysis = focus::analysis_size(size);
+	let command = sample_command(&sampled, analysis);
+	log::debug!("Running: {command}");
+	let output = match runner.run(&command) {
+		Ok(output) if output.success() => output,
+		Ok(output) => {
+			log::debug!("Couldn't measure how busy the frames are: {}", String::from_utf8_lossy(&output.stderr).trim());
+			return None;
+		}
+		Err(e) => {
+			log::debug!("Couldn't measure how busy the frames are: {e}");
+			return None;
+		}
+	};
+	let Some(busyness) = measure(&output.stdout, analysis.0 as usize * analysis.1 as usize * 3) else {
+		log::debug!("ffmpeg decoded {} bytes of {} frames, not advising", output.stdout.len(), sampled.len());
+		return None;
+	};
+	log::debug!(
+		"Busyness of {} frames: {:.2} unique colors, {:.3} difference from one to the next",
+		busyness.sampled, busyness.unique_colors, busyness.difference,
+	);
+	for advice in advice(&busyness, fps, pre_quantized) {
+		progress(Progress::Info(advice));
+	}
+	Some(busyness)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pairs_are_spread_over_the_frames() {
+		assert_eq!(pair_starts(100), [0, 12, 24, 37, 49, 61, 74, 86]);
+		assert_eq!(pair_starts(4), [0, 1, 2], "every pair of a short gif");
+		assert!(pair_starts(1).is_empty());
+		assert!(pair_starts(0).is_empty());
+	}
+
+	#[test]
+	fn measures_colors_and_difference() {
+		// Two 2x1 frames: black and white, then black and grey.
+		let pixels = [0, 0, 0, 255, 255, 255, 0, 0, 0, 51, 51, 51];
+		let busyness = measure(&pixels, 6).unwrap();
+		assert_eq!((busyness.sampled, busyness.unique_colors), (2, 1.0));
+		assert!((busyness.difference - 0.4).abs() < 1e-9, "{busyness:?}");
+		let flat = measure(&[9; 24], 6).unwrap();
+		assert_eq!((flat.unique_colors, flat.difference), (0.5, 0.0));
+		assert_eq!(measure(&pixels[..9], 6), None, "not whole pairs");
+		assert_eq!(measure(&[], 6), None);
+	}
+
+	#[test]
+	fn advises_only_on_busy_frames_and_what_isnt_done() {
+		let calm = Busyness { sampled: 16, unique_colors: 0.1, difference: 0.004 };
+		assert!(advice(&calm, 30.0, false).is_empty());
+		let noisy = Busyness { sampled: 16, unique_colors: 0.9, difference: 0.09 };
+		let both = advice(&noisy, 30.0, false);
+		assert_eq!(both.len(), 2);
+		assert!(both[0].starts_with("High inter-frame noise detected") && both[0].ends_with("--pre-quantize 64, --fps 15, --also-mp4 for a video of it too"), "{}", both[0]);
+		assert!(both[1].contains("--pre-quantize 128"));
+		assert_eq!(advice(&noisy, 12.0, true), ["High inter-frame noise detected, each frame is 9.0% off the next on average, which makes a big gif. Consider --also-mp4 for a video of it too"]);
+	}
+}
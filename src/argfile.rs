@@ -0,0 +1,236 @@
+//! `--save-args` and `@file` / `--args-from`: the options of a run saved to a file, to run with again.
+//!
+//! The file is in the config file's format, one option a line. Flags are `key = true`, the rest have their value in
+//! quotes, and options given more than once have a line each. The config file's settings are saved as the options
+//! that stand in for them, so the run is the same with another config file:
+//!
+//! ```text
+//! fps = "12"
+//! pad-color = "#1e1e1e"
+//! trim-idle = true
+//! ```
+
+use std::{ffi::OsString, fmt::Write, fs, path::Path};
+use anyhow::{Context, Result};
+use structopt::clap::{App, ArgMatches, ArgSettings};
+use crate::config::{self, Config, Entry};
+
+/// Not saved: the files, what reads and writes the file itself, and what runs instead of converting.
+const NOT_SAVED: [&str; 10] = ["INPUT", "OUTPUT", "save-args", "args-from", "version", "doctor", "batch", "concat", "grid-inputs", "report"];
+
+/// Writes the options given on the command line, and the settings in `config` they didn't override, to `path`.
+///
+/// # Errors
+/// If the file can't be written.
+pub fn save(matches: &ArgMatches, config: &Config, path: &Path) -> Result<()> {
+	fs::write(path, render(matches, config)).with_context(|| format!("couldn't save the options to {}", path.display()))
+}
+
+fn render(matches: &ArgMatches, config: &Config) -> String {
+	// clap 2 has no public list of the arguments that matched. The ones left at their default aren't saved, they'd
+	// conflict with some of those given when it's loaded, like --quality with --compare-quality.
+	let mut lines: Vec<(&str, Option<String>)> = Vec::new();
+	for name in matches.args.keys().copied().filter(|name| !NOT_SAVED.contains(name) && matches.occurrences_of(name) > 0) {
+		match matches.values_of_os(name).map(Iterator::collect::<Vec<_>>).filter(|values| !values.is_empty()) {
+			Some(values) => lines.extend(values.iter().map(|value| (name, Some(value.to_string_lossy().into_owned())))),
+			None => lines.push((name, None)),
+		}
+	}
+	let set = |key: &str| config.set.iter().any(|k| k == key) && !matches.is_present(key);
+	if set("max-auto-fps") { lines.push(("max-auto-fps", Some(config.max_auto_fps.to_string()))); }
+	if let Some(ramdisk) = config.ramdisk.as_ref().filter(|_| set("ramdisk")) { lines.push(("ramdisk", Some(ramdisk.to_string_lossy().into_owned()))); }
+	// Reports don't notify, and --notify conflicts with them.
+	if config.notify && set("notify") && !matches.is_present("no-notify") && !matches.is_present("report") { lines.push(("notify", None)); }
+	lines.sort_by_key(|&(name, _)| name);
+
+	let mut text = String::new();
+	for (name, value) in lines {
+		let _ = match value {
+			Some(value) => writeln!(text, "{name} = {}", config::quote(&value)),
+			None => writeln!(text, "{name} = true"),
+		};
+	}
+	text
+}
+
+/// What [`expand`] made of the arguments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expanded {
+	pub args: Vec<OsString>,
+	/// The names of the options that came from the files.
+	pub loaded: Vec<String>,
+}
+
+/// Whether `arg` is an option of `app` whose value is the next argument, and whether the ones after it are more of its
+/// values, like `--batch`'s.
+fn value_follows(app: &App, arg: &str) -> Option<bool> {
+	// clap 2 has no public list of its options either.
+	let opts = &app.p.opts;
+	let opt = if let Some(long) = arg.strip_prefix("--") {
+		opts.iter().find(|o| o.s.long == Some(long) || o.s.aliases.iter().flatten().any(|&(alias, _)| alias == long))
+	} else {
+		// Flags can be run together, `-vq 80`, and the first one taking a value is given the rest, `-q80`.
+		let shorts = arg.strip_prefix('-')?;
+		let (i, short, opt) = shorts.char_indices().find_map(|(i, short)| Some((i, short, opts.iter().find(|o| o.s.short == Some(short))?)))?;
+		Some(opt).filter(|_| i + short.len_utf8() == shorts.len())
+	}?;
+	(!opt.b.is_set(ArgSettings::RequireEquals)).then(|| opt.b.is_set(ArgSettings::Multiple))
+}
+
+/// `args` with the options in each `@file` and `--args-from file` added, except the ones also on the command line,
+/// for `app` to parse. `@file` itself is taken out.
+///
+/// Only arguments where clap expects a new one are files, not option values like `--comment @someone`. `@@` at the
+/// start of one stands for a `@`, for an input named `@clip.mp4`, and none are files after `--`.
+///
+/// # Errors
+/// If a file can't be read, or isn't in the format.
+pub fn expand(app: App, args: Vec<OsString>) -> Result<Expanded> {
+	let mut files = Vec::new();
+	let mut cli = Vec::with_capacity(args.len());
+	// Some(more) while the arguments are an option's values; more if the ones after the next are too.
+	let (mut values, mut escaped) = (None, false);
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		let text = arg.to_string_lossy();
+		if escaped || cli.is_empty() {
+			cli.push(arg);
+			continue;
+		}
+		if let Some(more) = values.filter(|&more| !(more && text.starts_with('-'))) {
+			values = more.then_some(true);
+			cli.push(arg);
+			continue;
+		}
+		values = None;
+		if text == "--" {
+			escaped = true;
+		} else if let Some(literal) = text.strip_prefix("@@") {
+			cli.push(format!("@{literal}").into());
+			continue;
+		} else if let Some(file) = text.strip_prefix('@') {
+			files.push(file.to_string());
+			continue;
+		}
+		values = value_follows(&app, &text);
+		if let Some(file) = text.strip_prefix("--args-from=") {
+			files.push(file.to_string());
+		} else if text == "--args-from" {
+			if let Some(file) = args.next() {
+				files.push(file.to_string_lossy().into_owned());
+				cli.push(arg);
+				cli.push(file);
+				values = None;
+				continue;
+			}
+		}
+		cli.push(arg);
+	}
+	if files.is_empty() { return Ok(Expanded { args: cli, loaded: Vec::new() }); }
+	// Parsed without the files to see what's on the command line. If it doesn't parse by itself, the files wouldn't
+	// fix it, and clap reports the mistake or shows the help once it's parsed again afterwards.
+	let Ok(given) = app.get_matches_from_safe(&cli) else { return Ok(Expanded { args: cli, loaded: Vec::new() }); };
+
+	let (mut loaded, mut names) = (Vec::new(), Vec::new());
+	for file in files {
+		let text = fs::read_to_string(&file).with_context(|| format!("couldn't read the options in {file}"))?;
+		let entries = config::entries(&text).with_context(|| format!("in {file}"))?;
+		for Entry { key, value, quoted, .. } in entries {
+			if given.occurrences_of(&key) > 0 { continue; }
+			loaded.push(if !quoted && value == "true" { format!("--{key}") } else { format!("--{key}={value}") }.into());
+			names.push(key);
+		}
+	}
+	let mut cli = cli.into_iter();
+	Ok(Expanded { args: cli.next().into_iter().chain(loaded).chain(cli).collect(), loaded: names })
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use structopt::StructOpt;
+	use crate::Opt;
+	use super::*;
+
+	fn args(args: &[&str]) -> Vec<OsString> {
+		std::iter::once("gifski-ffmpeg").chain(args.iter().copied()).map(OsString::from).collect()
+	}
+
+	fn parse(args: Vec<OsString>) -> Opt {
+		Opt::from_clap(&Opt::clap().get_matches_from_safe(args).unwrap())
+	}
+
+	/// `argv` saved to a file, and the arguments to load it with.
+	fn saved(argv: &[&str]) -> (PathBuf, String) {
+		let path = std::env::temp_dir().join(format!("gifski-ffmpeg-args-test-{}-{}", std::process::id(), argv.join(" ").len()));
+		fs::write(&path, render(&Opt::clap().get_matches_from_safe(args(argv)).unwrap(), &Config::default())).unwrap();
+		let at = format!("@{}", path.display());
+		(path, at)
+	}
+
+	#[test]
+	fn saving_then_loading_gives_the_same_options() {
+		let argv = [
+			"in.mp4", "out", "-q", "80", "--fps", "12.5", "--start", "1:02", "--trim-idle", "--pad-color", "#1e1e1e",
+			"--comment", "say \"hi\" # not a comment", "--poster", "--width", "400", "--no-notify", "--save-args", "x",
+		];
+		let (path, at) = saved(&argv);
+		let text = fs::read_to_string(&path).unwrap();
+		assert!(text.contains("pad-color = \"#1e1e1e\"\n") && text.contains("trim-idle = true\n") && text.contains("poster = true\n"), "{text}");
+		assert!(!text.contains("in.mp4") && !text.contains("save-args") && !text.contains("quality = \"100\""), "{text}");
+
+		let loaded = parse(expand(Opt::clap(), args(&["in.mp4", "out", &at, "--save-args", "x"])).unwrap().args);
+		assert_eq!(format!("{loaded:?}"), format!("{:?}", parse(args(&argv))));
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn the_command_line_wins() {
+		let (path, at) = saved(&["in.mp4", "--fps", "12", "-q", "80"]);
+		let expanded = expand(Opt::clap(), args(&["-f", "24", "other.mp4", "--args-from", &at[1..]])).unwrap();
+		assert_eq!(expanded.loaded, ["quality"], "not the fps on the command line");
+		let loaded = parse(expanded.args);
+		assert_eq!(format!("{:?} {} {:?}", loaded.fps, loaded.quality, loaded.input), "Some(24.0) 80 Some(\"other.mp4\")");
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn the_config_files_settings_are_saved_unless_overridden() {
+		let config = Config { max_auto_fps: 12.0, notify: true, ramdisk: Some(PathBuf::from("/mnt/ram")), set: vec!["max-auto-fps".into(), "notify".into(), "ramdisk".into()] };
+		let argv = ["in.mp4", "--in-memory", "-q", "80"];
+		let path = std::env::temp_dir().join(format!("gifski-ffmpeg-args-test-{}-config", std::process::id()));
+		fs::write(&path, render(&Opt::clap().get_matches_from_safe(args(&argv)).unwrap(), &config)).unwrap();
+		assert_eq!(fs::read_to_string(&path).unwrap(), "in-memory = true\nmax-auto-fps = \"12\"\nnotify = true\nquality = \"80\"\nramdisk = \"/mnt/ram\"\n");
+
+		// Loaded without the config file, it's the same run.
+		let loaded = parse(expand(Opt::clap(), args(&["in.mp4", &format!("@{}", path.display())])).unwrap().args);
+		assert!(loaded.notify);
+		assert_eq!(format!("{:?}", loaded.into_options(&Config::default())), format!("{:?}", parse(args(&argv)).into_options(&config)));
+		fs::remove_file(path).unwrap();
+
+		let matches = Opt::clap().get_matches_from_safe(args(&["in.mp4", "--max-auto-fps", "20", "--no-notify"])).unwrap();
+		assert_eq!(render(&matches, &config), "max-auto-fps = \"20\"\nno-notify = true\nramdisk = \"/mnt/ram\"\n");
+		let matches = Opt::clap().get_matches_from_safe(args(&["in.mp4", "--estimate"])).unwrap();
+		assert_eq!(render(&matches, &Config { ramdisk: None, ..config }), "estimate = true\nmax-auto-fps = \"12\"\n", "reports don't notify");
+	}
+
+	#[test]
+	fn only_new_arguments_are_files() {
+		let (path, at) = saved(&["in.mp4", "--fps", "12"]);
+		let expanded = expand(Opt::clap(), args(&["--comment", &at, "in.mp4", "--batch", "a.mp4", &at, "-q", "@me"])).unwrap();
+		assert_eq!(expanded.args, args(&["--comment", &at, "in.mp4", "--batch", "a.mp4", &at, "-q", "@me"]), "all values");
+		assert!(expanded.loaded.is_empty());
+
+		let expanded = expand(Opt::clap(), args(&["-vq80", &at, "--comment=x", "@@clip.mp4", "--", "@@out"])).unwrap();
+		assert_eq!(expanded.args, args(&["--fps=12", "-vq80", "--comment=x", "@clip.mp4", "--", "@@out"]));
+		let loaded = parse(expanded.args);
+		assert_eq!(format!("{:?} {:?} {:?}", loaded.input, loaded.output, loaded.fps), "Some(\"@clip.mp4\") Some(\"@@out\") Some(12.0)");
+		fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn flags_have_no_value_and_repeats_a_line_each() {
+		let matches = Opt::clap().get_matches_from_safe(args(&["in.mp4", "--compare-quality", "60,90", "--keep-frames", "-v"])).unwrap();
+		assert_eq!(render(&matches, &Config::default()), "compare-quality = \"60\"\ncompare-quality = \"90\"\nkeep-frames = true\nverbose = true\n");
+	}
+}
@@ -0,0 +1,328 @@
+//! Converting several inputs with the same options, behind `--batch`.
+//!
+//! With a [`Session`], how far the batch has got is saved to a file after each input, so one that's cut short can
+//! carry on from there instead of starting over.
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
+use crate::{
+	gif,
+	runner::CommandRunner,
+	temp,
+	Conversion, ConvertError, ConvertOptions, Progress, Result, Warning,
+};
+
+/// Whether an input of a batch was converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Status {
+	Ok,
+	Failed,
+}
+
+/// One input's row of the results table. Serializes to what `--batch --json` prints.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct BatchResult {
+	pub input: PathBuf,
+	/// `None` if the conversion failed.
+	pub output: Option<PathBuf>,
+	/// Size of the gif in bytes.
+	pub size: Option<u64>,
+	/// How long the gif plays, in seconds.
+	pub duration: Option<f64>,
+	pub status: Status,
+	/// Why the conversion failed.
+	pub reason: Option<String>,
+	/// The conversion's [`ConvertReport::warnings`](crate::ConvertReport::warnings).
+	pub warnings: Vec<Warning>,
+	/// Converted by an earlier run of the [`Session`], and skipped this time.
+	pub resumed: bool,
+}
+
+/// The file a batch saves how far it's got to, after each input.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Session {
+	pub path: PathBuf,
+	/// Carry on from what's in the file instead of starting over. The inputs it has as converted are skipped, as long
+	/// as their gifs are still there and whole.
+	pub resume: bool,
+	/// Keep the file once every input is converted, when it's deleted.
+	pub keep: bool,
+}
+
+impl Session {
+	/// A new session, saved to `path`.
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Session { path: path.into(), resume: false, keep: false }
+	}
+
+	/// A new session, saved to `<temp>/gifski-ffmpeg/session-<seconds since 1970>-<pid>.json`.
+	#[must_use]
+	pub fn in_temp(temp: &Path) -> Self {
+		let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+		Session::new(temp.join(temp::PARENT).join(format!("session-{started}-{}.json", std::process::id())))
+	}
+
+	/// Carries on with the batch saved to `path`.
+	pub fn resume(path: impl Into<PathBuf>) -> Self {
+		Session { resume: true, ..Session::new(path) }
+	}
+}
+
+/// What's in a [`Session`] file.
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+	/// [`options_hash`] of the options the batch was started with.
+	options: String,
+	inputs: Vec<Entry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+	input: PathBuf,
+	/// `None` until it's been converted, or failed to be.
+	status: Option<Status>,
+	output: Option<PathBuf>,
+	duration: Option<f64>,
+}
+
+/// A hash of everything in `options` but the input. Unlike [`std::hash::Hash`]'s it's the same from one run to the
+/// next, though not from one version to the next.
+fn options_hash(options: &ConvertOptions) -> String {
+	let options = ConvertOptions { input: PathBuf::new(), ..options.clone() };
+	// FNV-1a.
+	let hash = format!("{options:?}").bytes()
+		.fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+	format!("{hash:016x}")
+}
+
+/// Loads the state of the `session` to resume, which has to be of the same `inputs` and options, hashed to `hash`.
+fn load(session: &Path, inputs: &[PathBuf], hash: &str) -> Result<State> {
+	let invalid = |message: String| ConvertError::InvalidOption { option: "resume session", message };
+	let json = fs::read(session).map_err(ConvertError::io(session))?;
+	let state: State = serde_json::from_slice(&json).map_err(|e| invalid(format!("{} isn't a session file, {e}", session.display())))?;
+	if state.options != hash {
+		return Err(invalid(format!(
+			"{} was started with other options, give the flags it was started with, or leave --resume-session out to start over",
+			session.display(),
+		)));
+	}
+	if !state.inputs.iter().map(|e| &e.input).eq(inputs) {
+		return Err(invalid(format!(
+			"{} was started with other inputs, give the --batch it was started with, or leave --resume-session out to start over",
+			session.display(),
+		)));
+	}
+	Ok(state)
+}
+
+/// Writes `state` to `session` all at once, through a file next to it that's renamed over it, so it's never left
+/// half written.
+fn save(session: &Path, state: &State) -> io::Result<()> {
+	if let Some(parent) = session.parent() { fs::create_dir_all(parent)?; }
+	let partial = session.with_extension("json.partial");
+	let file = fs::File::create(&partial)?;
+	serde_json::to_writer_pretty(&file, state)?;
+	file.sync_all()?;
+	drop(file);
+	fs::rename(&partial, session)
+}
+
+/// The row of an input an earlier run converted, if its gif is still there and whole.
+fn converted(entry: &Entry) -> Option<BatchResult> {
+	let output = entry.output.as_ref().filter(|_| entry.status == Some(Status::Ok))?;
+	let gif = fs::read(output).ok().filter(|gif| gif::is_whole(gif))?;
+	Some(BatchResult {
+		input: entry.input.clone(),
+		output: Some(output.clone()),
+		size: Some(gif.len() as u64),
+		duration: entry.duration,
+		status: Status::Ok,
+		reason: None,
+		warnings: Vec::new(),
+		resumed: true,
+	})
+}
+
+/// Converts each of `inputs` with a copy of `options`, carrying on past the ones that fail.
+/// `on_progress` gets the index of the input each event is about.
+///
+/// Every gif is written next to its input, the same as without [`ConvertOptions::output`]. With a `session`, how far
+/// it's got is saved after each input, and the file is deleted once they've all been converted, unless
+/// [`Session::keep`]. It's kept if any failed, to resume and try those again.
+///
+/// # Errors
+/// - [`ConvertError::InvalidOption`] if `options.output` is set, since every input would be written to it, or if the
+///   session to resume is of other inputs or options, or isn't a session file.
+/// - [`ConvertError::Io`] if the session can't be read, or written before the first input.
+///
+/// Failed conversions are rows of the result instead.
+pub fn run(
+	options: &ConvertOptions,
+	inputs: &[PathBuf],
+	session: Option<&Session>,
+	runner: &dyn CommandRunner,
+	mut on_progress: impl FnMut(usize, Progress),
+) -> Result<Vec<BatchResult>> {
+	if options.output.is_some() {
+		return Err(ConvertError::InvalidOption { option: "batch", message: "every input gets its own output, so one can't be given".to_string() });
+	}
+
+	let hash = options_hash(options);
+	let mut state = match session {
+		Some(session) if session.resume => load(&session.path, inputs, &hash)?,
+		_ => State {
+			options: hash,
+			inputs: inputs.iter().map(|input| Entry { input: input.clone(), status: None, output: None, duration: None }).collect(),
+		},
+	};
+	if let Some(session) = session { save(&session.path, &state).map_err(ConvertError::io(&session.path))?; }
+
+	let mut results = Vec::new();
+	for (i, input) in inputs.iter().enumerate() {
+		if let Some(row) = converted(&state.inputs[i]) {
+			results.push(row);
+			continue;
+		}
+		if state.inputs[i].status == Some(Status::Ok) {
+			on_progress(i, Progress::Info(format!("The gif of {} is gone or broken, converting it again", input.display())));
+		}
+		let options = ConvertOptions { input: input.clone(), ..options.clone() };
+		let result = Conversion::new(options)
+			.runner(runner)
+			.on_progress(|p| on_progress(i, p))
+			.run();
+		let row = BatchResult {
+			input: input.clone(),
+			output: None,
+			size: None,
+			duration: None,
+			status: Status::Failed,
+			reason: None,
+			warnings: Vec::new(),
+			resumed: false,
+		};
+		let row = match result {
+			Ok(report) => BatchResult {
+				size: fs::metadata(&report.output).ok().map(|m| m.len()),
+				duration: Some(report.duration),
+				output: Some(report.output),
+				status: Status::Ok,
+				warnings: report.warnings,
+				..row
+			},
+			Err(e) => {
+				log::debug!("{} failed: {e}", input.display());
+				BatchResult { reason: Some(e.to_string()), ..row }
+			}
+		};
+		if let Some(session) = session {
+			state.inputs[i] = Entry { input: input.clone(), status: Some(row.status), output: row.output.clone(), duration: row.duration };
+			// The batch is worth more than the file, which only costs converting some inputs again.
+			if let Err(e) = save(&session.path, &state) { log::warn!("Couldn't save the session to {}: {e}", session.path.display()); }
+		}
+		results.push(row);
+	}
+	if let Some(session) = session {
+		if !session.keep && results.iter().all(|r| r.status == Status::Ok) { let _ = fs::remove_file(&session.path); }
+	}
+	Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	/// A new `dir` with b.mp4 in it, and options that make 12 frames of a 2 second input in it.
+	fn options(name: &str) -> (ConvertOptions, PathBuf) {
+		let dir = crate::test_dir(&format!("batch-{name}"));
+		fs::write(dir.join("b.mp4"), b"").unwrap();
+		crate::growing::written_an_hour_ago(&dir.join("b.mp4"));
+		let mut options = ConvertOptions::new("");
+		options.frames_dir = Some(dir.join("frames"));
+		options.comment = crate::Comment::Off;
+		options.trust_metadata = true;
+		(options, dir)
+	}
+
+	fn mock() -> MockRunner {
+		let runner = MockRunner::new();
+		runner.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright"));
+		runner.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		runner.respond("ffprobe", CommandOutput::ok_with_stdout(crate::probe::TEST_PROBE));
+		runner.respond_with("ffmpeg", |command| {
+			let pattern = PathBuf::from(command.args.last().unwrap());
+			for i in 1..=12 { fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?; }
+			Ok(CommandOutput::ok_with_stderr("Stream #0:0: Video: h264, 24 fps"))
+		});
+		runner
+	}
+
+	#[test]
+	fn failures_are_rows_too() {
+		let (options, dir) = options("failures");
+		let mut events = Vec::new();
+
+		let results = run(&options, &[dir.join("a.mp4"), dir.join("b.mp4")], None, &mock(), |i, _| events.push(i)).unwrap();
+
+		assert_eq!(results[0].status, Status::Failed);
+		assert!(results[0].reason.as_ref().unwrap().contains("does not exist"));
+		assert_eq!(results[1].status, Status::Ok);
+		assert_eq!(results[1].output, Some(dir.join("b-gif.gif")));
+		assert_eq!(results[1].duration, Some(0.5));
+		assert!(!events.is_empty() && events.iter().all(|&i| i == 1), "{events:?}");
+		let json = serde_json::to_value(&results).unwrap();
+		assert_eq!(json[0]["status"], "failed");
+	}
+
+	#[test]
+	fn resuming_skips_what_was_converted() {
+		let (options, dir) = options("resume");
+		let inputs = [dir.join("a.mp4"), dir.join("b.mp4")];
+		let session = Session::new(dir.join("session.json"));
+		let first = run(&options, &inputs, Some(&session), &mock(), |_, _| {}).unwrap();
+		assert_eq!(first[0].status, Status::Failed);
+		assert!(session.path.is_file(), "kept to try a.mp4 again");
+		fs::write(dir.join("a.mp4"), b"").unwrap();
+		crate::growing::written_an_hour_ago(&dir.join("a.mp4"));
+		fs::write(dir.join("b-gif.gif"), gif::TEST_GIF).unwrap();
+
+		let resume = Session::resume(&session.path);
+		let mut other = options.clone();
+		other.quality = 50;
+		let err = run(&other, &inputs, Some(&resume), &mock(), |_, _| {}).unwrap_err().to_string();
+		assert!(err.contains("was started with other options"), "{err}");
+		let err = run(&options, &inputs[1..], Some(&resume), &mock(), |_, _| {}).unwrap_err().to_string();
+		assert!(err.contains("was started with other inputs"), "{err}");
+
+		let runner = mock();
+		let results = run(&options, &inputs, Some(&resume), &runner, |_, _| {}).unwrap();
+		assert_eq!((results[0].status, results[0].resumed), (Status::Ok, false));
+		assert_eq!((results[1].status, results[1].resumed, results[1].duration), (Status::Ok, true, Some(0.5)));
+		assert_eq!(runner.calls().iter().filter(|c| c.program == "ffprobe").count(), 1, "only a.mp4");
+		assert!(!session.path.exists(), "done with");
+	}
+
+	#[test]
+	fn a_gif_thats_gone_is_converted_again() {
+		let (options, dir) = options("gone");
+		let inputs = [dir.join("b.mp4")];
+		let session = Session { keep: true, ..Session::new(dir.join("session.json")) };
+		run(&options, &inputs, Some(&session), &mock(), |_, _| {}).unwrap();
+		assert!(session.path.is_file());
+		let _ = fs::remove_file(dir.join("b-gif.gif"));
+
+		let mut events = Vec::new();
+		let results = run(&options, &inputs, Some(&Session::resume(&session.path)), &mock(), |_, p| events.push(p)).unwrap();
+		assert!(!results[0].resumed);
+		assert!(events.iter().any(|p| matches!(p, Progress::Info(m) if m.contains("gone or broken"))), "{events:?}");
+	}
+}
@@ -0,0 +1,179 @@
+//! Timing repeated conversions, behind `--benchmark`.
+
+use std::{
+	env,
+	time::Duration,
+};
+use serde::{Serialize, Serializer};
+use crate::{
+	disk,
+	runner::CommandRunner,
+	Conversion, ConvertError, ConvertOptions, Progress, Result, Stage,
+};
+
+/// How often the conversion runs when no count is given.
+pub const DEFAULT_RUNS: usize = 3;
+
+/// Fastest, middle and slowest of a set of timings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Timings {
+	#[serde(serialize_with = "seconds")]
+	pub min: Duration,
+	#[serde(serialize_with = "seconds")]
+	pub median: Duration,
+	#[serde(serialize_with = "seconds")]
+	pub max: Duration,
+}
+
+impl Timings {
+	fn of(mut times: Vec<Duration>) -> Self {
+		times.sort();
+		Timings { min: times[0], median: times[times.len() / 2], max: times[times.len() - 1] }
+	}
+}
+
+/// Timings of one stage across all runs.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct StageTimings {
+	pub stage: Stage,
+	#[serde(flatten)]
+	pub timings: Timings,
+}
+
+/// The outcome of [`run`]. Serializes to the JSON `--benchmark --json` prints, with times in seconds.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct BenchmarkReport {
+	/// Measured runs, not counting the warm-up.
+	pub runs: usize,
+	pub frame_count: usize,
+	/// In the order the stages ran.
+	pub stages: Vec<StageTimings>,
+	/// Wall-clock time of whole conversions.
+	pub total: Timings,
+	/// The most the frames directory took up on disk, in bytes.
+	pub peak_frames_size: u64,
+}
+
+fn seconds<S: Serializer>(d: &Duration, s: S) -> std::result::Result<S::Ok, S::Error> {
+	s.serialize_f64(d.as_secs_f64())
+}
+
+/// Converts `options.input` once to warm the caches, then `runs` more times, timing every stage.
+///
+/// Everything is written to a scratch directory in the temp dir and deleted afterwards.
+/// `options.frames_dir` is kept, so different disks can be compared.
+///
+/// # Errors
+/// [`ConvertError::InvalidOption`] if `options.output` is set, since it would be overwritten `runs` times,
+/// or if `runs` is 0. Otherwise whatever the first failing conversion returned.
+pub fn run(mut options: ConvertOptions, runner: &dyn CommandRunner, runs: usize) -> Result<BenchmarkReport> {
+	let invalid = |message: &str| ConvertError::InvalidOption { option: "benchmark", message: message.to_string() };
+	if options.output.is_some() { return Err(invalid("outputs are discarded, so an output can't be given")); }
+	if runs == 0 { return Err(invalid("needs at least one run")); }
+
+	let scratch = env::temp_dir().join("gifski-ffmpeg-benchmark");
+	let _ = std::fs::create_dir_all(&scratch);
+	options.output = Some(scratch.join("benchmark.gif").into_os_string());
+	let frames_dir = options.frames_dir.get_or_insert_with(|| scratch.join("frames")).clone();
+
+	let mut stages: Vec<(Stage, Vec<Duration>)> = Vec::new();
+	let mut totals = Vec::new();
+	let mut peak_frames_size = 0;
+	let mut frame_count = 0;
+	for i in 0..=runs {
+		let warm_up = i == 0;
+		let mut finished = Vec::new();
+		let report = Conversion::new(options.clone())
+			.runner(runner)
+			.on_progress(|p| if let Progress::Finished(stage, time) = p {
+				// Nothing is added to the frames after extraction, so this is as big as it gets.
+				if stage == Stage::Extract { peak_frames_size = peak_frames_size.max(disk::dir_size(&frames_dir)); }
+				finished.push((stage, time));
+			})
+			.run();
+		let report = match report {
+			Ok(report) => report,
+			Err(e) => {
+				let _ = std::fs::remove_dir_all(&scratch);
+				return Err(e);
+			}
+		};
+		log::debug!("{} took {:?}", if warm_up { "warm-up".to_string() } else { format!("run {i}") }, report.total_time);
+		if warm_up { continue; }
+		frame_count = report.frame_count;
+		totals.push(report.total_time);
+		for (stage, time) in finished {
+			match stages.iter_mut().find(|(s, _)| *s == stage) {
+				Some((_, times)) => times.push(time),
+				None => stages.push((stage, vec![time])),
+			}
+		}
+	}
+	let _ = std::fs::remove_dir_all(&scratch);
+
+	Ok(BenchmarkReport {
+		runs,
+		frame_count,
+		stages: stages.into_iter().map(|(stage, times)| StageTimings { stage, timings: Timings::of(times) }).collect(),
+		total: Timings::of(totals),
+		peak_frames_size,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{fs, path::PathBuf};
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	#[test]
+	fn timings() {
+		let ms = Duration::from_millis;
+		assert_eq!(Timings::of(vec![ms(30), ms(10), ms(20)]), Timings { min: ms(10), median: ms(20), max: ms(30) });
+		assert_eq!(Timings::of(vec![ms(5)]), Timings { min: ms(5), median: ms(5), max: ms(5) });
+	}
+
+	#[test]
+	fn warms_up_then_times_each_run() {
+		let dir = crate::test_dir("benchmark");
+		fs::write(dir.join("input.mp4"), b"").unwrap();
+		crate::growing::written_an_hour_ago(&dir.join("input.mp4"));
+		let mut options = ConvertOptions::new(dir.join("input.mp4"));
+		options.frames_dir = Some(dir.join("frames"));
+		options.comment = crate::Comment::Off;
+		let mock = MockRunner::new();
+		for _ in 0..3 {
+			mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright"));
+			mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+			mock.respond("ffprobe", CommandOutput::ok_with_stdout(crate::probe::TEST_PROBE));
+			mock.respond_with("ffmpeg", |command| {
+				let pattern = PathBuf::from(command.args.last().unwrap());
+				fs::write(pattern.with_file_name("frame0001.png"), [0; 100])?;
+				Ok(CommandOutput::ok_with_stderr("Stream #0:0: Video: h264, 24 fps"))
+			});
+		}
+
+		let report = run(options, &mock, 2).unwrap();
+
+		assert_eq!(mock.calls_to("ffmpeg").len(), 6);
+		assert_eq!((report.runs, report.frame_count, report.peak_frames_size), (2, 1, 100));
+		let stages: Vec<Stage> = report.stages.iter().map(|s| s.stage).collect();
+		assert_eq!(stages, [Stage::Extract, Stage::Encode, Stage::Cleanup]);
+		let json = serde_json::to_value(&report).unwrap();
+		assert!(json["total"]["median"].is_f64(), "{json}");
+		assert_eq!(json["stages"][0]["stage"], "extract");
+	}
+
+	#[test]
+	fn refuses_to_overwrite_an_output() {
+		let mut options = ConvertOptions::new("input.mp4");
+		options.output = Some("keep-me.gif".into());
+		let mock = MockRunner::new();
+
+		assert!(matches!(run(options, &mock, 3), Err(ConvertError::InvalidOption { option: "benchmark", .. })));
+		assert!(mock.calls().is_empty());
+	}
+}
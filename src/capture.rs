@@ -0,0 +1,338 @@
+//! `--capture`: records the screen, or a region of it, with ffmpeg's own capture device for the platform, and
+//! converts the recording like any other input. x11grab on Linux and the BSDs, gdigrab on Windows and avfoundation
+//! on macOS, which can't grab a region, so the recording is cropped to it instead.
+//!
+//! ffmpeg has no Wayland device, so a Wayland session is recorded through Xwayland's display, which only has the
+//! windows running under Xwayland in it. An `interactive` region is picked with slurp on Wayland and slop on X11.
+//!
+//! Recording stops after `--capture-duration`, or at Ctrl+C. That's the end of the recording rather than of this
+//! process: Ctrl+C is caught while ffmpeg records, and ffmpeg is asked to stop with a `q` on its stdin, which
+//! finishes the file off properly, whether the signal reached it too or only us.
+
+use std::{
+	env, fmt, fs,
+	io::{Read, Write},
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, RecvTimeoutError}},
+	thread,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use gifski_ffmpeg::{
+	runner::{CommandLine, CommandOutput, CommandRunner},
+	ConvertError,
+};
+
+/// The frame rate the screen is recorded at without `--fps`.
+pub const DEFAULT_FPS: f32 = 30.0;
+
+/// How often to check whether Ctrl+C was pressed while ffmpeg records.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set by the Ctrl+C handler while [`record`] runs.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Where on the screen `--capture` records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+	/// All of it, when `--capture` has no value.
+	Screen,
+	/// `WxH+X+Y`, in the screen's pixels from its top left corner.
+	Area { width: u32, height: u32, x: u32, y: u32 },
+	/// Dragged out with the mouse, see [`select`].
+	Interactive,
+}
+
+impl FromStr for Region {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, String> {
+		if s.trim().eq_ignore_ascii_case("interactive") { return Ok(Region::Interactive); }
+		let invalid = || format!("expected WxH+X+Y like 800x600+0+0, or interactive, got {s:?}");
+		let parse = |n: &str| n.trim().parse::<u32>().ok();
+		let (size, offset) = s.split_once('+').ok_or_else(invalid)?;
+		let ((width, height), (x, y)) = size.split_once(['x', 'X']).zip(offset.split_once('+')).ok_or_else(invalid)?;
+		Ok(Region::Area {
+			width: parse(width).filter(|&w| w > 0).ok_or_else(invalid)?,
+			height: parse(height).filter(|&h| h > 0).ok_or_else(invalid)?,
+			x: parse(x).ok_or_else(invalid)?,
+			y: parse(y).ok_or_else(invalid)?,
+		})
+	}
+}
+
+impl fmt::Display for Region {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Region::Screen => f.write_str("the screen"),
+			Region::Area { width, height, x, y } => write!(f, "{width}x{height} at {x},{y}"),
+			Region::Interactive => f.write_str("the region picked"),
+		}
+	}
+}
+
+/// The ffmpeg capture device the screen is recorded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Device {
+	/// x11grab, of the X display, e.g. `:0`.
+	X11 { display: String },
+	/// gdigrab, of the Windows desktop.
+	Gdi,
+	/// avfoundation, of the first screen.
+	AvFoundation,
+}
+
+/// The device for this platform, and on Wayland a note on what the recording won't have.
+///
+/// # Errors
+/// Where ffmpeg has no capture device, or on Linux without an X display to record.
+pub fn device() -> Result<(Device, Option<&'static str>), String> {
+	if cfg!(target_os = "macos") {
+		Ok((Device::AvFoundation, None))
+	} else if cfg!(windows) {
+		Ok((Device::Gdi, None))
+	} else if cfg!(unix) {
+		let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+		let Some(display) = env::var("DISPLAY").ok().filter(|d| !d.is_empty()) else {
+			return Err(if wayland {
+				"ffmpeg can't record Wayland itself, and there's no Xwayland $DISPLAY to record instead".to_string()
+			} else {
+				"there's no X display to record, $DISPLAY isn't set".to_string()
+			});
+		};
+		let note = wayland.then_some("Recording through Xwayland, which only has the windows running under it, Wayland ones come out black");
+		Ok((Device::X11 { display }, note))
+	} else {
+		Err("ffmpeg has no screen capture device for this platform".to_string())
+	}
+}
+
+/// Resolves [`Region::Interactive`] by running slurp on Wayland or slop on X11, for the region dragged out.
+///
+/// # Errors
+/// If neither could be run, or the selection was cancelled.
+pub fn select(runner: &dyn CommandRunner, region: Region) -> Result<Region, String> {
+	if region != Region::Interactive { return Ok(region); }
+	if cfg!(any(windows, target_os = "macos")) {
+		return Err("interactive needs slop or slurp, which aren't for this platform, give the region as WxH+X+Y".to_string());
+	}
+	let tool = if env::var_os("WAYLAND_DISPLAY").is_some() { "slurp" } else { "slop" };
+	let command = CommandLine::new(tool).args(["-f", "%wx%h+%x+%y"]);
+	log::debug!("Running: {command}");
+	let output = runner.run(&command).map_err(|e| format!("couldn't run {tool} to pick the region, install it or give the region as WxH+X+Y ({e})"))?;
+	if !output.success() { return Err(format!("no region was picked, {tool} {}", String::from_utf8_lossy(&output.stderr).trim())); }
+	let picked = String::from_utf8_lossy(&output.stdout);
+	picked.trim().parse().map_err(|e| format!("{tool} printed an unexpected region: {e}"))
+}
+
+/// The ffmpeg command recording `region` of `device` at `fps` to `output`, for `duration` seconds or until stopped.
+pub fn command(device: &Device, region: Region, fps: f32, duration: Option<f64>, output: &Path) -> CommandLine {
+	let area = match region {
+		Region::Area { width, height, x, y } => Some((width, height, x, y)),
+		Region::Screen | Region::Interactive => None,
+	};
+	let mut command = CommandLine::new("ffmpeg").args(["-hide_banner", "-v", "error", "-y"]).args(["-framerate".to_string(), fps.to_string()]);
+	command = match device {
+		Device::X11 { display } => {
+			let (size, input) = match area {
+				Some((width, height, x, y)) => (Some(format!("{width}x{height}")), format!("{display}+{x},{y}")),
+				None => (None, display.clone()),
+			};
+			command.args(["-f", "x11grab"]).args(size.map(|size| ["-video_size".to_string(), size]).into_iter().flatten()).args(["-i".to_string(), input])
+		}
+		Device::Gdi => {
+			let offset = area.map(|(width, height, x, y)| [
+				"-offset_x".to_string(), x.to_string(), "-offset_y".to_string(), y.to_string(), "-video_size".to_string(), format!("{width}x{height}"),
+			]);
+			command.args(["-f", "gdigrab"]).args(offset.into_iter().flatten()).args(["-i", "desktop"])
+		}
+		Device::AvFoundation => {
+			let crop = area.map(|(width, height, x, y)| ["-vf".to_string(), format!("crop={width}:{height}:{x}:{y}")]);
+			command.args(["-f", "avfoundation", "-capture_cursor", "1", "-i", "Capture screen 0:none"]).args(crop.into_iter().flatten())
+		}
+	};
+	if let Some(seconds) = duration { command = command.args(["-t".to_string(), seconds.to_string()]); }
+	// Lossless and quick enough to keep up with the screen, and whatever ffmpeg was built with has it.
+	command.args(["-c:v", "utvideo"]).arg(output)
+}
+
+/// A new `capture-<seconds since 1970>-<pid>.mkv` in the temp directory, to record to.
+pub fn recording_path() -> PathBuf {
+	let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+	env::temp_dir().join("gifski-ffmpeg").join(format!("capture-{started}-{}.mkv", std::process::id()))
+}
+
+/// `capture.gif` in `dir`, or `capture-2.gif` and on if it's taken.
+pub fn default_output(dir: &Path) -> PathBuf {
+	(1..1000).map(|n| dir.join(if n == 1 { "capture.gif".to_string() } else { format!("capture-{n}.gif") }))
+		.find(|path| !path.exists())
+		.unwrap_or_else(|| dir.join("capture.gif"))
+}
+
+/// Runs the recording `command`, which writes `output`, until it's done or Ctrl+C stops it.
+///
+/// # Errors
+/// If ffmpeg couldn't be run, or failed before it recorded anything.
+pub fn record(runner: &dyn CommandRunner, command: &CommandLine, output: &Path) -> Result<(), String> {
+	if let Some(dir) = output.parent() {
+		fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {e}", dir.display()))?;
+	}
+	log::debug!("Running: {command}");
+	STOP.store(false, Ordering::SeqCst);
+	let caught = catch_ctrl_c();
+	let result = run_until_stopped(runner, command);
+	restore_ctrl_c(caught);
+	let (exit, streamed) = result?;
+	let recorded = fs::metadata(output).is_ok_and(|m| m.len() > 0);
+	// Stopped by the signal rather than a q, ffmpeg exits with 255 once it's finished the file.
+	if recorded && (exit.success() || STOP.load(Ordering::SeqCst)) { return Ok(()); }
+	if exit.success() { return Err("ffmpeg finished without recording anything".to_string()); }
+	let stderr = String::from_utf8_lossy(&streamed).into_owned() + &String::from_utf8_lossy(&exit.stderr);
+	Err(ConvertError::FfmpegFailed { code: exit.code, stderr }.to_string())
+}
+
+/// Runs `command`, sending it a `q` once [`STOP`] is set, and returns how it exited with what it printed to stderr.
+fn run_until_stopped(runner: &dyn CommandRunner, command: &CommandLine) -> Result<(CommandOutput, Vec<u8>), String> {
+	let mut child = runner.spawn(command).map_err(|e| format!("couldn't run ffmpeg to record, make sure it's installed ({e})"))?;
+	let mut stdin = child.take_stdin();
+	let (done, finished) = mpsc::channel();
+	let pipe = child.take_stderr();
+	thread::spawn(move || {
+		let mut text = Vec::new();
+		if let Some(mut pipe) = pipe { let _ = pipe.read_to_end(&mut text); }
+		let _ = done.send(text);
+	});
+	// ffmpeg closes its stderr when it exits.
+	let streamed = loop {
+		match finished.recv_timeout(POLL_INTERVAL) {
+			Err(RecvTimeoutError::Timeout) => {
+				if !STOP.load(Ordering::SeqCst) { continue; }
+				if let Some(mut pipe) = stdin.take() {
+					log::debug!("Stopping the recording");
+					let _ = pipe.write_all(b"q").and_then(|()| pipe.flush());
+				}
+			}
+			done => break done.unwrap_or_default(),
+		}
+	};
+	drop(stdin);
+	let exit = child.wait().map_err(|e| format!("couldn't wait for ffmpeg: {e}"))?;
+	Ok((exit, streamed))
+}
+
+#[cfg(unix)]
+extern "C" fn stop(_: libc::c_int) {
+	STOP.store(true, Ordering::SeqCst);
+}
+
+/// Sets [`STOP`] on Ctrl+C instead of exiting, returning the handler to put back with [`restore_ctrl_c`].
+#[cfg(unix)]
+fn catch_ctrl_c() -> libc::sighandler_t {
+	// SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+	unsafe { libc::signal(libc::SIGINT, stop as *const () as libc::sighandler_t) }
+}
+
+#[cfg(unix)]
+fn restore_ctrl_c(previous: libc::sighandler_t) {
+	// SAFETY: puts back what was there before catch_ctrl_c.
+	unsafe { libc::signal(libc::SIGINT, previous); }
+}
+
+#[cfg(windows)]
+extern "system" {
+	fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn stop(event: u32) -> i32 {
+	const CTRL_BREAK_EVENT: u32 = 1;
+	if event > CTRL_BREAK_EVENT { return 0; }
+	STOP.store(true, Ordering::SeqCst);
+	1
+}
+
+#[cfg(windows)]
+fn catch_ctrl_c() {
+	// SAFETY: the handler only stores to an atomic.
+	unsafe { SetConsoleCtrlHandler(Some(stop), 1); }
+}
+
+#[cfg(windows)]
+fn restore_ctrl_c(_: ()) {
+	// SAFETY: removes the handler catch_ctrl_c added.
+	unsafe { SetConsoleCtrlHandler(Some(stop), 0); }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn catch_ctrl_c() {}
+
+#[cfg(not(any(unix, windows)))]
+fn restore_ctrl_c(_: ()) {}
+
+#[cfg(test)]
+mod tests {
+	use gifski_ffmpeg::runner::MockRunner;
+	use super::*;
+
+	#[test]
+	fn regions() {
+		assert_eq!("800x600+10+20".parse(), Ok(Region::Area { width: 800, height: 600, x: 10, y: 20 }));
+		assert_eq!("Interactive".parse(), Ok(Region::Interactive));
+		assert!("800x600".parse::<Region>().is_err());
+		assert!("0x600+0+0".parse::<Region>().is_err());
+		assert!("800x600+-1+0".parse::<Region>().unwrap_err().contains("WxH+X+Y"));
+	}
+
+	#[test]
+	fn each_device_records_the_region() {
+		let area = Region::Area { width: 800, height: 600, x: 10, y: 20 };
+		let output = Path::new("/tmp/capture.mkv");
+		let x11 = Device::X11 { display: ":0".to_string() };
+		assert_eq!(
+			command(&x11, area, 30.0, Some(5.0), output).to_string(),
+			"ffmpeg -hide_banner -v error -y -framerate 30 -f x11grab -video_size 800x600 -i :0+10,20 -t 5 -c:v utvideo /tmp/capture.mkv",
+		);
+		assert_eq!(command(&x11, Region::Screen, 12.5, None, output).to_string(), "ffmpeg -hide_banner -v error -y -framerate 12.5 -f x11grab -i :0 -c:v utvideo /tmp/capture.mkv");
+		assert!(command(&Device::Gdi, area, 30.0, None, output).to_string()
+			.contains("-f gdigrab -offset_x 10 -offset_y 20 -video_size 800x600 -i desktop -c:v"));
+		assert!(command(&Device::AvFoundation, area, 30.0, None, output).args_lossy().join(" ")
+			.contains("-i Capture screen 0:none -vf crop=800:600:10:20 -c:v"), "it can't grab a region, so it crops");
+	}
+
+	#[test]
+	fn picks_the_region_with_the_mouse() {
+		let runner = MockRunner::new();
+		for tool in ["slop", "slurp"] { runner.respond(tool, CommandOutput::ok_with_stdout("640x480+100+50\n")); }
+		if cfg!(any(windows, target_os = "macos")) {
+			assert!(select(&runner, Region::Interactive).is_err());
+			return;
+		}
+		assert_eq!(select(&runner, Region::Interactive), Ok(Region::Area { width: 640, height: 480, x: 100, y: 50 }));
+		assert_eq!(select(&MockRunner::new(), Region::Screen), Ok(Region::Screen), "nothing to pick");
+		let runner = MockRunner::new();
+		for tool in ["slop", "slurp"] { runner.respond(tool, CommandOutput::failed(1, "Selection was cancelled")); }
+		assert!(select(&runner, Region::Interactive).unwrap_err().starts_with("no region was picked"));
+	}
+
+	#[test]
+	fn a_recording_needs_something_recorded() {
+		let dir = crate::test_dir("capture");
+		let output = dir.join("capture.mkv");
+		let command = command(&Device::Gdi, Region::Screen, 30.0, Some(1.0), &output);
+
+		let runner = MockRunner::new();
+		runner.respond_with("ffmpeg", |command| {
+			fs::write(command.args.last().unwrap(), b"recording")?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+		assert_eq!(record(&runner, &command, &output), Ok(()));
+
+		fs::remove_file(&output).unwrap();
+		let runner = MockRunner::new();
+		runner.respond("ffmpeg", CommandOutput::failed(1, "[gdigrab @ 0x1] Can't find window 'desktop'"));
+		let error = record(&runner, &command, &output).unwrap_err();
+		assert!(error.starts_with("ffmpeg exited with code 1") && error.ends_with("Can't find window 'desktop'"), "{error}");
+		assert!(default_output(&dir).ends_with("capture.gif"));
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
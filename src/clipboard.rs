@@ -0,0 +1,282 @@
+//! `--copy`: puts the finished gif on the clipboard with the platform's own tools, and `--from-clipboard`: takes
+//! the video to convert from it.
+//!
+//! The gif itself is copied where the clipboard can hold files, as a file drop on Windows, a file on macOS and a
+//! `text/uri-list` on Linux, which is how file managers copy files. Otherwise its path is copied as text. Pasting
+//! reads the same file lists back, and on Linux a path copied as text too.
+
+use std::{
+	env,
+	io::{self, IsTerminal, Write},
+	path::{self, Path, PathBuf},
+	process::{Command, Stdio},
+};
+use gifski_ffmpeg::{
+	probe,
+	runner::{CommandLine, CommandRunner},
+};
+
+/// What ended up on the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Copied {
+	File,
+	/// The absolute path as text, because the file couldn't be copied.
+	Path,
+}
+
+/// One way of copying, tried in order until one works.
+struct Attempt {
+	command: CommandLine,
+	stdin: Option<String>,
+	copies: Copied,
+}
+
+/// Whether copying makes sense: someone is at a terminal to paste it, and it isn't CI.
+pub fn interactive() -> bool {
+	env::var_os("CI").is_none() && io::stdout().is_terminal()
+}
+
+/// Copies the gif at `output`, falling back to its path.
+///
+/// # Errors
+/// With why the last way tried failed, when none of them worked.
+pub fn copy(output: &Path) -> Result<Copied, String> {
+	let path = path::absolute(output).map_err(|e| format!("couldn't find the absolute path of {}: {e}", output.display()))?;
+	let mut error = String::from("no clipboard tool for this platform");
+	for attempt in attempts(&path, env::var_os("WAYLAND_DISPLAY").is_some()) {
+		match run(&attempt) {
+			Ok(()) => return Ok(attempt.copies),
+			Err(e) => {
+				log::debug!("{} didn't copy: {e}", attempt.command.program_name());
+				error = format!("{}: {e}", attempt.command.program_name());
+			}
+		}
+	}
+	Err(error)
+}
+
+fn attempts(path: &Path, wayland: bool) -> Vec<Attempt> {
+	let text = path.to_string_lossy().into_owned();
+	let attempt = |command: CommandLine, stdin: Option<String>, copies| Attempt { command, stdin, copies };
+	if cfg!(target_os = "macos") {
+		let script = format!("set the clipboard to (POSIX file \"{}\")", text.replace('\\', "\\\\").replace('"', "\\\""));
+		vec![
+			attempt(CommandLine::new("osascript").args(["-e", &script]), None, Copied::File),
+			attempt(CommandLine::new("pbcopy"), Some(text), Copied::Path),
+		]
+	} else if cfg!(windows) {
+		// -Path puts it on the clipboard as a CF_HDROP file drop, like Explorer's copy.
+		let script = format!("Set-Clipboard -Path '{}'", text.replace('\'', "''"));
+		vec![
+			attempt(CommandLine::new("powershell").args(["-NoProfile", "-NonInteractive", "-Command", &script]), None, Copied::File),
+			attempt(CommandLine::new("clip"), Some(text), Copied::Path),
+		]
+	} else {
+		let uris = format!("{}\r\n", file_uri(path));
+		let wl = [
+			attempt(CommandLine::new("wl-copy").args(["--type", "text/uri-list"]), Some(uris.clone()), Copied::File),
+			attempt(CommandLine::new("wl-copy"), Some(text.clone()), Copied::Path),
+		];
+		let x = [
+			attempt(CommandLine::new("xclip").args(["-selection", "clipboard", "-t", "text/uri-list"]), Some(uris), Copied::File),
+			attempt(CommandLine::new("xclip").args(["-selection", "clipboard"]), Some(text), Copied::Path),
+		];
+		if wayland { wl.into_iter().chain(x).collect() } else { x.into_iter().chain(wl).collect() }
+	}
+}
+
+/// `file://` and the path, with everything but unreserved characters and `/` percent-encoded.
+fn file_uri(path: &Path) -> String {
+	let encoded = path.to_string_lossy().bytes()
+		.map(|b| if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) { char::from(b).to_string() } else { format!("%{b:02X}") })
+		.collect::<Vec<_>>()
+		.concat();
+	format!("file://{encoded}")
+}
+
+/// How what a paste command prints lists the files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Listing {
+	/// A path per line.
+	Lines,
+	/// A `text/uri-list`, a `file://` URI per line.
+	Uris,
+	/// Text that might be the one path.
+	Text,
+}
+
+/// The ways of reading files off the clipboard, tried in order until one has some.
+fn paste_commands(wayland: bool) -> Vec<(CommandLine, Listing)> {
+	if cfg!(target_os = "macos") {
+		// Finder puts the files it copies on the pasteboard as file URLs.
+		let script = "ObjC.import('AppKit');\
+			var urls = $.NSPasteboard.generalPasteboard.readObjectsForClassesOptions($([$.NSURL]), $());\
+			var paths = [];\
+			for (var i = 0; i < urls.count; i++) { var url = urls.objectAtIndex(i); if (url.isFileURL) paths.push(url.path.js); }\
+			paths.join('\\n');";
+		vec![(CommandLine::new("osascript").args(["-l", "JavaScript", "-e", script]), Listing::Lines)]
+	} else if cfg!(windows) {
+		// Explorer's CF_HDROP file drop.
+		let script = "Get-Clipboard -Format FileDropList | ForEach-Object { $_.FullName }";
+		vec![(CommandLine::new("powershell").args(["-NoProfile", "-NonInteractive", "-Command", script]), Listing::Lines)]
+	} else {
+		let wl = [
+			(CommandLine::new("wl-paste").args(["--no-newline", "--type", "text/uri-list"]), Listing::Uris),
+			(CommandLine::new("wl-paste").args(["--no-newline"]), Listing::Text),
+		];
+		let x = [
+			(CommandLine::new("xclip").args(["-selection", "clipboard", "-o", "-t", "text/uri-list"]), Listing::Uris),
+			(CommandLine::new("xclip").args(["-selection", "clipboard", "-o"]), Listing::Text),
+		];
+		if wayland { wl.into_iter().chain(x).collect() } else { x.into_iter().chain(wl).collect() }
+	}
+}
+
+/// The paths in what a paste command printed.
+fn pasted_paths(pasted: &str, listing: Listing) -> Vec<PathBuf> {
+	let lines = pasted.lines().map(str::trim).filter(|line| !line.is_empty());
+	match listing {
+		Listing::Lines => lines.map(PathBuf::from).collect(),
+		Listing::Uris => lines.filter(|line| !line.starts_with('#')).filter_map(file_path).collect(),
+		Listing::Text => {
+			let text = pasted.trim();
+			// Only the one, a paragraph that happens to have a path in it isn't meant to be converted.
+			if text.lines().count() != 1 { return Vec::new(); }
+			let unquoted = text.strip_prefix(['"', '\'']).and_then(|t| t.strip_suffix(['"', '\''])).unwrap_or(text);
+			vec![file_path(unquoted).unwrap_or_else(|| PathBuf::from(unquoted))]
+		}
+	}
+}
+
+/// The path of a `file://` URI, [`file_uri`] backwards. `None` for other URIs, or ones for another host.
+fn file_path(uri: &str) -> Option<PathBuf> {
+	let rest = uri.strip_prefix("file://")?;
+	let path = rest.strip_prefix("localhost").unwrap_or(rest);
+	if !path.starts_with('/') { return None; }
+	let (raw, mut bytes, mut i) = (path.as_bytes(), Vec::with_capacity(path.len()), 0);
+	while i < raw.len() {
+		let hex = raw.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+		match hex {
+			Some(byte) if raw[i] == b'%' => { bytes.push(byte); i += 3; }
+			_ => { bytes.push(raw[i]); i += 1; }
+		}
+	}
+	Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+/// The first video among the files on the clipboard, for `--from-clipboard`.
+///
+/// # Errors
+/// When the clipboard can't be read, has no files on it, or none of them is a video ffprobe can read.
+pub fn paste_video(runner: &dyn CommandRunner) -> Result<PathBuf, String> {
+	let (mut pasted, mut read, mut error) = (Vec::new(), false, String::new());
+	for (command, listing) in paste_commands(env::var_os("WAYLAND_DISPLAY").is_some()) {
+		log::debug!("Running: {command}");
+		let failed = match runner.run(&command) {
+			Ok(output) if output.success() => {
+				read = true;
+				pasted = pasted_paths(&String::from_utf8_lossy(&output.stdout), listing);
+				if !pasted.is_empty() { break; }
+				continue;
+			}
+			// Most often there's nothing of that type on the clipboard.
+			Ok(output) => String::from_utf8_lossy(&output.stderr).trim().to_string(),
+			Err(e) => e.to_string(),
+		};
+		log::debug!("{} didn't paste: {failed}", command.program_name());
+		if error.is_empty() { error = format!("{}: {failed}", command.program_name()); }
+	}
+	if pasted.is_empty() {
+		return Err(if read { "there's no file on the clipboard, copy a video file first".to_string() } else { format!("couldn't read the clipboard, {error}") });
+	}
+	for path in &pasted {
+		match probe::probe_input(runner, path) {
+			Ok(info) if !info.is_still() => return Ok(path.clone()),
+			Ok(_) => log::debug!("{} is a still image", path.display()),
+			Err(e) => log::debug!("{} isn't a video: {e}", path.display()),
+		}
+	}
+	let names = pasted.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ");
+	Err(format!("nothing on the clipboard is a video ffmpeg can read, it has {names}"))
+}
+
+fn run(attempt: &Attempt) -> io::Result<()> {
+	// Not piped: xclip and wl-copy stay around in the background to serve the clipboard, and would hold a pipe open.
+	let mut child = Command::new(&attempt.command.program)
+		.args(&attempt.command.args)
+		.stdin(if attempt.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.spawn()?;
+	if let (Some(text), Some(mut stdin)) = (&attempt.stdin, child.stdin.take()) {
+		stdin.write_all(text.as_bytes())?;
+	}
+	let status = child.wait()?;
+	if status.success() { Ok(()) } else { Err(io::Error::other(format!("exited with {status}"))) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn uris_are_percent_encoded() {
+		assert_eq!(file_uri(Path::new("/home/me/my gifs/50%.gif")), "file:///home/me/my%20gifs/50%25.gif");
+		assert_eq!(file_uri(Path::new("/tmp/café.gif")), "file:///tmp/caf%C3%A9.gif");
+	}
+
+	#[test]
+	fn pasted_files() {
+		let uris = "# copied by a file manager\r\nfile:///home/me/my%20clips/50%25.mp4\r\nhttps://example.com/a.mp4\r\nfile://localhost/tmp/b.mp4\r\n";
+		assert_eq!(pasted_paths(uris, Listing::Uris), [PathBuf::from("/home/me/my clips/50%.mp4"), PathBuf::from("/tmp/b.mp4")]);
+		assert_eq!(pasted_paths("/tmp/a.mp4\n/tmp/b.mp4\n", Listing::Lines).len(), 2);
+		assert_eq!(pasted_paths(" '/tmp/my clip.mp4'\n", Listing::Text), [PathBuf::from("/tmp/my clip.mp4")]);
+		assert_eq!(pasted_paths("file:///tmp/caf%C3%A9.mp4", Listing::Text), [PathBuf::from("/tmp/café.mp4")]);
+		assert!(pasted_paths("a paragraph\nof text", Listing::Text).is_empty());
+		assert_eq!(file_path("file://server/share/a.mp4"), None, "another host");
+		assert_eq!(file_path(&file_uri(Path::new("/tmp/50% off.mp4"))), Some(PathBuf::from("/tmp/50% off.mp4")));
+	}
+
+	#[cfg(all(unix, not(target_os = "macos")))]
+	#[test]
+	fn the_first_video_on_the_clipboard_is_taken() {
+		use gifski_ffmpeg::runner::{CommandOutput, MockRunner};
+		let dir = crate::test_dir("paste");
+		for name in ["notes.txt", "clip.mp4"] { std::fs::write(dir.join(name), b"").unwrap(); }
+		let uris = format!("{}\r\n{}\r\n", file_uri(&dir.join("notes.txt")), file_uri(&dir.join("clip.mp4")));
+		let probe = r#"{"format":{"format_name":"mov,mp4,m4a,3gp,3g2,mj2","duration":"2.0"},"streams":[{"index":0,"codec_type":"video","r_frame_rate":"24/1"}]}"#;
+		let runner = MockRunner::new();
+		for program in ["wl-paste", "xclip"] { runner.respond(program, CommandOutput::ok_with_stdout(uris.clone())); }
+		runner.respond("ffprobe", CommandOutput::failed(1, "Invalid data found when processing input"));
+		runner.respond("ffprobe", CommandOutput::ok_with_stdout(probe));
+		assert_eq!(paste_video(&runner), Ok(dir.join("clip.mp4")));
+
+		let runner = MockRunner::new();
+		for program in ["wl-paste", "xclip"] { runner.respond(program, CommandOutput::ok_with_stdout(file_uri(&dir.join("notes.txt")))); }
+		runner.respond("ffprobe", CommandOutput::failed(1, "Invalid data found when processing input"));
+		let err = paste_video(&runner).unwrap_err();
+		assert!(err.starts_with("nothing on the clipboard is a video") && err.contains("notes.txt"), "{err}");
+
+		// Every command pasting nothing.
+		assert_eq!(paste_video(&MockRunner::new()).unwrap_err(), "there's no file on the clipboard, copy a video file first");
+		let runner = MockRunner::new();
+		for _ in 0..2 { for program in ["wl-paste", "xclip"] { runner.respond(program, CommandOutput::failed(1, "Error: target STRING not available")); } }
+		assert!(paste_video(&runner).unwrap_err().starts_with("couldn't read the clipboard, "));
+	}
+
+	#[cfg(all(unix, not(target_os = "macos")))]
+	#[test]
+	fn linux_tries_the_file_then_the_path_with_the_running_display_first() {
+		let order = |wayland| attempts(Path::new("/tmp/out.gif"), wayland).iter()
+			.map(|a| (a.command.to_string(), a.copies))
+			.collect::<Vec<_>>();
+		assert_eq!(order(true), [
+			("wl-copy --type text/uri-list".to_string(), Copied::File),
+			("wl-copy".to_string(), Copied::Path),
+			("xclip -selection clipboard -t text/uri-list".to_string(), Copied::File),
+			("xclip -selection clipboard".to_string(), Copied::Path),
+		]);
+		assert_eq!(order(false)[0].0, "xclip -selection clipboard -t text/uri-list");
+		assert_eq!(attempts(Path::new("/tmp/out.gif"), false)[0].stdin.as_deref(), Some("file:///tmp/out.gif\r\n"));
+	}
+}
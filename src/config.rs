@@ -0,0 +1,172 @@
+//! Defaults read from a config file, for the binary.
+//!
+//! It has `key = value` lines, and `#` starts a comment. Values may be in double quotes, with `\"` and `\\` escapes,
+//! to have a `#` in them:
+//!
+//! ```text
+//! # Sources faster than this are resampled down when no --fps is given.
+//! max-auto-fps = 30
+//! # Always --notify.
+//! notify = true
+//! # Where --in-memory puts the frames, instead of /dev/shm.
+//! ramdisk = /mnt/ram
+//! ```
+//!
+//! `--save-args` files are written in the same format, see [`entries`].
+
+use std::{
+	env, fs, io,
+	path::PathBuf,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use gifski_ffmpeg::DEFAULT_MAX_AUTO_FPS;
+
+/// Everything the config file can set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+	pub max_auto_fps: f32,
+	pub notify: bool,
+	pub ramdisk: Option<PathBuf>,
+	/// The settings the file has, for `--explain`.
+	pub set: Vec<String>,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Config { max_auto_fps: DEFAULT_MAX_AUTO_FPS, notify: false, ramdisk: None, set: Vec::new() }
+	}
+}
+
+/// `$GIFSKI_FFMPEG_CONFIG`, otherwise `gifski-ffmpeg/config` in the platform's config directory.
+pub fn path() -> Option<PathBuf> {
+	if let Some(path) = env::var_os("GIFSKI_FFMPEG_CONFIG") { return Some(path.into()); }
+	let dir = if cfg!(windows) {
+		env::var_os("APPDATA").map(PathBuf::from)
+	} else {
+		env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+	};
+	Some(dir?.join("gifski-ffmpeg").join("config"))
+}
+
+/// Reads the config file, or the defaults if there isn't one.
+pub fn load() -> Result<Config> {
+	let Some(path) = path() else { return Ok(Config::default()); };
+	match fs::read_to_string(&path) {
+		Ok(text) => parse(&text).with_context(|| format!("in {}", path.display())),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+		Err(e) => Err(e).with_context(|| format!("couldn't read {}", path.display())),
+	}
+}
+
+/// One `key = value` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+	/// From 1, for errors.
+	pub line: usize,
+	pub key: String,
+	pub value: String,
+	/// Whether the value was in quotes, so `"true"` can be told apart from `true`.
+	pub quoted: bool,
+}
+
+/// The `key = value` lines of a config or `--save-args` file, without comments and blank lines.
+///
+/// # Errors
+/// On lines that aren't `key = value`, and badly quoted values.
+pub fn entries(text: &str) -> Result<Vec<Entry>> {
+	let mut entries = Vec::new();
+	for (i, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') { continue; }
+		let Some((key, value)) = line.split_once('=').filter(|(key, _)| !key.contains('#')) else {
+			bail!("line {}: expected `key = value`", i + 1)
+		};
+		let value = value.trim();
+		let (value, quoted) = match value.strip_prefix('"') {
+			Some(quoted) => (unquote(quoted).with_context(|| format!("line {}: the quoted value isn't closed, or has more after it", i + 1))?, true),
+			None => (value.split('#').next().unwrap_or_default().trim().to_string(), false),
+		};
+		entries.push(Entry { line: i + 1, key: key.trim().to_string(), value, quoted });
+	}
+	Ok(entries)
+}
+
+/// `value` in quotes, for [`entries`] to read back.
+pub fn quote(value: &str) -> String {
+	format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The string at the start of `text`, which is just after the opening quote. `None` if it isn't closed, or is
+/// followed by anything but a comment.
+fn unquote(text: &str) -> Option<String> {
+	let mut value = String::new();
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' => {
+				let rest = chars.as_str().trim_start();
+				return (rest.is_empty() || rest.starts_with('#')).then_some(value);
+			}
+			'\\' => value.push(chars.next()?),
+			c => value.push(c),
+		}
+	}
+	None
+}
+
+/// A max-auto-fps, from the file or `--max-auto-fps`.
+///
+/// # Errors
+/// If it isn't a positive number.
+pub fn parse_max_auto_fps(value: &str) -> Result<f32, String> {
+	value.parse().ok().filter(|&fps: &f32| fps > 0.0).ok_or_else(|| format!("max-auto-fps should be a positive number, not {value:?}"))
+}
+
+fn parse(text: &str) -> Result<Config> {
+	let mut config = Config::default();
+	for Entry { line, key, value, .. } in entries(text)? {
+		match key.as_str() {
+			"max-auto-fps" => config.max_auto_fps = parse_max_auto_fps(&value).map_err(|e| anyhow!("line {line}: {e}"))?,
+			"notify" => {
+				config.notify = value.parse()
+					.with_context(|| format!("line {line}: notify should be true or false, not {value:?}"))?;
+			}
+			"ramdisk" if value.is_empty() => bail!("line {line}: ramdisk should be a directory"),
+			"ramdisk" => config.ramdisk = Some(value.into()),
+			key => bail!("line {line}: unknown setting {key:?}"),
+		}
+		config.set.push(key);
+	}
+	Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_settings_and_comments() {
+		assert_eq!(parse("").unwrap(), Config::default());
+		assert_eq!(parse("# mine\n\nmax-auto-fps = 24 # smaller gifs\n").unwrap().max_auto_fps.to_string(), "24");
+		assert!(parse("notify = true").unwrap().notify);
+		assert_eq!(parse("notify = true\nramdisk = /mnt/ram").unwrap().set, ["notify", "ramdisk"]);
+		assert_eq!(parse("notify = yes").unwrap_err().to_string(), "line 1: notify should be true or false, not \"yes\"");
+		assert_eq!(parse("max-auto-fps = 0").unwrap_err().to_string(), "line 1: max-auto-fps should be a positive number, not \"0\"");
+		assert_eq!(parse("\nfps = 10").unwrap_err().to_string(), "line 2: unknown setting \"fps\"");
+		assert!(parse("notify = \"true\" # quoted").unwrap().notify);
+		assert_eq!(parse("ramdisk = /mnt/ram").unwrap().ramdisk, Some(PathBuf::from("/mnt/ram")));
+		assert_eq!(parse("ramdisk =").unwrap_err().to_string(), "line 1: ramdisk should be a directory");
+	}
+
+	#[test]
+	fn quoted_values_can_have_comment_characters() {
+		for value in ["#1e1e1e", r#"say "hi" \ bye"#, "", " padded "] {
+			let entries = entries(&format!("pad-color = {}", quote(value))).unwrap();
+			assert_eq!((entries[0].value.as_str(), entries[0].quoted), (value, true));
+		}
+		assert_eq!(entries("width = 400 # px").unwrap()[0].value, "400");
+		assert_eq!(entries("comment = \"open").unwrap_err().to_string(), "line 1: the quoted value isn't closed, or has more after it");
+		assert_eq!(entries("comment = \"a\" b").unwrap_err().to_string(), "line 1: the quoted value isn't closed, or has more after it");
+		assert_eq!(entries("# a = b\nwidth # = 1").unwrap_err().to_string(), "line 2: expected `key = value`");
+	}
+}
@@ -0,0 +1,321 @@
+//! [`ConvertOptions::cursor_overlay`]: a cursor drawn onto a screen recording made without one, where the recorder
+//! logged it.
+//!
+//! The events are `{"x": 10, "y": 20, "t": 1.5}`, in the input's pixels and seconds, as a JSON array or one per line.
+//! Each frame gets the [`cursor_image`](ConvertOptions::cursor_image) where the cursor was at the frame's timestamp,
+//! on the line between the events either side of it, so those far outside the trimmed range don't matter. Before
+//! the first event and after the last it stays where they put it.
+//!
+//! It's blended onto each frame here rather than by ffmpeg, [`ConvertOptions::jobs`] frames at a time like
+//! [`frame_filter`](ConvertOptions::frame_filter), which has changed the frames first. The parts of it off the
+//! frame, like past an edge that was cropped off, are left out.
+
+use std::{
+	fs,
+	io,
+	num::NonZeroUsize,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Mutex,
+	},
+	thread,
+};
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use serde::Deserialize;
+use crate::{
+	ffmpeg::{self, Crop, Extraction},
+	pattern::FramePattern,
+	probe::VideoStream,
+	ConvertError,
+	ConvertOptions,
+	Fit,
+	Progress,
+	Result,
+	Stages,
+	WarningKind,
+};
+
+fn invalid(message: impl Into<String>) -> ConvertError {
+	ConvertError::InvalidOption { option: "cursor overlay", message: message.into() }
+}
+
+/// Where the cursor was, `x` and `y` pixels into the input `t` seconds in.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub(crate) struct Event {
+	pub x: f64,
+	pub y: f64,
+	pub t: f64,
+}
+
+/// The cursor image and where it goes, read from [`ConvertOptions::cursor_overlay`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Cursor {
+	pub image: RgbaImage,
+	/// In order of `t`.
+	pub events: Vec<Event>,
+}
+
+/// Checks there's an image to draw and events to place it with, and that each frame's timestamp and position in
+/// the input can be worked out.
+///
+/// # Errors
+/// With one of [`ConvertOptions::cursor_overlay`] and [`ConvertOptions::cursor_image`] but not the other, or options
+/// that encode the frames as they're extracted, space them unevenly, extract more than one input or move the frame
+/// around in ways the events can't follow.
+pub(crate) fn check(opt: &ConvertOptions) -> Result<()> {
+	let Some(events) = &opt.cursor_overlay else {
+		if opt.cursor_image.is_some() { return Err(invalid("--cursor-image needs --cursor-overlay events to place it")); }
+		return Ok(());
+	};
+	if opt.cursor_image.is_none() { return Err(invalid(format!("{} needs a --cursor-image to draw", events.display()))); }
+	let conflict = [
+		(opt.stages == Stages::Encode, "--stage encode, the frames' timestamps are only known while extracting"),
+		(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds, gifski has the frames before the cursor could be drawn"),
+		(opt.keyframes_only, "--keyframes-only, which spaces the frames unevenly"),
+		(!opt.concat.is_empty() || opt.grid.is_some(), "--concat or --grid, the events are for one input"),
+		(opt.width.is_some() && opt.height.is_some() && opt.fit != Fit::Stretch, "--width and --height with --fit crop or pad, which move the picture"),
+	].into_iter().find(|(set, _)| *set);
+	if let Some((_, what)) = conflict { return Err(invalid(format!("can't be combined with {what}"))); }
+	Ok(())
+}
+
+/// The events in `text`, a JSON array of them or one on each line, in order of `t`.
+///
+/// # Errors
+/// If it isn't either, or has no events.
+pub(crate) fn parse(text: &str) -> Result<Vec<Event>> {
+	let mut events: Vec<Event> = if text.trim_start().starts_with('[') {
+		serde_json::from_str(text).map_err(|e| invalid(e.to_string()))?
+	} else {
+		text.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty())
+			.map(|(i, line)| serde_json::from_str(line).map_err(|e| invalid(format!("line {}: {e}", i + 1))))
+			.collect::<Result<_>>()?
+	};
+	if events.is_empty() { return Err(invalid("has no events")); }
+	events.sort_by(|a, b| a.t.total_cmp(&b.t));
+	Ok(events)
+}
+
+/// The [`Cursor`] `opt` draws, `None` without one.
+///
+/// # Errors
+/// If the events can't be read, or [`parse`] fails, or the image isn't a PNG.
+pub(crate) fn read(opt: &ConvertOptions) -> Result<Option<Cursor>> {
+	let (Some(path), Some(image)) = (&opt.cursor_overlay, &opt.cursor_image) else { return Ok(None) };
+	let text = fs::read_to_string(path).map_err(ConvertError::io(path))?;
+	let events = parse(&text).map_err(|e| match e {
+		ConvertError::InvalidOption { option, message } => ConvertError::InvalidOption { option, message: format!("{}, {message}", path.display()) },
+		e => e,
+	})?;
+	let decoded = ImageReader::open(image).map_err(ConvertError::io(image))?
+		.with_guessed_format().map_err(ConvertError::io(image))?
+		.decode()
+		.map_err(|e| ConvertError::InvalidOption { option: "cursor image", message: format!("{} isn't a PNG that can be read: {e}", image.display()) })?;
+	Ok(Some(Cursor { image: decoded.to_rgba8(), events }))
+}
+
+/// Where the cursor is `t` seconds in, between the events either side of it, or at the first or last one.
+pub(crate) fn position(events: &[Event], t: f64) -> (f64, f64) {
+	let next = events.partition_point(|e| e.t <= t);
+	match (next.checked_sub(1).map(|i| events[i]), events.get(next)) {
+		(Some(before), Some(after)) => {
+			let share = (t - before.t) / (after.t - before.t);
+			(before.x + (after.x - before.x) * share, before.y + (after.y - before.y) * share)
+		}
+		(Some(event), None) | (None, Some(&event)) => (event.x, event.y),
+		(None, None) => (0.0, 0.0),
+	}
+}
+
+/// From the input's pixels to the extracted frames', through the crop and scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Mapping {
+	crop: Crop,
+	scaled: (u32, u32),
+}
+
+impl Mapping {
+	/// The mapping of the frames `extraction` makes of `video`.
+	///
+	/// # Errors
+	/// If the input's size isn't known, or the crop doesn't fit in it.
+	pub(crate) fn new(opt: &ConvertOptions, extraction: &Extraction, video: &VideoStream) -> Result<Self> {
+		if video.width == 0 || video.height == 0 { return Err(invalid("couldn't find the dimensions of the input to place the cursor in")); }
+		let resized = ffmpeg::resized(video, opt, extraction.focus, opt.allow_odd_dimensions)?;
+		let crop = resized.crop.unwrap_or(Crop { width: video.width, height: video.height, x: 0, y: 0 });
+		Ok(Mapping { crop, scaled: resized.scaled })
+	}
+
+	/// The `x`,`y` of the input in the frame's pixels, which is outside it if it was cropped off.
+	#[allow(clippy::cast_possible_truncation)]
+	pub(crate) fn frame_pixel(self, (x, y): (f64, f64)) -> (i64, i64) {
+		let along = |at: f64, from: u32, side: u32, scaled: u32| ((at - f64::from(from)) * f64::from(scaled) / f64::from(side.max(1))).round() as i64;
+		(along(x, self.crop.x, self.crop.width, self.scaled.0), along(y, self.crop.y, self.crop.height, self.scaled.1))
+	}
+}
+
+/// Blends `cursor` onto `frame` with its top left corner at `x`,`y`, leaving out what's off the frame.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+pub(crate) fn blend(frame: &mut RgbaImage, cursor: &RgbaImage, (x, y): (i64, i64)) {
+	let on_frame = |at: i64, side: u32, frame_side: u32| at.max(0)..(at + i64::from(side)).min(i64::from(frame_side));
+	for frame_y in on_frame(y, cursor.height(), frame.height()) {
+		for frame_x in on_frame(x, cursor.width(), frame.width()) {
+			let over = cursor.get_pixel((frame_x - x) as u32, (frame_y - y) as u32).0;
+			let under = frame.get_pixel_mut(frame_x as u32, frame_y as u32);
+			let (over_alpha, under_alpha) = (f32::from(over[3]) / 255.0, f32::from(under.0[3]) / 255.0);
+			let alpha = over_alpha + under_alpha * (1.0 - over_alpha);
+			if alpha == 0.0 { continue; }
+			for (channel, &over) in under.0.iter_mut().zip(&over[..3]) {
+				*channel = ((f32::from(over) * over_alpha + f32::from(*channel) * under_alpha * (1.0 - over_alpha)) / alpha).round() as u8;
+			}
+			under.0[3] = (alpha * 255.0).round() as u8;
+		}
+	}
+}
+
+/// Draws `cursor` onto the PNG `frame`, writing it to `output` with the same channels it had.
+fn draw_onto(frame: &Path, cursor: &RgbaImage, at: (i64, i64), output: &Path) -> Result<()> {
+	let unreadable = |e: image::ImageError| ConvertError::io(frame)(io::Error::new(io::ErrorKind::InvalidData, e));
+	let image = ImageReader::open(frame).map_err(ConvertError::io(frame))?.with_guessed_format().map_err(ConvertError::io(frame))?
+		.decode().map_err(unreadable)?;
+	let had_alpha = image.color().has_alpha();
+	let mut pixels = image.to_rgba8();
+	blend(&mut pixels, cursor, at);
+	let drawn = if had_alpha { DynamicImage::ImageRgba8(pixels) } else { DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(pixels).to_rgb8()) };
+	drawn.save_with_format(output, ImageFormat::Png)
+		.map_err(|e| ConvertError::io(output)(io::Error::other(e)))
+}
+
+impl Cursor {
+	/// Draws the cursor onto each of `frames`, numbered from 1 by the pattern at `fps` from `start` seconds into the input, at most
+	/// `jobs` at once or one per CPU. Warns when none of the events are in the time the frames cover, the cursor
+	/// doesn't move then.
+	///
+	/// # Errors
+	/// If a frame can't be read, drawn on or replaced. None are replaced then.
+	pub(crate) fn draw(
+		&self,
+		mapping: Mapping,
+		(frames, pattern): (&[PathBuf], &FramePattern),
+		(start, fps): (f64, f64),
+		jobs: Option<usize>,
+		progress: &mut dyn FnMut(Progress),
+	) -> Result<()> {
+		#[allow(clippy::cast_precision_loss)]
+		let times: Vec<f64> = frames.iter().enumerate()
+			.map(|(i, frame)| start + pattern.number(frame).unwrap_or(i + 1).saturating_sub(1) as f64 / fps)
+			.collect();
+		let (Some(&first), Some(&last)) = (times.first(), times.last()) else { return Ok(()) };
+		if !self.events.iter().any(|e| (first..=last).contains(&e.t)) {
+			progress(Progress::warning(WarningKind::CursorEventsOutsideRange, format!(
+				"None of the {} cursor events are between {first:.2}s and {last:.2}s, so the cursor stays where the nearest one put it",
+				self.events.len(),
+			)));
+		}
+		// Named so they're never frames themselves, whatever the pattern.
+		let drawn: Vec<PathBuf> = frames.iter().map(|f| f.with_file_name(format!("cursor-{}", f.file_name().unwrap_or_default().to_string_lossy()))).collect();
+		let jobs = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get));
+		progress(Progress::Info(format!("Drawing the cursor on {} frames from {} events, {jobs} at a time", frames.len(), self.events.len())));
+
+		// Each thread takes the next frame until they're all drawn, or one of them fails.
+		let (next, failed) = (AtomicUsize::new(0), Mutex::new(None));
+		thread::scope(|scope| {
+			for _ in 0..jobs.clamp(1, frames.len()) {
+				scope.spawn(|| loop {
+					let i = next.fetch_add(1, Ordering::Relaxed);
+					if i >= frames.len() || failed.lock().is_ok_and(|f| f.is_some()) { break; }
+					let at = mapping.frame_pixel(position(&self.events, times[i]));
+					if let Err(e) = draw_onto(&frames[i], &self.image, at, &drawn[i]) {
+						if let Ok(mut failed) = failed.lock() { failed.get_or_insert(e); }
+						break;
+					}
+				});
+			}
+		});
+		if let Some(e) = failed.into_inner().ok().flatten() {
+			for output in &drawn { let _ = fs::remove_file(output); }
+			return Err(e);
+		}
+		for (output, frame) in drawn.iter().zip(frames) {
+			fs::rename(output, frame).map_err(ConvertError::io(frame))?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn event(x: f64, y: f64, t: f64) -> Event {
+		Event { x, y, t }
+	}
+
+	#[test]
+	fn reads_arrays_and_lines() {
+		let events = [event(10.0, 20.0, 0.0), event(30.0, 40.0, 0.5)];
+		assert_eq!(parse(r#"[{"x": 30, "y": 40, "t": 0.5}, {"x": 10, "y": 20, "t": 0}]"#).unwrap(), events, "in order");
+		assert_eq!(parse("{\"x\": 10, \"y\": 20, \"t\": 0}\n\n{\"x\": 30, \"y\": 40, \"t\": 0.5, \"button\": 1}\n").unwrap(), events);
+		assert_eq!(parse("{\"x\": 10, \"y\": 20, \"t\": 0}\n{\"x\": 30}\n").unwrap_err().to_string(), "Invalid cursor overlay: line 2: missing field `y` at line 1 column 9");
+		assert!(parse("[]").is_err());
+	}
+
+	#[test]
+	fn moves_in_a_line_between_events() {
+		let events = [event(0.0, 0.0, 1.0), event(100.0, 50.0, 2.0), event(100.0, 50.0, 3.0), event(0.0, 0.0, 3.0)];
+		assert_eq!(position(&events, 1.5), (50.0, 25.0));
+		assert_eq!(position(&events, 2.5), (100.0, 50.0));
+		assert_eq!(position(&events, 0.0), (0.0, 0.0), "before the first");
+		assert_eq!(position(&events, 9.0), (0.0, 0.0), "after the last, which jumped");
+	}
+
+	#[test]
+	fn maps_through_the_crop_and_scale() {
+		let mapping = Mapping { crop: Crop { width: 800, height: 600, x: 100, y: 50 }, scaled: (400, 300) };
+		assert_eq!(mapping.frame_pixel((100.0, 50.0)), (0, 0));
+		assert_eq!(mapping.frame_pixel((501.0, 350.0)), (201, 150));
+		assert_eq!(mapping.frame_pixel((0.0, 0.0)), (-50, -25), "cropped off");
+	}
+
+	#[test]
+	fn blends_by_the_cursors_alpha() {
+		let mut frame = RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 200, 255]));
+		let mut cursor = RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255]));
+		cursor.put_pixel(1, 1, image::Rgba([255, 0, 0, 128]));
+		cursor.put_pixel(0, 1, image::Rgba([9, 9, 9, 0]));
+		blend(&mut frame, &cursor, (1, 1));
+		assert_eq!(frame.get_pixel(1, 1).0, [255, 255, 255, 255], "opaque");
+		assert_eq!(frame.get_pixel(2, 2).0, [128, 0, 100, 255], "half way");
+		assert_eq!(frame.get_pixel(1, 2).0, [0, 0, 200, 255], "see-through");
+		assert_eq!(frame.get_pixel(0, 0).0, [0, 0, 200, 255], "not under it");
+
+		let mut clear = RgbaImage::new(2, 2);
+		blend(&mut clear, &RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 128])), (0, 0));
+		assert_eq!(clear.get_pixel(0, 0).0, [255, 0, 0, 128], "over nothing it's itself");
+	}
+
+	#[test]
+	fn leaves_out_what_is_off_the_frame() {
+		let cursor = RgbaImage::from_pixel(3, 3, image::Rgba([255, 255, 255, 255]));
+		for (at, white) in [((-2, -2), vec![(0, 0)]), ((3, 2), vec![(3, 2), (3, 3)]), ((-5, 0), vec![]), ((4, 4), vec![]), ((0, 9), vec![])] {
+			let mut frame = RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]));
+			blend(&mut frame, &cursor, at);
+			let drawn: Vec<(u32, u32)> = frame.enumerate_pixels().filter(|(_, _, p)| p.0[0] == 255).map(|(x, y, _)| (x, y)).collect();
+			assert_eq!(drawn, white, "at {at:?}");
+		}
+	}
+
+	#[test]
+	fn needs_both_the_events_and_the_image() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.cursor_overlay = Some(PathBuf::from("events.json"));
+		assert_eq!(check(&opt).unwrap_err().to_string(), "Invalid cursor overlay: events.json needs a --cursor-image to draw");
+		opt.cursor_image = Some(PathBuf::from("cursor.png"));
+		assert!(check(&opt).is_ok());
+		opt.keyframes_only = true;
+		assert!(check(&opt).is_err());
+	}
+}
@@ -0,0 +1,126 @@
+//! Disk space queries.
+
+use std::{
+	fs,
+	path::Path,
+	sync::mpsc::{self, RecvTimeoutError},
+	thread::{self, JoinHandle},
+	time::Duration,
+};
+
+/// What [`ConvertOptions::reserve_space`](crate::ConvertOptions::reserve_space) is unless it's set, 256 MiB.
+pub const DEFAULT_RESERVE_SPACE: u64 = 256 * 1024 * 1024;
+
+/// How often a [`SpaceWatch`] checks the free space.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Free bytes available to unprivileged users on the filesystem `path` is on.
+///
+/// `None` if that can't be determined, including on platforms where it isn't implemented.
+#[must_use]
+pub fn free_space(path: &Path) -> Option<u64> {
+	#[cfg(unix)]
+	{
+		use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+		let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+		// SAFETY: statvfs only writes into the zeroed struct we hand it.
+		let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+		if unsafe { libc::statvfs(c_path.as_ptr(), &raw mut stat) } != 0 { return None; }
+		#[allow(clippy::unnecessary_cast)] // The field types differ between platforms.
+		Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = path;
+		None
+	}
+}
+
+/// Watches the free space on a filesystem from another thread, see [`watch`].
+pub(crate) struct SpaceWatch {
+	stop: mpsc::Sender<()>,
+	thread: JoinHandle<Option<u64>>,
+}
+
+/// Checks straight away, then every [`WATCH_INTERVAL`] until it's [stopped](SpaceWatch::stop), whether the
+/// filesystem `dir` is on has fewer than `reserve` bytes free, and calls `low` and stops the first time it does.
+pub(crate) fn watch(dir: &Path, reserve: u64, low: impl FnOnce() + Send + 'static) -> SpaceWatch {
+	let (stop, stopped) = mpsc::channel();
+	let dir = dir.to_path_buf();
+	let thread = thread::spawn(move || loop {
+		if let Some(free) = free_space(&dir).filter(|&free| free < reserve) {
+			low();
+			return Some(free);
+		}
+		if stopped.recv_timeout(WATCH_INTERVAL) != Err(RecvTimeoutError::Timeout) { return None; }
+	});
+	SpaceWatch { stop, thread }
+}
+
+impl SpaceWatch {
+	/// Stops watching, and returns the free bytes there were when they dropped below the reserve, if they did.
+	pub(crate) fn stop(self) -> Option<u64> {
+		let SpaceWatch { stop, thread } = self;
+		drop(stop);
+		thread.join().unwrap_or_default()
+	}
+}
+
+/// Total size of the files in `dir` and its subdirectories. Anything that can't be read counts as 0.
+#[must_use]
+pub fn dir_size(dir: &Path) -> u64 {
+	let Ok(entries) = fs::read_dir(dir) else { return 0 };
+	entries.flatten().map(|entry| match entry.metadata() {
+		Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+		Ok(meta) => meta.len(),
+		Err(_) => 0,
+	}).sum()
+}
+
+/// `1536` -> `1.5 KiB`.
+#[must_use]
+#[allow(clippy::cast_precision_loss)] // It's for display.
+pub fn human_size(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+	let mut size = bytes as f64;
+	let mut unit = 0;
+	while size >= 1024.0 && unit < UNITS.len() - 1 {
+		size /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 { format!("{bytes} B") } else { format!("{size:.1} {}", UNITS[unit]) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn human_sizes() {
+		assert_eq!(human_size(0), "0 B");
+		assert_eq!(human_size(1023), "1023 B");
+		assert_eq!(human_size(1536), "1.5 KiB");
+		assert_eq!(human_size(8 * 1024 * 1024), "8.0 MiB");
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn temp_dir_has_some_free_space() {
+		assert!(free_space(&std::env::temp_dir()).is_some_and(|free| free > 0));
+		assert_eq!(free_space(Path::new("/definitely/not/a/dir")), None);
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn the_watch_calls_low_once_below_the_reserve() {
+		let (called, low) = mpsc::channel();
+		let free = watch(&std::env::temp_dir(), u64::MAX, move || called.send(()).unwrap()).stop();
+		assert!(free.is_some_and(|free| free > 0));
+		assert_eq!(low.try_recv(), Ok(()));
+
+		let (called, low) = mpsc::channel();
+		assert_eq!(watch(&std::env::temp_dir(), 1, move || called.send(()).unwrap()).stop(), None);
+		assert!(low.try_recv().is_err(), "never called");
+	}
+}
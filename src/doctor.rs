@@ -0,0 +1,153 @@
+//! Self checks for setup problems, behind `--doctor`.
+
+use std::{
+	env,
+	fs,
+	path::Path,
+};
+use crate::{
+	disk,
+	runner::{CommandLine, CommandRunner},
+	tools::{self, Tool},
+	Conversion, ConvertOptions,
+};
+
+/// Free space in the temp directory below which the frames directory check fails.
+pub const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Check {
+	pub name: &'static str,
+	pub passed: bool,
+	/// What was found, or what went wrong and how to fix it.
+	pub detail: String,
+}
+
+impl Check {
+	fn new(name: &'static str, passed: bool, detail: impl Into<String>) -> Self {
+		Check { name, passed, detail: detail.into() }
+	}
+}
+
+/// Runs every check. The conversion check only runs if both tools were found.
+#[must_use]
+pub fn run_checks(runner: &dyn CommandRunner) -> Vec<Check> {
+	let ffmpeg = check_tool(runner, Tool::Ffmpeg);
+	let gifski = check_tool(runner, Tool::Gifski);
+	let tools_ok = ffmpeg.passed && gifski.passed;
+	let mut checks = vec![ffmpeg, gifski, check_temp_dir(&env::temp_dir())];
+	checks.push(if tools_ok {
+		check_conversion(runner)
+	} else {
+		Check::new("conversion", false, "skipped, it needs both ffmpeg and gifski")
+	});
+	checks
+}
+
+fn check_tool(runner: &dyn CommandRunner, tool: Tool) -> Check {
+	let name = tool.program();
+	let Ok(info) = tools::probe(runner, tool) else {
+		let path = env::var_os("PATH").unwrap_or_default();
+		let searched: Vec<String> = env::split_paths(&path).map(|p| p.display().to_string()).collect();
+		return Check::new(name, false, format!(
+			"not found. Install it or add the directory it's in to the PATH, which is:\n{}",
+			if searched.is_empty() { "(empty)".to_string() } else { searched.join("\n") },
+		));
+	};
+	let location = info.path.as_ref().map_or_else(String::new, |p| format!(" at {}", p.display()));
+	let version = info.version.as_deref().unwrap_or("(unknown version)");
+	let (major, minor) = tool.minimum_version();
+	match info.is_adequate() {
+		Some(false) => Check::new(name, false, format!("{version}{location} is older than the required {major}.{minor}")),
+		Some(true) => Check::new(name, true, format!("{version}{location}")),
+		None => Check::new(name, true, format!("{version}{location}, couldn't tell if it's at least {major}.{minor}")),
+	}
+}
+
+fn check_temp_dir(temp: &Path) -> Check {
+	let probe = temp.join("gifski-ffmpeg-doctor-write-test");
+	if let Err(e) = fs::write(&probe, b"") {
+		return Check::new("frames directory", false, format!("can't write to {}: {e}", temp.display()));
+	}
+	let _ = fs::remove_file(&probe);
+	match disk::free_space(temp) {
+		Some(free) if free < MIN_FREE_SPACE => Check::new("frames directory", false, format!(
+			"only {} free in {}, long videos need a lot more", disk::human_size(free), temp.display(),
+		)),
+		Some(free) => Check::new("frames directory", true, format!("{} is writable, {} free", temp.display(), disk::human_size(free))),
+		None => Check::new("frames directory", true, format!("{} is writable, couldn't check free space", temp.display())),
+	}
+}
+
+/// Generates a one second test clip and converts it for real.
+fn check_conversion(runner: &dyn CommandRunner) -> Check {
+	let dir = env::temp_dir().join("gifski-ffmpeg-doctor");
+	let _ = fs::remove_dir_all(&dir);
+	if let Err(e) = fs::create_dir_all(&dir) {
+		return Check::new("conversion", false, format!("can't create {}: {e}", dir.display()));
+	}
+	let check = convert_test_clip(runner, &dir);
+	let _ = fs::remove_dir_all(&dir);
+	check
+}
+
+fn convert_test_clip(runner: &dyn CommandRunner, dir: &Path) -> Check {
+	let clip = dir.join("testsrc.mp4");
+	let generate = CommandLine::new("ffmpeg")
+		.args(["-y", "-loglevel", "error", "-f", "lavfi", "-i", "testsrc=duration=1:size=160x120:rate=10", "-pix_fmt", "yuv420p"])
+		.arg(&clip);
+	match runner.run(&generate) {
+		Ok(output) if output.success() => {}
+		Ok(output) => return Check::new("conversion", false, format!(
+			"ffmpeg couldn't generate a test clip: {}", String::from_utf8_lossy(&output.stderr).trim(),
+		)),
+		Err(e) => return Check::new("conversion", false, format!("ffmpeg couldn't generate a test clip: {e}")),
+	}
+
+	let mut options = ConvertOptions::new(&clip);
+	options.output = Some(dir.join("testsrc.gif").into_os_string());
+	options.frames_dir = Some(dir.join("frames"));
+	match Conversion::new(options).runner(runner).run() {
+		Ok(report) if fs::read(&report.output).is_ok_and(|gif| gif.starts_with(b"GIF8")) =>
+			Check::new("conversion", true, format!("converted a test clip into {} frames", report.frame_count)),
+		Ok(report) => Check::new("conversion", false, format!("gifski didn't write a valid gif to {}", report.output.display())),
+		Err(e) => Check::new("conversion", false, e.to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io;
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	#[test]
+	fn missing_tool_fails_and_skips_the_conversion() {
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright"));
+		mock.respond_with("gifski", |_| Err(io::ErrorKind::NotFound.into()));
+
+		let checks = run_checks(&mock);
+
+		assert!(checks[0].passed, "{:?}", checks[0]);
+		assert!(!checks[1].passed);
+		assert!(checks[1].detail.contains("PATH"), "{}", checks[1].detail);
+		assert!(!checks[3].passed);
+		// Nothing but the version checks ran.
+		assert_eq!(mock.calls().len(), 2);
+	}
+
+	#[test]
+	fn old_versions_fail() {
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 3.4.8 Copyright"));
+
+		let check = check_tool(&mock, Tool::Ffmpeg);
+
+		assert!(!check.passed);
+		assert!(check.detail.starts_with("3.4.8"), "{}", check.detail);
+		assert!(check.detail.contains("older than the required 4.0"), "{}", check.detail);
+	}
+}
@@ -0,0 +1,214 @@
+//! Telling whether a directory is on a network share or a removable drive, where writing thousands of small frames
+//! is many times slower than on a local disk.
+//!
+//! What the platform says is turned into a [`Drive`] by small functions of their own, [`network_filesystem`],
+//! [`removable_block_device`] and [`windows_drive_type`], so each platform's rules are tested on any of them.
+
+use std::{
+	fmt,
+	path::{Path, PathBuf},
+};
+
+/// What kind of drive a directory is on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Drive {
+	/// Or not known to be anything else.
+	Local,
+	/// A network filesystem, with its type, e.g. `nfs4`.
+	Network(String),
+	/// A USB drive, an SD card and the like.
+	Removable,
+}
+
+impl fmt::Display for Drive {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Drive::Local => write!(f, "a local drive"),
+			Drive::Network(fstype) => write!(f, "a network share ({fstype})"),
+			Drive::Removable => write!(f, "a removable drive"),
+		}
+	}
+}
+
+/// Filesystem types that live on another machine. FUSE ones are `fuse.<type>` on Linux, which is left off first.
+const NETWORK_FILESYSTEMS: &[&str] = &[
+	"nfs", "nfs4", "cifs", "smb", "smb2", "smb3", "smbfs", "afs", "afpfs", "ncpfs", "9p", "ceph", "glusterfs", "lustre",
+	"gpfs", "sshfs", "davfs", "webdav", "s3fs", "rclone",
+];
+
+/// [`Drive::Network`] if `fstype` is one of the [`NETWORK_FILESYSTEMS`].
+#[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(dead_code))] // Tested everywhere.
+pub(crate) fn network_filesystem(fstype: &str) -> Option<Drive> {
+	let name = fstype.strip_prefix("fuse.").unwrap_or(fstype);
+	NETWORK_FILESYSTEMS.contains(&name).then(|| Drive::Network(fstype.to_string()))
+}
+
+/// Whether a Linux block device is removable, given the `removable` flag in its sysfs directory, or its disk's for a
+/// partition, and where that directory really is. External USB disks say they aren't removable, but they're on the
+/// USB bus all the same, and card readers are on the MMC one.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn removable_block_device(removable_flag: Option<&str>, sys_path: &Path) -> bool {
+	let sys_path = sys_path.to_string_lossy();
+	removable_flag.map(str::trim) == Some("1") || sys_path.contains("/usb") || sys_path.contains("/mmc")
+}
+
+/// What `GetDriveTypeW` returned.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) fn windows_drive_type(drive_type: u32) -> Drive {
+	const DRIVE_REMOVABLE: u32 = 2;
+	const DRIVE_REMOTE: u32 = 4;
+	match drive_type {
+		DRIVE_REMOVABLE => Drive::Removable,
+		DRIVE_REMOTE => Drive::Network("remote".to_string()),
+		_ => Drive::Local,
+	}
+}
+
+/// The mount point, device and filesystem type of the mount `path` is on, from the text of `/proc/self/mounts`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn mount_of<'m>(mounts: &'m str, path: &Path) -> Option<(PathBuf, &'m str, &'m str)> {
+	mounts.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			let (device, mount_point, fstype) = (fields.next()?, fields.next()?, fields.next()?);
+			// Spaces and the like are octal escapes.
+			let mount_point = PathBuf::from(mount_point.replace("\\040", " ").replace("\\011", "\t").replace("\\134", "\\"));
+			path.starts_with(&mount_point).then_some((mount_point, device, fstype))
+		})
+		// The deepest, which is mounted over the ones it's in. Of those the last, mounted over the others.
+		.fold(None, |deepest: Option<(PathBuf, &str, &str)>, mount| match &deepest {
+			Some((point, ..)) if point.components().count() > mount.0.components().count() => deepest,
+			_ => Some(mount),
+		})
+}
+
+/// What `dir` is on, the nearest of its parents that exists if it doesn't yet. [`Drive::Local`] if that can't be
+/// told.
+pub(crate) fn drive(dir: &Path) -> Drive {
+	let Some(existing) = dir.ancestors().find(|d| d.exists()) else { return Drive::Local };
+	let dir = existing.canonicalize().unwrap_or_else(|_| existing.to_path_buf());
+	platform_drive(&dir)
+}
+
+#[cfg(target_os = "linux")]
+fn platform_drive(dir: &Path) -> Drive {
+	let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else { return Drive::Local };
+	let Some((_, device, fstype)) = mount_of(&mounts, dir) else { return Drive::Local };
+	if let Some(network) = network_filesystem(fstype) { return network; }
+	let Some(name) = device.strip_prefix("/dev/") else { return Drive::Local };
+	let sys = Path::new("/sys/class/block").join(name);
+	let flag = std::fs::read_to_string(sys.join("removable")).or_else(|_| std::fs::read_to_string(sys.join("../removable"))).ok();
+	let sys_path = sys.canonicalize().unwrap_or(sys);
+	if removable_block_device(flag.as_deref(), &sys_path) { Drive::Removable } else { Drive::Local }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_drive(dir: &Path) -> Drive {
+	use std::{ffi::{CStr, CString}, os::unix::ffi::OsStrExt};
+
+	let Ok(c_path) = CString::new(dir.as_os_str().as_bytes()) else { return Drive::Local };
+	// SAFETY: statfs only writes into the zeroed struct we hand it.
+	let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+	if unsafe { libc::statfs(c_path.as_ptr(), &raw mut stat) } != 0 { return Drive::Local; }
+	// SAFETY: the kernel fills it in as a NUL-terminated string.
+	let fstype = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) }.to_string_lossy();
+	match network_filesystem(&fstype) {
+		Some(network) => network,
+		None if stat.f_flags & libc::MNT_LOCAL as u32 == 0 => Drive::Network(fstype.into_owned()),
+		None => Drive::Local,
+	}
+}
+
+#[cfg(windows)]
+fn platform_drive(dir: &Path) -> Drive {
+	use std::{os::windows::ffi::OsStrExt, path::{Component, Prefix}};
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetDriveTypeW(root: *const u16) -> u32;
+	}
+	let root = match dir.components().next() {
+		Some(Component::Prefix(prefix)) => match prefix.kind() {
+			Prefix::UNC(..) | Prefix::VerbatimUNC(..) => return Drive::Network("smb".to_string()),
+			_ => Path::new(prefix.as_os_str()).join("\\"),
+		},
+		_ => return Drive::Local,
+	};
+	let wide: Vec<u16> = root.as_os_str().encode_wide().chain([0]).collect();
+	// SAFETY: `wide` is a NUL-terminated path that outlives the call.
+	windows_drive_type(unsafe { GetDriveTypeW(wide.as_ptr()) })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn platform_drive(_: &Path) -> Drive {
+	Drive::Local
+}
+
+/// The usual local temp directories, to use instead of a temp directory that isn't local.
+pub(crate) fn local_temp_dirs() -> Vec<PathBuf> {
+	if cfg!(windows) {
+		let local = std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Temp"));
+		local.into_iter().chain([PathBuf::from("C:\\Windows\\Temp")]).collect()
+	} else {
+		vec![PathBuf::from("/tmp"), PathBuf::from("/var/tmp")]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn network_filesystems() {
+		assert_eq!(network_filesystem("nfs4"), Some(Drive::Network("nfs4".to_string())));
+		assert_eq!(network_filesystem("fuse.sshfs"), Some(Drive::Network("fuse.sshfs".to_string())));
+		assert_eq!(network_filesystem("cifs").unwrap().to_string(), "a network share (cifs)");
+		for local in ["ext4", "btrfs", "apfs", "tmpfs", "fuse.gocryptfs", "vfat", "nfsd"] {
+			assert_eq!(network_filesystem(local), None, "{local}");
+		}
+	}
+
+	#[test]
+	fn removable_block_devices() {
+		let usb = Path::new("/sys/devices/pci0000:00/0000:00:14.0/usb2/2-1/2-1:1.0/host6/target6:0:0/6:0:0:0/block/sdb/sdb1");
+		let sata = Path::new("/sys/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda/sda1");
+		assert!(removable_block_device(Some("1\n"), sata));
+		assert!(removable_block_device(Some("0\n"), usb), "an external disk");
+		assert!(removable_block_device(None, Path::new("/sys/devices/platform/soc/mmc0/mmc_host/mmc0/mmc0:0001/block/mmcblk0")));
+		assert!(!removable_block_device(Some("0\n"), sata));
+		assert!(!removable_block_device(None, Path::new("/sys/devices/virtual/block/loop0")));
+	}
+
+	#[test]
+	fn windows_drive_types() {
+		assert_eq!(windows_drive_type(2), Drive::Removable);
+		assert_eq!(windows_drive_type(4), Drive::Network("remote".to_string()));
+		for local in [0, 1, 3, 5, 6] {
+			assert_eq!(windows_drive_type(local), Drive::Local);
+		}
+	}
+
+	#[test]
+	fn the_deepest_mount_wins() {
+		let mounts = "\
+/dev/sda2 / ext4 rw,relatime 0 0
+tmpfs /tmp tmpfs rw 0 0
+server:/export /mnt/videos nfs4 rw 0 0
+/dev/sdb1 /media/me/My\\040Stick vfat rw 0 0
+server:/export/clips /mnt/videos/clips cifs rw 0 0
+";
+		let mount = |path: &str| mount_of(mounts, Path::new(path)).map(|(point, device, fstype)| (point.display().to_string(), device, fstype));
+		assert_eq!(mount("/home/me/clip.mp4"), Some(("/".to_string(), "/dev/sda2", "ext4")));
+		assert_eq!(mount("/mnt/videos/a/frames"), Some(("/mnt/videos".to_string(), "server:/export", "nfs4")));
+		assert_eq!(mount("/mnt/videos/clips"), Some(("/mnt/videos/clips".to_string(), "server:/export/clips", "cifs")));
+		assert_eq!(mount("/mnt/videoshare"), Some(("/".to_string(), "/dev/sda2", "ext4")), "by whole components");
+		assert_eq!(mount("/media/me/My Stick/frames").unwrap().1, "/dev/sdb1");
+		assert_eq!(mount_of("", Path::new("/")), None);
+	}
+
+	#[test]
+	fn directories_not_made_yet_are_where_their_parent_is() {
+		let temp = std::env::temp_dir();
+		assert_eq!(drive(&temp.join("not").join("made").join("yet")), drive(&temp));
+	}
+}
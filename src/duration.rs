@@ -0,0 +1,162 @@
+//! [`ConvertOptions::exact_duration`]: a gif exactly so long whatever the input's length, for a slot that plays it
+//! for a fixed time.
+//!
+//! GIF delays are whole centiseconds, and gifski rounds each frame's start to the nearest one from its number, rather
+//! than adding up rounded delays, so the frames that fit are counted the same way. Frames past the length are
+//! deleted before the encode, and once it's done the last one gets whatever delay is left, holding it if the input
+//! was shorter.
+
+use std::{fs, path::{Path, PathBuf}};
+use crate::{gif, ConvertError, ConvertOptions, Progress, Result, Stages};
+
+/// The shortest delay browsers keep, they slow anything under it down to 10 centiseconds.
+const MIN_DELAY: u64 = 2;
+
+fn invalid(message: impl Into<String>) -> ConvertError {
+	ConvertError::InvalidOption { option: "exact duration", message: message.into() }
+}
+
+/// Checks the length can be a GIF's, and the gif is encoded from the frames in one go.
+///
+/// # Errors
+/// With a length under 0.02 seconds, or one the last frame can't be held for, or options that encode the gif some
+/// other way or set the delays themselves.
+pub(crate) fn check(opt: &ConvertOptions) -> Result<()> {
+	let Some(seconds) = opt.exact_duration else { return Ok(()) };
+	let longest = f64::from(u16::MAX) / 100.0;
+	if !(0.02..=longest).contains(&seconds) { return Err(invalid(format!("{seconds}s isn't between 0.02s and {longest}s"))); }
+	let conflict = [
+		(opt.stages == Stages::Extract, "--stage extract, which makes no gif"),
+		(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds, gifski has the frames before they could be counted"),
+		(opt.source_timing, "--source-timing, which gives the frames their own delays"),
+		(!opt.quality_map.is_empty() || !opt.compare_quality.is_empty() || !opt.sizes.is_empty(), "--quality-map, --compare-quality or --sizes"),
+	].into_iter().find(|(set, _)| *set);
+	if let Some((_, what)) = conflict { return Err(invalid(format!("can't be combined with {what}"))); }
+	Ok(())
+}
+
+/// `seconds` in whole centiseconds.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn ticks(seconds: f64) -> u64 {
+	(seconds * 100.0).round().max(0.0) as u64
+}
+
+/// The centisecond frame `index` starts at, at `fps`, the way gifski rounds it. From the index each time, so nothing
+/// adds up.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+fn start_tick(index: usize, fps: f32) -> u64 {
+	(index as f64 * 100.0 / f64::from(fps)).round() as u64
+}
+
+/// How many of `available` frames at `fps` fit in `ticks` centiseconds, and the delay the last one needs to end
+/// right at them. Each frame kept starts at least [`MIN_DELAY`] before the end, so the last one isn't slowed down.
+pub(crate) fn fit(ticks: u64, fps: f32, available: usize) -> (usize, u64) {
+	let mut count = available.max(1);
+	// What fits, from a guess that float error leaves a frame or so off either way.
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+	let guess = (ticks as f64 * f64::from(fps) / 100.0).ceil() as usize;
+	count = count.min(guess + 1);
+	while count > 1 && start_tick(count - 1, fps) + MIN_DELAY > ticks { count -= 1; }
+	(count, ticks.saturating_sub(start_tick(count - 1, fps)))
+}
+
+/// Deletes the frames past what fits in `seconds` at `fps`, saying how the gif gets to that length: with fewer
+/// frames, the last one held longer, or both as they are.
+///
+/// # Errors
+/// If a frame can't be deleted.
+pub(crate) fn cut_frames(mut frames: Vec<PathBuf>, seconds: f64, fps: f32, progress: &mut dyn FnMut(Progress)) -> Result<Vec<PathBuf>> {
+	let (count, last) = fit(ticks(seconds), fps, frames.len());
+	let cut = frames.len() - count.min(frames.len());
+	for frame in frames.drain(count.min(frames.len())..) {
+		fs::remove_file(&frame).map_err(ConvertError::io(frame))?;
+	}
+	let usual = start_tick(count, fps) - start_tick(count - 1, fps);
+	#[allow(clippy::cast_precision_loss)]
+	let held = last.saturating_sub(usual) as f64 / 100.0;
+	progress(Progress::Info(match (cut, held > 0.0) {
+		(0, false) => format!("The {count} frames make {seconds:.2}s as they are"),
+		(0, true) => format!("Holding the last of the {count} frames {held:.2}s longer to make {seconds:.2}s"),
+		(cut, _) => format!("Deleted the {cut} frames past {seconds:.2}s, {count} left"),
+	}));
+	Ok(frames)
+}
+
+/// Gives the last frame of `gif` the delay that makes the gif's `ticks` long.
+///
+/// # Errors
+/// If the gif can't be read or written.
+pub(crate) fn hold_last(gif: &Path, ticks: u64) -> Result<()> {
+	let bytes = fs::read(gif).map_err(ConvertError::io(gif))?;
+	let mut delays = gif::delays(&bytes).unwrap_or_default();
+	let Some((last, others)) = delays.split_last_mut() else {
+		log::debug!("{} has no frames to hold the last of", gif.display());
+		return Ok(());
+	};
+	let before = others.iter().map(|&d| u64::from(d)).sum::<u64>();
+	*last = u16::try_from(ticks.saturating_sub(before).max(MIN_DELAY)).unwrap_or(u16::MAX);
+	let last = *last;
+	log::debug!("Holding the last frame for {last}cs, after {before}cs of the others");
+	let Some(held) = gif::set_delays(&bytes, &delays) else { return Ok(()) };
+	fs::write(gif, held).map_err(ConvertError::io(gif))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// How long `count` frames play at `fps` once the last one has `last`, in centiseconds.
+	fn total(count: usize, fps: f32, last: u64) -> u64 {
+		start_tick(count - 1, fps) + last
+	}
+
+	#[test]
+	fn fits_frames_in_whole_centiseconds() {
+		assert_eq!(fit(500, 12.0, 100), (60, 8), "the usual 8 or 9 for the last");
+		assert_eq!(fit(500, 30.0, 150), (150, 3));
+		// 23.976fps frames are 4.17cs, which don't divide 5s, so the last one makes up the difference.
+		let (count, last) = fit(500, 23.976, 1000);
+		assert_eq!(count, 120);
+		assert_eq!(total(count, 23.976, last), 500);
+		// 7.5cs frames: the one at 502.5 rounds to 503, just the 2cs a last frame needs before 5.05s.
+		assert_eq!(fit(505, 13.333_333, 1000), (68, 2));
+		assert_eq!(fit(504, 13.333_333, 1000), (67, 9), "not 1cs, which browsers would make 10");
+	}
+
+	#[test]
+	fn no_drift_over_long_gifs() {
+		for fps in [10.0, 12.5, 15.0, 23.976, 24.0, 29.97, 30.0, 50.0] {
+			for ticks in [100, 499, 500, 501, 3000, 59_999] {
+				let (count, last) = fit(ticks, fps, usize::MAX);
+				assert_eq!(total(count, fps, last), ticks, "{fps}fps, {ticks}cs");
+				assert!((MIN_DELAY..=MIN_DELAY + 10).contains(&last), "{fps}fps, {ticks}cs: {last}");
+			}
+		}
+	}
+
+	#[test]
+	fn short_inputs_hold_the_last_frame() {
+		assert_eq!(fit(500, 10.0, 20), (20, 310));
+		assert_eq!(fit(500, 10.0, 0), (1, 500), "a single frame for all of it");
+		assert_eq!(fit(2, 50.0, 10), (1, 2));
+	}
+
+	#[test]
+	fn seconds_round_to_centiseconds() {
+		assert_eq!(ticks(5.0), 500);
+		assert_eq!(ticks(0.026), 3);
+		assert_eq!(ticks(1.004_999), 100);
+	}
+
+	#[test]
+	fn the_last_frame_takes_what_is_left() {
+		let path = crate::test_dir("hold-last").join("hold-last.gif");
+		let gif = gif::concat(&vec![gif::TEST_GIF.to_vec(); 3]).unwrap();
+		fs::write(&path, gif::set_delays(&gif, &[8, 9, 8]).unwrap()).unwrap();
+
+		hold_last(&path, 500).unwrap();
+
+		assert_eq!(gif::delays(&fs::read(&path).unwrap()), Some(vec![8, 9, 483]));
+		let _ = fs::remove_file(path);
+	}
+}
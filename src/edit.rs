@@ -0,0 +1,178 @@
+//! [`ConvertOptions::edit_list`](crate::ConvertOptions::edit_list): a file of the extracted frames to make the gif
+//! of, in the order they're listed.
+//!
+//! ```text
+//! # The intro, then the jump twice.
+//! 10-45
+//! 120-180
+//! 120-180
+//! # A still before looping.
+//! 180
+//! ```
+//!
+//! Frames are numbered from 1, the same as the `frame0001.png` files. A range is inclusive, and one that goes down,
+//! `45-10`, plays backwards.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+use crate::{pattern::FramePattern, ConvertError, Result};
+
+/// The directory in the frames directory the listed frames are linked into, numbered from 1 in the listed order.
+pub(crate) const EDITED_DIR: &str = "edited";
+
+/// One line of an edit list: frames `first` to `last`, the same for a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Entry {
+	/// From 1, for errors.
+	pub line: usize,
+	pub first: usize,
+	pub last: usize,
+}
+
+fn invalid(message: String) -> ConvertError {
+	ConvertError::InvalidOption { option: "edit list", message }
+}
+
+/// The entries of an edit list, without comments and blank lines.
+///
+/// # Errors
+/// On lines that aren't a frame or a range, frame 0, and a list without any frames.
+pub(crate) fn parse(text: &str) -> Result<Vec<Entry>> {
+	let mut entries = Vec::new();
+	for (i, line) in text.lines().enumerate() {
+		let line_number = i + 1;
+		let line = line.split('#').next().unwrap_or_default().trim();
+		if line.is_empty() { continue; }
+		let frame = |s: &str| s.trim().parse::<usize>().ok();
+		let (first, last) = match line.split_once('-') {
+			Some((first, last)) => (frame(first), frame(last)),
+			None => (frame(line), frame(line)),
+		};
+		let (Some(first), Some(last)) = (first, last) else {
+			return Err(invalid(format!("line {line_number}: expected a frame or a range like 10-45, got {line:?}")));
+		};
+		if first == 0 || last == 0 { return Err(invalid(format!("line {line_number}: frames are numbered from 1"))); }
+		entries.push(Entry { line: line_number, first, last });
+	}
+	if entries.is_empty() { return Err(invalid("lists no frames".to_string())); }
+	Ok(entries)
+}
+
+/// The indexes, from 0, into `frame_count` extracted frames that `entries` list, in order.
+///
+/// # Errors
+/// If an entry goes past the last frame, saying which line it's on.
+pub(crate) fn resolve(entries: &[Entry], frame_count: usize) -> Result<Vec<usize>> {
+	let mut picked = Vec::new();
+	for entry in entries {
+		let furthest = entry.first.max(entry.last);
+		if furthest > frame_count {
+			return Err(invalid(format!("line {}: frame {furthest} is past the last extracted frame, {frame_count}", entry.line)));
+		}
+		if entry.first <= entry.last {
+			picked.extend(entry.first - 1..entry.last);
+		} else {
+			picked.extend((entry.last - 1..entry.first).rev());
+		}
+	}
+	Ok(picked)
+}
+
+/// Reads and parses the edit list at `path`, before anything is extracted so a broken one fails early.
+///
+/// # Errors
+/// If it can't be read, or [`parse`] fails.
+pub(crate) fn read(path: &Path) -> Result<Vec<Entry>> {
+	let text = fs::read_to_string(path).map_err(ConvertError::io(path))?;
+	parse(&text).map_err(|e| match e {
+		ConvertError::InvalidOption { option, message } => ConvertError::InvalidOption { option, message: format!("{}, {message}", path.display()) },
+		e => e,
+	})
+}
+
+/// Links the `frames` that `entries` pick into [`EDITED_DIR`] in `frames_dir`, named by `pattern` in the listed order, and
+/// returns them. The extracted frames are left as they are, so repeats are only another link, and an edited
+/// [`Stages::Encode`](crate::Stages::Encode) directory can be encoded again with another list.
+///
+/// # Errors
+/// If [`resolve`] fails, or the frames can't be linked or copied.
+pub(crate) fn apply(frames_dir: &Path, (frames, pattern): (&[PathBuf], &FramePattern), entries: &[Entry]) -> Result<Vec<PathBuf>> {
+	let picked = resolve(entries, frames.len())?;
+	let edited = frames_dir.join(EDITED_DIR);
+	let _ = fs::remove_dir_all(&edited);
+	fs::create_dir_all(&edited).map_err(ConvertError::io(&edited))?;
+	picked.iter().enumerate().map(|(i, &index)| {
+		let (from, to) = (&frames[index], edited.join(pattern.name(i + 1)));
+		// Copied where links aren't a thing, e.g. FAT.
+		fs::hard_link(from, &to).or_else(|_| fs::copy(from, &to).map(drop)).map_err(ConvertError::io(&to))?;
+		Ok(to)
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(line: usize, first: usize, last: usize) -> Entry {
+		Entry { line, first, last }
+	}
+
+	#[test]
+	fn parses_frames_ranges_and_comments() {
+		let text = "# from the other tool\n10-45\n\n  120 - 180  # the jump\n7\n10-45\n45-40\n";
+		assert_eq!(parse(text).unwrap(), [entry(2, 10, 45), entry(4, 120, 180), entry(5, 7, 7), entry(6, 10, 45), entry(7, 45, 40)]);
+	}
+
+	#[test]
+	fn rejects_what_isnt_a_frame() {
+		let error = |text| parse(text).unwrap_err().to_string();
+		assert_eq!(error("1\n2-x"), "Invalid edit list: line 2: expected a frame or a range like 10-45, got \"2-x\"");
+		assert_eq!(error("1-2-3"), "Invalid edit list: line 1: expected a frame or a range like 10-45, got \"1-2-3\"");
+		assert_eq!(error("-5"), "Invalid edit list: line 1: expected a frame or a range like 10-45, got \"-5\"");
+		assert_eq!(error("5-"), "Invalid edit list: line 1: expected a frame or a range like 10-45, got \"5-\"");
+		assert_eq!(error("1.5"), "Invalid edit list: line 1: expected a frame or a range like 10-45, got \"1.5\"");
+		assert_eq!(error("\n\n0-4"), "Invalid edit list: line 3: frames are numbered from 1");
+		assert_eq!(error("# nothing\n\n"), "Invalid edit list: lists no frames");
+		assert_eq!(error(""), "Invalid edit list: lists no frames");
+	}
+
+	#[test]
+	fn resolves_in_the_listed_order() {
+		let entries = parse("3-5\n1\n3-5\n2-1\n4").unwrap();
+		assert_eq!(resolve(&entries, 5).unwrap(), [2, 3, 4, 0, 2, 3, 4, 1, 0, 3]);
+		assert_eq!(resolve(&parse("1-1").unwrap(), 1).unwrap(), [0]);
+		assert_eq!(resolve(&parse("5-1").unwrap(), 5).unwrap(), [4, 3, 2, 1, 0]);
+	}
+
+	#[test]
+	fn frames_past_the_end_say_which_line() {
+		let entries = parse("1-3\n# the end\n4-6\n").unwrap();
+		assert_eq!(resolve(&entries, 6).unwrap().len(), 6, "the last frame is in range");
+		assert_eq!(resolve(&entries, 5).unwrap_err().to_string(), "Invalid edit list: line 3: frame 6 is past the last extracted frame, 5");
+		assert_eq!(
+			resolve(&parse("9-2").unwrap(), 5).unwrap_err().to_string(),
+			"Invalid edit list: line 1: frame 9 is past the last extracted frame, 5",
+			"backwards too",
+		);
+		assert!(resolve(&entries, 0).is_err());
+	}
+
+	#[test]
+	fn links_the_frames_in_order() {
+		let dir = crate::test_dir("edit-list");
+		let frames: Vec<PathBuf> = (1..=3).map(|i| {
+			let frame = dir.join(format!("frame{i:04}.png"));
+			fs::write(&frame, i.to_string()).unwrap();
+			frame
+		}).collect();
+
+		let edited = apply(&dir, (&frames, &FramePattern::default()), &parse("3\n1-2\n3").unwrap()).unwrap();
+
+		let contents: Vec<String> = edited.iter().map(|f| fs::read_to_string(f).unwrap()).collect();
+		assert_eq!(contents, ["3", "1", "2", "3"]);
+		assert_eq!(edited[3], dir.join("edited").join("frame0004.png"));
+		assert!(frames.iter().all(|f| f.exists()), "the extracted frames are left alone");
+	}
+}
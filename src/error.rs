@@ -0,0 +1,220 @@
+use std::{
+	error::Error,
+	fmt,
+	io,
+	path::PathBuf,
+};
+
+/// Everything that can go wrong in a conversion.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConvertError {
+	/// The input video doesn't exist or isn't a file.
+	InputNotFound(PathBuf),
+	/// An option has a value that can't work, e.g. a poster timestamp that isn't a number.
+	InvalidOption {
+		option: &'static str,
+		message: String,
+	},
+	/// ffprobe couldn't be started. It comes with ffmpeg, so most likely neither is installed.
+	FfprobeNotInstalled(io::Error),
+	/// ffprobe couldn't read the input as a media file, e.g. it's a text file or a broken download.
+	UnrecognizedFormat {
+		path: PathBuf,
+		stderr: String,
+	},
+	/// The input has no video in it, e.g. it's an mp3.
+	NoVideoStream(PathBuf),
+	/// The input is a single image, which would make a gif of one frame, and
+	/// [`ConvertOptions::still_duration`](crate::ConvertOptions::still_duration) wasn't set.
+	StillImage(PathBuf),
+	/// The gif would be longer than [`SOFT_MAX_DURATION`](crate::SOFT_MAX_DURATION) seconds, and
+	/// [`ConvertOptions::force`](crate::ConvertOptions::force) wasn't set.
+	TooLong {
+		seconds: f64,
+		/// A ballpark of the gif's size in bytes, see [`estimate`](crate::estimate::estimate).
+		gif_size: u64,
+	},
+	/// ffmpeg couldn't be started, most likely it isn't installed or not on the PATH.
+	FfmpegNotInstalled(io::Error),
+	/// ffmpeg ran but exited unsuccessfully.
+	FfmpegFailed {
+		/// Exit code, `None` if it was killed by a signal.
+		code: Option<i32>,
+		stderr: String,
+	},
+	/// The ffmpeg doesn't have a filter an option needs, e.g. it was built without libvidstab.
+	MissingFilter {
+		filter: &'static str,
+		/// The option that needs it, as it's passed on the command line.
+		needed_by: &'static str,
+	},
+	/// gifski couldn't be started, most likely it isn't installed or not on the PATH.
+	GifskiNotInstalled(io::Error),
+	/// gifski ran but exited unsuccessfully.
+	GifskiFailed {
+		/// Exit code, `None` if it was killed by a signal.
+		code: Option<i32>,
+		stderr: String,
+	},
+	/// gifski ran out of memory, e.g. on a long or large video. Either the OS killed it or an allocation failed.
+	GifskiOutOfMemory {
+		stderr: String,
+	},
+	/// No fps was given and ffmpeg's output didn't mention the input's.
+	FpsDetectionFailed,
+	/// ffmpeg succeeded but didn't write a single frame, e.g. the start was past the end of the input or a filter
+	/// dropped every frame.
+	NoFramesExtracted {
+		/// The part of the input that was asked for.
+		range: String,
+		/// The -vf filters it was extracted with.
+		filters: Vec<String>,
+	},
+	/// The requested poster frame is past the last extracted frame.
+	PosterOutOfRange {
+		index: usize,
+		frame_count: usize,
+	},
+	/// The directory the gif would be written to doesn't exist, and [`ConvertOptions::create_parents`](crate::ConvertOptions::create_parents) wasn't set.
+	OutputDirMissing(PathBuf),
+	/// A file can't be created in the directory the gif would be written to, e.g. it's read-only.
+	OutputNotWritable {
+		dir: PathBuf,
+		source: io::Error,
+	},
+	/// The gif would be written over the input.
+	OutputIsInput(PathBuf),
+	/// The drive the frames are on got below [`ConvertOptions::reserve_space`](crate::ConvertOptions::reserve_space)
+	/// while extracting, and [`ConvertOptions::strict_space`](crate::ConvertOptions::strict_space) was set.
+	LowDiskSpace {
+		dir: PathBuf,
+		free: u64,
+		reserve: u64,
+	},
+	/// The [`ConvertOptions::frame_filter`](crate::ConvertOptions::frame_filter) command couldn't be started.
+	FrameFilterNotStarted {
+		program: String,
+		source: io::Error,
+	},
+	/// The [`ConvertOptions::frame_filter`](crate::ConvertOptions::frame_filter) command exited unsuccessfully.
+	FrameFilterFailed {
+		command: String,
+		/// Exit code, `None` if it was killed by a signal.
+		code: Option<i32>,
+		stderr: String,
+	},
+	/// The conversion's [`CancelToken`](crate::CancelToken) was cancelled. Whatever it had written so far was deleted.
+	Cancelled,
+	/// Reading or writing `path` failed.
+	Io {
+		path: PathBuf,
+		source: io::Error,
+	},
+}
+
+impl ConvertError {
+	/// `input-not-found`, `ffmpeg-failed`, ... which never change, unlike the message.
+	#[must_use]
+	pub fn id(&self) -> &'static str {
+		match self {
+			ConvertError::InputNotFound(_) => "input-not-found",
+			ConvertError::InvalidOption { .. } => "invalid-option",
+			ConvertError::FfprobeNotInstalled(_) => "ffprobe-not-installed",
+			ConvertError::UnrecognizedFormat { .. } => "unrecognized-format",
+			ConvertError::NoVideoStream(_) => "no-video-stream",
+			ConvertError::StillImage(_) => "still-image",
+			ConvertError::TooLong { .. } => "too-long",
+			ConvertError::FfmpegNotInstalled(_) => "ffmpeg-not-installed",
+			ConvertError::FfmpegFailed { .. } => "ffmpeg-failed",
+			ConvertError::MissingFilter { .. } => "missing-filter",
+			ConvertError::GifskiNotInstalled(_) => "gifski-not-installed",
+			ConvertError::GifskiFailed { .. } => "gifski-failed",
+			ConvertError::GifskiOutOfMemory { .. } => "gifski-out-of-memory",
+			ConvertError::FpsDetectionFailed => "fps-detection-failed",
+			ConvertError::NoFramesExtracted { .. } => "no-frames-extracted",
+			ConvertError::PosterOutOfRange { .. } => "poster-out-of-range",
+			ConvertError::OutputDirMissing(_) => "output-dir-missing",
+			ConvertError::OutputNotWritable { .. } => "output-not-writable",
+			ConvertError::OutputIsInput(_) => "output-is-input",
+			ConvertError::LowDiskSpace { .. } => "low-disk-space",
+			ConvertError::FrameFilterNotStarted { .. } => "frame-filter-not-started",
+			ConvertError::FrameFilterFailed { .. } => "frame-filter-failed",
+			ConvertError::Cancelled => "cancelled",
+			ConvertError::Io { .. } => "io",
+		}
+	}
+
+	pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> ConvertError {
+		let path = path.into();
+		move |source| ConvertError::Io { path, source }
+	}
+}
+
+/// The last few lines of a tool's stderr, which is where the actual error usually is.
+fn stderr_tail(stderr: &str) -> String {
+	let lines: Vec<&str> = stderr.trim_end().lines().collect();
+	lines[lines.len().saturating_sub(5)..].join("\n")
+}
+
+fn exit_status(code: Option<i32>) -> String {
+	code.map_or_else(|| "was killed by a signal".to_string(), |c| format!("exited with code {c}"))
+}
+
+impl fmt::Display for ConvertError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConvertError::InputNotFound(path) => write!(f, "Input file {} does not exist.", path.display()),
+			ConvertError::InvalidOption { option, message } => write!(f, "Invalid {option}: {message}"),
+			ConvertError::FfprobeNotInstalled(e) => write!(f, "Failed to run the ffprobe command. It comes with ffmpeg, make sure you have both and they are accessible. ({e})"),
+			ConvertError::UnrecognizedFormat { path, stderr } if stderr.trim().is_empty() => write!(f, "{} isn't a video ffmpeg can read.", path.display()),
+			ConvertError::UnrecognizedFormat { path, stderr } => write!(f, "{} isn't a video ffmpeg can read:\n{}", path.display(), stderr_tail(stderr)),
+			ConvertError::NoVideoStream(path) => write!(f, "Input {} has no video stream, is it an audio file?", path.display()),
+			ConvertError::StillImage(path) => write!(f, "Input {} is a still image, so the gif would be one frame. Pass --still-duration to show it for that many seconds.", path.display()),
+			ConvertError::TooLong { seconds, gif_size } => write!(
+				f,
+				"The gif would be {seconds:.0} seconds long, probably around {}. Pass --max-duration to convert less of it, or --force to convert it all.",
+				crate::disk::human_size(*gif_size),
+			),
+			ConvertError::FfmpegNotInstalled(e) => write!(f, "Failed to run the ffmpeg command. Make sure you have ffmpeg and it is accessible. ({e})"),
+			ConvertError::FfmpegFailed { code, stderr } => write!(f, "ffmpeg {}:\n{}", exit_status(*code), stderr_tail(stderr)),
+			ConvertError::MissingFilter { filter, needed_by } =>
+				write!(f, "{needed_by} needs ffmpeg's {filter} filter, which this ffmpeg doesn't have (see ffmpeg -filters). Install a build that has it."),
+			ConvertError::GifskiNotInstalled(e) => write!(f, "Failed to run the gifski command. Make sure you have gifski and it is accessible. ({e})"),
+			ConvertError::GifskiFailed { code, stderr } => write!(f, "gifski {}:\n{}", exit_status(*code), stderr_tail(stderr)),
+			ConvertError::GifskiOutOfMemory { stderr } if stderr.trim().is_empty() =>
+				write!(f, "gifski ran out of memory. Try a lower --fps, a smaller --width or a shorter part of the video."),
+			ConvertError::GifskiOutOfMemory { stderr } =>
+				write!(f, "gifski ran out of memory. Try a lower --fps, a smaller --width or a shorter part of the video.\n{}", stderr_tail(stderr)),
+			ConvertError::FpsDetectionFailed => write!(f, "Couldn't find the fps of the input video, pass it with --fps, or for a raw stream give ffmpeg its rate with --input-arg=-framerate --input-arg=<fps>."),
+			ConvertError::NoFramesExtracted { range, filters } if filters.is_empty() =>
+				write!(f, "Extraction produced 0 frames, check your --start/--end/filters. It was {range}."),
+			ConvertError::NoFramesExtracted { range, filters } =>
+				write!(f, "Extraction produced 0 frames, check your --start/--end/filters. It was {range}, with the filters {}.", filters.join(",")),
+			ConvertError::PosterOutOfRange { index, frame_count } =>
+				write!(f, "Poster frame {index} is past the end of the video ({frame_count} frames)."),
+			ConvertError::OutputDirMissing(dir) => write!(f, "The output directory {} does not exist. Create it, or pass --parents.", dir.display()),
+			ConvertError::OutputNotWritable { dir, source } => write!(f, "Can't write to the output directory {}: {source}", dir.display()),
+			ConvertError::OutputIsInput(path) => write!(f, "The output {} is the input, so it would be overwritten. Pass a different output.", path.display()),
+			ConvertError::LowDiskSpace { dir, free, reserve } => write!(
+				f,
+				"Stopped extracting with {} left on the drive {} is on, under the {} to keep free. Free some space, or leave out --strict-space to make a gif of the frames extracted until then.",
+				crate::disk::human_size(*free), dir.display(), crate::disk::human_size(*reserve),
+			),
+			ConvertError::FrameFilterNotStarted { program, source } => write!(f, "Failed to run the frame filter {program}. Make sure it's installed and on the PATH. ({source})"),
+			ConvertError::FrameFilterFailed { command, code, stderr } => write!(f, "The frame filter {command} {}:\n{}", exit_status(*code), stderr_tail(stderr)),
+			ConvertError::Cancelled => write!(f, "The conversion was cancelled."),
+			ConvertError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+		}
+	}
+}
+
+impl Error for ConvertError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			ConvertError::FfprobeNotInstalled(e) | ConvertError::FfmpegNotInstalled(e) | ConvertError::GifskiNotInstalled(e) | ConvertError::Io { source: e, .. }
+				| ConvertError::OutputNotWritable { source: e, .. } | ConvertError::FrameFilterNotStarted { source: e, .. } => Some(e),
+			_ => None,
+		}
+	}
+}
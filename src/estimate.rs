@@ -0,0 +1,111 @@
+//! Guessing how big a conversion comes out before running it, behind `--estimate`.
+
+use serde::Serialize;
+use crate::{
+	ffmpeg::Extraction,
+	probe,
+	runner::CommandRunner,
+	ConvertError, ConvertOptions, Result, KEYFRAME_FPS, MAX_FPS,
+};
+
+/// Bits gifski spends per pixel of every frame at quality 0 and 100, in between it's linear.
+///
+/// Rough figures from camera footage through gifski 1.x: its output shrinks a lot where frames barely change,
+/// so screen recordings tend to come in well under the estimate. Adjust as more encodes are measured.
+const BITS_PER_PIXEL: (f64, f64) = (0.6, 2.0);
+
+/// Bytes an extracted RGB frame takes up per pixel as a PNG, about half of uncompressed.
+const PNG_BYTES_PER_PIXEL: f64 = 1.5;
+
+/// What a conversion is expected to produce.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct Estimate {
+	pub width: u32,
+	pub height: u32,
+	/// The fps the gif plays at.
+	pub fps: f32,
+	/// Frames ffmpeg will extract, all of which go into the gif.
+	pub frame_count: u64,
+	pub quality: u32,
+	/// Size of the gif in bytes. Only a ballpark, see [`estimate`].
+	pub gif_size: u64,
+	/// The most the frames directory will take up, in bytes.
+	pub frames_size: u64,
+}
+
+/// Estimates the gif and the extracted frames of converting with `options`, from what ffprobe says about the input.
+///
+/// The gif size is a heuristic of bits per pixel per frame, scaled by the quality; expect the real size to be
+/// off by a factor of two either way. [`ConvertOptions::keyframes_only`] assumes every frame is a keyframe,
+/// so it's an upper bound there.
+///
+/// # Errors
+/// The same as the checks [`Conversion::run`](crate::Conversion::run) does before extracting anything.
+pub fn estimate(options: &ConvertOptions, runner: &dyn CommandRunner) -> Result<Estimate> {
+	let mut extraction = Extraction::new(options)?;
+	let mut info = probe::probe_input_with(runner, &options.input, &extraction.input_options)?;
+	if let Some(grid) = options.grid { info = crate::grid::stack(runner, options, grid, &info, &mut extraction)?; }
+	extraction.resize(options, &info.video)?;
+	extraction.limit_frames(options, &info)?;
+	crate::fps::apply(&mut extraction, options, &info.video);
+	extraction.limit_duration(options, &info);
+	planned(options, &extraction, &info)
+}
+
+/// [`estimate`] once `extraction` is worked out for the input, `info`.
+pub(crate) fn planned(options: &ConvertOptions, extraction: &Extraction, info: &probe::InputInfo) -> Result<Estimate> {
+	let source_fps = extraction.fps.or(info.video.fps()).ok_or(ConvertError::FpsDetectionFailed)?;
+	let fps = options.fps
+		.or(extraction.fps)
+		.unwrap_or(if options.keyframes_only { KEYFRAME_FPS } else { source_fps })
+		.clamp(0.0, MAX_FPS);
+	let seconds = extraction.seconds(options, info).ok_or_else(|| ConvertError::InvalidOption {
+		option: "estimate",
+		message: format!("{} doesn't say how long it is", options.input.display()),
+	})?;
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let mut frame_count = (seconds.max(0.0) * f64::from(source_fps)).round() as u64;
+	if let Some(max) = extraction.max_frames { frame_count = frame_count.min(u64::from(max)); }
+	let (width, height) = extraction.frame_size.unwrap_or((info.video.width, info.video.height));
+	let quality = options.quality.min(100);
+
+	#[allow(clippy::cast_precision_loss)]
+	let pixels = f64::from(width) * f64::from(height) * frame_count as f64;
+	let bits_per_pixel = BITS_PER_PIXEL.0 + (BITS_PER_PIXEL.1 - BITS_PER_PIXEL.0) * f64::from(quality) / 100.0;
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let (gif_size, frames_size) = ((pixels * bits_per_pixel / 8.0) as u64, (pixels * PNG_BYTES_PER_PIXEL) as u64);
+	Ok(Estimate { width, height, fps, frame_count, quality, gif_size, frames_size })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	#[test]
+	fn scales_with_the_settings() {
+		let input = crate::test_dir("estimate").join("input.mp4");
+		std::fs::write(&input, b"").unwrap();
+		let estimate_with = |f: &dyn Fn(&mut ConvertOptions)| {
+			let mut options = ConvertOptions::new(&input);
+			f(&mut options);
+			let mock = MockRunner::new();
+			mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+			estimate(&options, &mock).unwrap()
+		};
+
+		let full = estimate_with(&|_| {});
+		assert_eq!((full.width, full.height, full.frame_count, full.fps), (640, 360, 48, 24.0));
+		assert_eq!(full.gif_size, 640 * 360 * 48 * 2 / 8);
+
+		let smaller = estimate_with(&|o| {
+			o.width = Some(320);
+			o.duration = Some(1.0);
+			o.quality = 50;
+		});
+		assert_eq!((smaller.width, smaller.height, smaller.frame_count), (320, 180, 24));
+		assert!(smaller.gif_size * 8 < full.gif_size, "{} vs {}", smaller.gif_size, full.gif_size);
+		assert_eq!(smaller.frames_size, 320 * 180 * 24 * 3 / 2);
+	}
+}
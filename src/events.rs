@@ -0,0 +1,154 @@
+//! `--progress-format json-lines`: one JSON object per line on stdout, for programs wrapping this one.
+//!
+//! The shapes are documented in the `--progress-format` help. Fields and events may be added, but the existing
+//! ones keep their names and types.
+
+use std::path::Path;
+use serde::Serialize;
+use gifski_ffmpeg::{Busyness, ConvertReport, Progress, Stage, Warning, WarningKind};
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event<'a> {
+	Started { stage: Stage },
+	Extracting { frame: usize, total: Option<usize>, percent: Option<f64> },
+	Encoding { frame: usize, total: usize, percent: f64, eta_seconds: Option<u64> },
+	Finished { stage: Stage, seconds: f64 },
+	Info { message: &'a str },
+	Warning { id: WarningKind, message: &'a str },
+	Result {
+		output: &'a Path,
+		poster: Option<&'a Path>,
+		mp4: Option<&'a Path>,
+		contact_sheet: Option<&'a Path>,
+		fps: f32,
+		quality: u32,
+		frame_count: usize,
+		duration: f64,
+		size: Option<u64>,
+		decode_error: Option<&'a str>,
+		busyness: Option<&'a Busyness>,
+		warnings: &'a [Warning],
+	},
+	Error { message: &'a str },
+}
+
+impl<'a> Event<'a> {
+	/// `None` for progress that has no event (yet).
+	pub fn from_progress(progress: &'a Progress) -> Option<Self> {
+		#[allow(clippy::cast_precision_loss)]
+		let percent = |frame: usize, total: usize| (frame.min(total) as f64 * 1000.0 / total.max(1) as f64).round() / 10.0;
+		Some(match progress {
+			Progress::Started(stage) => Event::Started { stage: *stage },
+			&Progress::Extracting { frame, total } => Event::Extracting { frame, total, percent: total.map(|t| percent(frame, t)) },
+			&Progress::Encoding { frame, total, eta } => Event::Encoding { frame, total, percent: percent(frame, total), eta_seconds: eta.map(|e| e.as_secs()) },
+			Progress::Finished(stage, time) => Event::Finished { stage: *stage, seconds: time.as_secs_f64() },
+			Progress::Info(message) => Event::Info { message },
+			Progress::Warning(warning) => Event::Warning { id: warning.kind, message: &warning.message },
+			_ => return None,
+		})
+	}
+
+	pub fn from_report(report: &'a ConvertReport) -> Self {
+		Event::Result {
+			output: &report.output,
+			poster: report.poster.as_deref(),
+			mp4: report.mp4.as_deref(),
+			contact_sheet: report.contact_sheet.as_deref(),
+			fps: report.fps,
+			quality: report.quality,
+			frame_count: report.frame_count,
+			duration: report.duration,
+			size: std::fs::metadata(&report.output).ok().map(|m| m.len()),
+			decode_error: report.decode_error.as_deref(),
+			busyness: report.busyness.as_ref(),
+			warnings: &report.warnings,
+		}
+	}
+
+	/// The event and a newline, flushed so the other end sees it right away.
+	pub fn print(&self) {
+		use std::io::Write;
+		let mut stdout = std::io::stdout().lock();
+		// Nothing in an event can fail to serialize.
+		let _ = writeln!(stdout, "{}", serde_json::to_string(self).unwrap_or_default());
+		let _ = stdout.flush();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use serde_json::Value;
+	use super::*;
+
+	type Check = fn(&Value) -> bool;
+
+	/// Checks `event` has exactly the fields the help promises, with the right types.
+	fn check_shape(event: &Value) {
+		fn number(v: &Value) -> bool { v.is_number() }
+		fn string(v: &Value) -> bool { v.is_string() }
+		fn nullable_number(v: &Value) -> bool { v.is_null() || v.is_number() }
+		fn nullable_string(v: &Value) -> bool { v.is_null() || v.is_string() }
+		fn warnings(v: &Value) -> bool {
+			v.as_array().is_some_and(|w| w.iter().all(|w| w.as_object().is_some_and(|o| o.len() == 2 && o["id"].is_string() && o["message"].is_string())))
+		}
+		fn busyness(v: &Value) -> bool {
+			v.is_null() || v.as_object().is_some_and(|o| o.len() == 3 && ["sampled", "unique_colors", "difference"].iter().all(|k| o[*k].is_number()))
+		}
+		fn stage(v: &Value) -> bool { ["stabilize", "extract", "encode", "poster", "cleanup"].contains(&v.as_str().unwrap_or_default()) }
+		let fields: &[(&str, Check)] = match event["event"].as_str().unwrap() {
+			"started" => &[("stage", stage)],
+			"extracting" => &[("frame", number), ("total", nullable_number), ("percent", nullable_number)],
+			"encoding" => &[("frame", number), ("total", number), ("percent", number), ("eta_seconds", nullable_number)],
+			"finished" => &[("stage", stage), ("seconds", number)],
+			"info" | "error" => &[("message", string)],
+			"warning" => &[("id", string), ("message", string)],
+			"result" => &[
+				("output", string), ("poster", nullable_string), ("mp4", nullable_string), ("contact_sheet", nullable_string), ("fps", number), ("quality", number),
+				("frame_count", number), ("duration", number), ("size", nullable_number), ("decode_error", nullable_string),
+				("busyness", busyness), ("warnings", warnings),
+			],
+			other => panic!("unknown event {other}"),
+		};
+		let object = event.as_object().unwrap();
+		assert_eq!(object.len(), fields.len() + 1, "{event}");
+		for (name, check) in fields {
+			assert!(object.get(*name).is_some_and(check), "{name} of {event}");
+		}
+	}
+
+	#[test]
+	fn every_event_has_the_documented_shape() {
+		let progress = [
+			Progress::Started(Stage::Extract),
+			Progress::Extracting { frame: 12, total: Some(48) },
+			Progress::Extracting { frame: 13, total: None },
+			Progress::Finished(Stage::Extract, Duration::from_millis(1500)),
+			Progress::Info("fps: using the source's 24fps".to_string()),
+			Progress::Warning(Warning::new(WarningKind::OutOfRange, "quality 120 is out of range, using 100")),
+			Progress::Encoding { frame: 3, total: 48, eta: None },
+			Progress::Encoding { frame: 24, total: 48, eta: Some(Duration::from_secs(2)) },
+		];
+		let mut stream: Vec<String> = progress.iter()
+			.filter_map(Event::from_progress)
+			.map(|e| serde_json::to_string(&e).unwrap())
+			.collect();
+		stream.push(serde_json::to_string(&Event::Result {
+			output: Path::new("out.gif"), poster: None, mp4: Some(Path::new("out.mp4")), contact_sheet: None, fps: 24.0, quality: 100, frame_count: 48, duration: 2.0, size: Some(1234),
+			decode_error: Some("ffmpeg exited with code 1:\nError while decoding stream #0:0: Invalid data found when processing input"),
+			busyness: None,
+			warnings: &[Warning::new(WarningKind::OutOfRange, "quality 120 is out of range, using 100")],
+		}).unwrap());
+		stream.push(serde_json::to_string(&Event::Error { message: "gifski failed" }).unwrap());
+		let stream = stream.join("\n");
+
+		let events: Vec<Value> = stream.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+		assert_eq!(events.len(), 10);
+		events.iter().for_each(check_shape);
+		assert_eq!(events[1]["percent"], 25.0);
+		assert_eq!(events[3]["seconds"], 1.5);
+		assert_eq!(events[7]["eta_seconds"], 2);
+		assert_eq!(events[5]["id"], "out-of-range");
+	}
+}
@@ -0,0 +1,297 @@
+//! What a conversion would do, without doing it, behind `--explain`.
+//!
+//! Everything is worked out the way [`Conversion::run`](crate::Conversion::run) would, reading the input with
+//! ffprobe but extracting and encoding nothing, and each setting that's decided along the way says where it came
+//! from: given, from a file, the default, or worked out from the input. Where the options themselves came from is
+//! only known to whatever gathered them, so it's passed in as [`Origins`].
+
+use std::{collections::BTreeMap, env, fmt, path::PathBuf};
+use serde::Serialize;
+use crate::{
+	cursor, duration, ffmpeg::{self, Extraction}, fps::{self, Choice}, frame_filter, gifski, grid, output, palette, probe, quality_map,
+	runner::{CommandLine, CommandRunner},
+	temp, timing, ConvertError, ConvertOptions, Encoder, Result, Stages, DEFAULT_MAX_AUTO_FPS, KEYFRAME_FPS, MAX_FPS,
+};
+
+/// Where an option or setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Origin {
+	/// Nothing set it.
+	#[default]
+	Default,
+	CommandLine,
+	/// An `@file` or `--args-from` file.
+	ArgsFile,
+	ConfigFile,
+	/// What the input is, like its fps.
+	Input,
+	/// Worked out from other settings.
+	Derived,
+}
+
+impl fmt::Display for Origin {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Origin::Default => "default",
+			Origin::CommandLine => "command line",
+			Origin::ArgsFile => "args file",
+			Origin::ConfigFile => "config file",
+			Origin::Input => "input",
+			Origin::Derived => "worked out",
+		})
+	}
+}
+
+/// Where each option came from, by its command line name like `fps`, or `OUTPUT`. Ones that aren't in it are
+/// [`Origin::Default`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Origins(BTreeMap<String, Origin>);
+
+impl Origins {
+	pub fn set(&mut self, name: impl Into<String>, origin: Origin) {
+		self.0.insert(name.into(), origin);
+	}
+
+	#[must_use]
+	pub fn of(&self, name: &str) -> Origin {
+		self.0.get(name).copied().unwrap_or_default()
+	}
+
+	/// The first of `names` that isn't a default, with its origin.
+	fn first<'n>(&self, names: &[&'n str]) -> Option<(&'n str, Origin)> {
+		names.iter().map(|&name| (name, self.of(name))).find(|&(_, origin)| origin != Origin::Default)
+	}
+}
+
+/// One setting the conversion ends up with, and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct Decision {
+	pub setting: &'static str,
+	pub value: String,
+	pub origin: Origin,
+	/// How it came to be, and how to change it.
+	pub note: String,
+}
+
+impl Decision {
+	fn new(setting: &'static str, value: impl fmt::Display, origin: Origin, note: impl Into<String>) -> Self {
+		Decision { setting, value: value.to_string(), origin, note: note.into() }
+	}
+}
+
+/// What [`explain`] found a conversion would do.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct Plan {
+	pub input: PathBuf,
+	pub output: PathBuf,
+	/// Where the frames are extracted to. A directory made when it runs, with the time in its name, unless
+	/// [`ConvertOptions::frames_dir`] says.
+	pub frames_dir: PathBuf,
+	pub decisions: Vec<Decision>,
+	/// ffmpeg's `-vf` chain.
+	pub filters: Vec<String>,
+	/// ffmpeg's arguments to extract the frames, with the program first. Empty with [`Stages::Encode`].
+	pub ffmpeg: Vec<String>,
+	/// gifski's, once for each gif it encodes. Empty where ffmpeg encodes, or with [`Stages::Extract`].
+	pub gifski: Vec<Vec<String>>,
+}
+
+fn argv(command: &CommandLine) -> Vec<String> {
+	std::iter::once(command.program_name()).chain(command.args_lossy()).collect()
+}
+
+/// How `value` of `setting` was given, for a [`Decision`]'s note: `from --fps`, or `the default`.
+fn given(setting: &str, origin: Origin) -> String {
+	match origin {
+		Origin::Default => "the default".to_string(),
+		Origin::ConfigFile => format!("{setting} in the config file"),
+		Origin::ArgsFile => format!("--{setting} in an args file"),
+		_ => format!("from --{setting}"),
+	}
+}
+
+/// Works out what converting with `options` would do, `origins` saying where the options came from.
+///
+/// # Errors
+/// The same as the checks [`Conversion::run`](crate::Conversion::run) does before extracting anything.
+pub fn explain(options: &ConvertOptions, runner: &dyn CommandRunner, origins: &Origins) -> Result<Plan> {
+	let mut opt = options.clone();
+	opt.make_absolute(&env::current_dir().map_err(ConvertError::io("."))?);
+	opt.expand_social();
+	opt.expand_sizes()?;
+	let mut extraction = Extraction::new(&opt)?;
+	palette::check(&opt)?;
+	quality_map::check(&opt)?;
+	frame_filter::check(&opt)?;
+	timing::check(&opt)?;
+	duration::check(&opt)?;
+	cursor::check(&opt)?;
+	extraction.timestamps = opt.source_timing;
+	let mut decisions = Vec::new();
+
+	let (extracting, encoding) = (opt.stages != Stages::Encode, opt.stages != Stages::Extract);
+	let mut fps = opt.fps.map(|fps| (fps, origins.of("fps"), given("fps", origins.of("fps"))));
+	if extracting {
+		let mut info = probe::probe_input_with(runner, &opt.input, &extraction.input_options)?;
+		if let Some(grid) = opt.grid { info = grid::stack(runner, &opt, grid, &info, &mut extraction)?; }
+		extraction.resize(&opt, &info.video)?;
+		decisions.push(size(&opt, &extraction, &info.video, origins));
+		if let Some(limited) = extraction.limit_frames(&opt, &info)? {
+			fps = Some((limited, Origin::Derived, format!("the most that stays within --max-frames {}", opt.max_frames.unwrap_or_default())));
+		}
+		fps = fps.or_else(|| opt.keyframes_only.then(|| (KEYFRAME_FPS, Origin::Default, "what --keyframes-only plays at, pass --fps to override".to_string())));
+		if let Some(choice) = fps::apply(&mut extraction, &opt, &info.video) {
+			let max_auto_fps = origins.of("max-auto-fps");
+			let cap = if max_auto_fps == Origin::Default { format!("the default {DEFAULT_MAX_AUTO_FPS}fps cap") } else { format!("the {} max-auto-fps cap", opt.max_auto_fps) };
+			fps = match choice {
+				Choice::Given(_) => fps,
+				Choice::Source(source) => Some((source, Origin::Input, format!("the source's, under {cap}"))),
+				Choice::Capped { source, fps } => Some((fps, max_auto_fps, format!("{cap}, the source is {source}fps; pass --fps {source} to override"))),
+				Choice::Unknown => None,
+			};
+		}
+		if let Some(from) = extraction.limit_duration(&opt, &info) {
+			decisions.push(Decision::new("duration", format!("{:.2}s", extraction.duration.unwrap_or_default()), origins.of("max-duration"), format!("cut from {from:.2}s by --max-duration")));
+		}
+		let trim = origins.first(&["start", "end", "duration", "start-frame", "end-frame"]);
+		decisions.push(Decision::new("range", extraction.range(&opt, Some(&info)), trim.map_or(Origin::Default, |(_, origin)| origin), match trim {
+			Some((name, origin)) => given(name, origin),
+			None => "all of it, pass --start and --end to trim".to_string(),
+		}));
+	}
+	let fps = fps.map(|(value, origin, note)| if value > MAX_FPS {
+		Decision::new("fps", MAX_FPS, Origin::Derived, format!("{value}fps, {note}, is over gifski's {MAX_FPS}fps"))
+	} else {
+		Decision::new("fps", value, origin, note)
+	});
+	let fps_value = fps.as_ref().and_then(|d| d.value.parse::<f32>().ok());
+	decisions.push(fps.unwrap_or_else(|| Decision::new("fps", "unknown", Origin::Input, "ffprobe doesn't say, so it's whatever ffmpeg finds when it extracts")));
+	let quality = opt.quality.min(100);
+	decisions.push(Decision::new("quality", quality, origins.of("quality"), if quality == opt.quality {
+		given("quality", origins.of("quality"))
+	} else {
+		format!("{} is over 100", opt.quality)
+	}));
+
+	let file_name = opt.input.file_stem().ok_or_else(|| ConvertError::InputNotFound(opt.input.clone()))?;
+	let output = output::parse_output(&output::output_base(&opt.input, opt.resolve_symlinks)?, opt.output.as_deref(), file_name);
+	decisions.push(Decision::new("output", output.display(), origins.of("OUTPUT"), match origins.of("OUTPUT") {
+		Origin::Default => "next to the input, named after it",
+		_ => "from OUTPUT",
+	}));
+	let frames_dir = match (extracting, &opt.frames_dir) {
+		(false, _) => opt.input.clone(),
+		(true, Some(dir)) => dir.clone(),
+		(true, None) => temp::run_dir(&env::temp_dir()),
+	};
+	decisions.push(Decision::new("frames directory", frames_dir.display(), origins.of("frames-dir"), match (extracting, &opt.frames_dir) {
+		(false, _) => "the input, which --stage encode takes the frames from".to_string(),
+		(true, Some(_)) => given("frames-dir", origins.of("frames-dir")),
+		(true, None) if opt.in_memory => "a new one in the ramdisk if the frames fit, otherwise in the temp directory".to_string(),
+		(true, None) if opt.keep_frames => "a new one in the temp directory, kept with --keep-frames".to_string(),
+		(true, None) => "a new one in the temp directory, deleted afterwards unless --keep-frames".to_string(),
+	}));
+
+	let ffmpeg = if !extracting {
+		Vec::new()
+	} else if opt.overlap || opt.chunk_seconds.is_some() {
+		argv(&ffmpeg::overlap_command(&opt.input, &frames_dir, &extraction))
+	} else {
+		argv(&ffmpeg::extract_command(&opt.input, &frames_dir, &extraction))
+	};
+	let gifski = match fps_value.filter(|_| encoding && opt.encoder == Encoder::Gifski) {
+		None => Vec::new(),
+		Some(fps) if opt.overlap || opt.chunk_seconds.is_some() => vec![argv(&gifski::stdin_command(quality, fps, &output))],
+		Some(fps) if !opt.sizes.is_empty() => opt.sizes.iter()
+			.map(|&width| argv(&gifski::encode_command(quality, fps, Some(width), (&frames_dir, &opt.frame_pattern), &output::size_variant(&output, width))))
+			.collect(),
+		Some(fps) if !opt.compare_quality.is_empty() => opt.compare_quality.iter()
+			.map(|&quality| argv(&gifski::encode_command(quality.min(100), fps, None, (&frames_dir, &opt.frame_pattern), &output::quality_variant(&output, quality.min(100)))))
+			.collect(),
+		Some(fps) => vec![argv(&gifski::encode_command(quality, fps, None, (&frames_dir, &opt.frame_pattern), &output))],
+	};
+	Ok(Plan { input: opt.input.clone(), output, frames_dir, decisions, filters: extraction.filters, ffmpeg, gifski })
+}
+
+/// The size of the frames, once `extraction` is [resized](Extraction::resize) for `video`.
+fn size(opt: &ConvertOptions, extraction: &Extraction, video: &probe::VideoStream, origins: &Origins) -> Decision {
+	let (width, height) = extraction.frame_size.unwrap_or((video.width, video.height));
+	let source = format!("{}x{}", video.width, video.height);
+	if !opt.sizes.is_empty() {
+		return Decision::new("size", format!("{width}x{height}"), origins.of("sizes"), format!("from {source}, the widest of --sizes, which gifski scales down for the others"));
+	}
+	match origins.first(&["width", "height", "scale", "aspect", "focus"]) {
+		Some((name, origin)) => Decision::new("size", format!("{width}x{height}"), origin, format!("from {source}, {}", given(name, origin))),
+		None => Decision::new("size", format!("{width}x{height}"), Origin::Input, "the input's, pass --width or --scale to shrink it"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	fn input(name: &str) -> PathBuf {
+		let input = crate::test_dir(&format!("explain-{name}")).join(format!("{name}.mp4"));
+		fs::write(&input, b"").unwrap();
+		input
+	}
+
+	fn probed() -> MockRunner {
+		let mock = MockRunner::new();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+		mock
+	}
+
+	fn decision<'p>(plan: &'p Plan, setting: &str) -> &'p Decision {
+		plan.decisions.iter().find(|d| d.setting == setting).unwrap()
+	}
+
+	#[test]
+	fn says_where_each_setting_came_from() {
+		let mut opt = ConvertOptions::new(input("origins"));
+		opt.width = Some(320);
+		opt.quality = 80;
+		opt.frames_dir = Some(env::temp_dir().join("frames"));
+		let mut origins = Origins::default();
+		origins.set("width", Origin::CommandLine);
+		origins.set("quality", Origin::ArgsFile);
+
+		let plan = explain(&opt, &probed(), &origins).unwrap();
+
+		assert_eq!(decision(&plan, "size"), &Decision::new("size", "320x180", Origin::CommandLine, "from 640x360, from --width"));
+		assert_eq!(decision(&plan, "quality").note, "--quality in an args file");
+		assert_eq!(decision(&plan, "fps"), &Decision::new("fps", 24, Origin::Input, "the source's, under the default 30fps cap"));
+		assert_eq!(decision(&plan, "output").origin, Origin::Default);
+		assert!(plan.output.ends_with("origins-gif.gif"));
+		assert_eq!(plan.filters, ["scale=320:180:flags=lanczos"]);
+		assert_eq!(plan.ffmpeg[..3], ["ffmpeg".to_string(), "-i".to_string(), opt.input.display().to_string()]);
+		assert_eq!(plan.gifski.len(), 1);
+		assert_eq!(plan.gifski[0][..5], ["gifski", "--fps", "24", "--quality", "80"]);
+	}
+
+	#[test]
+	fn a_capped_fps_says_how_to_keep_the_source_one() {
+		let mut opt = ConvertOptions::new(input("capped"));
+		opt.max_auto_fps = 12.0;
+		let mut origins = Origins::default();
+		origins.set("max-auto-fps", Origin::ConfigFile);
+
+		let plan = explain(&opt, &probed(), &origins).unwrap();
+
+		assert_eq!(decision(&plan, "fps"), &Decision::new("fps", 12, Origin::ConfigFile, "the 12 max-auto-fps cap, the source is 24fps; pass --fps 24 to override"));
+		assert!(plan.filters.contains(&"fps=12".to_string()), "{:?}", plan.filters);
+	}
+
+	#[test]
+	fn runs_the_same_checks() {
+		let mut opt = ConvertOptions::new(input("checks"));
+		opt.end = Some(1.0);
+		opt.start = Some(2.0);
+		assert!(matches!(explain(&opt, &probed(), &Origins::default()), Err(ConvertError::InvalidOption { option: "end", .. })));
+	}
+}
@@ -0,0 +1,1139 @@
+use std::{
+	fs,
+	io::Read,
+	path::{Path, PathBuf},
+	sync::mpsc::{self, RecvTimeoutError},
+	thread,
+	time::Duration,
+};
+use regex::Regex;
+use crate::{
+	disk,
+	paths,
+	pattern::FramePattern,
+	runner::{CommandLine, CommandRunner},
+	options::{Aspect, Fit, Focus, Gravity, Poster, SeekMode, Stages},
+	probe::{InputInfo, VideoStream},
+	ConvertError,
+	ConvertOptions,
+	Progress,
+	Result,
+};
+
+/// How often the frames directory is counted while ffmpeg extracts.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What part of the input ffmpeg extracts, resolved from the trim options.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[allow(clippy::struct_excessive_bools)] // Flags.
+pub(crate) struct Extraction {
+	/// [`ConvertOptions::input_format`] and [`ConvertOptions::input_args`], right before the first input's `-i`.
+	pub input_options: Vec<String>,
+	/// Seconds to seek to before decoding.
+	pub start: Option<f64>,
+	/// How to seek to `start`.
+	pub seek_mode: SeekMode,
+	/// Seconds to extract after `start`.
+	pub duration: Option<f64>,
+	/// The -vf filter chain.
+	pub filters: Vec<String>,
+	/// Only decode keyframes.
+	pub keyframes_only: bool,
+	/// Number of the first frame file, `None` for ffmpeg's default of 1.
+	pub start_number: Option<usize>,
+	/// The rate the fps filter resamples to, see [`limit_frames`](Self::limit_frames).
+	pub fps: Option<f32>,
+	/// Stop after this many frames.
+	pub max_frames: Option<u32>,
+	/// Width and height of the extracted frames, once [`resize`](Self::resize) has worked them out.
+	pub frame_size: Option<(u32, u32)>,
+	/// Inputs after the first, for a [`stack`](Self::stack).
+	pub more_inputs: Vec<PathBuf>,
+	/// A filter graph combining all the inputs into one video, which `filters` are applied to.
+	pub stack: Option<String>,
+	/// Loop the input, a still image, for `duration`.
+	pub loop_input: bool,
+	/// Ask for bit exact frames, without anything that depends on the build or the clock.
+	pub bitexact: bool,
+	/// The vidstabdetect transforms file to undo the shake with, ahead of `filters`.
+	pub stabilize: Option<PathBuf>,
+	/// The [`ConvertOptions::focus`] region, once it's known, which [`resize`](Self::resize) crops to first.
+	pub focus: Option<Crop>,
+	/// The [`ConvertOptions::pre_quantize`] colors, quantized to after `filters`.
+	pub pre_quantize: Option<u32>,
+	/// ffmpeg's `-threads` for the first input and `-filter_threads`.
+	pub threads: Option<usize>,
+	/// Print each frame's timestamp with showinfo for [`ConvertOptions::source_timing`], last of the filters.
+	pub timestamps: bool,
+	/// The [`ConvertOptions::waveform`] graph that stacks the strip under the filtered video, labelled `[video]`.
+	pub waveform: Option<String>,
+	/// Keep the frames ffmpeg got out before it failed, for [`ConvertOptions::tolerate_decode_errors`].
+	pub tolerate_errors: bool,
+	/// What the frames are named, [`ConvertOptions::frame_pattern`].
+	pub frame_pattern: FramePattern,
+}
+
+impl Extraction {
+	/// Checks the trim options make sense together and turns them into ffmpeg terms.
+	pub fn new(opt: &ConvertOptions) -> Result<Self> {
+		let invalid = |option, message: &str| Err(ConvertError::InvalidOption { option, message: message.to_string() });
+		let time_based = opt.start.is_some() || opt.end.is_some() || opt.duration.is_some();
+		let frame_based = opt.start_frame.is_some() || opt.end_frame.is_some();
+		if time_based && frame_based {
+			return invalid("trim", "frame based (--start-frame/--end-frame) and time based (--start/--end/--duration) trimming can't be combined");
+		}
+
+		if !(0.0..=1.0).contains(&opt.trim_idle_threshold) {
+			return invalid("trim idle threshold", &format!("{} is not between 0 and 1", opt.trim_idle_threshold));
+		}
+
+		if opt.overlap || opt.chunk_seconds.is_some() {
+			let option = if opt.overlap { "overlap" } else { "chunk seconds" };
+			let conflict = [
+				(opt.keyframes_only, "--keyframes-only"),
+				(opt.trim_idle, "--trim-idle"),
+				(opt.drop_flash_frames.is_some(), "--drop-flash-frames"),
+				(opt.tolerate_decode_errors, "--tolerate-decode-errors, gifski has the frames before ffmpeg could fail"),
+				(!opt.compare_quality.is_empty(), "--compare-quality"),
+			].into_iter().find(|(set, _)| *set);
+			if let Some((_, flag)) = conflict { return invalid(option, &format!("can't be combined with {flag}")); }
+		}
+		if opt.still_duration.is_some_and(|s| s <= 0.0) { return invalid("still duration", "must be more than 0 seconds"); }
+
+		if opt.max_duration.is_some_and(|s| s <= 0.0) { return invalid("max duration", "must be more than 0 seconds"); }
+
+		if opt.in_memory && opt.frames_dir.is_some() {
+			return invalid("in memory", "can't be combined with a frames directory, which already says where the frames go");
+		}
+
+		if let Some(seconds) = opt.chunk_seconds {
+			if seconds <= 0.0 { return invalid("chunk seconds", "must be more than 0 seconds"); }
+			if frame_based { return invalid("chunk seconds", "chunks are cut by time, so it can't be combined with --start-frame/--end-frame"); }
+			if opt.poster.is_some() { return invalid("chunk seconds", "the frames are deleted chunk by chunk, so there's none left for --poster"); }
+			if opt.also_mp4 { return invalid("chunk seconds", "the frames are deleted chunk by chunk, so there's none left for --also-mp4"); }
+			if opt.contact_sheet.is_some() {
+				return invalid("chunk seconds", "the frames are deleted chunk by chunk, so there's none left for --contact-sheet");
+			}
+		}
+
+		if let Some(max) = opt.max_frames {
+			if max == 0 { return invalid("max frames", "must be at least 1"); }
+			if opt.fps.is_some() { return invalid("max frames", "works out the fps itself, so it can't be combined with --fps"); }
+			if opt.keyframes_only { return invalid("max frames", "can't be combined with --keyframes-only, which has no fps to lower"); }
+		}
+
+		if !opt.concat.is_empty() {
+			let conflict = [
+				(time_based || frame_based, "trimming, which would only fit one of the inputs"),
+				(opt.keyframes_only, "--keyframes-only"),
+				(opt.max_frames.is_some(), "--max-frames"),
+				(matches!(opt.poster, Some(Poster::At(_))), "a --poster timestamp, use first or middle"),
+				(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds"),
+				(opt.max_duration.is_some(), "--max-duration, which would only cut the first input"),
+			].into_iter().find(|(set, _)| *set);
+			if let Some((_, what)) = conflict { return invalid("concat", &format!("can't be combined with {what}")); }
+		}
+
+		if let Some(grid) = opt.grid {
+			let cells = grid.columns as usize * grid.rows as usize;
+			let conflict = [
+				(opt.grid_inputs.is_empty(), "needs --grid-inputs to fill the other cells"),
+				(opt.grid_inputs.len() >= cells, "doesn't have a cell for every input"),
+				(!opt.concat.is_empty(), "can't be combined with --concat"),
+				(opt.keyframes_only, "can't be combined with --keyframes-only"),
+				(opt.overlap || opt.chunk_seconds.is_some(), "can't be combined with --overlap or --chunk-seconds"),
+			].into_iter().find(|(set, _)| *set);
+			if let Some((_, message)) = conflict { return invalid("grid", &format!("{}x{} {message}", grid.columns, grid.rows)); }
+		}
+
+		check_stages(opt, time_based || frame_based)?;
+		check_exact_end(opt)?;
+		check_effects(opt)?;
+
+		if opt.scale.is_some() && (opt.width.is_some() || opt.height.is_some()) {
+			return invalid("scale", "can't be combined with --width/--height, use one or the other");
+		}
+
+		// Anything but keyframes would be decoded after an accurate seek.
+		let seek_mode = if opt.keyframes_only { SeekMode::Fast } else { opt.seek_mode };
+		let focus = match opt.focus {
+			Some(Focus::Region { width, height, x, y }) => Some(Crop { width, height, x, y }),
+			_ => None,
+		};
+		let (input_options, bitexact, pre_quantize) = (input_options(opt)?, opt.deterministic, opt.pre_quantize);
+		let threads = opt.threads.or_else(|| opt.background.then(crate::priority::half_the_cores));
+		let (tolerate_errors, frame_pattern) = (opt.tolerate_decode_errors, opt.frame_pattern.clone());
+		let mut extraction = Extraction { input_options, keyframes_only: opt.keyframes_only, seek_mode, bitexact, focus, pre_quantize, threads, tolerate_errors, frame_pattern, ..Extraction::default() };
+		if opt.keyframes_only && matches!(opt.poster, Some(Poster::At(_))) {
+			return invalid("poster", "a timestamp can't be matched to a keyframe, use first or middle");
+		}
+		if frame_based {
+			let start = opt.start_frame.unwrap_or(0);
+			if let Some(end) = opt.end_frame {
+				if end < start { return invalid("end frame", &format!("{end} is before the start frame {start}")); }
+			}
+			extraction.filters.push(trim_frames_filter(start, opt.end_frame));
+			return Ok(extraction);
+		}
+
+		extraction.start = opt.start.filter(|&s| s > 0.0);
+		extraction.duration = match (opt.end, opt.duration) {
+			(Some(_), Some(_)) => return invalid("trim", "--end and --duration can't be combined"),
+			(Some(end), None) => {
+				let start = opt.start.unwrap_or(0.0);
+				if end <= start { return invalid("end", &format!("{end}s is not after the start {start}s")); }
+				Some(end - start)
+			}
+			(None, Some(d)) if d <= 0.0 => return invalid("duration", "must be more than 0 seconds"),
+			(None, duration) => duration,
+		};
+		Ok(extraction)
+	}
+
+	/// Adds the crop and scale filters, which need to know the input's dimensions.
+	pub fn resize(&mut self, opt: &ConvertOptions, video: &VideoStream) -> Result<()> {
+		let exact = resized(video, opt, self.focus, true)?;
+		let size = resized(video, opt, self.focus, opt.allow_odd_dimensions)?;
+		if size != exact {
+			log::info!(
+				"Rounded {}x{} down to {}x{}, odd sizes blur in gifski. Pass --allow-odd-dimensions to keep them.",
+				exact.scaled.0, exact.scaled.1, size.scaled.0, size.scaled.1,
+			);
+		}
+		if let Some(crop) = size.crop {
+			log::debug!("Cropping {}x{} to {}x{} at {},{}", video.width, video.height, crop.width, crop.height, crop.x, crop.y);
+			self.filters.push(format!("crop={}:{}:{}:{}", crop.width, crop.height, crop.x, crop.y));
+		}
+		let (width, height) = size.scaled;
+		let known = video.width > 0 && video.height > 0;
+		match (opt.scale, opt.width, opt.height) {
+			(None, None, None) => {}
+			(None, Some(_), Some(_)) => {
+				log::debug!("Fitting into {width}x{height} with {:?}", opt.fit);
+				self.filters.extend(fit_filters(width, height, opt.fit, opt.pad_color.as_deref().unwrap_or("black"))?);
+			}
+			// Without the input's size to keep the aspect ratio from, ffmpeg works the other side out, -2 for even.
+			(None, Some(_), None) if !known => self.filters.push(format!("scale={width}:{}:flags=lanczos", unknown_side(opt))),
+			(None, None, Some(_)) if !known => self.filters.push(format!("scale={}:{height}:flags=lanczos", unknown_side(opt))),
+			_ => {
+				log::debug!("Scaling to {width}x{height}");
+				self.filters.push(format!("scale={width}:{height}:flags=lanczos"));
+			}
+		}
+		self.frame_size = Some((width, height));
+		Ok(())
+	}
+
+	/// Resamples to the highest fps that keeps the frames within [`ConvertOptions::max_frames`], never higher than the
+	/// input's or gifski's limit. Returns that fps.
+	pub fn limit_frames(&mut self, opt: &ConvertOptions, info: &InputInfo) -> Result<Option<f32>> {
+		let Some(max) = opt.max_frames else { return Ok(None) };
+		let seconds = self.seconds(opt, info).filter(|&s| s > 0.0).ok_or_else(|| ConvertError::InvalidOption {
+			option: "max frames",
+			message: format!("{} doesn't say how long it is, so there's no fps to work out", opt.input.display()),
+		})?;
+		#[allow(clippy::cast_possible_truncation)]
+		let budget = (f64::from(max) / seconds) as f32;
+		let fps = budget.min(info.video.fps().unwrap_or(f32::MAX)).min(crate::MAX_FPS);
+		// Rounded down, so the rounding doesn't go over the budget.
+		let fps = ((fps * 100.0).floor() / 100.0).max(0.01);
+		self.resample(fps);
+		// The fps filter can round up to one frame more.
+		self.max_frames = Some(max);
+		Ok(Some(fps))
+	}
+
+	/// Cuts what's extracted down to [`ConvertOptions::max_duration`] seconds after wherever it starts. Returns how
+	/// long it would have been, `None` if it was no longer or that isn't known.
+	pub fn limit_duration(&mut self, opt: &ConvertOptions, info: &InputInfo) -> Option<f64> {
+		let max = opt.max_duration?;
+		let seconds = self.seconds(opt, info);
+		if seconds.is_some_and(|s| s <= max) { return None; }
+		// -t is harmless if it's shorter anyway.
+		self.duration = Some(max);
+		seconds
+	}
+
+	/// Puts the [`ConvertOptions::zoom_to`] zoompan at `at` in the filters, where it's fed frames at `fps`. Done
+	/// once the length is known, but placed ahead of the crop and scale, which work on its output.
+	pub fn zoom_to(&mut self, opt: &ConvertOptions, info: &InputInfo, (at, fps): (usize, Option<f32>)) -> Result<()> {
+		let Some(zoom) = opt.zoom_to else { return Ok(()) };
+		let fps = fps.ok_or(ConvertError::FpsDetectionFailed)?;
+		let (_, start) = self.seeks();
+		let filter = crate::zoom::zoompan_filter(zoom, (info.video.width, info.video.height), fps, start.unwrap_or(0.0), self.seconds(opt, info))?;
+		self.filters.insert(at, filter);
+		Ok(())
+	}
+
+	/// Loops a still image input into `seconds` of video at `fps`, which every frame being the same makes exact.
+	pub fn loop_still(&mut self, seconds: f64, fps: f32) {
+		self.loop_input = true;
+		self.duration = Some(seconds);
+		self.resample(fps);
+	}
+
+	/// Extracts at `fps` instead of the input's rate.
+	pub fn resample(&mut self, fps: f32) {
+		self.filters.push(format!("fps={fps}"));
+		self.fps = Some(fps);
+	}
+
+	/// The extraction of a [`ConvertOptions::concat`] input that comes after `self`'s: resized the same way, then
+	/// fitted to `self`'s frame size and resampled to `self`'s `fps` if they differ.
+	pub fn follow_on(&self, opt: &ConvertOptions, video: &VideoStream, fps: Option<f32>) -> Result<Extraction> {
+		let mut next = Extraction { frame_pattern: self.frame_pattern.clone(), ..Extraction::default() };
+		next.resize(opt, video)?;
+		if let (Some(size), Some(next_size)) = (self.frame_size, next.frame_size) {
+			if size != next_size {
+				log::debug!("Fitting {}x{} into {}x{}", next_size.0, next_size.1, size.0, size.1);
+				next.filters.extend(fit_filters(size.0, size.1, Fit::Pad, opt.pad_color.as_deref().unwrap_or("black"))?);
+				next.frame_size = Some(size);
+			}
+		}
+		if let Some(fps) = fps.filter(|&fps| video.fps() != Some(fps)) { next.resample(fps); }
+		Ok(next)
+	}
+
+	/// Roughly how many frames ffmpeg will write, if the input says how long it is.
+	pub fn expected_frames(&self, opt: &ConvertOptions, info: &InputInfo) -> Option<usize> {
+		if self.keyframes_only { return None; }
+		let fps = self.fps.or(info.video.fps())?;
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		let frames = (self.seconds(opt, info)?.max(0.0) * f64::from(fps)).round() as usize;
+		Some(self.max_frames.map_or(frames, |max| frames.min(max as usize)))
+	}
+
+	/// Where `start` is split between `-ss` before `-i`, a fast seek, and after it, an accurate one.
+	fn seeks(&self) -> (Option<f64>, Option<f64>) {
+		let Some(start) = self.start else { return (None, None) };
+		match self.seek_mode {
+			SeekMode::Fast => (Some(start), None),
+			SeekMode::Smart if start > SMART_SEEK_MARGIN => {
+				// Rounded, so the float subtraction doesn't show up in the command line.
+				(Some(((start - SMART_SEEK_MARGIN) * 1000.0).round() / 1000.0), Some(SMART_SEEK_MARGIN))
+			}
+			// Close to the start there's little to decode anyway.
+			SeekMode::Accurate | SeekMode::Smart => (None, Some(start)),
+		}
+	}
+
+	/// How long the extracted part of the input is, if the input says how long it is.
+	pub fn seconds(&self, opt: &ConvertOptions, info: &InputInfo) -> Option<f64> {
+		if self.duration.is_some() { return self.duration; }
+		let fps = info.video.fps().map(f64::from);
+		match opt.end_frame {
+			#[allow(clippy::cast_precision_loss)]
+			Some(end) => Some((end + 1 - opt.start_frame.unwrap_or(0)) as f64 / fps?),
+			None if opt.start_frame.is_some() => Some(info.duration? - self.start_seconds(opt, fps?)),
+			None => Some(info.duration? - self.start.unwrap_or(0.0)),
+		}
+	}
+
+	/// What part of the input was asked for, for errors: `2.00s to 4.50s of the 3.00s input`, `frame 10 to the end`.
+	pub fn range(&self, opt: &ConvertOptions, info: Option<&InputInfo>) -> String {
+		let of = info.and_then(|i| i.duration).map(|d| format!(" of the {d:.2}s input")).unwrap_or_default();
+		if opt.start_frame.is_some() || opt.end_frame.is_some() {
+			let end = opt.end_frame.map_or_else(|| "the end".to_string(), |e| format!("frame {e}"));
+			return format!("frame {} to {end}{of}", opt.start_frame.unwrap_or(0));
+		}
+		let start = self.start.unwrap_or(0.0);
+		let end = self.duration.map_or_else(|| "the end".to_string(), |d| format!("{:.2}s", start + d));
+		format!("{start:.2}s to {end}{of}")
+	}
+
+	/// Seconds into the input the first extracted frame is at, given the input's fps.
+	pub fn start_seconds(&self, opt: &ConvertOptions, fps: f64) -> f64 {
+		#[allow(clippy::cast_precision_loss)] // Frame numbers that large aren't a thing.
+		let from_frames = opt.start_frame.map(|f| f as f64 / fps);
+		self.start.or(from_frames).unwrap_or(0.0)
+	}
+}
+
+/// Seconds before `start` a [`SeekMode::Smart`] seek jumps to, comfortably more than a keyframe interval.
+const SMART_SEEK_MARGIN: f64 = 5.0;
+
+/// A rectangle of the input, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Crop {
+	pub width: u32,
+	pub height: u32,
+	pub x: u32,
+	pub y: u32,
+}
+
+/// What the crop and scale options make of a `video`: the [`ConvertOptions::aspect`] crop, if any, and the size
+/// after cropping and scaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Resized {
+	pub crop: Option<Crop>,
+	pub scaled: (u32, u32),
+}
+
+/// Works out every size the crop and scale options make, so they all agree on it. Unless `allow_odd`, changed
+/// sides are rounded down to even numbers, never below 2: odd sizes trip up some ffmpeg filters, and gifski's
+/// resizer blurs them. A [`ConvertOptions::scale`] is always even. Without any of the options the input's size
+/// is left alone.
+///
+/// The [`ConvertOptions::aspect`] crop is taken out of the `focus` region, if there is one.
+pub(crate) fn resized(video: &VideoStream, opt: &ConvertOptions, focus: Option<Crop>, allow_odd: bool) -> Result<Resized> {
+	let round = |side: u32| if allow_odd { side } else { even(side) };
+	let focus = focus.map(|f| f.inside(video.width, video.height, round)).transpose()?;
+	let region = focus.unwrap_or(Crop { width: video.width, height: video.height, x: 0, y: 0 });
+	let crop = opt.aspect
+		.map(|aspect| Crop::to_aspect(region.width, region.height, aspect, opt.gravity, round))
+		.transpose()?
+		.map(|c| Crop { x: region.x + c.x, y: region.y + c.y, ..c })
+		.or(focus);
+	let (w, h) = crop.map_or((video.width, video.height), |c| (c.width, c.height));
+	// The other side of a `to`/`from` scale, rounded like ffmpeg's -1 does.
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let keep_aspect = |to: u32, from: u32, other: u32| (f64::from(other) * f64::from(to) / f64::from(from.max(1))).round() as u32;
+	let scaled = match (opt.scale, opt.width, opt.height) {
+		(Some(scale), ..) => (scale.apply(w), scale.apply(h)),
+		(None, None, None) => (w, h),
+		(None, Some(width), Some(height)) => (round(width), round(height)),
+		(None, Some(width), None) => (round(width), round(keep_aspect(width, w, h))),
+		(None, None, Some(height)) => (round(keep_aspect(height, h, w)), round(height)),
+	};
+	Ok(Resized { crop, scaled })
+}
+
+/// `side` rounded down to an even number, at least 2.
+pub(crate) fn even(side: u32) -> u32 {
+	(side & !1).max(2)
+}
+
+/// The side ffmpeg works out itself when the input's size isn't known.
+fn unknown_side(opt: &ConvertOptions) -> &'static str {
+	if opt.allow_odd_dimensions { "-1" } else { "-2" }
+}
+
+impl Crop {
+	/// Checks the crop is inside the `width`x`height` input, with its sides passed through `round`.
+	fn inside(self, width: u32, height: u32, round: impl Fn(u32) -> u32) -> Result<Crop> {
+		let invalid = |message: String| Err(ConvertError::InvalidOption { option: "focus", message });
+		if width == 0 || height == 0 { return invalid("couldn't find the dimensions of the input".to_string()); }
+		if u64::from(self.x) + u64::from(self.width) > u64::from(width) || u64::from(self.y) + u64::from(self.height) > u64::from(height) {
+			return invalid(format!("{} isn't inside the {width}x{height} input", self.focus()));
+		}
+		Ok(Crop { width: round(self.width).min(width), height: round(self.height).min(height), ..self })
+	}
+
+	/// The [`Focus`] that crops to this.
+	pub fn focus(self) -> Focus {
+		Focus::Region { width: self.width, height: self.height, x: self.x, y: self.y }
+	}
+
+	/// The largest `aspect` rectangle that fits in `width`x`height`, with its sides passed through `round`, placed
+	/// according to `gravity`.
+	pub fn to_aspect(width: u32, height: u32, aspect: Aspect, gravity: Gravity, round: impl Fn(u32) -> u32) -> Result<Crop> {
+		let invalid = |message: String| ConvertError::InvalidOption { option: "aspect", message };
+		if width == 0 || height == 0 { return Err(invalid("couldn't find the dimensions of the input".to_string())); }
+		let (w, h, aw, ah) = (u64::from(width), u64::from(height), u64::from(aspect.width), u64::from(aspect.height));
+		// Wider than the aspect: full height, narrower width. Otherwise: full width, lower height.
+		let (crop_w, crop_h) = if w * ah > h * aw { ((h * aw + ah / 2) / ah, h) } else { (w, (w * ah + aw / 2) / aw) };
+		if crop_w == 0 || crop_h == 0 {
+			return Err(invalid(format!("{}:{} doesn't fit in the {width}x{height} input", aspect.width, aspect.height)));
+		}
+		let (crop_w, crop_h) = (u32::try_from(crop_w).unwrap_or(width), u32::try_from(crop_h).unwrap_or(height));
+		let (crop_w, crop_h) = (round(crop_w).min(width), round(crop_h).min(height));
+		let (spare_w, spare_h) = (width - crop_w, height - crop_h);
+		let x = match gravity { Gravity::Left => 0, Gravity::Right => spare_w, _ => spare_w / 2 };
+		let y = match gravity { Gravity::Top => 0, Gravity::Bottom => spare_h, _ => spare_h / 2 };
+		Ok(Crop { width: crop_w, height: crop_h, x, y })
+	}
+}
+
+/// Scales to exactly `width`x`height` the way `fit` says.
+fn fit_filters(width: u32, height: u32, fit: Fit, pad_color: &str) -> Result<Vec<String>> {
+	Ok(match fit {
+		Fit::Stretch => vec![format!("scale={width}:{height}:flags=lanczos")],
+		Fit::Crop => vec![
+			format!("scale={width}:{height}:force_original_aspect_ratio=increase:flags=lanczos"),
+			format!("crop={width}:{height}"),
+		],
+		Fit::Pad => {
+			// It ends up inside the filter chain, where these would start a new option or filter.
+			if pad_color.is_empty() || pad_color.contains([':', ',', ';', '=', '[', ']']) {
+				return Err(ConvertError::InvalidOption { option: "pad color", message: format!("{pad_color:?} isn't a color") });
+			}
+			vec![
+				format!("scale={width}:{height}:force_original_aspect_ratio=decrease:flags=lanczos"),
+				// rgba, so a translucent color stays translucent.
+				"format=rgba".to_string(),
+				format!("pad={width}:{height}:(ow-iw)/2:(oh-ih)/2:color={pad_color}"),
+			]
+		}
+	})
+}
+
+/// Keeps frames `start..=end`. ffmpeg's `end_frame` is exclusive, hence the `+ 1`.
+fn trim_frames_filter(start: u64, end: Option<u64>) -> String {
+	let trim = match end {
+		Some(end) => format!("trim=start_frame={start}:end_frame={}", end + 1),
+		None => format!("trim=start_frame={start}"),
+	};
+	// Without resetting the timestamps ffmpeg would pad the start with copies of the first frame.
+	format!("{trim},setpts=PTS-STARTPTS")
+}
+
+/// Escapes `text` for a filter option, then again for the filter graph it's in: ffmpeg unescapes it once for each.
+pub(crate) fn escape_option(text: &str) -> String {
+	let escape = |text: &str, special: &[char]| text.chars().fold(String::new(), |mut s, c| {
+		if special.contains(&c) { s.push('\\'); }
+		s.push(c);
+		s
+	});
+	escape(&escape(text, &['\\', '\'', ':']), &['\\', '\'', '[', ']', ',', ';'])
+}
+
+/// Checks [`ConvertOptions::stages`] isn't combined with options for the stage it skips. `trimmed` is whether
+/// any trim option is set.
+fn check_stages(opt: &ConvertOptions, trimmed: bool) -> Result<()> {
+	let invalid = |message: String| Err(ConvertError::InvalidOption { option: "stage", message });
+	match opt.stages {
+		Stages::Encode => {
+			let conflict = [
+				(opt.fps.is_none(), "needs --fps, there's no video to detect it from"),
+				(trimmed, "can't trim, the frames are already extracted"),
+				(opt.width.is_some() || opt.height.is_some() || opt.scale.is_some() || opt.aspect.is_some(), "can't resize, the frames are already extracted"),
+				(opt.stabilize, "can't stabilize, the frames are already extracted"),
+				(opt.zoom_to.is_some(), "can't zoom, the frames are already extracted"),
+				(opt.waveform.is_some(), "can't add a waveform, the frames are already extracted"),
+				(opt.pre_quantize.is_some(), "can't pre-quantize, the frames are already extracted"),
+				(opt.keyframes_only || opt.max_frames.is_some(), "can't be combined with --keyframes-only or --max-frames"),
+				(!opt.concat.is_empty() || opt.grid.is_some(), "can't be combined with --concat or --grid"),
+				(opt.overlap || opt.chunk_seconds.is_some(), "can't be combined with --overlap or --chunk-seconds"),
+				(matches!(opt.poster, Some(Poster::At(_))), "can't find a --poster timestamp, use first or middle"),
+			].into_iter().find(|(set, _)| *set);
+			if let Some((_, message)) = conflict { return invalid(format!("encode {message}")); }
+		}
+		Stages::Extract => {
+			let conflict = [
+				(!opt.compare_quality.is_empty(), "--compare-quality"),
+				(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds, which encode as they go"),
+				(opt.poster.is_some(), "--poster"),
+				(opt.also_mp4, "--also-mp4"),
+				(opt.contact_sheet.is_some(), "--contact-sheet"),
+				(opt.edit_list.is_some(), "--edit-list"),
+			].into_iter().find(|(set, _)| *set);
+			if let Some((_, what)) = conflict { return invalid(format!("extract doesn't encode, so it can't be combined with {what}")); }
+		}
+		Stages::All => {}
+	}
+	Ok(())
+}
+
+/// Checks the options that change the frames, [`ConvertOptions::zoom_to`], [`ConvertOptions::stabilize`],
+/// [`ConvertOptions::waveform`], [`ConvertOptions::edit_list`] and [`ConvertOptions::loop_smooth`], aren't combined with ones they can't work with.
+fn check_effects(opt: &ConvertOptions) -> Result<()> {
+	let invalid = |option, message: &str| Err(ConvertError::InvalidOption { option, message: message.to_string() });
+	if let Some(colors) = opt.pre_quantize.filter(|c| !(2..=256).contains(c)) {
+		return invalid("pre-quantize", &format!("{colors} is not between 2 and 256"));
+	}
+	if let Some(threshold) = opt.drop_flash_frames.filter(|t| !(t > &0.0 && t <= &1.0)) {
+		return invalid("drop flash frames", &format!("{threshold} is not more than 0 and up to 1"));
+	}
+	if opt.zoom_to.is_some() {
+		let conflict = [
+			(opt.keyframes_only, "--keyframes-only, which has no steady fps to zoom at"),
+			(!opt.concat.is_empty(), "--concat"),
+			(opt.grid.is_some(), "--grid"),
+		].into_iter().find(|(set, _)| *set);
+		if let Some((_, what)) = conflict { return invalid("zoom to", &format!("can't be combined with {what}")); }
+	}
+	if opt.stabilize {
+		let conflict = [
+			(opt.keyframes_only, "--keyframes-only"),
+			(opt.chunk_seconds.is_some(), "--chunk-seconds, each chunk would need its own transforms"),
+			(!opt.concat.is_empty(), "--concat"),
+			(opt.grid.is_some(), "--grid"),
+		].into_iter().find(|(set, _)| *set);
+		if let Some((_, what)) = conflict { return invalid("stabilize", &format!("can't be combined with {what}")); }
+	}
+	if opt.waveform.is_some() {
+		let conflict = [
+			(opt.start_frame.is_some() || opt.end_frame.is_some(), "--start-frame/--end-frame, which only trim the video"),
+			(opt.keyframes_only, "--keyframes-only"),
+			(!opt.concat.is_empty(), "--concat"),
+			(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds"),
+		].into_iter().find(|(set, _)| *set);
+		if let Some((_, what)) = conflict { return invalid("waveform", &format!("can't be combined with {what}")); }
+	}
+	if opt.edit_list.is_some() {
+		let conflict = [
+			(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds, gifski has the frames before they could be picked"),
+			(matches!(opt.poster, Some(Poster::At(_))), "a --poster timestamp, use first or middle"),
+		].into_iter().find(|(set, _)| *set);
+		if let Some((_, what)) = conflict { return invalid("edit list", &format!("can't be combined with {what}")); }
+	}
+	if opt.loop_smooth == Some(0) {
+		return invalid("loop smooth", "crossfades at least 1 frame, not 0");
+	}
+	if opt.loop_smooth.is_some() && (opt.overlap || opt.chunk_seconds.is_some()) {
+		return invalid("loop smooth", "can't be combined with --overlap or --chunk-seconds, gifski has the frames before they could be blended");
+	}
+	Ok(())
+}
+
+/// `-f` [`ConvertOptions::input_format`] and the [`ConvertOptions::input_args`], which can't have an `-i` of their own.
+fn input_options(opt: &ConvertOptions) -> Result<Vec<String>> {
+	let invalid = |option, message: &str| Err(ConvertError::InvalidOption { option, message: message.to_string() });
+	if opt.input_format.as_deref().is_some_and(|f| f.trim().is_empty()) { return invalid("input format", "can't be empty"); }
+	if opt.input_args.iter().any(|arg| arg == "-i") {
+		return invalid("input arg", "can't be -i, the input is the video given on its own");
+	}
+	Ok(opt.input_format.iter().flat_map(|f| ["-f".to_string(), f.clone()]).chain(opt.input_args.iter().cloned()).collect())
+}
+
+/// [`ConvertOptions::exact_end`] needs an end in seconds, and frames that are all there before gifski starts.
+fn check_exact_end(opt: &ConvertOptions) -> Result<()> {
+	if !opt.exact_end { return Ok(()); }
+	let conflict = [
+		(opt.end.is_none() && opt.duration.is_none() && opt.max_duration.is_none(), "needs --end, --duration or --max-duration to know where the end is"),
+		(opt.overlap || opt.chunk_seconds.is_some(), "can't be combined with --overlap or --chunk-seconds, gifski has the frames before they could be trimmed"),
+		(opt.keyframes_only, "can't be combined with --keyframes-only, which has no fixed fps"),
+		(!opt.concat.is_empty(), "can't be combined with --concat"),
+	].into_iter().find(|(set, _)| *set);
+	match conflict {
+		Some((_, message)) => Err(ConvertError::InvalidOption { option: "exact end", message: message.to_string() }),
+		None => Ok(()),
+	}
+}
+
+/// Deletes the frames past the end of the extracted [`duration`](Extraction::duration) at the fps they were
+/// extracted at, from the resample or ffmpeg's `stderr`, which ffmpeg can write a few of while flushing its
+/// decoder. Returns the frames left and how many were deleted.
+pub(crate) fn drop_past_end(extraction: &Extraction, stderr: &str, mut frames: Vec<PathBuf>) -> Result<(Vec<PathBuf>, usize)> {
+	let Some(duration) = extraction.duration else { return Ok((frames, 0)) };
+	let fps = f64::from(match extraction.fps { Some(fps) => fps, None => parse_fps(stderr)? });
+	let count = exact_frame_count(duration, fps);
+	let past = frames.split_off(count.min(frames.len()));
+	for frame in &past {
+		fs::remove_file(frame).map_err(ConvertError::io(frame))?;
+	}
+	Ok((frames, past.len()))
+}
+
+/// How many frames `fps` puts in the first `duration` seconds: the ones at `0`, `1 / fps`, ... before `duration`.
+fn exact_frame_count(duration: f64, fps: f64) -> usize {
+	// Less a little, so float error in a whole number of frames doesn't round up to one more.
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let count = (duration * fps - 1e-6).ceil().max(0.0) as usize;
+	count
+}
+
+/// `ffmpeg [-ss fast seek] [-skip_frame nokey] [-loop 1] [-threads N -filter_threads N] [input options] -i video.mp4 [-ss accurate seek] [-t duration] [-vsync vfr] [-vf filters] frame%04d.png`,
+/// in the [`Extraction::frame_pattern`].
+pub(crate) fn extract_command(input: &Path, frames_dir: &Path, extraction: &Extraction) -> CommandLine {
+	let mut command = CommandLine::new("ffmpeg");
+	let (fast_seek, _) = extraction.seeks();
+	if let Some(start) = fast_seek {
+		command = command.arg("-ss").arg(start.to_string());
+	}
+	if extraction.keyframes_only {
+		command = command.args(["-skip_frame", "nokey"]);
+	}
+	if extraction.loop_input {
+		command = command.args(["-loop", "1"]);
+	}
+	if let Some(threads) = extraction.threads {
+		command = command.arg("-threads").arg(threads.to_string()).arg("-filter_threads").arg(threads.to_string());
+	}
+	command = command.args(&extraction.input_options).arg("-i").arg(paths::for_tool(input));
+	for input in &extraction.more_inputs {
+		if let Some(start) = fast_seek {
+			command = command.arg("-ss").arg(start.to_string());
+		}
+		command = command.arg("-i").arg(paths::for_tool(input));
+	}
+	if extraction.keyframes_only || extraction.timestamps {
+		// Otherwise ffmpeg duplicates frames to fill the gaps back up to the input's frame rate.
+		command = command.args(["-vsync", "vfr"]);
+	}
+	command = command.args(output_options(extraction));
+	if let Some(number) = extraction.start_number {
+		command = command.arg("-start_number").arg(number.to_string());
+	}
+	command.arg(paths::for_tool(&frames_dir.join(extraction.frame_pattern.printf())))
+}
+
+/// [`extract_command`] with a second, yuv4mpeg, copy of the frames on stdout, for gifski to read while the
+/// frames are still being written.
+pub(crate) fn overlap_command(input: &Path, frames_dir: &Path, extraction: &Extraction) -> CommandLine {
+	// stdin is piped when spawned, and ffmpeg would wait for keyboard commands on it.
+	let extract = extract_command(input, frames_dir, extraction);
+	CommandLine::new("ffmpeg")
+		.arg("-nostdin")
+		.args(extract.args)
+		.args(output_options(extraction))
+		.args(["-f", "yuv4mpegpipe", "-pix_fmt", "yuv444p", "-"])
+}
+
+/// Options that apply to the one output they come before.
+fn output_options(extraction: &Extraction) -> Vec<String> {
+	let mut options = Vec::new();
+	if let (_, Some(start)) = extraction.seeks() {
+		options.extend(["-ss".to_string(), start.to_string()]);
+	}
+	if let Some(duration) = extraction.duration {
+		options.extend(["-t".to_string(), duration.to_string()]);
+	}
+	if let Some(max) = extraction.max_frames {
+		options.extend(["-frames:v".to_string(), max.to_string()]);
+	}
+	// --stabilize can't be combined with a stack.
+	let transform = extraction.stabilize.as_deref().map(crate::stabilize::transform_filter);
+	let quantize = extraction.pre_quantize.map(crate::quantize::filter);
+	let filters = extraction.stack.iter().cloned()
+		.chain(transform)
+		.chain(extraction.filters.iter().cloned())
+		.chain(quantize)
+		.chain(extraction.timestamps.then(|| "showinfo".to_string()))
+		.collect::<Vec<_>>();
+	match &extraction.waveform {
+		Some(strip) => {
+			let video = if extraction.stack.is_some() { "" } else { "[0:v]" };
+			let chain = if filters.is_empty() { "null".to_string() } else { filters.join(",") };
+			options.extend(["-filter_complex".to_string(), format!("{video}{chain}[video];{strip}")]);
+		}
+		None if extraction.stack.is_some() => options.extend(["-filter_complex".to_string(), filters.join(",")]),
+		None if !filters.is_empty() => options.extend(["-vf".to_string(), filters.join(",")]),
+		None => {}
+	}
+	if extraction.bitexact {
+		options.extend(["-fflags", "+bitexact", "-flags:v", "+bitexact"].map(String::from));
+	}
+	options
+}
+
+/// What [`extract_frames`] got done.
+pub(crate) struct Extracted {
+	/// Where ffmpeg prints the stream info.
+	pub stderr: String,
+	/// The free bytes left on the frames directory's filesystem, if ffmpeg was stopped because they dropped below
+	/// the reserve. The frames extracted until then are kept.
+	pub low_on_space: Option<u64>,
+	/// How ffmpeg failed, if it did after [`MIN_RECOVERED_FRAMES`] and [`Extraction::tolerate_errors`] kept them.
+	pub decode_error: Option<String>,
+}
+
+/// The fewest frames a failed ffmpeg has to have extracted for [`Extraction::tolerate_errors`] to keep them, fewer
+/// are more likely an input that can't be read at all than one broken part way.
+pub(crate) const MIN_RECOVERED_FRAMES: usize = 10;
+
+/// Runs [`extract_command`], reporting [`Progress::Extracting`] as the frames appear in `frames_dir`, out of
+/// `expected`.
+///
+/// Unless `reserve` is 0, ffmpeg is stopped once fewer than that many bytes are free where the frames are, or if
+/// it failed when they were, and the frame it was writing is deleted in case it was cut off. A failure otherwise is an
+/// error, unless [`Extraction::tolerate_errors`] and enough frames came out before it.
+pub(crate) fn extract_frames(
+	runner: &dyn CommandRunner,
+	input: &Path,
+	frames_dir: &Path,
+	extraction: &Extraction,
+	expected: Option<usize>,
+	reserve: u64,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Extracted> {
+	let command = extract_command(input, frames_dir, extraction);
+	log::debug!("Running: {}", &command);
+	let already = extraction.frame_pattern.count(frames_dir);
+	let mut child = runner.spawn(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	// Closed, or ffmpeg would wait for keyboard commands on it.
+	drop(child.take_stdin());
+	let watch = (reserve > 0).then(|| {
+		let kill = child.kill_handle();
+		disk::watch(frames_dir, reserve, move || if let Some(kill) = kill { kill(); })
+	});
+	let (done, stderr) = mpsc::channel();
+	if let Some(mut pipe) = child.take_stderr() {
+		thread::spawn(move || {
+			let mut text = Vec::new();
+			let _ = pipe.read_to_end(&mut text);
+			let _ = done.send(text);
+		});
+	}
+
+	let mut reported = 0;
+	let mut report = |progress: &mut dyn FnMut(Progress)| {
+		let frame = extraction.frame_pattern.count(frames_dir) - already;
+		if frame > reported {
+			reported = frame;
+			progress(Progress::Extracting { frame, total: expected });
+		}
+	};
+	// ffmpeg closes its stderr when it exits.
+	let streamed = loop {
+		match stderr.recv_timeout(POLL_INTERVAL) {
+			Err(RecvTimeoutError::Timeout) => report(progress),
+			done => break done.unwrap_or_default(),
+		}
+	};
+	report(progress);
+	let mut low_on_space = watch.and_then(disk::SpaceWatch::stop);
+
+	let output = child.wait().map_err(ConvertError::FfmpegNotInstalled)?;
+	log::debug!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+	let stderr = String::from_utf8_lossy(&streamed).into_owned() + &String::from_utf8_lossy(&output.stderr);
+	log::debug!("stderr: {}", &stderr);
+
+	if !output.success() && low_on_space.is_none() {
+		// Most likely a write failing because it filled up between two checks.
+		let extracted_some = extraction.frame_pattern.count(frames_dir) > already;
+		low_on_space = disk::free_space(frames_dir).filter(|&free| extracted_some && free < reserve);
+		if low_on_space.is_none() {
+			let extracted = extraction.frame_pattern.count(frames_dir).saturating_sub(already);
+			if !extraction.tolerate_errors || extracted < MIN_RECOVERED_FRAMES { return Err(ConvertError::FfmpegFailed { code: output.code, stderr }); }
+			let failure = ConvertError::FfmpegFailed { code: output.code, stderr: stderr.clone() }.to_string();
+			log::warn!("Keeping the {extracted} frames extracted before {failure}");
+			return Ok(Extracted { stderr, low_on_space: None, decode_error: Some(failure) });
+		}
+	}
+	if low_on_space.is_some() && extraction.frame_pattern.count(frames_dir) > already {
+		if let Some(last) = extraction.frame_pattern.list(frames_dir)?.pop() { fs::remove_file(&last).map_err(ConvertError::io(last))?; }
+	}
+	Ok(Extracted { stderr, low_on_space, decode_error: None })
+}
+
+/// Whether `extracted` frames are suspiciously few for what was asked: one or two, from seconds of video that
+/// should have made `expected`.
+pub(crate) fn too_few_frames(extracted: usize, expected: Option<usize>, seconds: Option<f64>) -> bool {
+	(1..=2).contains(&extracted) && seconds.is_some_and(|s| s >= 2.0) && expected.is_some_and(|e| e >= 5)
+}
+
+pub(crate) fn parse_fps(ffmpeg_stderr: &str) -> Result<f32> {
+	let re = Regex::new(r"(\d+(\.\d+)?)\s(fps)").unwrap();
+	let video_fps = re.captures(ffmpeg_stderr)
+		.and_then(|c| c[1].parse().ok())
+		.ok_or(ConvertError::FpsDetectionFailed)?;
+	log::debug!("Original Video FPS: {}", &video_fps);
+	Ok(video_fps)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn one_or_two_frames_of_seconds_are_too_few() {
+		assert!(too_few_frames(1, Some(48), Some(2.0)));
+		assert!(too_few_frames(2, Some(240), Some(10.0)));
+		assert!(!too_few_frames(3, Some(240), Some(10.0)));
+		assert!(!too_few_frames(0, Some(240), Some(10.0)), "an error instead");
+		assert!(!too_few_frames(1, Some(24), Some(1.0)), "a second isn't seconds");
+		assert!(!too_few_frames(2, Some(2), Some(4.0)), "--fps 0.5 asked for two");
+		assert!(!too_few_frames(1, None, None), "keyframes or an unknown length");
+	}
+
+	#[test]
+	fn exact_frame_counts() {
+		assert_eq!(exact_frame_count(2.0, 24.0), 48);
+		assert_eq!(exact_frame_count(1.03, 10.0), 11, "a frame at 1.0s starts before the end");
+		assert_eq!(exact_frame_count(0.1 * 3.0, 10.0), 3, "0.30000000000000004s");
+		assert_eq!(exact_frame_count(1.0, 29.97), 30);
+	}
+
+	fn args(opt: &ConvertOptions) -> Vec<String> {
+		let extraction = Extraction::new(opt).unwrap();
+		extract_command(&opt.input, Path::new("/tmp/frames"), &extraction).args_lossy()
+	}
+
+	#[test]
+	fn extract_command_writes_numbered_frames() {
+		let command = extract_command(Path::new("videos/input.mp4"), Path::new("/tmp/frames"), &Extraction::default());
+		assert_eq!(command.program_name(), "ffmpeg");
+		assert_eq!(command.args_lossy(), ["-i", "videos/input.mp4", "/tmp/frames/frame%04d.png"]);
+	}
+
+	#[test]
+	fn time_trim_seeks_then_limits_the_duration() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.start = Some(1.5);
+		opt.end = Some(4.0);
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-ss", "1.5", "-t", "2.5", "/tmp/frames/frame%04d.png"]);
+
+		opt.end = None;
+		opt.duration = Some(3.0);
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-ss", "1.5", "-t", "3", "/tmp/frames/frame%04d.png"]);
+
+		opt.start = None;
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-t", "3", "/tmp/frames/frame%04d.png"]);
+	}
+
+	#[test]
+	fn seek_mode_puts_ss_before_or_after_the_input() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.start = Some(62.5);
+		opt.seek_mode = SeekMode::Fast;
+		assert_eq!(args(&opt), ["-ss", "62.5", "-i", "in.mp4", "/tmp/frames/frame%04d.png"]);
+
+		opt.seek_mode = SeekMode::Accurate;
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-ss", "62.5", "/tmp/frames/frame%04d.png"]);
+
+		opt.seek_mode = SeekMode::Smart;
+		assert_eq!(args(&opt), ["-ss", "57.5", "-i", "in.mp4", "-ss", "5", "/tmp/frames/frame%04d.png"]);
+
+		opt.start = Some(4.0);
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-ss", "4", "/tmp/frames/frame%04d.png"]);
+	}
+
+	#[test]
+	fn end_frame_is_inclusive() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.start_frame = Some(10);
+		opt.end_frame = Some(19);
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-vf", "trim=start_frame=10:end_frame=20,setpts=PTS-STARTPTS", "/tmp/frames/frame%04d.png"]);
+
+		// A single frame.
+		opt.start_frame = Some(19);
+		assert_eq!(args(&opt)[3], "trim=start_frame=19:end_frame=20,setpts=PTS-STARTPTS");
+
+		opt.start_frame = None;
+		assert_eq!(args(&opt)[3], "trim=start_frame=0:end_frame=20,setpts=PTS-STARTPTS");
+
+		opt.start_frame = Some(5);
+		opt.end_frame = None;
+		assert_eq!(args(&opt)[3], "trim=start_frame=5,setpts=PTS-STARTPTS");
+	}
+
+	#[test]
+	fn max_frames_lowers_the_fps_to_fit() {
+		let info = |duration| InputInfo { format: "mp4".to_string(), duration, video: crate::probe::test_video(640, 360), has_audio: false };
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.max_frames = Some(150);
+		opt.duration = Some(45.0);
+		let mut extraction = Extraction::new(&opt).unwrap();
+
+		assert_eq!(extraction.limit_frames(&opt, &info(None)).unwrap(), Some(3.33));
+		let args = extract_command(&opt.input, Path::new("/tmp/frames"), &extraction).args_lossy().join(" ");
+		assert!(args.ends_with("-t 45 -frames:v 150 -vf fps=3.33 /tmp/frames/frame%04d.png"), "{args}");
+
+		opt.duration = None;
+		opt.start_frame = Some(24);
+		let mut extraction = Extraction::new(&opt).unwrap();
+		assert_eq!(extraction.limit_frames(&opt, &info(Some(2.0))).unwrap(), Some(24.0), "never more than the input's fps");
+		assert!(Extraction::new(&opt).unwrap().limit_frames(&opt, &info(None)).is_err());
+
+		opt.fps = Some(10.0);
+		assert!(Extraction::new(&opt).is_err());
+	}
+
+	#[test]
+	fn keyframes_only_skips_the_rest() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.keyframes_only = true;
+		opt.start = Some(60.0);
+		assert_eq!(args(&opt), ["-ss", "60", "-skip_frame", "nokey", "-i", "in.mp4", "-vsync", "vfr", "/tmp/frames/frame%04d.png"]);
+
+		opt.poster = Some(Poster::At(61.0));
+		assert!(Extraction::new(&opt).is_err());
+	}
+
+	#[test]
+	fn background_extracts_on_half_the_cores() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.background = true;
+		let half = crate::priority::half_the_cores().to_string();
+		assert_eq!(args(&opt)[..6], ["-threads", &half, "-filter_threads", &half, "-i", "in.mp4"]);
+		opt.threads = Some(0);
+		assert_eq!(args(&opt)[..4], ["-threads", "0", "-filter_threads", "0"], "ffmpeg's own choice");
+		opt.background = false;
+		opt.threads = None;
+		assert_eq!(args(&opt)[0], "-i");
+	}
+
+	#[test]
+	fn input_options_come_right_before_the_first_input() {
+		let mut opt = ConvertOptions::new("in.h264");
+		opt.input_format = Some("h264".to_string());
+		opt.input_args = ["-framerate", "30"].map(String::from).to_vec();
+		opt.start = Some(2.0);
+		opt.seek_mode = SeekMode::Fast;
+		assert_eq!(args(&opt), ["-ss", "2", "-f", "h264", "-framerate", "30", "-i", "in.h264", "/tmp/frames/frame%04d.png"]);
+
+		let mut extraction = Extraction::new(&opt).unwrap();
+		extraction.more_inputs.push(PathBuf::from("more.mp4"));
+		let args = extract_command(&opt.input, Path::new("/tmp/frames"), &extraction).args_lossy();
+		assert_eq!(args[..9], ["-ss", "2", "-f", "h264", "-framerate", "30", "-i", "in.h264", "-ss"], "only for the first input");
+
+		opt.input_args.push("-i".to_string());
+		assert!(Extraction::new(&opt).unwrap_err().to_string().contains("can't be -i"));
+		opt.input_args.clear();
+		opt.input_format = Some(String::new());
+		assert!(Extraction::new(&opt).is_err());
+	}
+
+	#[test]
+	fn pre_quantizing_is_the_last_filter() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.pre_quantize = Some(64);
+		let quantize = "split[q0][q1];[q0]palettegen=max_colors=64:stats_mode=single[qp];[q1][qp]paletteuse=new=1:dither=none";
+		assert_eq!(args(&opt), ["-i", "in.mp4", "-vf", quantize, "/tmp/frames/frame%04d.png"]);
+
+		opt.width = Some(100);
+		let mut extraction = Extraction::new(&opt).unwrap();
+		extraction.resize(&opt, &crate::probe::test_video(640, 360)).unwrap();
+		extraction.filters.push("fps=10".to_string());
+		extraction.stabilize = Some(PathBuf::from("/tmp/frames/transforms.trf"));
+		let args = extract_command(&opt.input, Path::new("/tmp/frames"), &extraction).args_lossy();
+		let chain = &args[args.iter().position(|a| a == "-vf").unwrap() + 1];
+		assert!(chain.starts_with("vidstabtransform="), "{chain}");
+		assert!(chain.ends_with(&format!(",scale=100:56:flags=lanczos,fps=10,{quantize}")), "{chain}");
+
+		extraction.stabilize = None;
+		extraction.waveform = Some("[0:a]showwavespic[strip];[video][strip]vstack".to_string());
+		let args = extract_command(&opt.input, Path::new("/tmp/frames"), &extraction).args_lossy();
+		assert!(args.contains(&format!("[0:v]scale=100:56:flags=lanczos,fps=10,{quantize}[video];[0:a]showwavespic[strip];[video][strip]vstack")), "{args:?}");
+
+		for colors in [1, 257] {
+			opt.pre_quantize = Some(colors);
+			assert!(Extraction::new(&opt).unwrap_err().to_string().contains("not between 2 and 256"));
+		}
+	}
+
+	#[test]
+	fn overlap_repeats_the_output_options_for_the_pipe() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.start = Some(1.0);
+		opt.duration = Some(2.0);
+		opt.width = Some(100);
+		let mut extraction = Extraction::new(&opt).unwrap();
+		extraction.resize(&opt, &crate::probe::test_video(640, 360)).unwrap();
+
+		let command = overlap_command(&opt.input, Path::new("/tmp/frames"), &extraction);
+
+		assert_eq!(command.args_lossy(), [
+			"-nostdin", "-i", "in.mp4",
+			"-ss", "1", "-t", "2", "-vf", "scale=100:56:flags=lanczos", "/tmp/frames/frame%04d.png",
+			"-ss", "1", "-t", "2", "-vf", "scale=100:56:flags=lanczos", "-f", "yuv4mpegpipe", "-pix_fmt", "yuv444p", "-",
+		]);
+	}
+
+	#[test]
+	fn nonsensical_trims_are_rejected() {
+		let rejected = |f: &dyn Fn(&mut ConvertOptions)| {
+			let mut opt = ConvertOptions::new("in.mp4");
+			f(&mut opt);
+			matches!(Extraction::new(&opt), Err(ConvertError::InvalidOption { .. }))
+		};
+		assert!(rejected(&|o| { o.start_frame = Some(20); o.end_frame = Some(19); }));
+		assert!(rejected(&|o| { o.start = Some(5.0); o.end = Some(5.0); }));
+		assert!(rejected(&|o| { o.end = Some(5.0); o.duration = Some(1.0); }));
+		assert!(rejected(&|o| o.duration = Some(0.0)));
+		assert!(rejected(&|o| { o.start = Some(1.0); o.end_frame = Some(10); }));
+		assert!(!rejected(&|o| { o.start_frame = Some(3); o.end_frame = Some(3); }));
+	}
+
+	#[test]
+	fn stages_reject_what_they_skip() {
+		let message = |f: &dyn Fn(&mut ConvertOptions)| {
+			let mut opt = ConvertOptions::new("frames");
+			f(&mut opt);
+			match Extraction::new(&opt) {
+				Err(ConvertError::InvalidOption { option: "stage", message }) => Some(message),
+				_ => None,
+			}
+		};
+		assert_eq!(message(&|o| o.stages = Stages::Encode).as_deref(), Some("encode needs --fps, there's no video to detect it from"));
+		assert!(message(&|o| { o.stages = Stages::Encode; o.fps = Some(10.0); o.start = Some(1.0); }).unwrap().contains("trim"));
+		assert!(message(&|o| { o.stages = Stages::Encode; o.fps = Some(10.0); o.width = Some(100); }).unwrap().contains("resize"));
+		assert!(message(&|o| { o.stages = Stages::Extract; o.poster = Some(Poster::First); }).unwrap().contains("--poster"));
+		assert_eq!(message(&|o| { o.stages = Stages::Encode; o.fps = Some(10.0); }), None);
+	}
+
+	#[test]
+	fn aspect_crops() {
+		let crop = |w, h, aspect: &str, gravity| Crop::to_aspect(w, h, aspect.parse().unwrap(), gravity, |s| s).unwrap();
+		assert_eq!(crop(1920, 1080, "1:1", Gravity::Center), Crop { width: 1080, height: 1080, x: 420, y: 0 });
+		assert_eq!(crop(1920, 1080, "1:1", Gravity::Left), Crop { width: 1080, height: 1080, x: 0, y: 0 });
+		assert_eq!(crop(1920, 1080, "1:1", Gravity::Right), Crop { width: 1080, height: 1080, x: 840, y: 0 });
+		assert_eq!(crop(1080, 1920, "16:9", Gravity::Top), Crop { width: 1080, height: 608, x: 0, y: 0 });
+		assert_eq!(crop(1080, 1920, "16:9", Gravity::Bottom), Crop { width: 1080, height: 608, x: 0, y: 1312 });
+		// Already the right shape.
+		assert_eq!(crop(640, 360, "16:9", Gravity::Center), Crop { width: 640, height: 360, x: 0, y: 0 });
+		assert!(Crop::to_aspect(640, 360, "10000:1".parse().unwrap(), Gravity::Center, |s| s).is_err());
+		assert!(Crop::to_aspect(0, 0, Aspect::SQUARE, Gravity::Center, |s| s).is_err());
+	}
+
+	#[test]
+	fn sizes_are_rounded_down_to_even() {
+		let size = |(w, h), f: &dyn Fn(&mut ConvertOptions), allow_odd| {
+			let mut opt = ConvertOptions::new("in.mp4");
+			f(&mut opt);
+			let resized = resized(&crate::probe::test_video(w, h), &opt, None, allow_odd).unwrap();
+			(resized.crop.map(|c| (c.width, c.height, c.x, c.y)), resized.scaled)
+		};
+		// Nothing to resize, so even odd inputs are left alone.
+		assert_eq!(size((641, 361), &|_| {}, false), (None, (641, 361)));
+		// Width or height, keeping the aspect ratio, and both.
+		assert_eq!(size((640, 360), &|o| o.width = Some(101), false), (None, (100, 56)));
+		assert_eq!(size((640, 360), &|o| o.width = Some(101), true), (None, (101, 57)));
+		assert_eq!(size((640, 360), &|o| o.height = Some(99), false), (None, (176, 98)));
+		assert_eq!(size((640, 360), &|o| o.height = Some(99), true), (None, (176, 99)));
+		assert_eq!(size((640, 360), &|o| { o.width = Some(321); o.height = Some(239); }, false), (None, (320, 238)));
+		assert_eq!(size((640, 360), &|o| { o.width = Some(321); o.height = Some(239); }, true), (None, (321, 239)));
+		// Scales are always even.
+		assert_eq!(size((641, 361), &|o| o.scale = Some(crate::Scale(0.5)), true), (None, (320, 180)));
+		// Crops, recentered after rounding, then scaled from what's left.
+		assert_eq!(size((641, 361), &|o| o.aspect = Some(Aspect::SQUARE), false), (Some((360, 360, 140, 0)), (360, 360)));
+		assert_eq!(size((641, 361), &|o| o.aspect = Some(Aspect::SQUARE), true), (Some((361, 361, 140, 0)), (361, 361)));
+		assert_eq!(size((640, 360), &|o| { o.aspect = Some(Aspect::SQUARE); o.width = Some(129); }, false), (Some((360, 360, 140, 0)), (128, 128)));
+		// Never below 2.
+		assert_eq!(size((640, 360), &|o| o.height = Some(1), false), (None, (2, 2)));
+	}
+
+	#[test]
+	fn crop_comes_before_scaling() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.aspect = Some(Aspect::SQUARE);
+		opt.width = Some(128);
+		let mut extraction = Extraction::new(&opt).unwrap();
+		extraction.resize(&opt, &crate::probe::test_video(640, 360)).unwrap();
+		assert_eq!(extraction.filters, ["crop=360:360:140:0", "scale=128:128:flags=lanczos"]);
+	}
+
+	#[test]
+	fn scale_is_a_factor_of_the_cropped_size() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.aspect = Some(Aspect::SQUARE);
+		opt.scale = Some(crate::Scale(0.5));
+		let mut extraction = Extraction::new(&opt).unwrap();
+		extraction.resize(&opt, &crate::probe::test_video(640, 360)).unwrap();
+		assert_eq!(extraction.filters, ["crop=360:360:140:0", "scale=180:180:flags=lanczos"]);
+
+		opt.height = Some(100);
+		assert!(matches!(Extraction::new(&opt), Err(ConvertError::InvalidOption { option: "scale", .. })));
+	}
+
+	#[test]
+	fn loop_smooth_of_nothing_is_refused_before_extracting() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.loop_smooth = Some(0);
+		assert!(matches!(Extraction::new(&opt), Err(ConvertError::InvalidOption { option: "loop smooth", .. })));
+		opt.loop_smooth = Some(1);
+		assert!(Extraction::new(&opt).is_ok());
+	}
+
+	#[test]
+	fn fitting_an_exact_size() {
+		let filters = |fit, color| fit_filters(640, 360, fit, color).unwrap().join(",");
+		assert_eq!(filters(Fit::Stretch, "black"), "scale=640:360:flags=lanczos");
+		assert_eq!(filters(Fit::Crop, "black"), "scale=640:360:force_original_aspect_ratio=increase:flags=lanczos,crop=640:360");
+		assert_eq!(
+			filters(Fit::Pad, "white@0.5"),
+			"scale=640:360:force_original_aspect_ratio=decrease:flags=lanczos,format=rgba,pad=640:360:(ow-iw)/2:(oh-ih)/2:color=white@0.5",
+		);
+		assert!(fit_filters(640, 360, Fit::Pad, "black,drawtext=x").is_err());
+	}
+
+	#[test]
+	#[allow(clippy::float_cmp)] // Parsed, not computed.
+	fn parse_fps_reads_the_stream_info() {
+		let stderr = "Stream #0:0: Video: h264 (High), yuv420p, 1920x1080, 5000 kb/s, 29.97 fps, 29.97 tbr, 30k tbn";
+		assert_eq!(parse_fps(stderr).unwrap(), 29.97);
+		assert_eq!(parse_fps("Stream #0:0: Video: vp9, 640x480, 60 fps").unwrap(), 60.0);
+		assert!(parse_fps("Stream #0:0: Audio: mp3, 44100 Hz").is_err());
+	}
+}
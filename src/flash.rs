@@ -0,0 +1,130 @@
+//! Finding single frames much brighter or darker than the ones either side of them, like the white flash of a
+//! stream switching scenes, for `--drop-flash-frames`.
+//!
+//! ffmpeg's signalstats measures how bright each frame is on average. A frame is a flash when both its neighbours
+//! differ from it by more than the threshold, the same way, and are close to each other, so a cut to a brighter
+//! scene, which stays bright, isn't one. A flash is replaced with the frame before it, so the timing is kept.
+
+use std::{fs, path::{Path, PathBuf}};
+use regex::Regex;
+use crate::{
+	paths,
+	pattern::FramePattern,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Progress,
+	Result,
+	WarningKind,
+};
+
+/// The default for [`ConvertOptions::drop_flash_frames`](crate::ConvertOptions::drop_flash_frames): half the way
+/// from black to white, so only a real flash counts.
+pub const DEFAULT_FLASH_THRESHOLD: f64 = 0.5;
+
+/// Runs signalstats over the frames from `first` on, printing each one's average luma.
+pub(crate) fn measure_command(first: &Path, pattern: &FramePattern) -> CommandLine {
+	let (start_number, sequence) = pattern.sequence(Some(first));
+	CommandLine::new("ffmpeg")
+		.args(["-hide_banner", "-framerate", "1"])
+		.arg("-start_number").arg(start_number.to_string())
+		.arg("-i").arg(paths::for_tool(&sequence))
+		.args(["-vf", "signalstats,metadata=print:key=lavfi.signalstats.YAVG"])
+		.args(["-an", "-f", "null", "-"])
+}
+
+/// Each frame's average luma from 0 for black to 1 for white, in order. signalstats measures it in the 16 to 235
+/// of the limited range the frames are converted to.
+fn parse_luma(stderr: &str) -> Vec<f64> {
+	let luma = Regex::new(r"lavfi\.signalstats\.YAVG=([\d.]+)").unwrap();
+	luma.captures_iter(stderr)
+		.filter_map(|c| c[1].parse::<f64>().ok())
+		.map(|y| ((y - 16.0) / 219.0).clamp(0.0, 1.0))
+		.collect()
+}
+
+/// The indices of the frames in `luma` that are flashes: more than `threshold` brighter or darker than both the
+/// frame before and the one after, which are within half of it of each other. Never the first or last frame, which
+/// only have the one neighbour to tell a flash from a cut.
+pub(crate) fn flashes(luma: &[f64], threshold: f64) -> Vec<usize> {
+	(1..luma.len().saturating_sub(1))
+		.filter(|&i| {
+			let (before, frame, after) = (luma[i - 1], luma[i], luma[i + 1]);
+			let same_way = (frame - before).signum() == (frame - after).signum();
+			same_way && (frame - before).abs() > threshold && (frame - after).abs() > threshold && (before - after).abs() < threshold / 2.0
+		})
+		.collect()
+}
+
+/// Finds the flashes among `frames`, named by `pattern`, and replaces each with a copy of the frame before it,
+/// saying which were. Warns, and replaces none, if ffmpeg didn't measure them all.
+///
+/// # Errors
+/// If ffmpeg fails, or a frame can't be copied.
+pub(crate) fn drop_flashes(runner: &dyn CommandRunner, frames: &[PathBuf], pattern: &FramePattern, threshold: f64, progress: &mut dyn FnMut(Progress)) -> Result<()> {
+	let sequence = pattern.consecutive(frames)?;
+	let Some(first) = sequence.frames.first() else { return Ok(()) };
+	let command = measure_command(first, pattern);
+	log::debug!("Running: {}", &command);
+	let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	if !output.success() { return Err(ConvertError::FfmpegFailed { code: output.code, stderr: stderr.into_owned() }); }
+	let luma = parse_luma(&stderr);
+	if luma.len() != frames.len() {
+		progress(Progress::warning(WarningKind::FlashFramesUnmeasured, format!(
+			"ffmpeg's signalstats measured {} of the {} frames, so no flash frames were dropped",
+			luma.len(),
+			frames.len(),
+		)));
+		return Ok(());
+	}
+	let flashes = flashes(&luma, threshold);
+	if flashes.is_empty() {
+		log::debug!("No flash frames");
+		return Ok(());
+	}
+	for &i in &flashes {
+		fs::copy(&frames[i - 1], &frames[i]).map_err(ConvertError::io(&frames[i]))?;
+	}
+	let numbers = flashes.iter().map(|i| (i + 1).to_string()).collect::<Vec<_>>().join(", ");
+	progress(Progress::Info(format!("Replaced {} flash frames with the one before each: {numbers}", flashes.len())));
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reads_the_luma_of_each_frame() {
+		let stderr = "\
+[Parsed_metadata_1 @ 0x5581] frame:0    pts:0       pts_time:0
+[Parsed_metadata_1 @ 0x5581] lavfi.signalstats.YAVG=16
+[Parsed_metadata_1 @ 0x5581] frame:1    pts:1       pts_time:1
+[Parsed_metadata_1 @ 0x5581] lavfi.signalstats.YAVG=235.000
+[Parsed_metadata_1 @ 0x5581] frame:2    pts:2       pts_time:2
+[Parsed_metadata_1 @ 0x5581] lavfi.signalstats.YAVG=125.5
+frame=    3 fps=0.0 q=-0.0 Lsize=N/A time=00:00:03.00
+";
+		assert_eq!(parse_luma(stderr), [0.0, 1.0, 0.5]);
+	}
+
+	#[test]
+	fn single_frames_off_from_both_sides_are_flashes() {
+		let dark = [0.1, 0.12, 0.95, 0.11, 0.1, 0.02, 0.1];
+		assert_eq!(flashes(&dark, DEFAULT_FLASH_THRESHOLD), [2], "white, and the darker one isn't far enough off");
+		assert_eq!(flashes(&[0.9, 0.85, 0.05, 0.9], DEFAULT_FLASH_THRESHOLD), [2], "black in a bright scene");
+		assert_eq!(flashes(&[0.1, 0.12, 0.5, 0.11], 0.3), [2], "a lower threshold counts a dimmer one");
+		assert!(flashes(&[0.1, 0.12, 0.5, 0.11], DEFAULT_FLASH_THRESHOLD).is_empty());
+	}
+
+	#[test]
+	fn cuts_fades_and_the_ends_are_not_flashes() {
+		assert!(flashes(&[0.1, 0.1, 0.9, 0.9, 0.9], DEFAULT_FLASH_THRESHOLD).is_empty(), "a cut to a brighter scene stays bright");
+		assert!(flashes(&[0.1, 0.9, 0.4, 0.4], DEFAULT_FLASH_THRESHOLD).is_empty(), "a cut through a flash to another scene");
+		assert!(flashes(&[0.1, 0.95, 0.95, 0.1], DEFAULT_FLASH_THRESHOLD).is_empty(), "two frames long");
+		assert!(flashes(&[0.0, 0.2, 0.4, 0.6, 0.8, 1.0], DEFAULT_FLASH_THRESHOLD).is_empty(), "a fade");
+		assert!(flashes(&[0.95, 0.1, 0.1, 0.95], DEFAULT_FLASH_THRESHOLD).is_empty(), "the first and last have one neighbour");
+		assert!(flashes(&[0.1, 0.95], DEFAULT_FLASH_THRESHOLD).is_empty());
+		assert!(flashes(&[], DEFAULT_FLASH_THRESHOLD).is_empty());
+	}
+}
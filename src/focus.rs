@@ -0,0 +1,187 @@
+//! [`Focus::Auto`](crate::Focus::Auto): finding the part of the frame the motion is in, to crop to.
+//!
+//! ffmpeg decodes a few small grey copies of frames spread over what's extracted onto stdout. Where pixels change
+//! from one to the next is the motion; the box around nearly all of it, with a margin, is what's cropped to.
+
+use std::path::Path;
+use crate::{
+	disk,
+	ffmpeg::{self, Crop, Extraction},
+	probe::InputInfo,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	ConvertOptions,
+	Progress,
+	Result,
+	WarningKind,
+};
+
+/// The most frames compared, which bounds the cost whatever the length of the gif.
+const SAMPLES: u32 = 24;
+
+/// Width the frames are compared at, smaller frames are left as they are.
+const ANALYSIS_WIDTH: u32 = 160;
+
+/// How much a grey level has to change by to count as motion rather than compression noise.
+const CHANGED: u8 = 16;
+
+/// The share of the motion on each side that's left out of the box, so a few stray pixels don't stretch it.
+const OUTLIERS: f64 = 0.02;
+
+/// How much bigger than the box the region is on each side, as a share of its size.
+const MARGIN: f64 = 0.1;
+
+/// The size the frames are compared at: at most [`ANALYSIS_WIDTH`] wide, with the input's aspect ratio.
+pub(crate) fn analysis_size((width, height): (u32, u32)) -> (u32, u32) {
+	let w = width.min(ANALYSIS_WIDTH);
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let h = (f64::from(height) * f64::from(w) / f64::from(width.max(1))).round() as u32;
+	(w, h.max(1))
+}
+
+/// `ffmpeg [seeks] -i video.mp4 [-t duration] -frames:v 24 -vf fps,scale,format=gray -f rawvideo -`, up to
+/// [`SAMPLES`] grey `size` frames spread over the `seconds` that `extraction` extracts.
+pub(crate) fn sample_command(input: &Path, extraction: &Extraction, size: (u32, u32), seconds: Option<f64>) -> CommandLine {
+	let spread = seconds.filter(|&s| s > 0.0).map(|s| format!("fps={SAMPLES}/{s}"));
+	let sample = Extraction {
+		filters: extraction.filters.iter().cloned()
+			.chain(spread)
+			.chain([format!("scale={}:{}", size.0, size.1), "format=gray".to_string()])
+			.collect(),
+		max_frames: Some(SAMPLES),
+		stabilize: None,
+		start_number: None,
+		waveform: None,
+		pre_quantize: None,
+		bitexact: false,
+		..extraction.clone()
+	};
+	let mut command = ffmpeg::extract_command(input, Path::new(""), &sample);
+	// To stdout instead of the frames.
+	command.args.pop();
+	command.args(["-f", "rawvideo", "-"])
+}
+
+/// The box, `(left, top, right, bottom)` with the ends exclusive, around nearly all the pixels that change between
+/// the grey `width`x`height` `frames` one after another. `None` if nothing does.
+fn motion_box(frames: &[u8], (width, height): (usize, usize)) -> Option<(usize, usize, usize, usize)> {
+	let frames: Vec<&[u8]> = frames.chunks_exact(width * height).collect();
+	let (mut columns, mut rows) = (vec![0u64; width], vec![0u64; height]);
+	for pair in frames.windows(2) {
+		for (i, (a, b)) in pair[0].iter().zip(pair[1]).enumerate() {
+			if a.abs_diff(*b) >= CHANGED {
+				columns[i % width] += 1;
+				rows[i / width] += 1;
+			}
+		}
+	}
+	// The first and last places the motion adds up to more than the outliers.
+	let bounds = |counts: &[u64]| -> Option<(usize, usize)> {
+		let total: u64 = counts.iter().sum();
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+		let skip = (total as f64 * OUTLIERS) as u64;
+		let mut seen = 0;
+		let start = counts.iter().position(|&c| { seen += c; seen > skip })?;
+		seen = 0;
+		let end = counts.len() - counts.iter().rev().position(|&c| { seen += c; seen > skip })?;
+		Some((start, end))
+	};
+	let ((left, right), (top, bottom)) = (bounds(&columns)?, bounds(&rows)?);
+	Some((left, top, right, bottom))
+}
+
+/// `motion`, a box in the `analysis` sized frames, grown by the [`MARGIN`] and scaled up to the `full` size frame,
+/// with an even position and size and kept inside the frame.
+fn region(motion: (usize, usize, usize, usize), analysis: (u32, u32), full: (u32, u32)) -> Crop {
+	let (left, top, right, bottom) = motion;
+	#[allow(clippy::cast_precision_loss)]
+	let grown = |start: usize, end: usize, size: u32, full: u32| -> (u32, u32) {
+		let (start, end) = (start as f64, end as f64);
+		let margin = ((end - start) * MARGIN).max(1.0);
+		let scale = f64::from(full) / f64::from(size);
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		let (start, end) = (((start - margin).max(0.0) * scale).floor() as u32, ((end + margin) * scale).ceil() as u32);
+		let start = start & !1;
+		(start, ffmpeg::even(end.min(full) - start))
+	};
+	let (x, width) = grown(left, right, analysis.0, full.0);
+	let (y, height) = grown(top, bottom, analysis.1, full.1);
+	Crop { width, height, x, y }
+}
+
+/// Finds the region of `info`'s video the motion is in, over the part `extraction` extracts. `None`, with a
+/// warning, if there's no motion.
+///
+/// # Errors
+/// If ffmpeg fails, or the video's size isn't known.
+pub(crate) fn detect(
+	runner: &dyn CommandRunner,
+	opt: &ConvertOptions,
+	extraction: &Extraction,
+	info: &InputInfo,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Option<Crop>> {
+	let full = (info.video.width, info.video.height);
+	if full.0 == 0 || full.1 == 0 {
+		return Err(ConvertError::InvalidOption { option: "focus", message: "couldn't find the dimensions of the input".to_string() });
+	}
+	let analysis = analysis_size(full);
+	let command = sample_command(&opt.input, extraction, analysis, extraction.seconds(opt, info));
+	log::debug!("Running: {command}");
+	let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	if !output.success() {
+		return Err(ConvertError::FfmpegFailed { code: output.code, stderr: String::from_utf8_lossy(&output.stderr).into_owned() });
+	}
+	log::debug!("Compared {} of motion for --focus auto", disk::human_size(output.stdout.len() as u64));
+	let Some(motion) = motion_box(&output.stdout, (analysis.0 as usize, analysis.1 as usize)) else {
+		progress(Progress::warning(WarningKind::NoMotion, "--focus auto found no motion, so the whole frame is kept"));
+		return Ok(None);
+	};
+	let crop = region(motion, analysis, full);
+	let focus = crop.focus();
+	progress(Progress::Info(format!("Focusing on the motion, at {focus}. --focus {focus} picks the same region again.")));
+	Ok(Some(crop))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// `count` 8x4 frames, black but for a white pixel moving along the second row from x 2 to 5.
+	fn moving_dot(count: usize) -> Vec<u8> {
+		(0..count).flat_map(|i| {
+			let mut frame = vec![0u8; 32];
+			frame[8 + 2 + i % 4] = 255;
+			frame
+		}).collect()
+	}
+
+	#[test]
+	fn boxes_the_pixels_that_change() {
+		assert_eq!(motion_box(&moving_dot(4), (8, 4)), Some((2, 1, 6, 2)));
+		assert_eq!(motion_box(&[7; 64], (8, 4)), None, "nothing moves");
+		assert_eq!(motion_box(&moving_dot(1), (8, 4)), None, "one frame");
+	}
+
+	#[test]
+	fn the_region_is_grown_scaled_and_even() {
+		// 2..6 of 8 is grown to 1..7, which is 240..1680 of 1920.
+		assert_eq!(region((2, 1, 6, 2), (8, 4), (1920, 1080)), Crop { width: 1440, height: 810, x: 240, y: 0 });
+		// Never past the edges.
+		assert_eq!(region((0, 0, 8, 4), (8, 4), (641, 361)), Crop { width: 640, height: 360, x: 0, y: 0 });
+		assert_eq!(analysis_size((3840, 2160)), (160, 90));
+		assert_eq!(analysis_size((100, 50)), (100, 50));
+	}
+
+	#[test]
+	fn samples_are_spread_over_the_extraction() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.start = Some(10.0);
+		opt.duration = Some(6.0);
+		let extraction = Extraction::new(&opt).unwrap();
+		let command = sample_command(Path::new("in.mp4"), &extraction, (160, 90), Some(6.0));
+		assert_eq!(command.args_lossy(), [
+			"-ss", "5", "-i", "in.mp4", "-ss", "5", "-t", "6", "-frames:v", "24", "-vf", "fps=24/6,scale=160:90,format=gray", "-f", "rawvideo", "-",
+		]);
+	}
+}
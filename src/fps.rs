@@ -0,0 +1,99 @@
+//! Which fps a gif plays at when none is given.
+
+use std::fmt;
+use crate::{ffmpeg::Extraction, probe::VideoStream, ConvertOptions};
+
+/// The fps to use, and why.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Choice {
+	/// Passed with `--fps`.
+	Given(f32),
+	/// The source's, which is at most the max.
+	Source(f32),
+	/// The source is faster than the max, so it's resampled down to it.
+	Capped { source: f32, fps: f32 },
+	/// ffprobe didn't say, so it's left to ffmpeg's output.
+	Unknown,
+}
+
+/// Picks `given` if there is one, otherwise the `source` fps capped at `max`.
+pub(crate) fn choose(source: Option<f32>, given: Option<f32>, max: f32) -> Choice {
+	match (given, source) {
+		(Some(fps), _) => Choice::Given(fps),
+		(None, Some(source)) if source > max => Choice::Capped { source, fps: max },
+		(None, Some(source)) => Choice::Source(source),
+		(None, None) => Choice::Unknown,
+	}
+}
+
+/// Resamples `extraction` down if [`choose`] caps the source fps. `None` if something else already picks the fps.
+pub(crate) fn apply(extraction: &mut Extraction, opt: &ConvertOptions, video: &VideoStream) -> Option<Choice> {
+	if opt.keyframes_only || extraction.fps.is_some() { return None; }
+	let choice = choose(video.fps(), opt.fps, opt.max_auto_fps);
+	if let Choice::Capped { fps, .. } = choice { extraction.resample(fps); }
+	Some(choice)
+}
+
+/// How far off, as a fraction, the fps the extracted frames play at can be from the detected one before it's used
+/// instead.
+const MEASURED_TOLERANCE: f64 = 0.05;
+
+/// The fps `frame_count` frames extracted from `seconds` of the input play at, if it's too far off the `detected`
+/// one to be rounding, which it is by a frame or so at either end.
+pub(crate) fn measured(detected: f32, frame_count: usize, seconds: f64) -> Option<f32> {
+	if frame_count == 0 || seconds <= 0.0 { return None; }
+	#[allow(clippy::cast_precision_loss)]
+	let measured = frame_count as f64 / seconds;
+	let slack = (f64::from(detected) * MEASURED_TOLERANCE).max(1.5 / seconds);
+	if (measured - f64::from(detected)).abs() <= slack { return None; }
+	#[allow(clippy::cast_possible_truncation)] // gifski takes an f32.
+	Some(((measured * 1000.0).round() / 1000.0) as f32)
+}
+
+impl fmt::Display for Choice {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Choice::Given(fps) => write!(f, "using {fps}fps from --fps"),
+			Choice::Source(fps) => write!(f, "using the source's {fps}fps"),
+			Choice::Capped { source, fps } => write!(f, "source {source}fps → using {fps}fps default, pass --fps {source} to keep"),
+			Choice::Unknown => write!(f, "the source fps isn't known yet, using whatever ffmpeg finds"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn given_beats_source_beats_nothing() {
+		assert_eq!(choose(Some(60.0), Some(45.0), 30.0), Choice::Given(45.0));
+		assert_eq!(choose(None, Some(12.0), 30.0), Choice::Given(12.0));
+		assert_eq!(choose(Some(24.0), None, 30.0), Choice::Source(24.0));
+		assert_eq!(choose(Some(30.0), None, 30.0), Choice::Source(30.0));
+		assert_eq!(choose(Some(60.0), None, 30.0), Choice::Capped { source: 60.0, fps: 30.0 });
+		assert_eq!(choose(None, None, 30.0), Choice::Unknown);
+	}
+
+	#[test]
+	fn the_measured_fps_wins_when_its_far_off() {
+		assert_eq!(measured(25.0, 250, 10.0), None);
+		assert_eq!(measured(25.0, 251, 10.0), None, "a frame more at the end");
+		assert_eq!(measured(30.0, 299, 10.0), None, "29.97 reported as 30");
+		assert_eq!(measured(1000.0, 240, 10.0), Some(24.0), "nonsense from the container");
+		assert_eq!(measured(24.0, 500, 10.0), Some(50.0));
+		assert_eq!(measured(24.0, 100, 3.0), Some(33.333));
+	}
+
+	#[test]
+	fn few_frames_are_rounding() {
+		assert_eq!(measured(24.0, 3, 0.1), None, "30fps, but a frame either way is 10");
+		assert_eq!(measured(24.0, 0, 2.0), None);
+		assert_eq!(measured(24.0, 48, 0.0), None);
+	}
+
+	#[test]
+	fn says_why() {
+		assert_eq!(choose(Some(60.0), None, 30.0).to_string(), "source 60fps → using 30fps default, pass --fps 60 to keep");
+	}
+}
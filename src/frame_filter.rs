@@ -0,0 +1,471 @@
+//! [`ConvertOptions::frame_filter`]: a command of the user's own run over the extracted frames before they're
+//! encoded, changing them in place.
+//!
+//! Per frame, up to [`ConvertOptions::jobs`] of them run at once. Each is [spawned](CommandRunner::spawn), its
+//! output drained as it goes and waited on by a thread of its own, so the next starts as soon as any one of them is
+//! done and a busy one never blocks on a full pipe. In batch
+//! mode the frames are split into runs that fit in a command line, run one after another like xargs does.
+
+use std::{
+	io::{self, Read},
+	num::NonZeroUsize,
+	path::PathBuf,
+	sync::mpsc,
+	thread::{self, JoinHandle},
+};
+use crate::{
+	paths,
+	runner::{CommandLine, CommandRunner, KillHandle, RunningCommand},
+	ConvertError,
+	ConvertOptions,
+	FrameFilter,
+	FrameFilterMode,
+	Progress,
+	Result,
+};
+
+/// Where the frame goes in the command.
+const PLACEHOLDER: &str = "{}";
+
+/// How much of a failed command its error shows.
+const COMMAND_SHOWN: usize = 200;
+
+fn invalid(message: impl Into<String>) -> ConvertError {
+	ConvertError::InvalidOption { option: "frame filter", message: message.into() }
+}
+
+/// Checks the command can be split and run, and isn't combined with options that hand the frames to gifski as
+/// they're extracted.
+///
+/// # Errors
+/// With an empty or unsplittable command, [`ConvertOptions::jobs`] of 0, [`ConvertOptions::overlap`] or
+/// [`ConvertOptions::chunk_seconds`].
+pub(crate) fn check(opt: &ConvertOptions) -> Result<()> {
+	if opt.jobs == Some(0) { return Err(ConvertError::InvalidOption { option: "jobs", message: "must be at least 1".to_string() }); }
+	let Some(filter) = &opt.frame_filter else { return Ok(()) };
+	if opt.overlap || opt.chunk_seconds.is_some() {
+		return Err(invalid("can't be combined with --overlap or --chunk-seconds, gifski has the frames before they could be filtered"));
+	}
+	if filter.command.trim().is_empty() { return Err(invalid("the command is empty")); }
+	if !filter.shell { split(&filter.command)?; }
+	Ok(())
+}
+
+/// Splits `command` into words the way a POSIX shell would, without running one: whitespace separates them,
+/// single quotes keep everything, double quotes keep everything but backslashed `"`, `\` and `$`, and a backslash
+/// outside quotes keeps the next character.
+///
+/// # Errors
+/// If a quote isn't closed or it ends in a backslash.
+pub(crate) fn split(command: &str) -> Result<Vec<String>> {
+	let mut words = Vec::new();
+	let mut word: Option<String> = None;
+	let mut chars = command.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			c if c.is_whitespace() => words.extend(word.take()),
+			'\'' => {
+				let word = word.get_or_insert_with(String::new);
+				loop {
+					match chars.next() {
+						Some('\'') => break,
+						Some(c) => word.push(c),
+						None => return Err(invalid(format!("{command:?} has a ' that isn't closed"))),
+					}
+				}
+			}
+			'"' => {
+				let word = word.get_or_insert_with(String::new);
+				loop {
+					match chars.next() {
+						Some('"') => break,
+						Some('\\') => match chars.next() {
+							Some(c @ ('"' | '\\' | '$')) => word.push(c),
+							Some(c) => word.extend(['\\', c]),
+							None => return Err(invalid(format!("{command:?} has a \" that isn't closed"))),
+						},
+						Some(c) => word.push(c),
+						None => return Err(invalid(format!("{command:?} has a \" that isn't closed"))),
+					}
+				}
+			}
+			'\\' => match chars.next() {
+				Some(c) => word.get_or_insert_with(String::new).push(c),
+				None => return Err(invalid(format!("{command:?} ends in a \\"))),
+			},
+			c => word.get_or_insert_with(String::new).push(c),
+		}
+	}
+	words.extend(word);
+	Ok(words)
+}
+
+/// How much of a command line a [`FrameFilterMode::Batch`] command's frames can take, like xargs keeps to: bytes of
+/// arguments on Unix, well under `ARG_MAX` to leave room for the environment, or characters on Windows, where
+/// `CreateProcess` takes 32,767.
+const COMMAND_LINE_MAX: usize = if cfg!(windows) { 32_767 } else { 128 * 1024 };
+
+/// How long a line `cmd /C` takes, script and all.
+const CMD_LINE_MAX: usize = 8_191;
+
+/// How much of [`COMMAND_LINE_MAX`] `arg` takes: its bytes, the NUL after it and the pointer to it on Unix, or its
+/// characters, and the quotes and space around them, on Windows.
+fn arg_len(arg: &str) -> usize {
+	if cfg!(windows) { arg.encode_utf16().count() + 3 } else { arg.len() + 1 + size_of::<usize>() }
+}
+
+/// `args`, one after another, split into runs that each fit in `room` by `len`, like xargs does. One that doesn't fit
+/// on its own gets a run anyway, for the command to fail on.
+fn chunks(args: &[String], room: usize, len: impl Fn(&str) -> usize) -> Vec<&[String]> {
+	let mut chunks = Vec::new();
+	let (mut start, mut used) = (0, 0);
+	for (i, arg) in args.iter().enumerate() {
+		let taken = len(arg);
+		if i > start && used + taken > room {
+			chunks.push(&args[start..i]);
+			(start, used) = (i, 0);
+		}
+		used += taken;
+	}
+	if start < args.len() { chunks.push(&args[start..]); }
+	chunks
+}
+
+/// The `sh -c` commands, or `cmd /C` ones on Windows, to run `filter` over `paths` with.
+///
+/// `sh` gets the frames as arguments of its own, `"$@"` in the script, so they don't need quoting and don't count
+/// towards how long one argument can be. `cmd` doesn't take any, so they're quoted into the script.
+fn shell_commands(filter: &FrameFilter, paths: &[String]) -> Vec<CommandLine> {
+	let with = |frames: &str| if filter.command.contains(PLACEHOLDER) {
+		filter.command.replace(PLACEHOLDER, frames)
+	} else {
+		format!("{} {frames}", filter.command)
+	};
+	let batch = filter.mode == FrameFilterMode::Batch;
+	if cfg!(windows) {
+		let quoted: Vec<String> = paths.iter().map(|p| format!("\"{p}\"")).collect();
+		let copies = filter.command.matches(PLACEHOLDER).count().max(1);
+		let room = CMD_LINE_MAX.saturating_sub("cmd /C ".len() + with("").chars().count());
+		let runs = if batch { chunks(&quoted, room, |q| (q.chars().count() + 1) * copies) } else { quoted.chunks(1).collect() };
+		return runs.into_iter().map(|run| CommandLine::new("cmd").arg("/C").arg(with(&run.join(" ")))).collect();
+	}
+	let script = with("\"$@\"");
+	let room = COMMAND_LINE_MAX.saturating_sub(["sh", "-c", &script, "sh"].map(arg_len).iter().sum());
+	let runs = if batch { chunks(paths, room, arg_len) } else { paths.chunks(1).collect() };
+	// The `sh` after the script is `$0`, what it calls itself in errors.
+	runs.into_iter().map(|run| CommandLine::new("sh").arg("-c").arg(&script).arg("sh").args(run)).collect()
+}
+
+/// The commands to run for `frames`, one per frame, or as few as the frames fit in for
+/// [`FrameFilterMode::Batch`].
+///
+/// # Errors
+/// If the command can't be [split](split), or in batch mode has `{}` inside a longer word.
+pub(crate) fn commands(filter: &FrameFilter, frames: &[PathBuf]) -> Result<Vec<CommandLine>> {
+	let paths: Vec<String> = frames.iter().map(|f| paths::for_tool(f).to_string_lossy().into_owned()).collect();
+	if filter.shell { return Ok(shell_commands(filter, &paths)); }
+
+	let words = split(&filter.command)?;
+	let Some((program, args)) = words.split_first() else { return Err(invalid("the command is empty")) };
+	let has_placeholder = args.iter().any(|a| a.contains(PLACEHOLDER));
+	Ok(match filter.mode {
+		FrameFilterMode::PerFrame => paths.iter().map(|path| {
+			let command = CommandLine::new(program).args(args.iter().map(|a| a.replace(PLACEHOLDER, path)));
+			if has_placeholder { command } else { command.arg(path) }
+		}).collect(),
+		FrameFilterMode::Batch => {
+			if args.iter().any(|a| a.contains(PLACEHOLDER) && a != PLACEHOLDER) {
+				return Err(invalid("with --frame-filter-mode batch, {} has to be an argument of its own, it's every frame"));
+			}
+			let copies = args.iter().filter(|a| *a == PLACEHOLDER).count().max(1);
+			let room = COMMAND_LINE_MAX.saturating_sub(words.iter().filter(|w| *w != PLACEHOLDER).map(|w| arg_len(w)).sum());
+			chunks(&paths, room, |p| arg_len(p) * copies).into_iter().map(|run| {
+				let mut command = CommandLine::new(program);
+				for arg in args {
+					command = if arg == PLACEHOLDER { command.args(run) } else { command.arg(arg) };
+				}
+				if has_placeholder { command } else { command.args(run) }
+			}).collect()
+		}
+	})
+}
+
+/// A command that's running, with its stderr read on another thread.
+struct Started<'c> {
+	command: &'c CommandLine,
+	child: Box<dyn RunningCommand>,
+	stderr: Option<JoinHandle<Vec<u8>>>,
+}
+
+fn start<'c>(runner: &dyn CommandRunner, command: &'c CommandLine) -> Result<Started<'c>> {
+	log::debug!("Running: {command}");
+	let mut child = runner.spawn(command).map_err(|source| ConvertError::FrameFilterNotStarted { program: command.program_name(), source })?;
+	drop(child.take_stdin());
+	// Thrown away, but read so the command never waits on it.
+	if let Some(mut stdout) = child.take_stdout() { thread::spawn(move || io::copy(&mut stdout, &mut io::sink())); }
+	let stderr = child.take_stderr().map(|mut pipe| thread::spawn(move || {
+		let mut text = Vec::new();
+		let _ = pipe.read_to_end(&mut text);
+		text
+	}));
+	Ok(Started { command, child, stderr })
+}
+
+fn finish(started: Started) -> Result<()> {
+	let Started { command, child, stderr } = started;
+	let output = child.wait().map_err(ConvertError::io(command.program_name()))?;
+	if output.success() { return Ok(()); }
+	let stderr = stderr.and_then(|s| s.join().ok()).unwrap_or(output.stderr);
+	// A batch one has every frame in it.
+	let mut command = command.to_string();
+	if let Some((cut, _)) = command.char_indices().nth(COMMAND_SHOWN) {
+		command.truncate(cut);
+		command.push_str(" ...");
+	}
+	Err(ConvertError::FrameFilterFailed { command, code: output.code, stderr: String::from_utf8_lossy(&stderr).into_owned() })
+}
+
+/// Runs `commands` in order, at most `jobs` at once, starting the next whenever any of them is done. On the first that
+/// fails the ones still running are killed, none are started after it, and its error is returned.
+///
+/// # Errors
+/// If a command can't be started or exits unsuccessfully.
+pub(crate) fn run_all(runner: &dyn CommandRunner, commands: &[CommandLine], jobs: usize) -> Result<()> {
+	let (done, finished) = mpsc::channel();
+	thread::scope(|scope| {
+		// By index, with what kills them.
+		let mut running = Vec::new();
+		let mut next = commands.iter().enumerate();
+		let mut failed = None;
+		loop {
+			while failed.is_none() && running.len() < jobs.max(1) {
+				let Some((i, command)) = next.next() else { break };
+				match start(runner, command) {
+					Ok(started) => {
+						running.push((i, started.child.kill_handle()));
+						let done = done.clone();
+						scope.spawn(move || done.send((i, finish(started))));
+					}
+					Err(e) => failed = Some(stop(&running, e)),
+				}
+			}
+			if running.is_empty() { return failed.map_or(Ok(()), Err); }
+			let (i, result) = finished.recv().expect("each running command has a thread to send its result");
+			running.retain(|&(r, _)| r != i);
+			if let Err(e) = result { failed = failed.or_else(|| Some(stop(&running, e))); }
+		}
+	})
+}
+
+/// Kills the `running` commands, which their threads then wait for, and hands back `error`.
+fn stop(running: &[(usize, Option<KillHandle>)], error: ConvertError) -> ConvertError {
+	for kill in running.iter().filter_map(|(_, kill)| kill.as_ref()) { kill(); }
+	error
+}
+
+/// Runs `filter` over `frames`, [`ConvertOptions::jobs`] at a time or one per CPU, or in batch mode one run of them
+/// after another, the way xargs does.
+///
+/// # Errors
+/// See [`commands`] and [`run_all`].
+pub(crate) fn filter_frames(
+	runner: &dyn CommandRunner,
+	filter: &FrameFilter,
+	frames: &[PathBuf],
+	jobs: Option<usize>,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<()> {
+	if frames.is_empty() { return Ok(()); }
+	let commands = commands(filter, frames)?;
+	let jobs = match filter.mode {
+		FrameFilterMode::PerFrame => jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get)),
+		FrameFilterMode::Batch => 1,
+	};
+	progress(Progress::Info(match filter.mode {
+		FrameFilterMode::PerFrame => format!("Filtering {} frames with {}, {jobs} at a time", frames.len(), filter.command),
+		FrameFilterMode::Batch if commands.len() > 1 => {
+			format!("Filtering {} frames with {}, in {} runs to fit the command line", frames.len(), filter.command, commands.len())
+		}
+		FrameFilterMode::Batch => format!("Filtering {} frames with {}", frames.len(), filter.command),
+	}));
+	run_all(runner, &commands, jobs)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	};
+	use crate::runner::{CommandOutput, KillHandle, MockRunner};
+	use super::*;
+
+	fn frames(count: usize) -> Vec<PathBuf> {
+		(1..=count).map(|i| PathBuf::from(format!("/tmp/frames/frame{i:04}.png"))).collect()
+	}
+
+	fn filter(command: &str, mode: FrameFilterMode) -> FrameFilter {
+		FrameFilter { command: command.to_string(), mode, shell: false }
+	}
+
+	#[test]
+	fn splits_like_a_shell() {
+		assert_eq!(split("blur  --region '0 0 100 20' {}").unwrap(), ["blur", "--region", "0 0 100 20", "{}"]);
+		assert_eq!(split(r#"a "b \"c\" \d" e\ f '' x'y'z"#).unwrap(), ["a", r#"b "c" \d"#, "e f", "", "xyz"]);
+		assert_eq!(split("  ").unwrap(), Vec::<String>::new());
+		for bad in ["a 'b", "a \"b", "a \\"] {
+			assert!(split(bad).is_err(), "{bad:?}");
+		}
+	}
+
+	#[test]
+	fn the_frame_goes_where_the_braces_are() {
+		let args = |command: &str, mode| commands(&filter(command, mode), &frames(2)).unwrap().iter().map(|c| (c.program_name(), c.args_lossy())).collect::<Vec<_>>();
+		assert_eq!(args("redact --in={} -o {}", FrameFilterMode::PerFrame), [
+			("redact".to_string(), vec!["--in=/tmp/frames/frame0001.png".to_string(), "-o".to_string(), "/tmp/frames/frame0001.png".to_string()]),
+			("redact".to_string(), vec!["--in=/tmp/frames/frame0002.png".to_string(), "-o".to_string(), "/tmp/frames/frame0002.png".to_string()]),
+		]);
+		assert_eq!(args("redact", FrameFilterMode::PerFrame)[1].1, ["/tmp/frames/frame0002.png"], "at the end without braces");
+		assert_eq!(args("mogrify -blur 2 {} --done", FrameFilterMode::Batch), [
+			("mogrify".to_string(), ["-blur", "2", "/tmp/frames/frame0001.png", "/tmp/frames/frame0002.png", "--done"].map(String::from).to_vec()),
+		]);
+		assert!(commands(&filter("mogrify --in={}", FrameFilterMode::Batch), &frames(2)).is_err());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn shell_commands_get_quoted_frames() {
+		let shell = FrameFilter { shell: true, ..filter("convert {} -blur 2 {} | tee -a log", FrameFilterMode::PerFrame) };
+		let commands = commands(&shell, &[PathBuf::from("/tmp/it's/frame0001.png")]).unwrap();
+		assert_eq!(commands[0].program_name(), "sh");
+		assert_eq!(commands[0].args_lossy(), ["-c", r#"convert "$@" -blur 2 "$@" | tee -a log"#, "sh", "/tmp/it's/frame0001.png"]);
+		let batch = FrameFilter { shell: true, ..filter("mogrify", FrameFilterMode::Batch) };
+		assert_eq!(super::commands(&batch, &frames(2)).unwrap()[0].args_lossy(), [
+			"-c", r#"mogrify "$@""#, "sh", "/tmp/frames/frame0001.png", "/tmp/frames/frame0002.png",
+		]);
+	}
+
+	#[test]
+	fn batches_too_long_for_one_command_line_are_split_like_xargs() {
+		let frames = frames(9_000);
+		for shell in [false, true] {
+			let batch = FrameFilter { shell, ..filter("mogrify -blur 2", FrameFilterMode::Batch) };
+			let commands = commands(&batch, &frames).unwrap();
+			assert!(commands.len() > 1, "{} frames in one", frames.len());
+			let limit = if cfg!(windows) && shell { CMD_LINE_MAX } else { COMMAND_LINE_MAX };
+			for command in &commands {
+				let length = if cfg!(windows) {
+					command.to_string().chars().count()
+				} else {
+					command.args_lossy().iter().chain([&command.program_name()]).map(|a| arg_len(a)).sum()
+				};
+				assert!(length <= limit, "{length} past {limit}");
+			}
+			let passed: Vec<String> = commands.iter().flat_map(CommandLine::args_lossy).filter(|a| a.contains("frame")).collect();
+			assert_eq!(passed.len(), frames.len(), "every frame once, shell {shell}");
+			assert!(passed.windows(2).all(|pair| pair[0] < pair[1]), "in order");
+		}
+		assert_eq!(chunks(&["a".repeat(10), "b".to_string(), "c".to_string()], 4, str::len), [&["a".repeat(10)][..], &["b".to_string(), "c".to_string()][..]]);
+	}
+
+	#[test]
+	fn the_first_failure_stops_the_rest() {
+		let runner = MockRunner::new();
+		runner.respond("redact", CommandOutput::ok_with_stderr(""));
+		runner.respond("redact", CommandOutput::failed(3, "frame0002.png: no text found"));
+		let commands = commands(&filter("redact {}", FrameFilterMode::PerFrame), &frames(6)).unwrap();
+
+		let error = run_all(&runner, &commands, 2).unwrap_err();
+
+		assert_eq!(error.to_string(), "The frame filter redact /tmp/frames/frame0002.png exited with code 3:\nframe0002.png: no text found");
+		// The third starts once the first is done, if that's before the second fails.
+		assert!((2..=3).contains(&runner.calls().len()), "none were started after");
+		assert!(run_all(&MockRunner::new(), &commands, 4).is_ok());
+	}
+
+	/// Counts how many of its commands are running at once.
+	#[derive(Default)]
+	struct CountingRunner {
+		running: Arc<AtomicUsize>,
+		most: Arc<AtomicUsize>,
+		started: AtomicUsize,
+	}
+
+	struct CountedChild(Arc<AtomicUsize>);
+
+	impl RunningCommand for CountedChild {
+		fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> { None }
+		fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> { None }
+		fn take_stdin(&mut self) -> Option<Box<dyn io::Write + Send>> { None }
+		fn wait(self: Box<Self>) -> io::Result<CommandOutput> {
+			self.0.fetch_sub(1, Ordering::SeqCst);
+			Ok(CommandOutput::ok_with_stderr(""))
+		}
+		fn kill_handle(&self) -> Option<KillHandle> { None }
+	}
+
+	impl CommandRunner for CountingRunner {
+		fn run(&self, _: &CommandLine) -> io::Result<CommandOutput> { unreachable!("frame filters are spawned") }
+		fn spawn(&self, _: &CommandLine) -> io::Result<Box<dyn RunningCommand>> {
+			self.started.fetch_add(1, Ordering::SeqCst);
+			let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+			self.most.fetch_max(now, Ordering::SeqCst);
+			Ok(Box::new(CountedChild(Arc::clone(&self.running))))
+		}
+	}
+
+	/// Its first command runs until the rest have started, or a few seconds have gone by.
+	#[derive(Default)]
+	struct SlowFirstRunner {
+		started: Arc<AtomicUsize>,
+		/// How many had started when the first was done.
+		seen: Arc<AtomicUsize>,
+	}
+
+	struct SlowChild {
+		total: usize,
+		started: Arc<AtomicUsize>,
+		seen: Arc<AtomicUsize>,
+	}
+
+	impl RunningCommand for SlowChild {
+		fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> { None }
+		fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> { None }
+		fn take_stdin(&mut self) -> Option<Box<dyn io::Write + Send>> { None }
+		fn wait(self: Box<Self>) -> io::Result<CommandOutput> {
+			let since = std::time::Instant::now();
+			while self.started.load(Ordering::SeqCst) < self.total && since.elapsed().as_secs() < 5 {
+				thread::sleep(std::time::Duration::from_millis(1));
+			}
+			self.seen.store(self.started.load(Ordering::SeqCst), Ordering::SeqCst);
+			Ok(CommandOutput::ok_with_stderr(""))
+		}
+	}
+
+	impl CommandRunner for SlowFirstRunner {
+		fn run(&self, _: &CommandLine) -> io::Result<CommandOutput> { unreachable!("frame filters are spawned") }
+		fn spawn(&self, _: &CommandLine) -> io::Result<Box<dyn RunningCommand>> {
+			if self.started.fetch_add(1, Ordering::SeqCst) > 0 { return Ok(Box::new(CountedChild(Arc::new(AtomicUsize::new(1))))); }
+			Ok(Box::new(SlowChild { total: 10, started: Arc::clone(&self.started), seen: Arc::clone(&self.seen) }))
+		}
+	}
+
+	#[test]
+	fn a_slow_command_holds_up_only_its_own_job() {
+		let commands = commands(&filter("redact", FrameFilterMode::PerFrame), &frames(10)).unwrap();
+		let runner = SlowFirstRunner::default();
+		run_all(&runner, &commands, 2).unwrap();
+		assert_eq!(runner.seen.load(Ordering::SeqCst), 10, "the rest ran in the other job while the first did");
+	}
+
+	#[test]
+	fn runs_as_many_at_once_as_there_are_jobs() {
+		let commands = commands(&filter("redact", FrameFilterMode::PerFrame), &frames(10)).unwrap();
+		for (jobs, most) in [(3, 3), (1, 1), (20, 10)] {
+			let runner = CountingRunner::default();
+			run_all(&runner, &commands, jobs).unwrap();
+			assert_eq!((runner.started.load(Ordering::SeqCst), runner.most.load(Ordering::SeqCst)), (10, most), "{jobs} jobs");
+			assert_eq!(runner.running.load(Ordering::SeqCst), 0, "all waited for");
+		}
+	}
+}
@@ -0,0 +1,229 @@
+//! The little bit of the GIF format needed to add to gifski's output.
+
+/// Size of the color table a GIF flags byte with the table bit set says follows.
+fn color_table_len(flags: u8) -> usize {
+	if flags & 0x80 != 0 { 3 * (1 << ((flags & 0x07) + 1)) } else { 0 }
+}
+
+/// Where the first block after the header, screen descriptor and global color table is.
+fn blocks_start(gif: &[u8]) -> Option<usize> {
+	if !(gif.starts_with(b"GIF89a") || gif.starts_with(b"GIF87a")) || gif.len() < 13 { return None; }
+	let start = 13 + color_table_len(gif[10]);
+	(start <= gif.len()).then_some(start)
+}
+
+/// Returns the GIF with a comment extension holding `comment` before its first block.
+///
+/// Comments are a `GIF89a` feature, so an 87a header is upgraded. `None` if `gif` isn't a GIF.
+pub(crate) fn insert_comment(gif: &[u8], comment: &str) -> Option<Vec<u8>> {
+	let start = blocks_start(gif)?;
+	let mut out = Vec::with_capacity(gif.len() + comment.len() + comment.len() / 255 + 4);
+	out.extend_from_slice(&gif[..start]);
+	out[..6].copy_from_slice(b"GIF89a");
+	out.extend_from_slice(&[0x21, 0xFE]);
+	// Data sub-blocks hold at most 255 bytes each, an empty one ends the extension.
+	for chunk in comment.as_bytes().chunks(255) {
+		out.push(u8::try_from(chunk.len()).ok()?);
+		out.extend_from_slice(chunk);
+	}
+	out.push(0);
+	out.extend_from_slice(&gif[start..]);
+	Some(out)
+}
+
+/// Where the data sub-blocks starting at `i` end, after the empty one.
+fn sub_blocks_end(gif: &[u8], mut i: usize) -> Option<usize> {
+	while *gif.get(i)? != 0 { i += usize::from(gif[i]) + 1; }
+	Some(i + 1)
+}
+
+/// The frames of every GIF in `gifs` one after another, in one GIF with the first one's screen, loop count and
+/// comments.
+///
+/// The later GIFs' frames that use their own global color table get it as a local one. `None` if one of them isn't a
+/// whole GIF, or has another size than the first.
+pub(crate) fn concat(gifs: &[Vec<u8>]) -> Option<Vec<u8>> {
+	let first = gifs.first()?;
+	let mut out = first[..blocks_start(first)?].to_vec();
+	for (n, gif) in gifs.iter().enumerate() {
+		let mut i = blocks_start(gif)?;
+		if gif[6..10] != first[6..10] { return None; }
+		let global_table = &gif[13..i];
+		loop {
+			match *gif.get(i)? {
+				0x21 => {
+					let end = sub_blocks_end(gif, i + 2)?;
+					// Only the first one's loop count and comments, the rest would repeat them.
+					let label = *gif.get(i + 1)?;
+					if n == 0 || !matches!(label, 0xFF | 0xFE) { out.extend_from_slice(&gif[i..end]); }
+					i = end;
+				}
+				0x2C => {
+					let descriptor = gif.get(i..i + 10)?;
+					let flags = descriptor[9];
+					out.extend_from_slice(&descriptor[..9]);
+					if n > 0 && flags & 0x80 == 0 && !global_table.is_empty() {
+						out.push(flags | 0x80 | (gif[10] & 0x07));
+						out.extend_from_slice(global_table);
+					} else {
+						out.push(flags);
+					}
+					let table_end = i + 10 + color_table_len(flags);
+					// The local color table, then the LZW code size and the image data.
+					let end = sub_blocks_end(gif, table_end + 1)?;
+					out.extend_from_slice(gif.get(i + 10..end)?);
+					i = end;
+				}
+				0x3B => break,
+				_ => return None,
+			}
+		}
+	}
+	out.push(0x3B);
+	Some(out)
+}
+
+/// Whether `gif` is a whole GIF, with every block there up to the trailer.
+pub(crate) fn is_whole(gif: &[u8]) -> bool {
+	concat(&[gif.to_vec()]).is_some()
+}
+
+/// Where the delay of each graphic control extension is, in order, one per frame in gifski's. `None` if `gif` isn't
+/// a whole GIF.
+fn delay_offsets(gif: &[u8]) -> Option<Vec<usize>> {
+	let mut i = blocks_start(gif)?;
+	let mut offsets = Vec::new();
+	loop {
+		match *gif.get(i)? {
+			0x21 => {
+				if gif.get(i + 1..i + 3)? == [0xF9, 0x04] { offsets.push(i + 4); }
+				i = sub_blocks_end(gif, i + 2)?;
+			}
+			0x2C => {
+				let flags = *gif.get(i + 9)?;
+				i = sub_blocks_end(gif, i + 10 + color_table_len(flags) + 1)?;
+			}
+			0x3B => return Some(offsets),
+			_ => return None,
+		}
+	}
+}
+
+/// Each frame's delay, in centiseconds. `None` if `gif` isn't a whole GIF.
+pub(crate) fn delays(gif: &[u8]) -> Option<Vec<u16>> {
+	Some(delay_offsets(gif)?.into_iter().map(|i| u16::from_le_bytes([gif[i], gif[i + 1]])).collect())
+}
+
+/// Returns the GIF with its frames' delays set to `delays`, in centiseconds. `None` if `gif` isn't a whole GIF, or
+/// has another number of frames.
+pub(crate) fn set_delays(gif: &[u8], delays: &[u16]) -> Option<Vec<u8>> {
+	let offsets = delay_offsets(gif)?;
+	if offsets.len() != delays.len() { return None; }
+	let mut out = gif.to_vec();
+	for (i, delay) in offsets.into_iter().zip(delays) {
+		out[i..i + 2].copy_from_slice(&delay.to_le_bytes());
+	}
+	Some(out)
+}
+
+/// Every comment in the GIF, in order.
+#[cfg(test)]
+pub(crate) fn comments(gif: &[u8]) -> Vec<String> {
+	let sub_blocks = |mut i: usize| {
+		let mut data = Vec::new();
+		while gif[i] != 0 {
+			data.extend_from_slice(&gif[i + 1..=i + gif[i] as usize]);
+			i += gif[i] as usize + 1;
+		}
+		(data, i + 1)
+	};
+	let mut comments = Vec::new();
+	let mut i = blocks_start(gif).expect("not a gif");
+	loop {
+		match gif[i] {
+			0x21 => {
+				let (data, next) = sub_blocks(i + 2);
+				if gif[i + 1] == 0xFE { comments.push(String::from_utf8(data).unwrap()); }
+				i = next;
+			}
+			0x2C => {
+				i += 10 + color_table_len(gif[i + 9]);
+				i = sub_blocks(i + 1).1;
+			}
+			0x3B => return comments,
+			b => panic!("unexpected block {b:#x} at {i}"),
+		}
+	}
+}
+
+/// A 1x1 transparent GIF.
+#[cfg(test)]
+pub(crate) const TEST_GIF: &[u8] = &[
+	0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
+	0x00, 0x00, 0x00, 0x21, 0xF9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2C, 0x00, 0x00, 0x00, 0x00,
+	0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3B,
+];
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn comment_goes_after_the_color_table() {
+		let gif = insert_comment(TEST_GIF, "hi").unwrap();
+		assert_eq!(&gif[..19], &TEST_GIF[..19]);
+		assert_eq!(&gif[19..25], [0x21, 0xFE, 2, b'h', b'i', 0]);
+		assert_eq!(&gif[25..], &TEST_GIF[19..]);
+		assert_eq!(comments(&gif), ["hi"]);
+	}
+
+	#[test]
+	fn long_comments_are_split_into_sub_blocks() {
+		let long = "x".repeat(600);
+		let gif = insert_comment(TEST_GIF, &long).unwrap();
+		assert_eq!(gif.len(), TEST_GIF.len() + 2 + 3 + 600 + 1);
+		assert_eq!(comments(&gif), [long]);
+	}
+
+	#[test]
+	fn concatenated_frames_get_their_color_table() {
+		let commented = insert_comment(TEST_GIF, "second").unwrap();
+		let gif = concat(&[TEST_GIF.to_vec(), commented.clone(), TEST_GIF.to_vec()]).unwrap();
+		// The first whole, then a graphic control extension and an image with the 2 color global table each.
+		assert_eq!(&gif[..42], &TEST_GIF[..42]);
+		assert_eq!(gif.len(), TEST_GIF.len() + 2 * (8 + 10 + 6 + 5));
+		assert_eq!(&gif[42..50], &TEST_GIF[19..27]);
+		assert_eq!(gif[59], 0x80, "a local color table of 2");
+		assert_eq!(&gif[60..66], &TEST_GIF[13..19]);
+		assert_eq!(comments(&gif), Vec::<String>::new(), "only the first one's");
+		assert_eq!(*gif.last().unwrap(), 0x3B);
+
+		let mut wider = TEST_GIF.to_vec();
+		wider[6] = 2;
+		assert_eq!(concat(&[TEST_GIF.to_vec(), wider]), None);
+		assert_eq!(concat(&[TEST_GIF.to_vec(), TEST_GIF[..30].to_vec()]), None);
+	}
+
+	#[test]
+	fn only_gifs() {
+		let mut old = TEST_GIF.to_vec();
+		old[4] = b'7';
+		assert!(insert_comment(&old, "hi").unwrap().starts_with(b"GIF89a"));
+		assert_eq!(insert_comment(b"\x89PNG\r\n\x1a\n", "hi"), None);
+		assert_eq!(insert_comment(b"GIF89a", "hi"), None);
+		assert!(is_whole(TEST_GIF));
+		assert!(!is_whole(&TEST_GIF[..TEST_GIF.len() - 1]), "no trailer");
+		assert!(!is_whole(b"GIF89a"));
+	}
+
+	#[test]
+	fn delays_are_set_frame_by_frame() {
+		let gif = concat(&[TEST_GIF.to_vec(), insert_comment(TEST_GIF, "hi").unwrap(), TEST_GIF.to_vec()]).unwrap();
+		assert_eq!(delays(&gif), Some(vec![0, 0, 0]));
+		let retimed = set_delays(&gif, &[250, 6, 300]).unwrap();
+		assert_eq!(delays(&retimed), Some(vec![250, 6, 300]));
+		assert_eq!(retimed.len(), gif.len());
+		assert_eq!(set_delays(&gif, &[10, 10]), None, "a delay for every frame");
+		assert_eq!(delays(&TEST_GIF[..30]), None);
+	}
+}
@@ -0,0 +1,246 @@
+use std::{
+	fs,
+	io::{BufReader, Read},
+	path::{Path, PathBuf},
+	sync::mpsc::{self, RecvTimeoutError},
+	thread,
+	time::{Duration, Instant},
+};
+use regex::Regex;
+use crate::{
+	paths,
+	pattern::FramePattern,
+	runner::{CommandLine, CommandOutput, CommandRunner, RunningCommand},
+	ConvertError,
+	Progress,
+	Result,
+};
+
+/// How many frames have to be done before the remaining time is extrapolated from them.
+const ETA_AFTER_FRAMES: usize = 5;
+
+/// How often the output is checked when gifski doesn't report its progress.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// gifski -o file.gif frame*.png, the [`glob`](FramePattern::glob) of the frames in `frames_dir`, scaling them down
+/// to `width` if given.
+pub(crate) fn encode_command(quality: u32, fps: f32, width: Option<u32>, (frames_dir, pattern): (&Path, &FramePattern), output: &Path) -> CommandLine {
+	let mut command = CommandLine::new("gifski")
+		.arg("--fps").arg(fps.to_string())
+		.arg("--quality").arg(quality.to_string());
+	if let Some(width) = width {
+		command = command.arg("--width").arg(width.to_string());
+	}
+	command
+		.arg("-o").arg(paths::for_tool(output))
+		.arg(paths::for_tool(&frames_dir.join(pattern.glob())))
+}
+
+/// An [`encode_command`] given `frames` in `dir` by name instead of the `frame*.png` pattern, for when the pattern
+/// would get others or the wrong order. It runs in `dir` and gets bare file names, so thousands of them still fit in
+/// the 32,767 characters of a Windows command line.
+pub(crate) fn listed(mut command: CommandLine, dir: &Path, frames: &[PathBuf]) -> CommandLine {
+	command.args.pop();
+	let names = frames.iter().map(|frame| {
+		let name = frame.file_name().unwrap_or(frame.as_os_str());
+		// Not to be taken for an option.
+		if name.to_string_lossy().starts_with('-') { Path::new(".").join(name).into_os_string() } else { name.to_owned() }
+	});
+	command.args(names).current_dir(dir)
+}
+
+/// `gifski -o file.gif -`, reading yuv4mpeg video from stdin.
+pub(crate) fn stdin_command(quality: u32, fps: f32, output: &Path) -> CommandLine {
+	CommandLine::new("gifski")
+		.arg("--fps").arg(fps.to_string())
+		.arg("--quality").arg(quality.to_string())
+		.arg("-o").arg(paths::for_tool(output))
+		.arg("-")
+}
+
+/// Runs an [`encode_command`] writing `output`, reporting [`Progress::Encoding`] as it goes.
+pub(crate) fn encode(
+	runner: &dyn CommandRunner,
+	command: &CommandLine,
+	output: &Path,
+	frame_count: usize,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<()> {
+	log::debug!("Running: {command}");
+	let child = runner.spawn(command).map_err(ConvertError::GifskiNotInstalled)?;
+	watch(child, output, frame_count, progress)
+}
+
+/// Waits for a running gifski writing to `output`, reporting [`Progress::Encoding`] as it goes.
+pub(crate) fn watch(mut child: Box<dyn RunningCommand>, output: &Path, frame_count: usize, progress: &mut dyn FnMut(Progress)) -> Result<()> {
+	let watch = Watch::new(&mut *child, frame_count);
+	watch.finish(child, output, progress)
+}
+
+/// Follows a running gifski's progress.
+///
+/// gifski's own progress bar goes to the piped stderr, so it never reaches the terminal; it's parsed and
+/// replaced by our progress instead. Versions that don't print one while piped are tracked by counting
+/// the frames already written to the output.
+pub(crate) struct Watch {
+	frames: mpsc::Receiver<usize>,
+	reader: Option<thread::JoinHandle<String>>,
+	started: Instant,
+	frame_count: usize,
+	reported: usize,
+	gifski_reports: bool,
+}
+
+impl Watch {
+	/// Starts reading `child`'s stderr.
+	pub fn new(child: &mut dyn RunningCommand, frame_count: usize) -> Self {
+		let (frames_tx, frames) = mpsc::channel();
+		let reader = child.take_stderr().map(|stderr| thread::spawn(move || read_progress(stderr, |frame| {
+			let _ = frames_tx.send(frame);
+		})));
+		Watch { frames, reader, started: Instant::now(), frame_count, reported: 0, gifski_reports: false }
+	}
+
+	/// Reports the frames done since the last call, waiting up to `timeout` for gifski to get further.
+	/// Returns `false` once gifski has closed its stderr, which it does when it exits.
+	pub fn poll(&mut self, output: &Path, timeout: Duration, progress: &mut dyn FnMut(Progress)) -> bool {
+		let frame = match self.frames.recv_timeout(timeout) {
+			Ok(frame) => {
+				self.gifski_reports = true;
+				frame
+			}
+			Err(RecvTimeoutError::Timeout) if !self.gifski_reports => frames_written(output),
+			Err(RecvTimeoutError::Timeout) => return true,
+			Err(RecvTimeoutError::Disconnected) => return false,
+		};
+		let frame = frame.min(self.frame_count);
+		if frame > self.reported {
+			self.reported = frame;
+			progress(Progress::Encoding { frame, total: self.frame_count, eta: eta(self.started.elapsed(), frame, self.frame_count) });
+		}
+		true
+	}
+
+	/// Reports progress until `child` exits.
+	pub fn finish(mut self, child: Box<dyn RunningCommand>, output: &Path, progress: &mut dyn FnMut(Progress)) -> Result<()> {
+		while self.poll(output, POLL_INTERVAL, progress) {}
+
+		let streamed = self.reader.map(|r| r.join().unwrap_or_default()).unwrap_or_default();
+		let output = child.wait().map_err(ConvertError::GifskiNotInstalled)?;
+		log::debug!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+		let stderr = streamed + &String::from_utf8_lossy(&output.stderr);
+		log::debug!("stderr: {}", &stderr);
+		if out_of_memory(&output, &stderr) { return Err(ConvertError::GifskiOutOfMemory { stderr }); }
+		if !output.success() { return Err(ConvertError::GifskiFailed { code: output.code, stderr }); }
+		Ok(())
+	}
+}
+
+/// Killed by the OOM killer's SIGKILL, or an allocation failing with gifski's or the OS's message.
+fn out_of_memory(output: &CommandOutput, stderr: &str) -> bool {
+	const MESSAGES: [&str; 3] = ["memory allocation of", "out of memory", "Cannot allocate memory"];
+	!output.success() && (output.signal == Some(9) || MESSAGES.iter().any(|m| stderr.contains(m)))
+}
+
+/// Reads gifski's stderr to the end, calling `on_frame` for every `Frame 12 / 345` of its progress bar.
+/// The total is a `?` when gifski doesn't know it.
+/// Returns everything it read, progress bar redraws excluded.
+fn read_progress(stderr: impl Read, mut on_frame: impl FnMut(usize)) -> String {
+	let frame = Regex::new(r"Frame (\d+) ?/ ?[\d?]").unwrap();
+	let mut text = String::new();
+	let mut handle = |line: &[u8]| {
+		let line = String::from_utf8_lossy(line);
+		match frame.captures(&line).and_then(|c| c[1].parse().ok()) {
+			Some(n) => on_frame(n),
+			None if !line.trim().is_empty() => text.push_str(line.trim_end_matches('\r')),
+			None => {}
+		}
+	};
+	let mut line = Vec::new();
+	for byte in BufReader::new(stderr).bytes() {
+		let Ok(byte) = byte else { break };
+		line.push(byte);
+		// The bar redraws itself with \r, so that ends a line as much as \n does.
+		if byte == b'\r' || byte == b'\n' {
+			handle(&line);
+			line.clear();
+		}
+	}
+	handle(&line);
+	text
+}
+
+/// Frames in a partially written gif, by counting graphic control extensions. gifski writes one per frame.
+fn frames_written(gif: &Path) -> usize {
+	fs::read(gif).map_or(0, |bytes| bytes.windows(3).filter(|w| *w == [0x21, 0xF9, 0x04]).count())
+}
+
+/// Extrapolates from the time the frames so far took, once there are enough of them to go by.
+fn eta(elapsed: Duration, frame: usize, total: usize) -> Option<Duration> {
+	if frame < ETA_AFTER_FRAMES.min(total) || frame == 0 { return None; }
+	let per_frame = elapsed / u32::try_from(frame).ok()?;
+	Some(per_frame * u32::try_from(total - frame).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::MockRunner;
+
+	#[test]
+	fn encode_command_passes_settings() {
+		let command = encode_command(80, 12.5, None, (Path::new("/tmp/frames"), &FramePattern::default()), Path::new("/videos/out.gif"));
+		assert_eq!(command.program_name(), "gifski");
+		assert_eq!(command.args_lossy(), ["--fps", "12.5", "--quality", "80", "-o", "/videos/out.gif", "/tmp/frames/frame*.png"]);
+		let command = encode_command(80, 12.5, Some(320), (Path::new("/tmp/frames"), &FramePattern::default()), Path::new("/videos/out.gif"));
+		assert_eq!(command.args_lossy()[4..6], ["--width", "320"]);
+		let frames = [PathBuf::from("/tmp/frames/frame0001.png"), PathBuf::from("/tmp/frames/-2.png")];
+		let listed = listed(command, Path::new("/tmp/frames"), &frames);
+		assert_eq!(listed.args_lossy()[6..], ["-o", "/videos/out.gif", "frame0001.png", "./-2.png"]);
+		assert_eq!(listed.dir.as_deref(), Some(Path::new("/tmp/frames")));
+	}
+
+	#[test]
+	fn reads_frames_from_the_progress_bar() {
+		let stderr = "Frame 1 / 10 #___ 5s\rFrame 2 / 10 ##__ 4s\rFrame 10 / 10 ####\nerror: disk full\n";
+		let mut frames = Vec::new();
+
+		let text = read_progress(stderr.as_bytes(), |f| frames.push(f));
+
+		assert_eq!(frames, [1, 2, 10]);
+		assert_eq!(text, "error: disk full\n");
+	}
+
+	#[test]
+	fn eta_waits_for_a_few_frames() {
+		let s = Duration::from_secs;
+		assert_eq!(eta(s(1), 1, 100), None);
+		assert_eq!(eta(s(5), 5, 100), Some(s(95)));
+		assert_eq!(eta(s(2), 2, 2), Some(s(0)));
+	}
+
+	#[test]
+	fn encode_reports_progress_and_keeps_errors() {
+		let mock = MockRunner::new();
+		mock.respond("gifski", CommandOutput::failed(1, "Frame 3 / 4\rerror: disk full\n"));
+		let mut events = Vec::new();
+		let command = encode_command(90, 10.0, None, (Path::new("/tmp/frames"), &FramePattern::default()), Path::new("/tmp/out.gif"));
+
+		let result = encode(&mock, &command, Path::new("/tmp/out.gif"), 4, &mut |p| events.push(p));
+
+		assert!(matches!(&events[..], [Progress::Encoding { frame: 3, total: 4, eta: None }]), "{events:?}");
+		assert!(matches!(result, Err(ConvertError::GifskiFailed { stderr, .. }) if stderr == "error: disk full\n"));
+	}
+
+	#[test]
+	fn running_out_of_memory_is_told_apart() {
+		let oom = |output: CommandOutput| {
+			let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+			out_of_memory(&output, &stderr)
+		};
+		assert!(oom(CommandOutput::killed(9)));
+		assert!(oom(CommandOutput::failed(134, "memory allocation of 1073741824 bytes failed")));
+		assert!(!oom(CommandOutput::killed(15)));
+		assert!(!oom(CommandOutput::failed(1, "error: disk full")));
+	}
+}
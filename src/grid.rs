@@ -0,0 +1,82 @@
+//! Stacking several inputs into one video with ffmpeg's xstack, behind [`ConvertOptions::grid`].
+
+use crate::{
+	ffmpeg::{self, Extraction},
+	options::Grid,
+	probe::{self, InputInfo, VideoStream},
+	runner::CommandRunner,
+	ConvertError, ConvertOptions, Result,
+};
+
+/// Probes the [`ConvertOptions::grid_inputs`] and sets `extraction` up to stack them with `first`. Returns what the
+/// stacked video looks like: cells the size of `first`, at its fps, as long as the shortest input.
+pub(crate) fn stack(runner: &dyn CommandRunner, opt: &ConvertOptions, grid: Grid, first: &InputInfo, extraction: &mut Extraction) -> Result<InputInfo> {
+	let mut durations = vec![first.duration];
+	for input in &opt.grid_inputs {
+		if !input.is_file() { return Err(ConvertError::InputNotFound(input.clone())); }
+		durations.push(probe::probe_input(runner, input)?.duration);
+	}
+	// Odd sizes would be rounded differently by each scale.
+	let cell = (ffmpeg::even(first.video.width), ffmpeg::even(first.video.height));
+	let labels = opt.grid_labels.then(|| {
+		std::iter::once(&opt.input).chain(&opt.grid_inputs)
+			.map(|p| p.file_name().unwrap_or_default().to_string_lossy().into_owned())
+			.collect::<Vec<_>>()
+	});
+	extraction.more_inputs.clone_from(&opt.grid_inputs);
+	extraction.stack = Some(filter_graph(grid, cell, first.video.fps(), opt.grid_inputs.len() + 1, labels.as_deref()));
+	Ok(InputInfo {
+		duration: durations.into_iter().flatten().reduce(f64::min),
+		video: VideoStream { width: cell.0 * grid.columns, height: cell.1 * grid.rows, frame_count: None, ..first.video.clone() },
+		..first.clone()
+	})
+}
+
+/// Scales each of the `inputs` to `cell`, labels them, and stacks them left to right, top to bottom. Cells
+/// without an input are black.
+fn filter_graph(grid: Grid, (width, height): (u32, u32), fps: Option<f32>, inputs: usize, labels: Option<&[String]>) -> String {
+	let rate = fps.map(|fps| format!(",fps={fps}")).unwrap_or_default();
+	let mut chains = Vec::new();
+	for i in 0..inputs {
+		let label = labels.and_then(|l| l.get(i)).map(|text| format!(
+			",drawtext=text={}:expansion=none:x=8:y=8:fontsize=20:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=4",
+			ffmpeg::escape_option(text),
+		)).unwrap_or_default();
+		chains.push(format!(
+			"[{i}:v]scale={width}:{height}:force_original_aspect_ratio=decrease:flags=lanczos,pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1{rate}{label}[c{i}]",
+		));
+	}
+	let cells = (grid.columns * grid.rows) as usize;
+	let fill_rate = fps.map(|fps| format!(":r={fps}")).unwrap_or_default();
+	for i in inputs..cells {
+		chains.push(format!("color=c=black:s={width}x{height}{fill_rate}[c{i}]"));
+	}
+	let layout = (0..grid.columns * grid.rows)
+		.map(|i| format!("{}_{}", i % grid.columns * width, i / grid.columns * height))
+		.collect::<Vec<_>>();
+	let pads = (0..cells).map(|i| format!("[c{i}]")).collect::<Vec<_>>().concat();
+	// The black cells never end, so it has to stop at the shortest input.
+	chains.push(format!("{pads}xstack=inputs={cells}:layout={}:shortest=1", layout.join("|")));
+	chains.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fills_the_grid_in_reading_order() {
+		let graph = filter_graph(Grid { columns: 2, rows: 2 }, (320, 180), Some(24.0), 3, None);
+		let chains: Vec<&str> = graph.split(';').collect();
+		assert_eq!(chains[0], "[0:v]scale=320:180:force_original_aspect_ratio=decrease:flags=lanczos,pad=320:180:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=24[c0]");
+		assert_eq!(chains[3], "color=c=black:s=320x180:r=24[c3]");
+		assert_eq!(chains[4], "[c0][c1][c2][c3]xstack=inputs=4:layout=0_0|320_0|0_180|320_180:shortest=1");
+	}
+
+	#[test]
+	fn labels_are_escaped_twice() {
+		assert_eq!(ffmpeg::escape_option("it's a:b,[c].mp4"), r"it\\\'s a\\:b\,\[c\].mp4");
+		let graph = filter_graph(Grid { columns: 2, rows: 1 }, (2, 2), None, 2, Some(&["a.mp4".to_string(), "b.mp4".to_string()]));
+		assert!(graph.contains(",setsar=1,drawtext=text=b.mp4:expansion=none:"), "{graph}");
+	}
+}
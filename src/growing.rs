@@ -0,0 +1,205 @@
+//! Inputs that are still being written, like a recording that hasn't been stopped: the gif of one would be missing
+//! its end, or ffmpeg would fail at the end of what's there so far.
+//!
+//! A file modified within [`SETTLED_FOR`] is looked at again [`RECHECK`] later, and warned about if its size or
+//! modification time changed. With [`ConvertOptions::wait_for_input`](crate::ConvertOptions::wait_for_input) it's
+//! waited for instead, until it's gone [`SETTLED_FOR`] without changing. Files modified longer ago than that are
+//! settled from the start, so the check costs nothing for those.
+
+use std::{
+	fs,
+	path::Path,
+	thread,
+	time::{Duration, Instant, SystemTime},
+};
+use crate::{disk, probe::InputInfo, ConvertError, Progress, Result, WarningKind};
+
+/// The default for [`ConvertOptions::wait_for_input`](crate::ConvertOptions::wait_for_input), in seconds.
+pub const DEFAULT_INPUT_WAIT: f64 = 60.0;
+
+/// How long a file has to go without changing to count as written. A recorder writes more often than this.
+const SETTLED_FOR: Duration = Duration::from_secs(2);
+
+/// How long after the first look a recently modified file is looked at again, when not waiting for it.
+const RECHECK: Duration = Duration::from_millis(500);
+
+/// How often a file is looked at while waiting for it.
+const POLL: Duration = Duration::from_millis(250);
+
+/// How recently a file without a duration has to have been modified to look like an unfinished recording, rather
+/// than a stream that never had one.
+const RECENTLY: Duration = Duration::from_mins(1);
+
+/// One look at a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Sample {
+	/// When it was taken, from the first one.
+	pub at: Duration,
+	/// When it was taken, by the clock the modification time is by.
+	pub taken: SystemTime,
+	pub size: u64,
+	pub modified: Option<SystemTime>,
+}
+
+impl Sample {
+	/// A look at `path` now, `None` if it can't be read.
+	fn take(path: &Path, started: Instant) -> Option<Sample> {
+		let metadata = fs::metadata(path).ok()?;
+		Some(Sample { at: started.elapsed(), taken: SystemTime::now(), size: metadata.len(), modified: metadata.modified().ok() })
+	}
+
+	/// How long before it was taken the file was modified, `None` if that can't be told, or is in the future, like
+	/// on a share with its clock ahead.
+	fn modified_ago(&self) -> Option<Duration> {
+		self.taken.duration_since(self.modified?).ok()
+	}
+}
+
+/// Whether the file changed between any two of `samples`, in size or modification time.
+pub(crate) fn changing(samples: &[Sample]) -> bool {
+	samples.windows(2).any(|pair| pair[0].size != pair[1].size || pair[0].modified != pair[1].modified)
+}
+
+/// How long the file `samples` were taken of is known to have gone without changing, as of the last of them: since
+/// it was last modified, or it changed between two of them, or since the first if when it was modified isn't known.
+pub(crate) fn quiet_for(samples: &[Sample]) -> Duration {
+	let Some(last) = samples.last() else { return Duration::ZERO };
+	let changed = samples.windows(2).rev().find(|pair| changing(pair)).map(|pair| pair[1].at);
+	let since = match (changed, last.modified_ago()) {
+		(Some(changed), _) => changed,
+		(None, Some(ago)) => return ago,
+		(None, None) => samples[0].at,
+	};
+	last.at.saturating_sub(since)
+}
+
+/// Whether the file `samples` were taken of has gone [`SETTLED_FOR`] without changing.
+pub(crate) fn settled(samples: &[Sample]) -> bool {
+	quiet_for(samples) >= SETTLED_FOR
+}
+
+/// Looks at `input` for whether it's still being written, waiting up to `wait` seconds for it to settle if given,
+/// and warns if it didn't. Whether it was warned about.
+///
+/// # Errors
+/// If `wait` isn't a positive number of seconds.
+pub(crate) fn check(input: &Path, wait: Option<f64>, progress: &mut dyn FnMut(Progress)) -> Result<bool> {
+	if let Some(seconds) = wait.filter(|s| !(s.is_finite() && *s > 0.0)) {
+		return Err(ConvertError::InvalidOption { option: "wait for input", message: format!("waits a positive number of seconds, got {seconds}") });
+	}
+	let started = Instant::now();
+	let Some(first) = Sample::take(input, started) else { return Ok(false) };
+	let mut samples = vec![first];
+	if settled(&samples) { return Ok(false); }
+	let Some(seconds) = wait else {
+		thread::sleep(RECHECK);
+		samples.extend(Sample::take(input, started));
+		if !changing(&samples) { return Ok(false); }
+		let last = samples[samples.len() - 1];
+		let change = match last.size.checked_sub(first.size).filter(|&grew| grew > 0) {
+			Some(grew) => format!("it grew by {}", disk::human_size(grew)),
+			None => "it was modified again".to_string(),
+		};
+		progress(Progress::warning(WarningKind::InputStillWritten, format!(
+			"{} is still being written, {change} in the last {:.1}s, so the gif may be missing its end. --wait-for-input waits for it to finish",
+			input.display(), last.at.as_secs_f64(),
+		)));
+		return Ok(true);
+	};
+	let timeout = Duration::from_secs_f64(seconds);
+	progress(Progress::Info(format!("Waiting up to {seconds}s for {} to finish being written", input.display())));
+	while !settled(&samples) && started.elapsed() < timeout {
+		thread::sleep(POLL);
+		samples.extend(Sample::take(input, started));
+	}
+	if settled(&samples) {
+		log::debug!("{} stopped changing after {:.1}s", input.display(), started.elapsed().as_secs_f64());
+		return Ok(false);
+	}
+	progress(Progress::warning(WarningKind::InputStillWritten, format!(
+		"{} was still being written after waiting {seconds}s for it, so the gif may be missing its end",
+		input.display(),
+	)));
+	Ok(true)
+}
+
+/// Warns if `input`, probed as `info`, has no duration and was modified within [`RECENTLY`], which is what an MKV
+/// still being recorded looks like.
+pub(crate) fn check_duration(input: &Path, info: &InputInfo, progress: &mut dyn FnMut(Progress)) {
+	if info.duration.is_some() || info.is_still() { return; }
+	let Some(ago) = Sample::take(input, Instant::now()).and_then(|s| s.modified_ago()) else { return };
+	if ago < RECENTLY {
+		progress(Progress::warning(WarningKind::InputStillWritten, format!(
+			"{} has no duration and was modified {}s ago, like a recording that's still going, so the gif may be missing its end. --wait-for-input waits for it to finish",
+			input.display(), ago.as_secs(),
+		)));
+	}
+}
+
+/// Backdates `path`'s modification time an hour, for a test's input, which was only just written but isn't still
+/// being written.
+#[cfg(test)]
+pub(crate) fn written_an_hour_ago(path: &Path) {
+	let hour_ago = SystemTime::now() - Duration::from_hours(1);
+	fs::File::options().write(true).open(path).unwrap().set_modified(hour_ago).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A look `at_ms` in at a file of `size` modified `modified_ago_ms` before.
+	fn sample(at_ms: u64, size: u64, modified_ago_ms: Option<u64>) -> Sample {
+		let taken = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000) + Duration::from_millis(at_ms);
+		Sample { at: Duration::from_millis(at_ms), taken, size, modified: modified_ago_ms.map(|ago| taken - Duration::from_millis(ago)) }
+	}
+
+	#[test]
+	fn files_modified_a_while_ago_are_settled_at_once() {
+		assert!(settled(&[sample(0, 1000, Some(3_600_000))]));
+		assert!(settled(&[sample(0, 1000, Some(2000))]));
+		assert!(!settled(&[sample(0, 1000, Some(100))]), "just modified");
+		assert!(!settled(&[sample(0, 1000, None)]), "not known when");
+		assert!(!settled(&[]));
+	}
+
+	#[test]
+	fn growing_files_are_changing_and_not_settled() {
+		let growing = [sample(0, 1000, Some(50)), sample(500, 1800, Some(20))];
+		assert!(changing(&growing));
+		assert!(!settled(&growing));
+		let rewritten = [sample(0, 1000, Some(50)), sample(500, 1000, Some(20))];
+		assert!(changing(&rewritten), "same size, but modified again, like a header being filled in");
+		let finished = [sample(0, 1000, Some(300)), sample(500, 1000, Some(800))];
+		assert!(!changing(&finished), "the same modification time, looked at later");
+	}
+
+	#[test]
+	fn settles_once_quiet_for_long_enough() {
+		// A share that doesn't update the modification time, so only the size tells.
+		let mut samples = vec![sample(0, 10, Some(60_000)), sample(250, 20, Some(60_250)), sample(500, 20, Some(60_500))];
+		assert!(!settled(&samples), "grew at 250ms");
+		assert_eq!(quiet_for(&samples), Duration::from_millis(250));
+		samples.push(sample(2250, 20, Some(62_250)));
+		assert!(settled(&samples));
+
+		let unknown = [sample(0, 10, None), sample(1000, 10, None), sample(2000, 10, None)];
+		assert_eq!(quiet_for(&unknown), Duration::from_secs(2), "watched for two seconds without a change");
+		assert!(settled(&unknown));
+	}
+
+	#[test]
+	fn old_inputs_cost_nothing_to_check() {
+		let input = crate::test_dir("growing").join("old.mkv");
+		fs::write(&input, b"recording").unwrap();
+		written_an_hour_ago(&input);
+
+		let mut warnings = Vec::new();
+		let started = Instant::now();
+		assert!(!check(&input, None, &mut |p| warnings.push(p)).unwrap());
+		assert!(!check(&input, Some(5.0), &mut |p| warnings.push(p)).unwrap());
+		assert!(started.elapsed() < RECHECK, "{:?}", started.elapsed());
+		assert!(warnings.is_empty(), "{warnings:?}");
+		assert!(check(&input, Some(0.0), &mut |_| {}).is_err());
+	}
+}
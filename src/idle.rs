@@ -0,0 +1,186 @@
+//! Finding black or frozen frames at the start and end of the extracted frames, for `--trim-idle`.
+
+use std::{
+	fs,
+	ops::Range,
+	path::{Path, PathBuf},
+};
+use regex::Regex;
+use crate::{
+	paths,
+	pattern::FramePattern,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Progress,
+	Result,
+	WarningKind,
+};
+
+/// The default for [`ConvertOptions::trim_idle_threshold`](crate::ConvertOptions::trim_idle_threshold).
+pub const DEFAULT_THRESHOLD: f64 = 0.1;
+
+/// A stretch of frames ffmpeg found to be black or frozen. Frame `start` is the first idle one, `end` the first
+/// one after it; `None` if it lasts until the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Idle {
+	kind: &'static str,
+	start: usize,
+	end: Option<usize>,
+}
+
+/// What's left after trimming, and what was cut.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Trim {
+	pub keep: Range<usize>,
+	/// Ranges of removed frames and why they were.
+	pub removed: Vec<(Range<usize>, &'static str)>,
+}
+
+/// Runs blackdetect and freezedetect over the frames from `first` on at one frame per second, so their timestamps
+/// are frame numbers from it.
+///
+/// `threshold` is blackdetect's pixel threshold; freezedetect's noise threshold is a hundredth of it, which is
+/// ffmpeg's default at the default 0.1.
+pub(crate) fn detect_command(first: &Path, pattern: &FramePattern, threshold: f64) -> CommandLine {
+	let (start_number, sequence) = pattern.sequence(Some(first));
+	CommandLine::new("ffmpeg")
+		.args(["-hide_banner", "-framerate", "1"])
+		.arg("-start_number").arg(start_number.to_string())
+		.arg("-i").arg(paths::for_tool(&sequence))
+		.arg("-vf").arg(format!("blackdetect=d=1:pix_th={threshold},freezedetect=n={}:d=1", threshold / 100.0))
+		.args(["-an", "-f", "null", "-"])
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Frame numbers at one frame per second.
+fn parse_idle(stderr: &str) -> Vec<Idle> {
+	let frame = |s: &str| s.parse::<f64>().ok().map(|t| t.round() as usize);
+	let black = Regex::new(r"black_start:(\S+) black_end:(\S+)").unwrap();
+	let mut idle: Vec<Idle> = black.captures_iter(stderr)
+		.filter_map(|c| Some(Idle { kind: "black", start: frame(&c[1])?, end: frame(&c[2]) }))
+		.collect();
+	let freeze = Regex::new(r"freezedetect\.freeze_(start|end): (\S+)").unwrap();
+	for c in freeze.captures_iter(stderr) {
+		let Some(at) = frame(&c[2]) else { continue };
+		match &c[1] {
+			"start" => idle.push(Idle { kind: "frozen", start: at, end: None }),
+			_ => if let Some(last) = idle.iter_mut().rev().find(|i| i.kind == "frozen" && i.end.is_none()) { last.end = Some(at); },
+		}
+	}
+	idle.sort_by_key(|i| i.start);
+	idle
+}
+
+/// Works out which of `frame_count` frames to keep. The last frame of a frozen stretch at the start and the
+/// first of one at the end are kept, since they're usually a still worth seeing; black frames all go.
+/// `None` if nothing would be left.
+fn trim(idle: &[Idle], frame_count: usize) -> Option<Trim> {
+	let mut first = 0;
+	for i in idle {
+		if i.start > first { break; }
+		let end = i.end.unwrap_or(frame_count);
+		first = first.max(if i.kind == "frozen" { end.saturating_sub(1).max(i.start) } else { end });
+	}
+	let mut end = frame_count;
+	for i in idle.iter().rev() {
+		if i.end.is_some_and(|e| e < end) { break; }
+		end = end.min(if i.kind == "frozen" { i.start + 1 } else { i.start });
+	}
+	if first >= end { return None; }
+
+	let kind_at = |frame: usize| idle.iter().find(|i| i.start <= frame && i.end.is_none_or(|e| frame < e)).map_or("idle", |i| i.kind);
+	let mut removed = Vec::new();
+	if first > 0 { removed.push((0..first, kind_at(0))); }
+	if end < frame_count { removed.push((end..frame_count, kind_at(frame_count - 1))); }
+	Some(Trim { keep: first..end, removed })
+}
+
+/// Finds the idle frames at either end of `frames`, named by `pattern`.
+///
+/// `None` if every frame looks idle.
+fn detect(runner: &dyn CommandRunner, frames: &[PathBuf], pattern: &FramePattern, threshold: f64) -> Result<Option<Trim>> {
+	let sequence = pattern.consecutive(frames)?;
+	let Some(first) = sequence.frames.first() else { return Ok(None) };
+	let command = detect_command(first, pattern, threshold);
+	log::debug!("Running: {}", &command);
+	let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	if !output.success() { return Err(ConvertError::FfmpegFailed { code: output.code, stderr: stderr.into_owned() }); }
+	let idle = parse_idle(&stderr);
+	log::debug!("Idle frames: {:?}", &idle);
+	Ok(trim(&idle, frames.len()))
+}
+
+/// The index of the first of `frames` that isn't black or frozen, keeping the last frame of a frozen start the
+/// same as trimming does. The first frame if they all look idle.
+pub(crate) fn first_interesting(runner: &dyn CommandRunner, frames: &[PathBuf], pattern: &FramePattern, threshold: f64) -> Result<usize> {
+	Ok(detect(runner, frames, pattern, threshold)?.map_or(0, |trim| trim.keep.start))
+}
+
+/// Detects the idle frames at either end and deletes them, reporting what was removed.
+/// Returns the remaining frames and how many were removed from the start.
+pub(crate) fn trim_frames(
+	runner: &dyn CommandRunner,
+	frames: Vec<PathBuf>,
+	pattern: &FramePattern,
+	threshold: f64,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<(Vec<PathBuf>, usize)> {
+	let Some(trim) = detect(runner, &frames, pattern, threshold)? else {
+		progress(Progress::warning(WarningKind::AllFramesIdle, "every frame looks idle, not trimming any".to_string()));
+		return Ok((frames, 0));
+	};
+	for (range, kind) in &trim.removed {
+		progress(Progress::Info(format!("Trimmed {} {kind} frames ({} to {})", range.len(), range.start + 1, range.end)));
+	}
+	Ok((remove_outside(frames, &trim.keep)?, trim.keep.start))
+}
+
+/// Deletes the frames outside `keep` and returns the rest.
+fn remove_outside(frames: Vec<PathBuf>, keep: &Range<usize>) -> Result<Vec<PathBuf>> {
+	let mut kept = Vec::new();
+	for (i, frame) in frames.into_iter().enumerate() {
+		if keep.contains(&i) {
+			kept.push(frame);
+		} else {
+			fs::remove_file(&frame).map_err(ConvertError::io(&frame))?;
+		}
+	}
+	Ok(kept)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const STDERR: &str = "\
+[blackdetect @ 0x5581] black_start:0 black_end:3 black_duration:3
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_start: 3
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_duration: 4
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_end: 7
+[freezedetect @ 0x5582] lavfi.freezedetect.freeze_start: 15
+frame=   20 fps=0.0 q=-0.0 Lsize=N/A time=00:00:20.00
+";
+
+	#[test]
+	fn parses_black_and_frozen_stretches() {
+		assert_eq!(parse_idle(STDERR), [
+			Idle { kind: "black", start: 0, end: Some(3) },
+			Idle { kind: "frozen", start: 3, end: Some(7) },
+			Idle { kind: "frozen", start: 15, end: None },
+		]);
+	}
+
+	#[test]
+	fn trims_both_ends_keeping_the_stills() {
+		// Black 0-2, frozen 3-6 of which 6 is kept, frozen from 15 of which 15 is kept.
+		assert_eq!(trim(&parse_idle(STDERR), 20), Some(Trim { keep: 6..16, removed: vec![(0..6, "black"), (16..20, "frozen")] }));
+		// Only idle in the middle.
+		let middle = [Idle { kind: "black", start: 5, end: Some(8) }];
+		assert_eq!(trim(&middle, 20), Some(Trim { keep: 0..20, removed: vec![] }));
+	}
+
+	#[test]
+	fn all_idle_keeps_nothing() {
+		assert_eq!(trim(&[Idle { kind: "black", start: 0, end: None }], 10), None);
+	}
+}
@@ -0,0 +1,2585 @@
+//! Converts a video to frames using your local ffmpeg, then runs gifski to convert the frames to a gif.
+//!
+//! The `gifski-ffmpeg` binary is a thin wrapper around this library; everything it does is available through
+//! [`convert`] and the [`Conversion`] builder.
+//!
+//! ```no_run
+//! use gifski_ffmpeg::{convert, ConvertOptions};
+//!
+//! let mut options = ConvertOptions::new("input.mp4");
+//! options.quality = 90;
+//! let report = convert(options)?;
+//! println!("{} frames at {} fps -> {}", report.frame_count, report.fps, report.output.display());
+//! # Ok::<(), gifski_ffmpeg::ConvertError>(())
+//! ```
+#![warn(
+clippy::all,
+clippy::pedantic,
+)]
+
+pub mod batch;
+pub mod benchmark;
+pub mod disk;
+pub mod doctor;
+mod advice;
+mod cursor;
+mod drive;
+mod duration;
+mod edit;
+mod error;
+pub mod estimate;
+pub mod explain;
+mod ffmpeg;
+mod flash;
+mod focus;
+mod frame_filter;
+mod fps;
+mod gif;
+mod gifski;
+mod grid;
+mod growing;
+mod idle;
+mod mp4;
+mod options;
+mod output;
+mod overlap;
+mod palette;
+mod paths;
+mod pattern;
+mod quality_map;
+mod quantize;
+mod retry;
+mod sheet;
+mod smooth;
+mod stabilize;
+mod stats;
+mod priority;
+mod temp;
+mod timing;
+mod warning;
+mod waveform;
+mod zoom;
+pub mod probe;
+pub mod runner;
+pub mod split;
+pub mod tools;
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	time::{Duration, Instant, SystemTime},
+};
+use drive::Drive;
+use probe::InputInfo;
+use runner::{BackgroundRunner, Cancellable, CommandLine, CommandRunner, SystemRunner};
+use tools::{Tool, ToolInfo};
+
+pub use advice::Busyness;
+pub use error::ConvertError;
+pub use flash::DEFAULT_FLASH_THRESHOLD;
+pub use growing::DEFAULT_INPUT_WAIT;
+pub use mp4::MP4_MAX_BYTES;
+pub use options::{parse_timestamp, Aspect, Comment, ConvertOptions, Dither, Encoder, Fit, Focus, FrameFilter, FrameFilterMode, Gravity, Grid, Poster, PosterFormat, QualityRegion, Scale, SeekMode, Stages, WaveformStyle, Zoom, DEFAULT_MAX_AUTO_FPS, SOFT_MAX_DURATION, KEYFRAME_FPS};
+pub use pattern::FramePattern;
+pub use retry::Downgrade;
+pub use runner::CancelToken;
+pub use sheet::DEFAULT_CONTACT_SHEET;
+pub use temp::{FramesLocation, DEFAULT_RAMDISK};
+pub use smooth::DEFAULT_LOOP_SMOOTH_FRAMES;
+pub use warning::{Warning, WarningKind};
+pub use waveform::DEFAULT_WAVEFORM_HEIGHT;
+
+/// The highest fps gifski can make a gif play at.
+pub(crate) const MAX_FPS: f32 = 50.0;
+
+/// The fps a [`ConvertOptions::still_duration`] still is looped at without an fps given. Every frame is the same.
+const STILL_FPS: f32 = 1.0;
+
+/// Result of everything in this crate.
+pub type Result<T, E = ConvertError> = std::result::Result<T, E>;
+
+/// A stage of the conversion, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Stage {
+	/// ffmpeg works out how the camera shook, for [`ConvertOptions::stabilize`].
+	Stabilize,
+	/// ffmpeg splits the video into frames.
+	Extract,
+	/// gifski encodes the frames into a gif.
+	Encode,
+	/// A frame is copied out as the poster image.
+	Poster,
+	/// The frames directory is deleted.
+	Cleanup,
+}
+
+impl Stage {
+	/// `stabilize`, `extract`, ... the same as it serializes to.
+	#[must_use]
+	pub fn id(self) -> &'static str {
+		match self {
+			Stage::Stabilize => "stabilize",
+			Stage::Extract => "extract",
+			Stage::Encode => "encode",
+			Stage::Poster => "poster",
+			Stage::Cleanup => "cleanup",
+		}
+	}
+}
+
+/// Progress events passed to the [`Conversion::on_progress`] callback, or a [`ProgressSink`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Progress {
+	/// A stage is starting.
+	Started(Stage),
+	/// A stage finished successfully after the given time.
+	Finished(Stage, Duration),
+	/// ffmpeg has extracted `frame` frames, of about `total` if the length of the input is known.
+	Extracting { frame: usize, total: Option<usize> },
+	/// gifski has encoded `frame` of `total` frames. `eta` is the estimated time until it's done, once there is one.
+	Encoding { frame: usize, total: usize, eta: Option<Duration> },
+	/// Something the user would want to know about, e.g. the settings gifski is run with.
+	Info(String),
+	/// Something that didn't go as asked but didn't stop the conversion, e.g. a setting that was out of range.
+	Warning(Warning),
+}
+
+impl Progress {
+	pub(crate) fn warning(kind: WarningKind, message: impl Into<String>) -> Self {
+		Progress::Warning(Warning::new(kind, message))
+	}
+}
+
+/// What a finished conversion produced.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ConvertReport {
+	/// The video that was converted.
+	pub input: PathBuf,
+	/// The gif that was written, or the frames directory with [`Stages::Extract`].
+	pub output: PathBuf,
+	/// The frames directory, if it was left behind.
+	pub frames_dir: Option<PathBuf>,
+	/// Where the frames went with [`ConvertOptions::in_memory`], `None` without it.
+	pub frames_location: Option<FramesLocation>,
+	/// The poster image, if one was requested.
+	pub poster: Option<PathBuf>,
+	/// The [`ConvertOptions::also_mp4`], if one was requested.
+	pub mp4: Option<PathBuf>,
+	/// The [`ConvertOptions::contact_sheet`], if one was requested and there were enough frames for it.
+	pub contact_sheet: Option<PathBuf>,
+	/// The fps passed to gifski, the last time it ran.
+	pub fps: f32,
+	/// The quality passed to gifski.
+	pub quality: u32,
+	/// Number of frames ffmpeg extracted.
+	pub frame_count: usize,
+	/// Width and height of the frames, with the [`ConvertOptions::waveform`] strip. `None` with [`Stages::Encode`],
+	/// or if the input's size wasn't known.
+	pub frame_size: Option<(u32, u32)>,
+	/// How long the gif plays, in seconds. All inputs together with [`ConvertOptions::concat`].
+	pub duration: f64,
+	/// How long the converted part of the input was before [`ConvertOptions::max_duration`] cut it, if it did.
+	pub truncated_from: Option<f64>,
+	/// How ffmpeg failed, with the end of what it printed, if [`ConvertOptions::tolerate_decode_errors`] kept the
+	/// frames from before that and the gif ends where it stopped.
+	pub decode_error: Option<String>,
+	/// How busy a few of the frames were, which [`ConvertOptions::advice`] looks at before encoding. `None` without
+	/// it, or when the frames couldn't be measured.
+	pub busyness: Option<Busyness>,
+	/// How many frames [`ConvertOptions::loop_smooth`] crossfaded, which the gif is shorter by.
+	pub loop_smoothed: Option<usize>,
+	/// Time spent in ffmpeg.
+	pub extract_time: Duration,
+	/// Time spent in gifski.
+	pub encode_time: Duration,
+	/// Wall-clock time of the whole conversion.
+	pub total_time: Duration,
+	/// The comment written into the gif, if any.
+	pub comment: Option<String>,
+	/// The settings the gif was encoded with instead, if gifski ran out of memory with the asked for ones.
+	pub downgrade: Option<Downgrade>,
+	/// One entry per quality when [`ConvertOptions::compare_quality`] was used, or per width with
+	/// [`ConvertOptions::sizes`], in which case [`output`](Self::output) is only the name the variants are derived from.
+	pub comparisons: Vec<QualityRun>,
+	/// One entry per stretch of the gif with [`ConvertOptions::quality_map`], the ones between its regions too.
+	pub quality_regions: Vec<RegionRun>,
+	/// What ffprobe found in the input, `None` with [`Stages::Encode`].
+	pub input_info: Option<InputInfo>,
+	/// The ffmpeg that was used, `None` with [`Stages::Encode`].
+	pub ffmpeg: Option<ToolInfo>,
+	/// The gifski that was used, `None` with [`Stages::Extract`] or [`Encoder::Ffmpeg`].
+	pub gifski: Option<ToolInfo>,
+	/// Every [`Progress::Warning`], in order.
+	pub warnings: Vec<Warning>,
+}
+
+/// One encode of a [`ConvertOptions::compare_quality`] or [`ConvertOptions::sizes`] run.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct QualityRun {
+	pub quality: u32,
+	/// The width gifski scaled this one to, with [`ConvertOptions::sizes`].
+	pub width: Option<u32>,
+	pub output: PathBuf,
+	/// Size of the gif in bytes, `None` if encoding failed.
+	pub size: Option<u64>,
+	/// Why encoding failed.
+	pub error: Option<String>,
+	/// The comment written into the gif.
+	pub comment: Option<String>,
+	pub encode_time: Duration,
+}
+
+/// A stretch of a [`ConvertOptions::quality_map`] gif.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RegionRun {
+	/// Seconds into the gif.
+	pub start: f64,
+	pub end: f64,
+	pub quality: u32,
+	pub frame_count: usize,
+	/// Size in bytes of the stretch as a gif of its own.
+	pub size: u64,
+}
+
+/// Receives a [`Conversion`]'s progress, one method per kind of [`Progress`] plus one for the result. The methods do
+/// nothing unless they're implemented.
+///
+/// An alternative to [`Conversion::on_progress`] for callers that want the events split up, e.g. a GUI updating a
+/// different widget for each.
+pub trait ProgressSink {
+	/// See [`Progress::Started`].
+	fn stage_started(&mut self, _stage: Stage) {}
+
+	/// See [`Progress::Finished`].
+	fn stage_finished(&mut self, _stage: Stage, _time: Duration) {}
+
+	/// See [`Progress::Extracting`].
+	fn extracting(&mut self, _frame: usize, _total: Option<usize>) {}
+
+	/// See [`Progress::Encoding`].
+	fn encoding(&mut self, _frame: usize, _total: usize, _eta: Option<Duration>) {}
+
+	/// See [`Progress::Info`].
+	fn info(&mut self, _message: &str) {}
+
+	/// See [`Progress::Warning`].
+	fn warning(&mut self, _warning: &Warning) {}
+
+	/// The conversion is over, with what [`Conversion::run`] is about to return.
+	fn finished(&mut self, _result: &Result<ConvertReport>) {}
+
+	/// Every event, as it happens. Passes it on to the method for its kind.
+	fn progress(&mut self, progress: Progress) {
+		match progress {
+			Progress::Started(stage) => self.stage_started(stage),
+			Progress::Finished(stage, time) => self.stage_finished(stage, time),
+			Progress::Extracting { frame, total } => self.extracting(frame, total),
+			Progress::Encoding { frame, total, eta } => self.encoding(frame, total, eta),
+			Progress::Info(message) => self.info(&message),
+			Progress::Warning(warning) => self.warning(&warning),
+		}
+	}
+}
+
+/// The sink behind [`Conversion::on_progress`].
+struct OnProgress<F>(F);
+
+impl<F: FnMut(Progress)> ProgressSink for OnProgress<F> {
+	fn progress(&mut self, progress: Progress) {
+		(self.0)(progress);
+	}
+}
+
+/// Runs a conversion with [`ConvertOptions`], optionally reporting [`Progress`] along the way.
+///
+/// ```no_run
+/// use gifski_ffmpeg::{Conversion, ConvertOptions, Progress};
+///
+/// let report = Conversion::new(ConvertOptions::new("input.mp4"))
+///     .on_progress(|p| if let Progress::Started(stage) = p { println!("{stage:?}...") })
+///     .run()?;
+/// # Ok::<(), gifski_ffmpeg::ConvertError>(())
+/// ```
+pub struct Conversion<'a> {
+	options: ConvertOptions,
+	runner: Box<dyn CommandRunner + 'a>,
+	sink: Box<dyn ProgressSink + 'a>,
+	cancel: Option<CancelToken>,
+}
+
+impl<'a> Conversion<'a> {
+	#[must_use]
+	pub fn new(options: ConvertOptions) -> Self {
+		let runner: Box<dyn CommandRunner> = if options.background { Box::new(BackgroundRunner) } else { Box::new(SystemRunner) };
+		Conversion { options, runner, sink: Box::new(OnProgress(|_| {})), cancel: None }
+	}
+
+	/// Runs ffmpeg and gifski through `runner` instead of [`SystemRunner`], or [`BackgroundRunner`] with
+	/// [`ConvertOptions::background`], e.g. a [`runner::MockRunner`] in tests.
+	#[must_use]
+	pub fn runner(mut self, runner: impl CommandRunner + 'a) -> Self {
+		self.runner = Box::new(runner);
+		self
+	}
+
+	/// Calls `f` for every [`Progress`] event. Replaces any [`progress_sink`](Self::progress_sink).
+	#[must_use]
+	pub fn on_progress(mut self, f: impl FnMut(Progress) + 'a) -> Self {
+		self.sink = Box::new(OnProgress(f));
+		self
+	}
+
+	/// Reports the progress and the result to `sink`. Replaces any [`on_progress`](Self::on_progress).
+	#[must_use]
+	pub fn progress_sink(mut self, sink: impl ProgressSink + 'a) -> Self {
+		self.sink = Box::new(sink);
+		self
+	}
+
+	/// Lets the conversion be cancelled from another thread with a clone of `token`. The running ffmpeg or gifski is
+	/// killed, whatever was written so far, the frames and a half written gif, is deleted and [`run`](Self::run)
+	/// returns [`ConvertError::Cancelled`].
+	///
+	/// ```no_run
+	/// use gifski_ffmpeg::{CancelToken, Conversion, ConvertOptions};
+	///
+	/// let token = CancelToken::new();
+	/// let worker = std::thread::spawn({
+	///     let token = token.clone();
+	///     move || Conversion::new(ConvertOptions::new("input.mp4")).cancel_token(token).run()
+	/// });
+	/// token.cancel();
+	/// assert!(worker.join().unwrap().is_err());
+	/// ```
+	#[must_use]
+	pub fn cancel_token(mut self, token: CancelToken) -> Self {
+		self.cancel = Some(token);
+		self
+	}
+
+	/// Runs the conversion: extracts the frames, encodes them, writes the poster if asked to, then deletes the frames.
+	/// [`ConvertOptions::stages`] can stop after extracting, or skip it.
+	///
+	/// # Errors
+	/// See [`ConvertError`]. The frames directory is left behind if ffmpeg or gifski fail, but not if the conversion
+	/// is cancelled.
+	pub fn run(mut self) -> Result<ConvertReport> {
+		let started = Instant::now();
+		let (mut written, mut warnings, mut stage) = (Vec::new(), Vec::new(), None);
+		let mut sink = std::mem::replace(&mut self.sink, Box::new(OnProgress(|_| {})));
+		let result = self.convert(&mut written, &mut |p| {
+			match &p {
+				Progress::Warning(w) => warnings.push(w.clone()),
+				Progress::Started(s) => stage = Some(*s),
+				_ => {}
+			}
+			sink.progress(p);
+		});
+		let result = match result {
+			Ok(report) => Ok(ConvertReport { warnings, ..report }),
+			// Cancelling is noticed as ffmpeg or gifski failing, or failing to start.
+			Err(_) if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) => {
+				for path in written.iter().rev() {
+					let _ = if self.options.frames_dir.as_ref() == Some(path) {
+						temp::clear_frames_dir(path)
+					} else if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+				}
+				Err(ConvertError::Cancelled)
+			}
+			Err(e) => Err(e),
+		};
+		if let Some(path) = &self.options.stats_file {
+			let run = stats::Run { input: &self.options.input, result: &result, stage, finished: SystemTime::now(), elapsed: started.elapsed() };
+			if let Err(e) = stats::append(path, &run) { log::warn!("Couldn't add to the stats file {}: {e}", path.display()); }
+		}
+		sink.finished(&result);
+		result
+	}
+
+	/// [`run`](Self::run), adding what it writes to `written` so it can be deleted if the conversion is cancelled.
+	fn convert(&mut self, written: &mut Vec<PathBuf>, progress: &mut dyn FnMut(Progress)) -> Result<ConvertReport> {
+		let started = Instant::now();
+		let cwd = std::env::current_dir().map_err(ConvertError::io("."))?;
+		self.options.make_absolute(&cwd);
+		self.options.expand_social();
+		self.options.expand_sizes()?;
+		let opt = &self.options;
+		let cancellable = self.cancel.clone().map(|token| Cancellable { inner: &*self.runner, token });
+		let runner: &dyn CommandRunner = match &cancellable { Some(c) => c, None => &*self.runner };
+
+		let (extracting, encoding) = (opt.stages != Stages::Encode, opt.stages != Stages::Extract);
+		check(opt, extracting)?;
+		let file_name = opt.input.file_stem().ok_or_else(|| ConvertError::InputNotFound(opt.input.clone()))?;
+		log::debug!("input: {}", &opt.input.display());
+		log::debug!("output: {}", if let Some(o) = &opt.output { o.to_string_lossy().into_owned() } else { format!("No output specified, using {}", file_name.to_string_lossy()) });
+
+		let mut extraction = ffmpeg::Extraction::new(opt)?;
+		extraction.timestamps = opt.source_timing;
+		if opt.background { log_background(&extraction); }
+		let edits = opt.edit_list.as_deref().map(edit::read).transpose()?;
+		let cursor = cursor::read(opt)?;
+
+		// Pre-flight, so a missing tool is reported before anything is touched.
+		let ffmpeg = extracting.then(|| tools::probe(runner, Tool::Ffmpeg)).transpose()?;
+		if extracting && opt.stabilize { stabilize::check_support(runner)?; }
+		let gifski = (encoding && opt.encoder == Encoder::Gifski).then(|| tools::probe(runner, Tool::Gifski)).transpose()?;
+		let plan = if extracting { Some(plan_extraction(runner, opt, &mut extraction, progress)?) } else { None };
+
+		let output = output::parse_output(&output::output_base(&opt.input, opt.resolve_symlinks)?, opt.output.as_deref(), file_name);
+		log::debug!("Output: {}", &output.display());
+		output::check_output(&opt.input, &output, opt.create_parents)?;
+		let given_frames_dir = opt.frames_dir.as_ref().filter(|_| extracting);
+		if let Some(dir) = given_frames_dir { temp::check_frames_dir(dir, &opt.input, &output, &opt.frame_pattern)?; }
+
+		let (temp_dir, frames_dir, frames_location) = frames_dirs(opt, &cwd, &extraction, plan.as_ref(), progress);
+		let extracted = match &plan {
+			Some((_, source, concat, _)) => {
+				let dirs = (temp_dir.as_path(), frames_dir.as_path(), given_frames_dir.is_some());
+				extract(runner, opt, (&mut extraction, source, concat), dirs, (&output, gifski.as_ref()), written, progress)?
+			}
+			None => Extracted::default(),
+		};
+		let (input_info, truncated_from) = plan.map_or((None, None), |(input_info, .., truncated_from)| (Some(input_info), truncated_from));
+		let frame_size = extraction.frame_size.filter(|&(w, h)| w > 0 && h > 0);
+		let frames = opt.frame_pattern.list(&frames_dir)?;
+		let extracted_count = frames.len();
+		let seconds = input_info.as_ref().and_then(|info| extraction.seconds(opt, info));
+		check_extracted(opt, &extraction, &extracted, (&frames_dir, extracted_count, seconds), (extracting, input_info.as_ref()), progress)?;
+		let passed = (edits.as_deref(), cursor.as_ref(), input_info.as_ref());
+		let (mut frames, encode_dir, idle_frames_dropped) = frame_passes(runner, opt, &extraction, (&frames_dir, frames), passed, &extracted.ffmpeg_stderr, progress)?;
+
+		let (fps, quality) = gif_settings(opt, &extraction, &extracted, (extracted_count, seconds), progress)?;
+		if let Some(seconds) = opt.exact_duration {
+			frames = duration::cut_frames(frames, seconds, fps, progress)?;
+		}
+		warn_cut_short(opt, &extracted, extracted_count, fps, progress);
+		// Before gifski's long stage, so there's time to stop it and try what's advised.
+		let busyness = match frame_size {
+			Some(size) if encoding && opt.advice && extracted.overlapped.is_none() => advice::analyze(runner, &frames, size, (fps, opt.pre_quantize.is_some()), progress),
+			_ => None,
+		};
+
+		let (output, encoded, (mp4, poster, contact_sheet), kept_frames) = if encoding {
+			written.push(output.clone());
+			let settings = (fps, quality, extracted.overlapped.map(|(_, _, time)| time));
+			let encoded = encode(runner, opt, (&frames_dir, &encode_dir), &mut frames, settings, (&output, &extracted.ffmpeg_stderr, gifski.as_ref()), progress)?;
+			let timing = (&extraction, extracted.ffmpeg_stderr.as_str(), idle_frames_dropped);
+			let extras = write_extras(runner, opt, (&output, &frames, fps), timing, self.cancel.as_ref(), written, progress)?;
+			let kept_frames = clean_up(opt, (&frames_dir, &encode_dir), (extracting, given_frames_dir.is_some()), progress);
+			(output, encoded, extras, kept_frames)
+		} else {
+			temp::keep(&frames_dir);
+			(frames_dir.clone(), Encoded::default(), (None, None, None), Some(frames_dir))
+		};
+
+		let fps = encoded.downgrade.map_or(fps, |d| d.fps);
+		let frame_count = extracted.chunked_frames.filter(|_| encoding).unwrap_or(frames.len());
+		Ok(ConvertReport {
+			input: opt.input.clone(),
+			output,
+			frames_dir: kept_frames,
+			frames_location,
+			poster,
+			mp4,
+			contact_sheet,
+			fps,
+			quality,
+			frame_count,
+			frame_size,
+			#[allow(clippy::cast_precision_loss)]
+			duration: encoded.retimed.unwrap_or(frame_count as f64 / f64::from(fps)),
+			truncated_from,
+			decode_error: extracted.decode_error,
+			busyness,
+			loop_smoothed: opt.loop_smooth,
+			extract_time: extracted.extract_time,
+			encode_time: encoded.encode_time,
+			total_time: started.elapsed(),
+			comment: encoded.comment,
+			downgrade: encoded.downgrade,
+			comparisons: encoded.comparisons,
+			quality_regions: encoded.quality_regions,
+			input_info,
+			ffmpeg,
+			gifski,
+			// Filled in by `run`.
+			warnings: Vec::new(),
+		})
+	}
+}
+
+/// Refuses an input that isn't what [`ConvertOptions::stages`] takes, and options that don't go together.
+fn check(opt: &ConvertOptions, extracting: bool) -> Result<()> {
+	if !extracting && opt.input.is_file() {
+		let message = format!("encode takes a directory of extracted frames, {} is a file", opt.input.display());
+		return Err(ConvertError::InvalidOption { option: "stage", message });
+	}
+	if !(if extracting { opt.input.is_file() } else { opt.input.is_dir() }) { return Err(ConvertError::InputNotFound(opt.input.clone())); }
+	palette::check(opt)?;
+	quality_map::check(opt)?;
+	frame_filter::check(opt)?;
+	timing::check(opt)?;
+	duration::check(opt)?;
+	cursor::check(opt)
+}
+
+/// Says how [`ConvertOptions::background`] runs ffmpeg and gifski.
+fn log_background(extraction: &ffmpeg::Extraction) {
+	match priority::applied() {
+		Some(applied) => log::debug!("Running ffmpeg and gifski in the background, at {applied}, and ffmpeg on {:?} threads", extraction.threads),
+		None => log::debug!("Lowering the priority isn't supported here, running ffmpeg and gifski as usual on {:?} threads", extraction.threads),
+	}
+}
+
+/// The temp directory, the frames directory, which is the one given, one for this run in the temp directory, or the
+/// input with [`Stages::Encode`], and where it is with [`ConvertOptions::in_memory`]. Warns when the one given is on
+/// a slow drive.
+fn frames_dirs(
+	opt: &ConvertOptions,
+	cwd: &Path,
+	extraction: &ffmpeg::Extraction,
+	plan: Option<&Plan>,
+	progress: &mut dyn FnMut(Progress),
+) -> (PathBuf, PathBuf, Option<FramesLocation>) {
+	let extracting = plan.is_some();
+	let temp_dir = paths::absolute_in(cwd, &std::env::temp_dir());
+	let temp_dir = if extracting && opt.frames_dir.is_none() { local_temp_dir(temp_dir, progress) } else { temp_dir };
+	let (temp_dir, frames_location) = match plan {
+		Some((_, source, ..)) if opt.in_memory => frames_temp_dir(opt, extraction, source, temp_dir, progress),
+		_ => (temp_dir, None),
+	};
+	let frames_dir = if extracting {
+		opt.frames_dir.clone().unwrap_or_else(|| temp::run_dir(&temp_dir))
+	} else {
+		opt.input.clone()
+	};
+	log::debug!("Frames directory: {}", &frames_dir.display());
+	if let (Some(dir), Some((_, source, ..))) = (&opt.frames_dir, plan) {
+		let drive = drive::drive(dir);
+		if drive != Drive::Local {
+			let frames = extraction.expected_frames(opt, source).map_or_else(|| "the frames".to_string(), |n| format!("its {n} frames"));
+			progress(Progress::warning(WarningKind::SlowFramesDir, format!(
+				"The frames directory {} is on {drive}, where writing {frames} one file at a time can make extracting and encoding several times slower. Leave out --frames-dir to use the temp directory",
+				dir.display(),
+			)));
+		}
+	}
+	(temp_dir, frames_dir, frames_location)
+}
+
+/// The temp directory the frames directory goes in with [`ConvertOptions::in_memory`]: the ramdisk if the frames
+/// are estimated to fit on it, otherwise `temp_dir`, saying why.
+fn frames_temp_dir(
+	opt: &ConvertOptions,
+	extraction: &ffmpeg::Extraction,
+	source: &InputInfo,
+	temp_dir: PathBuf,
+	progress: &mut dyn FnMut(Progress),
+) -> (PathBuf, Option<FramesLocation>) {
+	let ramdisk = opt.ramdisk.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_RAMDISK));
+	let needed = estimate::planned(opt, extraction, source).ok().map(|estimate| estimate.frames_size);
+	match temp::check_ramdisk(&ramdisk, needed) {
+		Ok(()) => (ramdisk.clone(), Some(FramesLocation::Memory(ramdisk))),
+		Err(reason) => {
+			progress(Progress::Info(format!("Extracting the frames to {} instead of memory: {reason}", temp_dir.display())));
+			(temp_dir.clone(), Some(FramesLocation::Disk(temp_dir)))
+		}
+	}
+}
+
+/// `temp_dir` if it's on a local drive, otherwise the first of the [`drive::local_temp_dirs`] that is, saying so.
+fn local_temp_dir(temp_dir: PathBuf, progress: &mut dyn FnMut(Progress)) -> PathBuf {
+	let drive = drive::drive(&temp_dir);
+	if drive == Drive::Local { return temp_dir; }
+	let local = drive::local_temp_dirs().into_iter().find(|dir| dir.is_dir() && drive::drive(dir) == Drive::Local);
+	let message = match &local {
+		Some(local) => format!("The temp directory {} is on {drive}, where writing the frames is slow, so they go in {} instead", temp_dir.display(), local.display()),
+		None => format!("The temp directory {} is on {drive}, where writing the frames is slow, and there's no local one to use instead", temp_dir.display()),
+	};
+	progress(Progress::warning(WarningKind::SlowFramesDir, message));
+	local.unwrap_or(temp_dir)
+}
+
+/// Each [`ConvertOptions::concat`] input and how it's extracted.
+type Concat<'o> = Vec<(&'o Path, ffmpeg::Extraction)>;
+
+/// What [`plan_extraction`] found out.
+type Plan<'o> = (InputInfo, InputInfo, Concat<'o>, Option<f64>);
+
+/// Probes the input and works out how it's extracted. Returns what ffprobe found in it, what's extracted from it,
+/// which is the stacked video with [`ConvertOptions::grid`], how each [`ConvertOptions::concat`] input is, and how
+/// long it was if [`ConvertOptions::max_duration`] cut it.
+///
+/// Refuses to go on past [`SOFT_MAX_DURATION`] without [`ConvertOptions::force`].
+fn plan_extraction<'o>(
+	runner: &dyn CommandRunner,
+	opt: &'o ConvertOptions,
+	extraction: &mut ffmpeg::Extraction,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Plan<'o>> {
+	let still_written = growing::check(&opt.input, opt.wait_for_input, progress)?;
+	let input_info = probe::probe_input_with(runner, &opt.input, &extraction.input_options)?;
+	if !still_written { growing::check_duration(&opt.input, &input_info, progress); }
+	if input_info.is_still() {
+		let seconds = opt.still_duration.ok_or_else(|| ConvertError::StillImage(opt.input.clone()))?;
+		if opt.start.is_some() || opt.end.is_some() || opt.duration.is_some() || opt.start_frame.is_some() || opt.end_frame.is_some() {
+			return Err(ConvertError::InvalidOption { option: "still duration", message: "a still image can't be trimmed, --still-duration is how long it's shown".to_string() });
+		}
+		extraction.loop_still(seconds, opt.fps.unwrap_or(STILL_FPS));
+	} else if opt.still_duration.is_some() {
+		progress(Progress::warning(WarningKind::StillDurationIgnored, format!("{} isn't a still image, so --still-duration is ignored", opt.input.display())));
+	}
+	let source = match opt.grid {
+		Some(grid) => grid::stack(runner, opt, grid, &input_info, extraction)?,
+		None => input_info.clone(),
+	};
+	if opt.focus == Some(Focus::Auto) { extraction.focus = focus::detect(runner, opt, extraction, &source, progress)?; }
+	let zoom_at = (extraction.filters.len(), extraction.fps.or(source.video.fps()));
+	extraction.resize(opt, &source.video)?;
+	if let Some(fps) = extraction.limit_frames(opt, &source)? {
+		progress(Progress::Info(format!("Extracting at {fps} fps to stay within {} frames", opt.max_frames.unwrap_or_default())));
+	}
+	if let Some(choice) = fps::apply(extraction, opt, &source.video) {
+		progress(Progress::Info(format!("fps: {choice}")));
+	}
+	if let Some(scale) = opt.scale.filter(|s| s.0 > 1.0) {
+		progress(Progress::warning(WarningKind::Upscaled, format!("scaling up to {}% only makes the gif bigger, not sharper", scale.0 * 100.0)));
+	}
+	let truncated_from = extraction.limit_duration(opt, &source);
+	extraction.zoom_to(opt, &source, zoom_at)?;
+	if opt.waveform.is_some() && !source.has_audio {
+		progress(Progress::warning(WarningKind::NoAudio, format!("{} has no audio, so there's no --waveform", opt.input.display())));
+	} else {
+		waveform::stack(extraction, opt, extraction.fps.or(source.video.fps()))?;
+	}
+	if let Some(seconds) = extraction.seconds(opt, &source).filter(|&s| s > SOFT_MAX_DURATION && !opt.force) {
+		let estimate = estimate::planned(opt, extraction, &source)?;
+		return Err(ConvertError::TooLong { seconds, gif_size: estimate.gif_size });
+	}
+	quantize::log_colors(runner, &opt.input, extraction);
+	let concat = concat_inputs(runner, opt, extraction, &source, progress)?;
+	Ok((input_info, source, concat, truncated_from))
+}
+
+/// Probes the [`ConvertOptions::concat`] inputs and works out how each is extracted after `first`. Warns if
+/// their fps differ from the first input's, which they're resampled to.
+fn concat_inputs<'o>(
+	runner: &dyn CommandRunner,
+	opt: &'o ConvertOptions,
+	first: &ffmpeg::Extraction,
+	first_info: &InputInfo,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Concat<'o>> {
+	let fps = first.fps.or(first_info.video.fps());
+	let mut extractions = Vec::new();
+	let mut rates = Vec::new();
+	for input in &opt.concat {
+		if !input.is_file() { return Err(ConvertError::InputNotFound(input.clone())); }
+		let info = probe::probe_input(runner, input)?;
+		extractions.push((input.as_path(), first.follow_on(opt, &info.video, fps)?));
+		rates.push(info.video.fps());
+	}
+	if let Some(fps) = fps.filter(|&fps| opt.fps.is_none() && rates.iter().any(|&r| r != Some(fps))) {
+		let rates = rates.iter().map(|r| r.map_or_else(|| "unknown".to_string(), |r| r.to_string())).collect::<Vec<_>>();
+		progress(Progress::warning(WarningKind::ConcatResampled, format!("the inputs after the first are at {} fps, so they're resampled to its {fps} fps", rates.join(", "))));
+	}
+	Ok(extractions)
+}
+
+/// Runs [`overlap::extract_and_encode`], or [`overlap::extract_and_encode_chunks`] for
+/// [`ConvertOptions::chunk_seconds`]. The fps has to be known before ffmpeg starts, so it comes from ffprobe
+/// rather than ffmpeg's output. Returns the clamped fps and quality with the result.
+fn overlapped(
+	runner: &dyn CommandRunner,
+	opt: &ConvertOptions,
+	extraction: &ffmpeg::Extraction,
+	input_info: &probe::InputInfo,
+	frames_dir: &Path,
+	output: &Path,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<((f32, u32), overlap::Overlapped)> {
+	let fps = opt.fps.or(extraction.fps).or(input_info.video.fps()).ok_or(ConvertError::FpsDetectionFailed)?;
+	let fps = clamped("fps", fps, 0.0, MAX_FPS, progress);
+	let quality = clamped("quality", opt.quality, 0, 100, progress);
+	progress(Progress::Started(Stage::Encode));
+	progress(Progress::Info(format!("fps: {fps}, quality: {quality}")));
+	// Only a guess for the progress bar: ffmpeg hasn't counted the frames yet.
+	let seconds = extraction.seconds(opt, input_info);
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let frame_estimate = seconds.map_or(0, |s| (s * f64::from(fps)).round().max(0.0) as usize);
+	let gifski_command = gifski::stdin_command(quality, fps, output);
+	let Some(chunk_seconds) = opt.chunk_seconds else {
+		let ffmpeg_command = ffmpeg::overlap_command(&opt.input, frames_dir, extraction);
+		let piped = overlap::extract_and_encode(runner, &ffmpeg_command, &gifski_command, output, frame_estimate, progress)?;
+		return Ok(((fps, quality), piped));
+	};
+
+	let start = extraction.start.unwrap_or(0.0);
+	let seconds = seconds.ok_or(ConvertError::InvalidOption {
+		option: "chunk seconds",
+		message: format!("{} doesn't say how long it is, so it can't be cut into chunks", opt.input.display()),
+	})?;
+	let mut chunk_start = 0.0;
+	let mut next_chunk = |start_number| {
+		if chunk_start >= seconds { return None; }
+		let chunk = ffmpeg::Extraction {
+			start: Some(start + chunk_start).filter(|&s| s > 0.0),
+			duration: Some(chunk_seconds.min(seconds - chunk_start)),
+			start_number: Some(start_number),
+			..extraction.clone()
+		};
+		chunk_start += chunk_seconds;
+		Some(ffmpeg::overlap_command(&opt.input, frames_dir, &chunk))
+	};
+	let piped = overlap::extract_and_encode_chunks(runner, &mut next_chunk, (frames_dir, &extraction.frame_pattern), &gifski_command, output, frame_estimate, progress)?;
+	Ok(((fps, quality), piped))
+}
+
+/// What [`extract`] did.
+#[derive(Debug, Default)]
+struct Extracted {
+	ffmpeg_stderr: String,
+	extract_time: Duration,
+	/// The fps, quality and encode time, when gifski encoded the frames as they came.
+	overlapped: Option<(f32, u32, Duration)>,
+	/// How many frames there were, when they were deleted chunk by chunk as they were encoded.
+	chunked_frames: Option<usize>,
+	/// See [`ffmpeg::Extracted`].
+	low_on_space: Option<u64>,
+	decode_error: Option<String>,
+	/// How many frames ffmpeg was expected to extract, if that could be worked out.
+	expected_frames: Option<usize>,
+}
+
+/// Extracts the frames of `source`, then each [`ConvertOptions::concat`] input's after them, into `frames_dir`,
+/// which is emptied first unless it was `given`, after deleting what crashed runs left in `temp_dir`. With
+/// [`ConvertOptions::overlap`] or [`ConvertOptions::chunk_seconds`] gifski encodes them into `output` as they come.
+///
+/// Stops at a [`ConvertOptions::strict_space`] drive getting low.
+fn extract(
+	runner: &dyn CommandRunner,
+	opt: &ConvertOptions,
+	(extraction, source, concat): (&mut ffmpeg::Extraction, &InputInfo, &Concat),
+	(temp_dir, frames_dir, given): (&Path, &Path, bool),
+	(output, gifski): (&Path, Option<&ToolInfo>),
+	written: &mut Vec<PathBuf>,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Extracted> {
+	if opt.gc {
+		let reclaimed = temp::collect(temp_dir);
+		if reclaimed.dirs > 0 {
+			progress(Progress::Info(format!(
+				"Deleted {} frames directories left behind by earlier runs, {}",
+				reclaimed.dirs, disk::human_size(reclaimed.bytes),
+			)));
+		}
+	}
+	if given {
+		let _ = temp::prepare_frames_dir(frames_dir);
+	} else {
+		let _ = fs::remove_dir_all(frames_dir);
+		let _ = fs::create_dir_all(frames_dir);
+	}
+	written.push(frames_dir.to_path_buf());
+	log::debug!("Created frames directory.");
+
+	if opt.stabilize {
+		progress(Progress::Started(Stage::Stabilize));
+		let stage = Instant::now();
+		let transforms = frames_dir.join(stabilize::TRANSFORMS_FILE);
+		stabilize::detect(runner, &opt.input, extraction, &transforms)?;
+		extraction.stabilize = Some(transforms);
+		progress(Progress::Finished(Stage::Stabilize, stage.elapsed()));
+	}
+	progress(Progress::Started(Stage::Extract));
+	let stage = Instant::now();
+	if opt.overlap || opt.chunk_seconds.is_some() {
+		written.push(output.to_path_buf());
+		let (settings, piped) = output::staged(output, frames_dir, |staging| {
+			let (settings, piped) = overlapped(runner, opt, extraction, source, frames_dir, staging, progress)?;
+			if let Some(comment) = comment_text(opt, settings.1, settings.0, gifski) { output::write_comment(staging, &comment)?; }
+			Ok((settings, piped))
+		})?;
+		return Ok(Extracted {
+			ffmpeg_stderr: piped.ffmpeg_stderr,
+			extract_time: piped.extract_time,
+			overlapped: Some((settings.0, settings.1, piped.encode_time)),
+			chunked_frames: piped.chunked_frames,
+			..Extracted::default()
+		});
+	}
+	let expected_frames = extraction.expected_frames(opt, source);
+	let extracted = ffmpeg::extract_frames(runner, &opt.input, frames_dir, extraction, expected_frames, opt.reserve_space, progress)?;
+	let (mut low_on_space, mut decode_error) = (extracted.low_on_space, extracted.decode_error);
+	for (input, next) in concat {
+		if low_on_space.is_some() || decode_error.is_some() { break; }
+		// Numbered on from the frames already there, so they sort after them.
+		let next = ffmpeg::Extraction { start_number: Some(opt.frame_pattern.list(frames_dir)?.len() + 1), ..next.clone() };
+		let more = ffmpeg::extract_frames(runner, input, frames_dir, &next, None, opt.reserve_space, progress)?;
+		(low_on_space, decode_error) = (more.low_on_space, more.decode_error);
+	}
+	if let Some(free) = low_on_space.filter(|_| opt.strict_space) {
+		return Err(ConvertError::LowDiskSpace { dir: frames_dir.to_path_buf(), free, reserve: opt.reserve_space });
+	}
+	progress(Progress::Finished(Stage::Extract, stage.elapsed()));
+	Ok(Extracted { ffmpeg_stderr: extracted.stderr, extract_time: stage.elapsed(), low_on_space, decode_error, expected_frames, ..Extracted::default() })
+}
+
+/// Fails when there are no frames in `frames_dir` to go on with, and warns when `count` of them are too few for the
+/// `seconds` of video they came from.
+fn check_extracted(
+	opt: &ConvertOptions,
+	extraction: &ffmpeg::Extraction,
+	extracted: &Extracted,
+	(frames_dir, count, seconds): (&Path, usize, Option<f64>),
+	(extracting, input_info): (bool, Option<&InputInfo>),
+	progress: &mut dyn FnMut(Progress),
+) -> Result<()> {
+	// Checked here rather than left to gifski, which only says its glob matched nothing.
+	if count == 0 && extracting && extracted.overlapped.is_none() {
+		if let Some(free) = extracted.low_on_space { return Err(ConvertError::LowDiskSpace { dir: frames_dir.to_path_buf(), free, reserve: opt.reserve_space }); }
+		return Err(ConvertError::NoFramesExtracted { range: extraction.range(opt, input_info), filters: extraction.filters.clone() });
+	}
+	if extracted.low_on_space.is_none() && extracted.decode_error.is_none() && ffmpeg::too_few_frames(count, extracted.expected_frames, seconds) {
+		progress(Progress::warning(WarningKind::FewFrames, format!(
+			"Only {count} frames came out of {:.1}s of video, check --start/--end and the filters. It was {}",
+			seconds.unwrap_or_default(), extraction.range(opt, input_info),
+		)));
+	}
+	if count == 0 && !extracting {
+		let message = format!("encode found no {} in {}", opt.frame_pattern.glob(), frames_dir.display());
+		return Err(ConvertError::InvalidOption { option: "stage", message });
+	}
+	Ok(())
+}
+
+/// The passes over the `frames` in `frames_dir` between extracting and encoding them, in the order they run. Returns
+/// the frames left, the directory they're encoded from, which is another one with an edit list, and how many
+/// [`ConvertOptions::trim_idle`] dropped.
+fn frame_passes(
+	runner: &dyn CommandRunner,
+	opt: &ConvertOptions,
+	extraction: &ffmpeg::Extraction,
+	(frames_dir, mut frames): (&Path, Vec<PathBuf>),
+	(edits, cursor, input_info): (Option<&[edit::Entry]>, Option<&cursor::Cursor>, Option<&InputInfo>),
+	ffmpeg_stderr: &str,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<(Vec<PathBuf>, PathBuf, usize)> {
+	if opt.exact_end {
+		let (left, dropped) = ffmpeg::drop_past_end(extraction, ffmpeg_stderr, frames)?;
+		if dropped > 0 { progress(Progress::Info(format!("Deleted {dropped} frames past the end, {} left", left.len()))); }
+		frames = left;
+	}
+	if let Some(threshold) = opt.drop_flash_frames {
+		flash::drop_flashes(runner, &frames, &opt.frame_pattern, threshold, progress)?;
+	}
+	// Before the edit list, which may link a frame more than once.
+	if let Some(filter) = &opt.frame_filter {
+		frame_filter::filter_frames(runner, filter, &frames, opt.jobs, progress)?;
+	}
+	if let (Some(cursor), Some(info)) = (cursor, input_info) {
+		let source_fps = f64::from(ffmpeg::parse_fps(ffmpeg_stderr)?);
+		let fps = extraction.fps.map_or(source_fps, f64::from);
+		let mapping = cursor::Mapping::new(opt, extraction, &info.video)?;
+		cursor.draw(mapping, (&frames, &opt.frame_pattern), (extraction.start_seconds(opt, source_fps), fps), opt.jobs, progress)?;
+	}
+	// Where the frames that are encoded are.
+	let encode_dir = match edits {
+		Some(entries) => {
+			frames = edit::apply(frames_dir, (&frames, &opt.frame_pattern), entries)?;
+			progress(Progress::Info(format!("Picked {} frames with the edit list", frames.len())));
+			frames_dir.join(edit::EDITED_DIR)
+		}
+		None => frames_dir.to_path_buf(),
+	};
+	let mut idle_frames_dropped = 0;
+	if opt.trim_idle {
+		(frames, idle_frames_dropped) = idle::trim_frames(runner, frames, &opt.frame_pattern, opt.trim_idle_threshold, progress)?;
+	}
+	if let Some(count) = opt.loop_smooth {
+		frames = smooth::smooth_loop(runner, frames, count, progress)?;
+	}
+	Ok((frames, encode_dir, idle_frames_dropped))
+}
+
+/// The fps and quality the gif is encoded at, clamped to what gifski takes. The fps is the one given or extracted at,
+/// otherwise the input's, or what `count` frames over `seconds` of it work out to when that's well off what it says.
+fn gif_settings(
+	opt: &ConvertOptions,
+	extraction: &ffmpeg::Extraction,
+	extracted: &Extracted,
+	(count, seconds): (usize, Option<f64>),
+	progress: &mut dyn FnMut(Progress),
+) -> Result<(f32, u32)> {
+	if let Some((fps, quality, _)) = extracted.overlapped { return Ok((fps, quality)); }
+	let fps = match opt.fps.or(extraction.fps) {
+		Some(f) => f,
+		None if opt.keyframes_only => options::KEYFRAME_FPS,
+		None => {
+			let detected = ffmpeg::parse_fps(&extracted.ffmpeg_stderr)?;
+			// Cut short, there are fewer frames than the length says.
+			let seconds = seconds.filter(|_| !opt.trust_metadata && extracted.low_on_space.is_none() && extracted.decode_error.is_none());
+			match seconds.and_then(|seconds| fps::measured(detected, count, seconds).map(|measured| (seconds, measured))) {
+				Some((seconds, measured)) => {
+					progress(Progress::Info(format!(
+						"The input says it's {detected}fps, but {seconds:.2}s of it made {count} frames, so using the {measured}fps they play at. Pass --trust-metadata to use {detected}fps",
+					)));
+					measured
+				}
+				None => detected,
+			}
+		}
+	};
+	Ok((clamped("fps", fps, 0.0, MAX_FPS, progress), clamped("quality", opt.quality, 0, 100, progress)))
+}
+
+/// Warns that the gif ends after the `count` frames extracted, when extracting stopped early.
+fn warn_cut_short(opt: &ConvertOptions, extracted: &Extracted, count: usize, fps: f32, progress: &mut dyn FnMut(Progress)) {
+	#[allow(clippy::cast_precision_loss)]
+	let seconds = count as f64 / f64::from(fps);
+	if let Some(free) = extracted.low_on_space {
+		progress(Progress::warning(WarningKind::LowDiskSpace, format!(
+			"Stopped extracting at {seconds:.1}s with {} left on the drive the frames are on, under the {} kept free, so the gif ends there",
+			disk::human_size(free), disk::human_size(opt.reserve_space),
+		)));
+	}
+	if extracted.decode_error.is_some() {
+		progress(Progress::warning(WarningKind::DecodeErrorTolerated, format!(
+			"ffmpeg failed after {count} frames, {seconds:.1}s of the video, so the gif is cut short there. Leave out --tolerate-decode-errors to fail instead",
+		)));
+	}
+}
+
+/// What [`encode`] made.
+#[derive(Debug, Default)]
+struct Encoded {
+	comment: Option<String>,
+	downgrade: Option<Downgrade>,
+	/// How long the gif plays for once its delays were rewritten, with `source_timing` or `exact_duration`.
+	retimed: Option<f64>,
+	comparisons: Vec<QualityRun>,
+	quality_regions: Vec<RegionRun>,
+	encode_time: Duration,
+}
+
+/// Encodes the `frames` in `encode_dir` into `output`, or a gif for each quality or width to compare, or each part of
+/// the [`ConvertOptions::quality_map`] at its own quality. With the encode time of an `overlapped` extraction,
+/// gifski already did.
+fn encode(
+	runner: &dyn CommandRunner,
+	opt: &ConvertOptions,
+	(frames_dir, encode_dir): (&Path, &Path),
+	frames: &mut [PathBuf],
+	(fps, quality, overlapped): (f32, u32, Option<Duration>),
+	(output, ffmpeg_stderr, gifski): (&Path, &str, Option<&ToolInfo>),
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Encoded> {
+	let gifski_command = |quality, fps, width, output: &Path| -> Result<CommandLine> {
+		let command = gifski::encode_command(quality, fps, width, (encode_dir, &opt.frame_pattern), output);
+		// Zero-padded frames sort in order under the glob. Others, or a glob that gets other files too, go by name.
+		let glob_is_off = !opt.frame_pattern.sorts_by_name() || opt.frame_pattern.glob_catches_others(encode_dir);
+		Ok(if glob_is_off { gifski::listed(command, encode_dir, &opt.frame_pattern.list(encode_dir)?) } else { command })
+	};
+	let stage = Instant::now();
+	let mut encoded = Encoded::default();
+	if overlapped.is_some() {
+		// gifski already ran alongside ffmpeg, and the comment is in.
+		encoded.comment = comment_text(opt, quality, fps, gifski);
+	} else if !opt.quality_map.is_empty() {
+		progress(Progress::Started(Stage::Encode));
+		progress(Progress::Info(format!("fps: {fps}, quality: {quality} outside the quality map")));
+		let segments = quality_map::segments(&opt.quality_map, quality, frames.len(), fps)?;
+		encoded.comment = comment_text(opt, quality, fps, gifski);
+		encoded.quality_regions = output::staged(output, frames_dir, |staging| {
+			let regions = quality_map::encode(runner, &segments, frames, fps, frames_dir, staging, progress)?;
+			if let Some(comment) = &encoded.comment { output::write_comment(staging, comment)?; }
+			Ok(regions)
+		})?;
+		progress(Progress::Finished(Stage::Encode, stage.elapsed()));
+	} else if opt.compare_quality.is_empty() && opt.sizes.is_empty() {
+		progress(Progress::Started(Stage::Encode));
+		progress(Progress::Info(format!("fps: {}, quality: {}", &fps, &quality)));
+		let kept = frames.to_vec();
+		(encoded.downgrade, encoded.comment, encoded.retimed) = output::staged(output, frames_dir, |staging| {
+			let downgrade = retry::encode(encode_dir, frames, fps, opt.retry, progress, &mut |fps, width, count, progress| match opt.encoder {
+				Encoder::Gifski => gifski::encode(runner, &gifski_command(quality, fps, width, staging)?, staging, count, progress),
+				// ffmpeg never runs out of memory the way gifski does, so it's never retried with fewer frames.
+				Encoder::Ffmpeg => palette::encode(runner, fps, width, opt, &kept, staging),
+			})?;
+			let retimed = if opt.source_timing {
+				timing::retime(staging, &opt.frame_pattern.list(encode_dir)?, &opt.frame_pattern, ffmpeg_stderr, progress)?
+			} else if let Some(seconds) = opt.exact_duration {
+				duration::hold_last(staging, duration::ticks(seconds))?;
+				#[allow(clippy::cast_precision_loss)]
+				Some(duration::ticks(seconds) as f64 / 100.0)
+			} else { None };
+			let comment = comment_text(opt, quality, downgrade.map_or(fps, |d| d.fps), gifski);
+			if let Some(comment) = &comment { output::write_comment(staging, comment)?; }
+			Ok((downgrade, comment, retimed))
+		})?;
+		progress(Progress::Finished(Stage::Encode, stage.elapsed()));
+	} else {
+		let variants = if opt.sizes.is_empty() {
+			opt.compare_quality.iter().map(|&q| (q, None)).collect::<Vec<_>>()
+		} else {
+			opt.sizes.iter().map(|&width| (quality, Some(width))).collect()
+		};
+		let count = frames.len();
+		let mut encode = |quality: u32, width: Option<u32>, output: &Path, progress: &mut dyn FnMut(Progress)| -> Result<Option<String>> {
+			output::staged(output, frames_dir, |staging| {
+				gifski::encode(runner, &gifski_command(quality, fps, width, staging)?, staging, count, progress)?;
+				let comment = comment_text(opt, quality, fps, gifski);
+				if let Some(comment) = &comment { output::write_comment(staging, comment)?; }
+				Ok(comment)
+			})
+		};
+		encoded.comparisons = encode_variants(progress, &variants, fps, output, &mut encode)?;
+	}
+	encoded.encode_time = overlapped.unwrap_or_else(|| stage.elapsed());
+	Ok(encoded)
+}
+
+/// Writes what goes with the gif: the [`ConvertOptions::also_mp4`], [`ConvertOptions::poster`] and
+/// [`ConvertOptions::contact_sheet`], returned in that order. The poster's time is mapped to a frame with the
+/// `extraction` and how many idle frames were trimmed. With [`ConvertOptions::social`] the MP4 and poster failing
+/// only loses them, unless it was cancelled.
+fn write_extras(
+	runner: &dyn CommandRunner,
+	opt: &ConvertOptions,
+	(output, frames, fps): (&Path, &[PathBuf], f32),
+	(extraction, ffmpeg_stderr, idle_frames_dropped): (&ffmpeg::Extraction, &str, usize),
+	cancel: Option<&CancelToken>,
+	written: &mut Vec<PathBuf>,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<(Option<PathBuf>, Option<PathBuf>, Option<PathBuf>)> {
+	let social_piece = |result: Result<PathBuf>, what: &str, progress: &mut dyn FnMut(Progress)| match result {
+		Err(e) if opt.social && !cancel.is_some_and(CancelToken::is_cancelled) => {
+			progress(Progress::warning(WarningKind::SocialPieceFailed, format!("Couldn't write the {what}, {e}")));
+			Ok(None)
+		}
+		result => result.map(Some),
+	};
+	let mp4 = if opt.also_mp4 {
+		progress(Progress::Info(format!("Writing {}", mp4::mp4_path(output).display())));
+		written.push(mp4::mp4_path(output));
+		social_piece(mp4::write_mp4(runner, (frames, &opt.frame_pattern), fps, output, progress), "MP4", progress)?
+	} else { None };
+	let poster = if let Some(p) = opt.poster {
+		progress(Progress::Started(Stage::Poster));
+		let stage = Instant::now();
+		let poster = output::write_poster(runner, (p, opt.poster_format), frames, output, |t| {
+			// ffmpeg extracts at the source frame rate unless it was resampled, so that maps timestamps to frames.
+			let source_fps = f64::from(ffmpeg::parse_fps(ffmpeg_stderr)?);
+			let fps = extraction.fps.map_or(source_fps, f64::from);
+			#[allow(clippy::cast_precision_loss)]
+			Ok((t - extraction.start_seconds(opt, source_fps)) * fps - (idle_frames_dropped + opt.loop_smooth.unwrap_or(0)) as f64)
+		// Trimmed frames already start at the first interesting one.
+		}, || if opt.trim_idle { Ok(0) } else { idle::first_interesting(runner, frames, &opt.frame_pattern, opt.trim_idle_threshold) });
+		let poster = social_piece(poster, "poster", progress)?;
+		if let Some(poster) = &poster {
+			progress(Progress::Finished(Stage::Poster, stage.elapsed()));
+			written.push(poster.clone());
+		}
+		poster
+	} else { None };
+	let contact_sheet = match opt.contact_sheet {
+		Some(grid) => {
+			written.push(sheet::sheet_path(output));
+			sheet::write_sheet(runner, grid, frames, fps, output, progress)?
+		}
+		None => None,
+	};
+	Ok((mp4, poster, contact_sheet))
+}
+
+/// Deletes the frames once they're encoded, unless [`ConvertOptions::keep_frames`], returning where they're kept
+/// then. A frames directory that was `given` is only emptied, and of the input of [`Stages::Encode`] only the frames
+/// go, with the `encode_dir` of an edit list.
+fn clean_up(opt: &ConvertOptions, (frames_dir, encode_dir): (&Path, &Path), (extracting, given): (bool, bool), progress: &mut dyn FnMut(Progress)) -> Option<PathBuf> {
+	if opt.keep_frames {
+		// With --stage encode it's the input, which is the user's to mark.
+		if extracting { temp::keep(frames_dir); }
+		return Some(frames_dir.to_path_buf());
+	}
+	progress(Progress::Started(Stage::Cleanup));
+	let stage = Instant::now();
+	if !extracting {
+		// The user's directory: only the frames go, and the edit list's links to them.
+		let removed = temp::remove_frames(frames_dir, &opt.frame_pattern);
+		if encode_dir != frames_dir { let _ = fs::remove_dir_all(encode_dir); }
+		log::debug!("Deleted the frames in {}: {}.", frames_dir.display(), if removed.is_ok() { "success" } else { "failed" });
+	} else if given {
+		let emptied = temp::clear_frames_dir(frames_dir);
+		log::debug!("Emptied frames directory: {}.", if emptied.is_ok() { "success" } else { "failed" });
+	} else {
+		let _ = fs::remove_dir_all(frames_dir);
+		log::debug!("Deleted frames directory: {}.", if frames_dir.exists() { "failed" } else { "success" });
+	}
+	progress(Progress::Finished(Stage::Cleanup, stage.elapsed()));
+	None
+}
+
+/// What [`ConvertOptions::comment`] says to write into a gif encoded with these settings.
+fn comment_text(opt: &ConvertOptions, quality: u32, fps: f32, gifski: Option<&ToolInfo>) -> Option<String> {
+	match &opt.comment {
+		Comment::Auto if opt.deterministic => Some(format!("{}, quality {quality}, fps {fps}", env!("CARGO_PKG_NAME"))),
+		Comment::Auto => Some(format!(
+			"{} {} (gifski {}), quality {quality}, fps {fps}",
+			env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), gifski.and_then(|g| g.version.as_deref()).unwrap_or("unknown"),
+		)),
+		Comment::Text(text) => Some(text.clone()),
+		Comment::Off => None,
+	}
+}
+
+/// Encodes the frames at the given quality into the given gif, returning the comment written into it.
+type EncodeFn<'a> = dyn FnMut(u32, Option<u32>, &Path, &mut dyn FnMut(Progress)) -> Result<Option<String>> + 'a;
+
+/// Clamps `value` to `min..=max`, warning if that changed it.
+fn clamped<T: PartialOrd + Copy + std::fmt::Display>(name: &str, value: T, min: T, max: T, progress: &mut dyn FnMut(Progress)) -> T {
+	let clamped = if value < min { min } else if value > max { max } else { value };
+	if clamped != value { progress(Progress::warning(WarningKind::OutOfRange, format!("{name} {value} is out of range, using {clamped}"))); }
+	clamped
+}
+
+/// Encodes the same frames once per quality, or width, of `variants` with `encode`. A failed encode doesn't stop the others, only all of
+/// them failing is an error.
+fn encode_variants(
+	progress: &mut dyn FnMut(Progress),
+	variants: &[(u32, Option<u32>)],
+	fps: f32,
+	output: &Path,
+	encode: &mut EncodeFn,
+) -> Result<Vec<QualityRun>> {
+	let mut runs = Vec::new();
+	let mut first_error = None;
+	for &(quality, width) in variants {
+		let quality = clamped("quality", quality, 0, 100, progress);
+		let path = width.map_or_else(|| output::quality_variant(output, quality), |width| output::size_variant(output, width));
+		progress(Progress::Started(Stage::Encode));
+		progress(Progress::Info(match width {
+			Some(width) => format!("fps: {fps}, quality: {quality}, width: {width}"),
+			None => format!("fps: {fps}, quality: {quality}"),
+		}));
+		let stage = Instant::now();
+		let result = encode(quality, width, &path, progress)
+			.and_then(|comment| Ok((comment, fs::metadata(&path).map_err(ConvertError::io(&path))?.len())));
+		let encode_time = stage.elapsed();
+		let run = QualityRun { quality, width, output: path, size: None, error: None, comment: None, encode_time };
+		match result {
+			Ok((comment, size)) => {
+				progress(Progress::Finished(Stage::Encode, encode_time));
+				runs.push(QualityRun { size: Some(size), comment, ..run });
+			}
+			Err(e) => {
+				let variant = width.map_or_else(|| format!("quality {quality}"), |width| format!("{width}px wide"));
+				progress(Progress::warning(WarningKind::ComparisonFailed, format!("{variant} failed: {e}")));
+				runs.push(QualityRun { error: Some(e.to_string()), ..run });
+				first_error.get_or_insert(e);
+			}
+		}
+	}
+	match first_error {
+		Some(e) if runs.iter().all(|r| r.error.is_some()) => Err(e),
+		_ => Ok(runs),
+	}
+}
+
+/// Converts a video with the given options. Shorthand for `Conversion::new(options).run()`.
+///
+/// # Errors
+/// See [`Conversion::run`].
+pub fn convert(options: ConvertOptions) -> Result<ConvertReport> {
+	Conversion::new(options).run()
+}
+
+/// A new, empty `<TEMP>/gifski-ffmpeg-test-<name>` for one test's files, so tests running at once don't share any.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("gifski-ffmpeg-test-{name}"));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use runner::{CommandOutput, MockRunner};
+
+	const FFMPEG_STDERR: &str = "Stream #0:0(und): Video: h264 (High), yuv420p, 640x360, 24 fps, 24 tbr";
+
+	/// A mock that has already answered the pre-flight version checks and input probe.
+	fn mock() -> MockRunner {
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright (c) 2000-2023 the FFmpeg developers"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+		mock
+	}
+
+	/// Mocks an ffmpeg that writes `count` frames wherever it's told to.
+	fn fake_ffmpeg(mock: &MockRunner, count: usize) {
+		mock.respond_with("ffmpeg", move |command| {
+			let pattern = PathBuf::from(command.args.last().unwrap());
+			for i in 1..=count {
+				fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?;
+			}
+			Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+		});
+	}
+
+	fn options(name: &str) -> (ConvertOptions, PathBuf) {
+		let dir = test_dir(name);
+		fs::write(dir.join("input.mp4"), b"").unwrap();
+		let mut options = ConvertOptions::new(dir.join("input.mp4"));
+		options.frames_dir = Some(dir.join("frames"));
+		growing::written_an_hour_ago(&dir.join("input.mp4"));
+		// The mocked gifski doesn't write a gif to put it in.
+		options.comment = Comment::Off;
+		// The fake ffmpeg makes a few frames, however long the input says it is.
+		options.trust_metadata = true;
+		// The fake frames are empty, there's nothing to advise on.
+		options.advice = false;
+		(options, dir)
+	}
+
+	#[test]
+	fn runs_ffmpeg_then_gifski_with_detected_fps() {
+		let (options, dir) = options("detected-fps");
+		let mock = mock();
+		fake_ffmpeg(&mock, 3);
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let frames = dir.join("frames");
+		let calls = mock.calls();
+		assert_eq!(calls.len(), 5);
+		assert_eq!(calls[0].args_lossy(), ["-version"]);
+		assert_eq!(calls[1].args_lossy(), ["--version"]);
+		assert_eq!(calls[2].program_name(), "ffprobe");
+		assert_eq!(calls[3].args_lossy(), ["-i", &dir.join("input.mp4").display().to_string(), &format!("{}/frame%04d.png", frames.display())]);
+		assert_eq!(calls[4].args_lossy(), [
+			"--fps", "24", "--quality", "100",
+			"-o", &dir.join(".input-gif.partial.gif").display().to_string(),
+			&format!("{}/frame*.png", frames.display()),
+		]);
+		assert!(dir.join("input-gif.gif").exists() && !dir.join(".input-gif.partial.gif").exists(), "moved into place");
+		assert_eq!(report.frame_count, 3);
+		assert_eq!(report.ffmpeg.as_ref().and_then(|f| f.version.as_deref()), Some("6.0"));
+		assert_eq!(fs::read_dir(&frames).unwrap().count(), 0, "emptied, but it's the user's to delete");
+	}
+
+	#[test]
+	fn the_fps_the_frames_play_at_beats_a_wrong_detected_one() {
+		let (mut options, _dir) = options("measured-fps");
+		options.trust_metadata = false;
+		let runner = mock();
+		// 2 seconds of the input, which says it's 24fps.
+		fake_ffmpeg(&runner, 60);
+		let mut infos = Vec::new();
+
+		Conversion::new(options.clone()).runner(&runner).on_progress(|p| if let Progress::Info(i) = p { infos.push(i); }).run().unwrap();
+
+		assert_eq!(runner.calls_to("gifski")[1].args_lossy()[..2], ["--fps", "30"]);
+		assert!(infos.iter().any(|i| i.starts_with("The input says it's 24fps, but 2.00s of it made 60 frames, so using the 30fps")), "{infos:?}");
+
+		options.trust_metadata = true;
+		let runner = mock();
+		fake_ffmpeg(&runner, 60);
+		Conversion::new(options).runner(&runner).run().unwrap();
+		assert_eq!(runner.calls_to("gifski")[1].args_lossy()[..2], ["--fps", "24"]);
+	}
+
+	#[test]
+	fn cancelling_deletes_the_frames_and_tells_the_sink() {
+		struct Recorder<'a>(&'a mut Vec<String>);
+		impl ProgressSink for Recorder<'_> {
+			fn stage_started(&mut self, stage: Stage) { self.0.push(format!("{stage:?}")); }
+			fn finished(&mut self, result: &Result<ConvertReport>) { self.0.push(result.as_ref().map_or_else(ToString::to_string, |_| "done".into())); }
+		}
+
+		let (options, dir) = options("cancel");
+		let mock = mock();
+		let token = CancelToken::new();
+		let cancel = token.clone();
+		mock.respond_with("ffmpeg", move |command| {
+			fs::write(PathBuf::from(command.args.last().unwrap()).with_file_name("frame0001.png"), b"")?;
+			// Halfway through the extraction.
+			cancel.cancel();
+			Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+		});
+
+		let mut events = Vec::new();
+		let result = Conversion::new(options).runner(&mock).progress_sink(Recorder(&mut events)).cancel_token(token).run();
+		assert!(matches!(result, Err(ConvertError::Cancelled)), "{result:?}");
+		assert_eq!(events, ["Extract", "The conversion was cancelled."]);
+		assert_eq!(fs::read_dir(dir.join("frames")).unwrap().count(), 0);
+		assert_eq!(mock.calls_to("gifski").len(), 1, "only the version check");
+	}
+
+	#[test]
+	fn extract_stage_keeps_the_frames_without_gifski() {
+		let (mut options, dir) = options("stage-extract");
+		options.stages = Stages::Extract;
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+		fake_ffmpeg(&mock, 2);
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+		assert!(mock.calls_to("gifski").is_empty());
+		assert_eq!(report.frames_dir.as_deref(), Some(dir.join("frames").as_path()));
+		assert_eq!((report.frame_count, report.fps.to_string()), (2, "24".to_string()));
+		assert!(dir.join("frames/frame0002.png").exists());
+	}
+
+	#[test]
+	fn encode_stage_encodes_a_frames_directory_without_ffmpeg() {
+		let (mut options, dir) = options("stage-encode");
+		fs::create_dir_all(dir.join("shots")).unwrap();
+		fs::write(dir.join("shots/frame0001.png"), b"").unwrap();
+		options.input = dir.join("shots");
+		options.stages = Stages::Encode;
+		options.fps = Some(12.0);
+		options.keep_frames = true;
+		let mock = MockRunner::new();
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+
+		let report = Conversion::new(options.clone()).runner(&mock).run().unwrap();
+		let calls = mock.calls();
+		assert!(calls.iter().all(|c| c.program_name() == "gifski"), "{calls:?}");
+		assert_eq!(calls[1].args_lossy()[..2], ["--fps", "12"]);
+		assert_eq!(report.output, dir.join("shots-gif.gif"));
+		assert!(dir.join("shots/frame0001.png").exists(), "--keep-frames");
+		assert!(report.input_info.is_none() && report.ffmpeg.is_none());
+
+		options.input = dir.join("input.mp4");
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(err.to_string().contains("is a file"), "{err}");
+	}
+
+	#[test]
+	fn encode_stage_deletes_only_the_frames_from_its_input() {
+		let (mut options, dir) = options("stage-encode-cleanup");
+		fs::create_dir_all(dir.join("shots/album")).unwrap();
+		for name in ["frame0001.png", "frame0002.png", "holiday.jpg", "album/frame0001.png"] {
+			fs::write(dir.join("shots").join(name), b"").unwrap();
+		}
+		options.input = dir.join("shots");
+		options.stages = Stages::Encode;
+		options.fps = Some(12.0);
+		let mock = MockRunner::new();
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+
+		let report = Conversion::new(options.clone()).runner(&mock).run().unwrap();
+		assert_eq!(report.frame_count, 2);
+		assert!(report.frames_dir.is_none());
+		assert!(!dir.join("shots/frame0001.png").exists() && !dir.join("shots/frame0002.png").exists());
+		assert!(dir.join("shots/holiday.jpg").exists() && dir.join("shots/album/frame0001.png").exists());
+
+		// Named like a run directory, still not marked as kept by this.
+		let run = dir.join("run-60-1-0");
+		fs::create_dir(&run).unwrap();
+		fs::write(run.join("frame0001.png"), b"").unwrap();
+		options.input = run.clone();
+		options.keep_frames = true;
+		Conversion::new(options).runner(&mock).run().unwrap();
+		assert_eq!(fs::read_dir(&run).unwrap().count(), 1, "no .keep");
+	}
+
+	#[test]
+	fn custom_output_and_clamped_settings_reach_gifski() {
+		let (mut options, dir) = options("custom-output");
+		options.output = Some("clip".into());
+		options.fps = Some(120.0);
+		options.quality = 150;
+		let mock = mock();
+		fake_ffmpeg(&mock, 10);
+		let mut warnings = Vec::new();
+
+		let report = Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Warning(w) = p { warnings.push(w.message); })
+			.run()
+			.unwrap();
+
+		let gifski = &mock.calls_to("gifski")[1];
+		assert_eq!(gifski.args_lossy()[..6], ["--fps", "50", "--quality", "100", "-o", &dir.join(".clip.partial.gif").display().to_string()]);
+		assert_eq!(report.output, dir.join("clip.gif"));
+		assert_eq!(warnings, ["fps 120 is out of range, using 50", "quality 150 is out of range, using 100"]);
+		assert!(report.warnings.iter().all(|w| w.kind == WarningKind::OutOfRange) && report.warnings.len() == 2, "{:?}", report.warnings);
+	}
+
+	#[test]
+	fn fast_sources_are_resampled_to_the_default_cap() {
+		let (options, _dir) = options("auto-fps");
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE.replace("24/1", "60/1")));
+		fake_ffmpeg(&mock, 2);
+		let mut infos = Vec::new();
+
+		let report = Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Info(i) = p { infos.push(i); })
+			.run()
+			.unwrap();
+
+		assert!(mock.calls_to("ffmpeg")[1].args_lossy().join(" ").contains("-vf fps=30"));
+		assert_eq!(mock.calls_to("gifski")[1].args_lossy()[..2], ["--fps", "30"]);
+		assert_eq!(report.fps.to_string(), "30");
+		assert_eq!(infos[0], "fps: source 60fps → using 30fps default, pass --fps 60 to keep");
+	}
+
+	#[test]
+	fn concatenated_inputs_are_numbered_on_and_fitted_to_the_first() {
+		let (mut options, dir) = options("concat");
+		fs::write(dir.join("second.mp4"), b"").unwrap();
+		options.concat = vec![dir.join("second.mp4")];
+		let mock = mock();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE.replace("640", "1280").replace("360", "720").replace("24/1", "30/1")));
+		for _ in 0..2 {
+			mock.respond_with("ffmpeg", |command| {
+				let args = command.args_lossy();
+				let first: usize = args.iter().position(|a| a == "-start_number").map_or(1, |i| args[i + 1].parse().unwrap());
+				let pattern = PathBuf::from(args.last().unwrap());
+				for i in first..first + 2 { fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?; }
+				Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+			});
+		}
+		let mut warnings = Vec::new();
+
+		let report = Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Warning(w) = p { warnings.push(w.message); })
+			.run()
+			.unwrap();
+
+		let second = mock.calls_to("ffmpeg")[2].args_lossy().join(" ");
+		assert!(second.contains("second.mp4"), "{second}");
+		assert!(second.contains("pad=640:360") && second.contains(",fps=24 -start_number 3 "), "{second}");
+		assert_eq!(warnings, ["the inputs after the first are at 30 fps, so they're resampled to its 24 fps"]);
+		assert_eq!((report.frame_count, format!("{:.3}", report.duration)), (4, "0.167".to_string()));
+	}
+
+	#[test]
+	fn grid_stacks_the_inputs_before_the_other_filters() {
+		let (mut options, dir) = options("grid");
+		for name in ["b.mp4", "c.mp4"] { fs::write(dir.join(name), b"").unwrap(); }
+		options.grid = Some(Grid { columns: 2, rows: 2 });
+		options.grid_inputs = vec![dir.join("b.mp4"), dir.join("c.mp4")];
+		options.width = Some(640);
+		let mock = mock();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE.replace("2.000000", "1.5")));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+		fake_ffmpeg(&mock, 2);
+
+		Conversion::new(options).runner(&mock).run().unwrap();
+
+		let args = mock.calls_to("ffmpeg")[1].args_lossy();
+		assert_eq!(args.iter().filter(|a| *a == "-i").count(), 3);
+		let graph = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+		assert!(graph.contains("[c0][c1][c2][c3]xstack=inputs=4:layout=0_0|640_0|0_360|640_360:shortest=1,scale=640:360:"), "{graph}");
+		assert!(!args.contains(&"-vf".to_string()));
+	}
+
+	#[test]
+	fn extraction_reports_the_frames_written_out_of_the_expected() {
+		let (mut options, _dir) = options("extract-progress");
+		options.duration = Some(0.5);
+		let mock = mock();
+		fake_ffmpeg(&mock, 12);
+		let mut extracting = Vec::new();
+
+		Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Extracting { frame, total } = p { extracting.push((frame, total)); })
+			.run()
+			.unwrap();
+
+		assert_eq!(extracting, [(12, Some(12))]);
+	}
+
+	#[test]
+	fn compare_quality_encodes_each_quality_from_one_extraction() {
+		let (mut options, dir) = options("compare-quality");
+		options.compare_quality = vec![60, 80, 100];
+		let mock = mock();
+		fake_ffmpeg(&mock, 2);
+		let fake_gifski = |command: &runner::CommandLine| {
+			let output = command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap();
+			fs::write(output, b"GIF89a")?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		};
+		mock.respond_with("gifski", fake_gifski);
+		mock.respond("gifski", CommandOutput::failed(1, "error: out of memory"));
+		mock.respond_with("gifski", fake_gifski);
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		assert_eq!(mock.calls_to("ffmpeg").len(), 2);
+		let encodes = &mock.calls_to("gifski")[1..];
+		assert_eq!(encodes.len(), 3);
+		assert_eq!(encodes[1].args_lossy()[2..6], ["--quality", "80", "-o", &dir.join(".input-gif-q80.partial.gif").display().to_string()]);
+		let results: Vec<_> = report.comparisons.iter().map(|r| (r.quality, r.size, r.error.is_some())).collect();
+		assert_eq!(results, [(60, Some(6), false), (80, None, true), (100, Some(6), false)]);
+		assert!(dir.join("input-gif-q100.gif").exists());
+		assert!(!dir.join("input-gif-q80.gif").exists() && !dir.join(".input-gif-q80.partial.gif").exists(), "nothing left of the failed one");
+		assert_eq!(fs::read_dir(dir.join("frames")).unwrap().count(), 0);
+	}
+
+	#[test]
+	fn sizes_encode_each_width_from_one_extraction() {
+		let (mut options, dir) = options("sizes");
+		options.sizes = vec![960, 480, 960, 240];
+		let runner = mock();
+		fake_ffmpeg(&runner, 2);
+		let fake_gifski = |command: &runner::CommandLine| {
+			let output = command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap();
+			fs::write(output, b"GIF89a")?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		};
+		runner.respond_with("gifski", fake_gifski);
+		runner.respond("gifski", CommandOutput::failed(1, "error: out of memory"));
+		runner.respond_with("gifski", fake_gifski);
+
+		let report = Conversion::new(options).runner(&runner).run().unwrap();
+
+		assert!(runner.calls_to("ffmpeg")[1].args_lossy().join(" ").contains("scale=960:"), "extracted at the widest");
+		let encodes = &runner.calls_to("gifski")[1..];
+		assert_eq!(encodes.len(), 3, "960 only once");
+		assert!(encodes[0].args_lossy().join(" ").contains("--width 240"));
+		let results: Vec<_> = report.comparisons.iter().map(|r| (r.width, r.size.is_some())).collect();
+		assert_eq!(results, [(Some(240), true), (Some(480), false), (Some(960), true)]);
+		assert!(dir.join("input-gif-240.gif").exists() && dir.join("input-gif-960.gif").exists());
+		assert!(!dir.join("input-gif-480.gif").exists());
+
+		let (mut widths, _dir) = self::options("sizes-width");
+		widths.sizes = vec![480];
+		widths.width = Some(320);
+		assert!(matches!(Conversion::new(widths).runner(mock()).run(), Err(ConvertError::InvalidOption { option: "sizes", .. })));
+	}
+
+	#[test]
+	fn trim_idle_deletes_the_idle_frames() {
+		let (mut options, _dir) = options("trim-idle");
+		options.trim_idle = true;
+		let mock = mock();
+		fake_ffmpeg(&mock, 10);
+		mock.respond("ffmpeg", CommandOutput::ok_with_stderr("[blackdetect @ 0x1] black_start:0 black_end:2 black_duration:2"));
+		let mut infos = Vec::new();
+
+		let report = Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Info(i) = p { infos.push(i); })
+			.run()
+			.unwrap();
+
+		assert!(mock.calls_to("ffmpeg")[2].args_lossy().join(" ").contains("blackdetect"));
+		assert_eq!(report.frame_count, 8);
+		assert!(infos.contains(&"Trimmed 2 black frames (1 to 2)".to_string()), "{infos:?}");
+	}
+
+	#[test]
+	fn frames_are_named_by_the_frame_pattern() {
+		let run = |pattern: &str, name: fn(usize) -> String| {
+			let (mut options, dir) = options("frame-pattern");
+			options.frame_pattern = pattern.parse().unwrap();
+			options.keep_frames = true;
+			let mock = mock();
+			mock.respond_with("ffmpeg", move |command: &runner::CommandLine| {
+				let pattern = PathBuf::from(command.args.last().unwrap());
+				for i in 1..=12 { fs::write(pattern.with_file_name(name(i)), b"")?; }
+				Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+			});
+			let report = Conversion::new(options).runner(&mock).run().unwrap();
+			assert_eq!(report.frame_count, 12);
+			assert!(dir.join("frames").join(name(12)).exists());
+			let gifski = &mock.calls_to("gifski")[1];
+			assert_eq!(gifski.dir.as_ref().map(|d| d == &dir.join("frames")), (!pattern.contains("%0")).then_some(true), "runs in the frames to list bare names");
+			(mock.calls_to("ffmpeg")[1].args_lossy(), gifski.args_lossy())
+		};
+
+		let (extract, gifski) = run("img_%06d.png", |i| format!("img_{i:06}.png"));
+		assert!(extract.last().unwrap().ends_with("img_%06d.png"), "{extract:?}");
+		assert!(gifski.last().unwrap().ends_with("img_*.png"), "{gifski:?}");
+
+		// Unpadded, 10 sorts before 2, so gifski is given them by name.
+		let (extract, gifski) = run("%d.png", |i| format!("{i}.png"));
+		assert!(extract.last().unwrap().ends_with("/%d.png"), "{extract:?}");
+		let names = &gifski[gifski.iter().position(|a| a == "-o").unwrap() + 2..];
+		assert_eq!(names[..3], ["1.png", "2.png", "3.png"]);
+		assert_eq!(names.last().unwrap(), "12.png");
+	}
+
+	#[test]
+	fn busy_frames_get_advice_before_encoding() {
+		let run = |pixel: fn(usize) -> u8| {
+			let (mut options, _dir) = options("advice");
+			options.advice = true;
+			let mock = mock();
+			fake_ffmpeg(&mock, 10);
+			mock.respond_with("ffmpeg", move |command: &runner::CommandLine| {
+				if command.args_lossy().last().is_some_and(|a| a == "-") {
+					// 16 frames of 160x90, the 640x360 input scaled down.
+					return Ok(CommandOutput::ok_with_stdout((0..16 * 160 * 90 * 3).map(pixel).collect::<Vec<_>>()));
+				}
+				let pattern = PathBuf::from(command.args.last().unwrap());
+				for i in 1..=10 { fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?; }
+				Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+			});
+			let mut infos = Vec::new();
+			let report = Conversion::new(options).runner(&mock).on_progress(|p| if let Progress::Info(i) = p { infos.push(i); }).run().unwrap();
+			assert!(mock.calls_to("ffmpeg")[2].args_lossy().join(" ").contains("concat=n=16:v=1:a=0,scale=160:90:flags=neighbor"));
+			(report.busyness.unwrap(), infos)
+		};
+
+		// Every byte made up from its index, so no two pixels are alike.
+		let (noisy, infos) = run(|i| {
+			let mixed = (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+			(mixed ^ (mixed >> 31)).wrapping_mul(0xBF58_476D_1CE4_E5B9).to_be_bytes()[0]
+		});
+		assert_eq!(noisy.sampled, 16);
+		assert!(noisy.difference > 0.2 && noisy.unique_colors > 0.9, "{noisy:?}");
+		assert!(infos.iter().any(|i| i.starts_with("High inter-frame noise detected")), "{infos:?}");
+
+		let (calm, infos) = run(|_| 40);
+		assert_eq!((calm.difference, calm.unique_colors), (0.0, 1.0 / (160.0 * 90.0)));
+		assert!(!infos.iter().any(|i| i.contains("Consider")), "{infos:?}");
+	}
+
+	#[test]
+	fn flash_frames_are_replaced_with_the_one_before() {
+		let (mut options, dir) = options("flash-frames");
+		options.drop_flash_frames = Some(DEFAULT_FLASH_THRESHOLD);
+		options.keep_frames = true;
+		let mock = mock();
+		fake_ffmpeg(&mock, 5);
+		mock.respond_with("ffmpeg", |command: &runner::CommandLine| {
+			let args = command.args_lossy();
+			let pattern = PathBuf::from(&args[args.iter().position(|a| a == "-i").unwrap() + 1]);
+			for i in 1..=5 { fs::write(pattern.with_file_name(format!("frame{i:04}.png")), format!("frame {i}"))?; }
+			let luma = [30, 32, 230, 31, 30].map(|y| format!("[Parsed_metadata_1 @ 0x1] lavfi.signalstats.YAVG={y}\n")).concat();
+			Ok(CommandOutput::ok_with_stderr(luma))
+		});
+		let mut infos = Vec::new();
+
+		let report = Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Info(i) = p { infos.push(i); })
+			.run()
+			.unwrap();
+
+		assert!(mock.calls_to("ffmpeg")[2].args_lossy().join(" ").contains("signalstats"));
+		assert_eq!(report.frame_count, 5, "the timing is kept");
+		assert_eq!(fs::read_to_string(dir.join("frames/frame0003.png")).unwrap(), "frame 2");
+		assert_eq!(fs::read_to_string(dir.join("frames/frame0004.png")).unwrap(), "frame 4");
+		assert!(infos.contains(&"Replaced 1 flash frames with the one before each: 3".to_string()), "{infos:?}");
+	}
+
+	#[test]
+	fn flash_frames_it_cant_measure_are_a_warning() {
+		let (mut options, dir) = options("flash-frames-unmeasured");
+		options.drop_flash_frames = Some(DEFAULT_FLASH_THRESHOLD);
+		options.keep_frames = true;
+		let mock = mock();
+		fake_ffmpeg(&mock, 3);
+		mock.respond("ffmpeg", CommandOutput::ok_with_stderr("[Parsed_metadata_1 @ 0x1] lavfi.signalstats.YAVG=30\n"));
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let kinds: Vec<WarningKind> = report.warnings.iter().map(|w| w.kind).collect();
+		assert_eq!(kinds, [WarningKind::FlashFramesUnmeasured]);
+		assert_eq!(report.warnings[0].message, "ffmpeg's signalstats measured 1 of the 3 frames, so no flash frames were dropped");
+		assert!(dir.join("frames/frame0003.png").exists());
+	}
+
+	#[test]
+	fn loop_smooth_makes_the_gif_shorter() {
+		let (mut options, _dir) = options("loop-smooth");
+		options.loop_smooth = Some(2);
+		let mock = mock();
+		fake_ffmpeg(&mock, 10);
+		for _ in 0..2 {
+			mock.respond_with("ffmpeg", |command: &runner::CommandLine| {
+				fs::write(command.args_lossy().last().unwrap(), b"")?;
+				Ok(CommandOutput::ok_with_stderr(""))
+			});
+		}
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		assert!(mock.calls_to("ffmpeg")[2].args_lossy().join(" ").contains("blend"));
+		assert_eq!(report.frame_count, 8);
+		assert_eq!(report.loop_smoothed, Some(2));
+	}
+
+	#[test]
+	fn stabilize_detects_the_shake_then_undoes_it() {
+		let (mut options, _dir) = options("stabilize");
+		options.stabilize = true;
+		options.width = Some(320);
+		let mock = mock();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout(" T.. vidstabdetect     V->V\n T.. vidstabtransform  V->V\n"));
+		mock.respond("ffmpeg", CommandOutput::ok_with_stderr(""));
+		fake_ffmpeg(&mock, 4);
+		let mut stages = Vec::new();
+
+		Conversion::new(options)
+			.runner(&mock)
+			.on_progress(|p| if let Progress::Started(stage) = p { stages.push(stage); })
+			.run()
+			.unwrap();
+
+		let calls = mock.calls_to("ffmpeg");
+		assert!(calls[2].args_lossy().join(" ").contains("-vf vidstabdetect=result="));
+		let filters = calls[3].args_lossy().join(" ");
+		assert!(filters.contains("-vf vidstabtransform=input=") && filters.contains("transforms.trf,scale=320:180"), "{filters}");
+		assert_eq!(stages[..2], [Stage::Stabilize, Stage::Extract]);
+	}
+
+	#[test]
+	fn zoom_to_comes_before_the_scaling() {
+		let (mut zoomed, _dir) = options("zoom-to");
+		zoomed.zoom_to = Some("320x180+0+0".parse().unwrap());
+		zoomed.width = Some(320);
+		let mock = mock();
+		fake_ffmpeg(&mock, 4);
+
+		Conversion::new(zoomed).runner(&mock).run().unwrap();
+
+		let args = mock.calls_to("ffmpeg")[1].args_lossy().join(" ");
+		assert!(args.contains("-vf zoompan=z='640/(640-320*clip((it-0)/2,0,1))'"), "{args}");
+		assert!(args.contains(":s=640x360:fps=24,scale=320:180"), "{args}");
+		let mut outside = options("zoom-to-outside").0;
+		outside.zoom_to = Some("320x180+400+0".parse().unwrap());
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+		assert!(Conversion::new(outside).runner(&mock).run().unwrap_err().to_string().contains("isn't inside the 640x360 input"));
+	}
+
+	#[test]
+	fn focus_crops_to_the_region_or_the_motion() {
+		let (mut manual, _dir) = options("focus");
+		manual.focus = Some("320x180@100,50".parse().unwrap());
+		manual.width = Some(160);
+		let manual_runner = mock();
+		fake_ffmpeg(&manual_runner, 4);
+		Conversion::new(manual).runner(&manual_runner).run().unwrap();
+		let args = manual_runner.calls_to("ffmpeg")[1].args_lossy().join(" ");
+		assert!(args.contains("-vf crop=320:180:100:50,scale=160:90"), "{args}");
+
+		let (mut auto, _dir) = options("focus-auto");
+		auto.focus = Some(Focus::Auto);
+		let auto_runner = mock();
+		// Two 160x90 frames, a quarter of the 640x360 input, with a block at 40,20 to 80,40 changed.
+		let mut frames = vec![0u8; 2 * 160 * 90];
+		for y in 20..40 { frames[160 * 90 + y * 160 + 40..160 * 90 + y * 160 + 80].fill(255); }
+		auto_runner.respond("ffmpeg", CommandOutput::ok_with_stdout(frames));
+		fake_ffmpeg(&auto_runner, 4);
+		let mut infos = Vec::new();
+		Conversion::new(auto).runner(&auto_runner).on_progress(|p| if let Progress::Info(i) = p { infos.push(i); }).run().unwrap();
+		let calls = auto_runner.calls_to("ffmpeg");
+		assert!(calls[1].args_lossy().join(" ").contains("scale=160:90,format=gray -f rawvideo -"));
+		let args = calls[2].args_lossy().join(" ");
+		assert!(args.contains("-vf crop=192:96:144:72"), "{args}");
+		assert!(infos.iter().any(|i| i.contains("--focus 192x96@144,72")), "{infos:?}");
+	}
+
+	#[test]
+	fn the_waveform_is_stacked_under_the_scaled_video() {
+		let (mut options, _dir) = options("waveform");
+		options.waveform = Some(DEFAULT_WAVEFORM_HEIGHT);
+		options.width = Some(320);
+		let mock = mock();
+		fake_ffmpeg(&mock, 4);
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let args = mock.calls_to("ffmpeg")[1].args_lossy().join(" ");
+		let graph = "[0:v]scale=320:180:flags=lanczos[video];[0:a]showwaves=s=320x64:mode=cline:colors=white:rate=24[strip];[video][strip]vstack=shortest=1";
+		assert!(args.contains(&format!("-filter_complex {graph}")), "{args}");
+		assert_eq!(report.frame_size, Some((320, 244)));
+	}
+
+	#[test]
+	fn in_memory_uses_the_ramdisk_if_the_frames_fit() {
+		let (mut options, dir) = options("in-memory");
+		options.frames_dir = None;
+		options.in_memory = true;
+		options.keep_frames = true;
+		options.ramdisk = Some(dir.join("ramdisk"));
+		fs::create_dir(dir.join("ramdisk")).unwrap();
+		let runner = mock();
+		fake_ffmpeg(&runner, 4);
+
+		let report = Conversion::new(options.clone()).runner(&runner).run().unwrap();
+		assert_eq!(report.frames_location, Some(FramesLocation::Memory(dir.join("ramdisk"))));
+		assert!(report.frames_dir.unwrap().starts_with(dir.join("ramdisk").join(temp::PARENT)));
+
+		options.ramdisk = Some(dir.join("missing"));
+		let runner = mock();
+		fake_ffmpeg(&runner, 4);
+		let mut infos = Vec::new();
+		let report = Conversion::new(options).runner(&runner).on_progress(|p| if let Progress::Info(i) = p { infos.push(i); }).run().unwrap();
+		let frames_dir = report.frames_dir.unwrap();
+		assert_eq!(report.frames_location, Some(FramesLocation::Disk(frames_dir.parent().unwrap().parent().unwrap().to_path_buf())));
+		assert!(infos.iter().any(|i| i.contains("instead of memory:") && i.ends_with("missing doesn't exist")), "{infos:?}");
+		let _ = fs::remove_dir_all(frames_dir);
+	}
+
+	#[test]
+	fn every_run_adds_a_row_to_the_stats_file() {
+		let (mut options, dir) = options("stats-file");
+		options.stats_file = Some(dir.join("stats.csv"));
+		options.width = Some(320);
+		let mock = mock();
+		fake_ffmpeg(&mock, 4);
+		Conversion::new(options.clone()).runner(&mock).run().unwrap();
+		options.input = dir.join("missing.mp4");
+		assert!(Conversion::new(options).runner(&mock).run().is_err());
+
+		let stats = fs::read_to_string(dir.join("stats.csv")).unwrap();
+		let rows: Vec<&str> = stats.lines().skip(1).collect();
+		assert!(rows[0].contains(".gif,0.167,24,100,320,180,4,"), "{}", rows[0]);
+		assert!(rows[0].ends_with(",ok,,,"), "{}", rows[0]);
+		assert!(rows[1].contains(",failed,,input-not-found,Input file "), "{}", rows[1]);
+	}
+
+	#[test]
+	fn the_edit_list_picks_the_frames_gifski_gets() {
+		let (mut options, dir) = options("edit-list");
+		fs::write(dir.join("edits.txt"), "# the end first\n4\n1-2\n").unwrap();
+		options.edit_list = Some(dir.join("edits.txt"));
+		let runner = mock();
+		fake_ffmpeg(&runner, 4);
+
+		let report = Conversion::new(options.clone()).runner(&runner).run().unwrap();
+
+		let gifski = runner.calls_to("gifski").last().unwrap().args_lossy().join(" ");
+		assert!(gifski.ends_with(&format!("{}", dir.join("frames").join("edited").join("frame*.png").display())), "{gifski}");
+		assert_eq!(report.frame_count, 3);
+
+		fs::write(dir.join("edits.txt"), "1-2\n3-5\n").unwrap();
+		let runner = mock();
+		fake_ffmpeg(&runner, 4);
+		let error = Conversion::new(options).runner(&runner).run().unwrap_err().to_string();
+		assert!(error.ends_with("line 2: frame 5 is past the last extracted frame, 4"), "{error}");
+	}
+
+	#[test]
+	fn the_frame_passes_say_where_the_frames_they_leave_are() {
+		let dir = test_dir("frame-passes");
+		let frames: Vec<PathBuf> = (1..=4).map(|i| dir.join(format!("frame{i:04}.png"))).collect();
+		for (i, frame) in frames.iter().enumerate() { fs::write(frame, format!("frame {}", i + 1)).unwrap(); }
+		let opt = ConvertOptions::new(dir.join("input.mp4"));
+		let extraction = ffmpeg::Extraction::new(&opt).unwrap();
+		let runner = MockRunner::new();
+		let mut progress = |_| {};
+
+		let none = frame_passes(&runner, &opt, &extraction, (&dir, frames.clone()), (None, None, None), "", &mut progress).unwrap();
+		assert_eq!(none, (frames.clone(), dir.clone(), 0), "nothing to do");
+
+		let edits = edit::parse("4\n1-2\n").unwrap();
+		let (left, encode_dir, _) = frame_passes(&runner, &opt, &extraction, (&dir, frames), (Some(&edits), None, None), "", &mut progress).unwrap();
+		assert_eq!(encode_dir, dir.join(edit::EDITED_DIR));
+		let contents: Vec<String> = left.iter().map(|f| fs::read_to_string(f).unwrap()).collect();
+		assert_eq!(contents, ["frame 4", "frame 1", "frame 2"]);
+		assert!(runner.calls().is_empty());
+	}
+
+	#[test]
+	fn no_frames_to_encode_is_an_error_before_anything_runs_on_them() {
+		let dir = test_dir("check-extracted");
+		let opt = ConvertOptions::new(dir.join("input.mp4"));
+		let extraction = ffmpeg::Extraction::new(&opt).unwrap();
+		let mut progress = |_| {};
+		let check = |extracted: &Extracted, count, extracting, progress: &mut dyn FnMut(Progress)| {
+			check_extracted(&opt, &extraction, extracted, (&dir, count, None), (extracting, None), progress).map_err(|e| e.id())
+		};
+
+		assert_eq!(check(&Extracted::default(), 0, true, &mut progress), Err("no-frames-extracted"));
+		assert_eq!(check(&Extracted { low_on_space: Some(1), ..Extracted::default() }, 0, true, &mut progress), Err("low-disk-space"));
+		assert_eq!(check(&Extracted::default(), 0, false, &mut progress), Err("invalid-option"), "--stage encode of an empty directory");
+		let overlapped = Extracted { overlapped: Some((10.0, 90, Duration::ZERO)), ..Extracted::default() };
+		assert_eq!(check(&overlapped, 0, true, &mut progress), Ok(()), "the frames were deleted as gifski took them");
+		assert_eq!(check(&Extracted::default(), 3, true, &mut progress), Ok(()));
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn running_low_on_space_cuts_the_gif_short() {
+		let (mut options, _dir) = options("low-on-space");
+		options.fps = Some(10.0);
+		// Always below it.
+		options.reserve_space = u64::MAX;
+		let runner = mock();
+		fake_ffmpeg(&runner, 4);
+
+		let report = Conversion::new(options.clone()).runner(&runner).run().unwrap();
+
+		assert_eq!(report.frame_count, 3, "the frame ffmpeg was writing is dropped");
+		let warning = report.warnings.iter().find(|w| w.kind == WarningKind::LowDiskSpace).unwrap();
+		assert!(warning.message.starts_with("Stopped extracting at 0.3s with "), "{warning}");
+
+		options.strict_space = true;
+		let runner = mock();
+		fake_ffmpeg(&runner, 4);
+		let error = Conversion::new(options).runner(&runner).run().unwrap_err();
+		assert!(matches!(error, ConvertError::LowDiskSpace { reserve: u64::MAX, .. }), "{error}");
+		assert_eq!(runner.calls_to("gifski").len(), 1, "only asked its version, it never encodes");
+	}
+
+	#[test]
+	fn decode_errors_can_be_tolerated_once_enough_frames_are_out() {
+		let (mut options, _dir) = options("decode-error");
+		options.fps = Some(12.0);
+		let broken = |count: usize| {
+			let runner = mock();
+			runner.respond_with("ffmpeg", move |command| {
+				let pattern = PathBuf::from(command.args.last().unwrap());
+				for i in 1..=count {
+					fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?;
+				}
+				let stderr = format!("{FFMPEG_STDERR}[h264 @ 0x5581] Invalid NAL unit size (1804 > 77).\nError while decoding stream #0:0: Invalid data found when processing input\n");
+				Ok(CommandOutput::failed(1, stderr))
+			});
+			runner
+		};
+
+		let error = Conversion::new(options.clone()).runner(broken(18)).run().unwrap_err();
+		assert!(matches!(error, ConvertError::FfmpegFailed { code: Some(1), .. }), "strict by default: {error}");
+
+		options.tolerate_decode_errors = true;
+		let runner = broken(18);
+		let report = Conversion::new(options.clone()).runner(&runner).run().unwrap();
+		assert_eq!(report.frame_count, 18);
+		let decode_error = report.decode_error.unwrap();
+		assert!(decode_error.starts_with("ffmpeg exited with code 1:\n") && decode_error.ends_with("Invalid data found when processing input"), "{decode_error}");
+		let warning = report.warnings.iter().find(|w| w.kind == WarningKind::DecodeErrorTolerated).unwrap();
+		assert!(warning.message.starts_with("ffmpeg failed after 18 frames, 1.5s of the video"), "{warning}");
+
+		let error = Conversion::new(options).runner(broken(ffmpeg::MIN_RECOVERED_FRAMES - 1)).run().unwrap_err();
+		assert!(matches!(error, ConvertError::FfmpegFailed { .. }), "too few to be worth it: {error}");
+	}
+
+	#[test]
+	fn the_ffmpeg_encoder_needs_no_gifski() {
+		let (mut options, _dir) = options("ffmpeg-encoder");
+		options.encoder = Encoder::Ffmpeg;
+		options.colors = Some(32);
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright (c) 2000-2023 the FFmpeg developers"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE));
+		fake_ffmpeg(&mock, 4);
+		mock.respond_with("ffmpeg", |command: &runner::CommandLine| {
+			fs::write(command.args_lossy().last().unwrap(), b"GIF89a")?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		assert!(mock.calls_to("gifski").is_empty());
+		assert!(mock.calls_to("ffmpeg")[2].args_lossy().join(" ").contains("palettegen=max_colors=32"));
+		assert!(report.gifski.is_none() && report.frames_dir.is_none());
+	}
+
+	#[test]
+	fn frames_deleted_by_hand_leave_no_gap_for_ffmpeg() {
+		let (mut options, dir) = options("frame-gap");
+		fs::create_dir_all(dir.join("shots")).unwrap();
+		for i in (1..=10).filter(|&i| i != 4) { fs::write(dir.join(format!("shots/frame{i:04}.png")), b"").unwrap(); }
+		options.input = dir.join("shots");
+		options.stages = Stages::Encode;
+		options.fps = Some(12.0);
+		options.encoder = Encoder::Ffmpeg;
+		options.also_mp4 = true;
+		options.keep_frames = true;
+		let mock = MockRunner::new();
+		let read = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+		for _ in 0..2 {
+			let read = std::sync::Arc::clone(&read);
+			// What an image2 sequence reads: frames from the start number until one is missing.
+			mock.respond_with("ffmpeg", move |command: &runner::CommandLine| {
+				let args = command.args_lossy();
+				let value = |flag: &str| args[args.iter().position(|a| a == flag).unwrap() + 1].clone();
+				let (start, pattern) = (value("-start_number").parse::<usize>().unwrap(), PathBuf::from(value("-i")));
+				let name = |n: usize| pattern.with_file_name(format!("frame{n:04}.png"));
+				read.lock().unwrap().push((start..start + 100).take_while(|&n| name(n).exists()).count());
+				fs::write(args.last().unwrap(), b"GIF89a")?;
+				Ok(CommandOutput::ok_with_stderr(""))
+			});
+		}
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		assert_eq!(report.frame_count, 9);
+		assert_eq!(*read.lock().unwrap(), [9, 9], "the gif and the MP4 get every frame");
+		assert!(!dir.join("shots/renumbered").exists(), "the links are gone after");
+		assert!(!dir.join("shots/frame0004.png").exists());
+	}
+
+	#[test]
+	fn overlap_pipes_ffmpeg_into_gifski() {
+		let (mut options, dir) = options("overlap");
+		options.overlap = true;
+		let mock = mock();
+		mock.respond_with("ffmpeg", |command: &runner::CommandLine| {
+			let pattern = command.args.iter().find(|a| a.to_string_lossy().ends_with("frame%04d.png")).unwrap();
+			fs::write(PathBuf::from(pattern).with_file_name("frame0001.png"), b"")?;
+			Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+		});
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let calls = mock.calls();
+		assert_eq!(calls[3].program_name(), "gifski", "gifski has to be reading before ffmpeg writes");
+		assert_eq!(calls[3].args_lossy().last().unwrap(), "-");
+		assert!(calls[4].args_lossy().join(" ").ends_with("-f yuv4mpegpipe -pix_fmt yuv444p -"));
+		assert_eq!(calls.len(), 5);
+		assert_eq!(calls[3].args_lossy()[..2], ["--fps", "24"], "the fps comes from ffprobe");
+		assert_eq!(report.frame_count, 1);
+		assert_eq!(fs::read_dir(dir.join("frames")).unwrap().count(), 0);
+	}
+
+	#[test]
+	fn the_quality_map_encodes_each_stretch_and_joins_them() {
+		let (mut options, dir) = options("quality-map");
+		options.fps = Some(10.0);
+		options.quality = 70;
+		options.quality_map = vec!["0.2-0.5:100".parse().unwrap()];
+		let runner = mock();
+		fake_ffmpeg(&runner, 8);
+		for _ in 0..3 {
+			runner.respond_with("gifski", |command: &runner::CommandLine| {
+				fs::write(command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap(), gif::TEST_GIF)?;
+				Ok(CommandOutput::ok_with_stderr(""))
+			});
+		}
+
+		let report = Conversion::new(options.clone()).runner(&runner).run().unwrap();
+
+		let encodes = &runner.calls_to("gifski")[1..];
+		let qualities: Vec<_> = encodes.iter().map(|c| (c.args_lossy()[3].clone(), c.args.len() - 6)).collect();
+		assert_eq!(qualities, [("70".to_string(), 2), ("100".to_string(), 3), ("70".to_string(), 3)], "quality and frames of each");
+		let regions: Vec<_> = report.quality_regions.iter().map(|r| (r.start, r.end, r.quality, r.frame_count)).collect();
+		assert_eq!(regions, [(0.0, 0.2, 70, 2), (0.2, 0.5, 100, 3), (0.5, 0.8, 70, 3)]);
+		assert!(report.quality_regions.iter().all(|r| r.size == gif::TEST_GIF.len() as u64));
+		assert!(fs::read(dir.join("input-gif.gif")).unwrap().len() > 3 * 24, "the frames of all three");
+
+		options.quality_map = vec!["0.5-1:100".parse().unwrap()];
+		let runner = mock();
+		fake_ffmpeg(&runner, 8);
+		let error = Conversion::new(options).runner(&runner).run().unwrap_err();
+		assert_eq!(error.to_string(), "Invalid quality map: 0.5-1 ends past the end of the gif, at 0.80s");
+	}
+
+	#[test]
+	fn the_frame_filter_runs_over_the_frames_before_gifski() {
+		let (mut options, dir) = options("frame-filter");
+		options.frame_filter = Some(FrameFilter::new("redact --region '0 0 10 10' {}"));
+		options.jobs = Some(2);
+		let runner = mock();
+		fake_ffmpeg(&runner, 3);
+
+		Conversion::new(options.clone()).runner(&runner).run().unwrap();
+
+		let calls: Vec<String> = runner.calls().iter().map(runner::CommandLine::program_name).filter(|p| p != "ffprobe").collect();
+		assert_eq!(calls, ["ffmpeg", "gifski", "ffmpeg", "redact", "redact", "redact", "gifski"]);
+		let frame = dir.join("frames").join("frame0003.png").to_string_lossy().into_owned();
+		assert_eq!(runner.calls_to("redact")[2].args_lossy(), ["--region", "0 0 10 10", frame.as_str()]);
+
+		let runner = mock();
+		fake_ffmpeg(&runner, 3);
+		runner.respond("redact", CommandOutput::failed(2, "no such region"));
+		let error = Conversion::new(options).runner(&runner).run().unwrap_err();
+		assert_eq!(error.id(), "frame-filter-failed");
+		assert_eq!(runner.calls_to("gifski").len(), 1, "not encoded");
+	}
+
+	#[test]
+	fn the_cursor_is_drawn_where_the_events_put_it() {
+		let (mut options, dir) = options("cursor-overlay");
+		fs::write(dir.join("events.json"), "{\"x\": 0, \"y\": 0, \"t\": 0}\n{\"x\": 480, \"y\": 240, \"t\": 2}\n{\"x\": 9999, \"y\": 0, \"t\": 60}\n").unwrap();
+		image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255])).save(dir.join("cursor.png")).unwrap();
+		options.cursor_overlay = Some(dir.join("events.json"));
+		options.cursor_image = Some(dir.join("cursor.png"));
+		options.start = Some(1.0);
+		options.width = Some(320);
+		options.keep_frames = true;
+		let runner = mock();
+		runner.respond_with("ffmpeg", |command| {
+			let pattern = PathBuf::from(command.args.last().unwrap());
+			for i in 1..=3 {
+				image::RgbImage::new(320, 180).save(pattern.with_file_name(format!("frame{i:04}.png"))).unwrap();
+			}
+			Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+		});
+
+		Conversion::new(options).runner(&runner).run().unwrap();
+
+		// 1s, 1 1/24s and 1 1/12s in, at half the size.
+		for (i, at) in [(1, (120, 60)), (2, (125, 63)), (3, (130, 65))] {
+			let frame = image::open(dir.join("frames").join(format!("frame{i:04}.png"))).unwrap();
+			assert!(!frame.color().has_alpha(), "still rgb");
+			let white: Vec<(u32, u32)> = frame.to_rgb8().enumerate_pixels().filter(|(_, _, p)| p.0 == [255; 3]).map(|(x, y, _)| (x, y)).collect();
+			assert_eq!(white, [at, (at.0 + 1, at.1), (at.0, at.1 + 1), (at.0 + 1, at.1 + 1)], "frame {i}");
+		}
+		assert_eq!(runner.calls_to("ffmpeg").len(), 2, "not drawn by ffmpeg");
+		assert!(!dir.join("frames").join("cursor-frame0002.png").exists());
+	}
+
+	#[test]
+	fn social_writes_what_it_can_of_the_mp4_and_poster() {
+		let (mut options, dir) = options("social");
+		options.social = true;
+		let runner = mock();
+		fake_ffmpeg(&runner, 10);
+		runner.respond("ffmpeg", CommandOutput::failed(1, "Unknown encoder 'libx264'"));
+		runner.respond("ffmpeg", CommandOutput::ok_with_stderr("[blackdetect @ 0x1] black_start:0 black_end:2 black_duration:2"));
+		let mut warnings = Vec::new();
+
+		let report = Conversion::new(options.clone())
+			.runner(&runner)
+			.on_progress(|p| if let Progress::Warning(w) = p { warnings.push(w); })
+			.run()
+			.unwrap();
+
+		let calls = runner.calls_to("ffmpeg");
+		assert!(calls[2].args_lossy().contains(&"libx264".to_string()));
+		assert_eq!(calls[4].args_lossy()[4], dir.join("frames").join("frame0003.png").to_string_lossy(), "the first that isn't black");
+		assert_eq!(report.mp4, None);
+		assert_eq!(report.poster, Some(dir.join("input-gif.jpg")));
+		assert_eq!(warnings.iter().map(|w| w.kind).collect::<Vec<_>>(), [WarningKind::SocialPieceFailed]);
+		assert!(warnings[0].message.starts_with("Couldn't write the MP4, ffmpeg exited"), "{}", warnings[0].message);
+
+		options.social = false;
+		options.also_mp4 = true;
+		let runner = mock();
+		fake_ffmpeg(&runner, 10);
+		runner.respond("ffmpeg", CommandOutput::failed(1, "Unknown encoder 'libx264'"));
+		assert!(matches!(Conversion::new(options).runner(&runner).run(), Err(ConvertError::FfmpegFailed { .. })), "on its own it's an error");
+	}
+
+	#[test]
+	fn failing_overlapped_ffmpeg_removes_the_partial_gif() {
+		let (mut options, dir) = options("overlap-failure");
+		options.overlap = true;
+		let mock = mock();
+		mock.respond_with("gifski", |command: &runner::CommandLine| {
+			fs::write(command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap(), gif::TEST_GIF)?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+		mock.respond("ffmpeg", CommandOutput::failed(1, "input.mp4: Invalid data found when processing input"));
+
+		let result = Conversion::new(options).runner(&mock).run();
+
+		assert!(matches!(result, Err(ConvertError::FfmpegFailed { code: Some(1), .. })), "{result:?}");
+		assert!(!dir.join("input-gif.gif").exists());
+	}
+
+	#[test]
+	fn chunks_number_their_frames_on_from_the_last_chunk() {
+		let (mut options, dir) = options("chunks");
+		options.chunk_seconds = Some(0.5);
+		let mock = mock();
+		for _ in 0..4 {
+			mock.respond_with("ffmpeg", |command: &runner::CommandLine| {
+				let args = command.args_lossy();
+				let pattern = PathBuf::from(args.iter().find(|a| a.ends_with("frame%04d.png")).unwrap());
+				let first: usize = args[args.iter().position(|a| a == "-start_number").unwrap() + 1].parse().unwrap();
+				assert_eq!(FramePattern::default().list(pattern.parent().unwrap()).unwrap().len(), 0, "the last chunk's frames are still there");
+				for i in first..first + 3 {
+					fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?;
+				}
+				Ok(CommandOutput::ok_with_stderr(FFMPEG_STDERR))
+			});
+		}
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let chunks: Vec<_> = mock.calls_to("ffmpeg")[1..].iter().map(|c| {
+			let args = c.args_lossy();
+			let value = |flag| args.iter().position(|a| a == flag).map(|i| args[i + 1].clone());
+			(value("-ss"), value("-t").unwrap(), value("-start_number").unwrap())
+		}).collect();
+		assert_eq!(chunks, [
+			(None, "0.5".into(), "1".into()),
+			(Some("0.5".into()), "0.5".into(), "4".into()),
+			(Some("1".into()), "0.5".into(), "7".into()),
+			(Some("1.5".into()), "0.5".into(), "10".into()),
+		]);
+		assert_eq!(mock.calls_to("gifski").len(), 2, "one gifski reads every chunk");
+		assert_eq!(report.frame_count, 12);
+		assert_eq!(fs::read_dir(dir.join("frames")).unwrap().count(), 0);
+	}
+
+	#[test]
+	fn out_of_memory_retries_at_half_the_fps() {
+		let (options, _dir) = options("retry");
+		let mock = mock();
+		fake_ffmpeg(&mock, 4);
+		mock.respond("gifski", CommandOutput::killed(9));
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let gifski = mock.calls_to("gifski");
+		assert_eq!(gifski.len(), 3);
+		assert_eq!(gifski[2].args_lossy()[..2], ["--fps", "12"]);
+		assert_eq!(report.downgrade, Some(Downgrade { fps: 12.0, frame_count: 2, width: None }));
+		assert_eq!(report.fps.to_string(), "12");
+	}
+
+	#[test]
+	fn comment_is_written_into_the_gif() {
+		let (mut options, dir) = options("comment");
+		options.comment = Comment::Auto;
+		options.quality = 90;
+		let mock = mock();
+		fake_ffmpeg(&mock, 1);
+		mock.respond_with("gifski", |command: &runner::CommandLine| {
+			fs::write(command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap(), gif::TEST_GIF)?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let expected = format!("gifski-ffmpeg {} (gifski 1.32.0), quality 90, fps 24", env!("CARGO_PKG_VERSION"));
+		assert_eq!(report.comment.as_deref(), Some(expected.as_str()));
+		assert_eq!(gif::comments(&fs::read(dir.join("input-gif.gif")).unwrap()), [expected]);
+	}
+
+	#[test]
+	fn deterministic_runs_leave_out_what_varies() {
+		let (mut options, dir) = options("deterministic");
+		options.comment = Comment::Auto;
+		options.deterministic = true;
+		let mock = mock();
+		fake_ffmpeg(&mock, 3);
+		mock.respond_with("gifski", |command: &runner::CommandLine| {
+			fs::write(command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap(), gif::TEST_GIF)?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+
+		let extract = mock.calls_to("ffmpeg")[1].args_lossy();
+		assert!(extract.windows(4).any(|w| w == ["-fflags", "+bitexact", "-flags:v", "+bitexact"]), "{extract:?}");
+		// Zero-padded, the glob already gives gifski them in order.
+		assert_eq!(mock.calls_to("gifski")[1].args_lossy()[6..], [dir.join("frames/frame*.png").display().to_string()]);
+		assert_eq!(report.comment.as_deref(), Some("gifski-ffmpeg, quality 100, fps 24"));
+	}
+
+	#[test]
+	fn exact_end_deletes_the_frames_ffmpeg_wrote_past_it() {
+		let (mut options, dir) = options("exact-end");
+		options.duration = Some(0.5);
+		options.exact_end = true;
+		options.keep_frames = true;
+		let mock = mock();
+		fake_ffmpeg(&mock, 15);
+		let mut events = Vec::new();
+
+		let report = Conversion::new(options).runner(&mock).on_progress(|p| events.push(p)).run().unwrap();
+
+		assert_eq!(report.frame_count, 12);
+		let gifski = mock.calls_to("gifski")[1].args_lossy();
+		assert!(gifski.last().unwrap().ends_with("frame*.png"), "{gifski:?}");
+		assert_eq!(FramePattern::default().list(&dir.join("frames")).unwrap().last(), Some(&dir.join("frames/frame0012.png")), "all the glob gets");
+		assert!(events.iter().any(|p| matches!(p, Progress::Info(m) if m == "Deleted 3 frames past the end, 12 left")), "{events:?}");
+	}
+
+	#[test]
+	fn source_timing_gives_keyframes_the_time_between_them() {
+		let (mut options, dir) = options("source-timing");
+		options.keyframes_only = true;
+		options.source_timing = true;
+		let mock = mock();
+		mock.respond_with("ffmpeg", |command| {
+			let pattern = PathBuf::from(command.args.last().unwrap());
+			for i in 1..=3 {
+				fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?;
+			}
+			Ok(CommandOutput::ok_with_stderr(format!(
+				"{FFMPEG_STDERR}\n[Parsed_showinfo_0 @ 0x1] n:   0 pts:      0 pts_time:0\n[Parsed_showinfo_0 @ 0x1] n:   1 pts:  60000 pts_time:5\n[Parsed_showinfo_0 @ 0x1] n:   2 pts:  72000 pts_time:6\n",
+			)))
+		});
+		let gifs = |count| gif::concat(&vec![gif::TEST_GIF.to_vec(); count]).unwrap();
+		let three = gifs(3);
+		mock.respond_with("gifski", move |command: &runner::CommandLine| {
+			fs::write(command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap(), &three)?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+
+		let report = Conversion::new(options.clone()).runner(&mock).run().unwrap();
+
+		let extract = mock.calls_to("ffmpeg")[1].args_lossy();
+		assert!(extract.windows(4).any(|w| w == ["-vsync", "vfr", "-vf", "showinfo"]), "{extract:?}");
+		assert_eq!(mock.calls_to("gifski")[1].args_lossy()[..2], ["--fps", "3"]);
+		// The last one for as long as they are on average.
+		assert_eq!(gif::delays(&fs::read(dir.join("input-gif.gif")).unwrap()), Some(vec![500, 100, 300]));
+		assert!((report.duration - 9.0).abs() < 1e-9, "{}", report.duration);
+
+		options.edit_list = Some(dir.join("edits.txt"));
+		assert!(matches!(Conversion::new(options).runner(&mock).run(), Err(ConvertError::InvalidOption { option: "source timing", .. })));
+	}
+
+	#[test]
+	fn exact_duration_cuts_frames_or_holds_the_last() {
+		let (mut options, dir) = options("exact-duration");
+		options.exact_duration = Some(1.0);
+		let runner = mock();
+		fake_ffmpeg(&runner, 3);
+		let gif = gif::set_delays(&gif::concat(&vec![gif::TEST_GIF.to_vec(); 3]).unwrap(), &[4, 4, 4]).unwrap();
+		runner.respond_with("gifski", move |command: &runner::CommandLine| {
+			fs::write(command.args.iter().skip_while(|a| *a != "-o").nth(1).unwrap(), &gif)?;
+			Ok(CommandOutput::ok_with_stderr(""))
+		});
+		let mut events = Vec::new();
+
+		let report = Conversion::new(options.clone()).runner(&runner).on_progress(|p| events.push(p)).run().unwrap();
+
+		assert_eq!(gif::delays(&fs::read(dir.join("input-gif.gif")).unwrap()), Some(vec![4, 4, 92]));
+		assert!((report.duration - 1.0).abs() < 1e-9, "{}", report.duration);
+		assert!(events.iter().any(|p| matches!(p, Progress::Info(m) if m == "Holding the last of the 3 frames 0.87s longer to make 1.00s")), "{events:?}");
+
+		options.exact_duration = Some(0.25);
+		let runner = mock();
+		fake_ffmpeg(&runner, 10);
+		let mut events = Vec::new();
+		let report = Conversion::new(options).runner(&runner).on_progress(|p| events.push(p)).run().unwrap();
+		assert_eq!(report.frame_count, 6);
+		assert!(events.iter().any(|p| matches!(p, Progress::Info(m) if m == "Deleted the 4 frames past 0.25s, 6 left")), "{events:?}");
+	}
+
+	#[test]
+	fn failing_ffmpeg_stops_before_gifski() {
+		let (options, _dir) = options("ffmpeg-fails");
+		let mock = mock();
+		mock.respond("ffmpeg", CommandOutput::failed(1, "input.mp4: No such file or directory"));
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::FfmpegFailed { code: Some(1), ref stderr } if stderr.contains("No such file")), "{err:?}");
+		// Only the version check.
+		assert_eq!(mock.calls_to("gifski").len(), 1);
+	}
+
+	#[test]
+	fn unreadable_input_fails_before_the_frames_dir_is_touched() {
+		let (options, dir) = options("unrecognized-input");
+		fs::create_dir_all(dir.join("frames")).unwrap();
+		fs::write(dir.join("frames/keep"), b"").unwrap();
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		mock.respond("ffprobe", CommandOutput::failed(1, "input.mp4: Invalid data found when processing input"));
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::UnrecognizedFormat { .. }), "{err:?}");
+		assert!(dir.join("frames/keep").exists());
+		assert_eq!(mock.calls_to("ffmpeg").len(), 1);
+	}
+
+	#[test]
+	fn missing_output_dir_fails_before_the_frames_dir_is_touched() {
+		let (mut options, dir) = options("missing-output-dir");
+		options.output = Some(dir.join("gifs/out.gif").into());
+		fs::create_dir_all(dir.join("frames")).unwrap();
+		fs::write(dir.join("frames/keep"), b"").unwrap();
+		let mock = mock();
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::OutputDirMissing(ref d) if d == &dir.join("gifs")), "{err:?}");
+		assert!(err.to_string().contains("gifs"), "{err}");
+		assert!(dir.join("frames/keep").exists());
+		assert_eq!(mock.calls_to("ffmpeg").len(), 1);
+	}
+
+	#[test]
+	fn a_frames_dir_with_something_else_in_it_is_refused() {
+		let (options, dir) = options("frames-dir-in-use");
+		fs::create_dir_all(dir.join("frames/scripts")).unwrap();
+		fs::write(dir.join("frames/notes.txt"), b"").unwrap();
+		let mock = mock();
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::InvalidOption { option: "frames dir", .. }), "{err:?}");
+		assert!(err.to_string().contains("already has notes.txt, scripts in it"), "{err}");
+		assert!(dir.join("frames/notes.txt").exists() && dir.join("frames/scripts").exists());
+		assert_eq!(mock.calls_to("ffmpeg").len(), 1);
+	}
+
+	#[test]
+	fn files_the_frame_pattern_would_take_for_frames_are_named() {
+		let (mut options, dir) = options("frames-dir-clashes");
+		options.frame_pattern = "img_%06d.png".parse().unwrap();
+		fs::create_dir_all(dir.join("frames")).unwrap();
+		for name in ["img_000001.png", "img_poster.png", "notes.txt"] { fs::write(dir.join("frames").join(name), b"").unwrap(); }
+		let mock = mock();
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err().to_string();
+		assert!(err.contains("already has img_000001.png, img_poster.png in it, which would be taken for frames named img_%06d.png"), "{err}");
+		assert!(dir.join("frames/img_000001.png").exists());
+		assert_eq!(mock.calls_to("ffmpeg").len(), 1);
+	}
+
+	#[test]
+	fn a_frames_dir_with_the_input_or_output_in_it_is_refused() {
+		let (mut options, dir) = options("frames-dir-has-input");
+		options.frames_dir = Some(dir.clone());
+		let err = Conversion::new(options.clone()).runner(mock()).run().unwrap_err();
+		assert!(err.to_string().contains("has the input in it"), "{err}");
+
+		fs::create_dir_all(dir.join("frames")).unwrap();
+		options.frames_dir = Some(dir.join("frames"));
+		options.output = Some(dir.join("frames/out.gif").into());
+		let err = Conversion::new(options).runner(mock()).run().unwrap_err();
+		assert!(err.to_string().contains("has the output in it"), "{err}");
+		assert!(dir.join("input.mp4").exists());
+	}
+
+	#[test]
+	fn a_frames_dir_an_earlier_run_kept_is_emptied_but_not_deleted() {
+		let (mut options, dir) = options("frames-dir-reused");
+		options.keep_frames = true;
+		let first = mock();
+		fake_ffmpeg(&first, 3);
+		Conversion::new(options.clone()).runner(&first).run().unwrap();
+		assert_eq!(options.frame_pattern.count(&dir.join("frames")), 3);
+
+		options.keep_frames = false;
+		let mock = mock();
+		fake_ffmpeg(&mock, 3);
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+		assert_eq!(report.frame_count, 3);
+		assert_eq!(fs::read_dir(dir.join("frames")).unwrap().count(), 0);
+	}
+
+	#[test]
+	fn stills_are_looped_for_the_still_duration() {
+		let (mut options, dir) = options("still");
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		let still = r#"{
+			"streams": [{ "index": 0, "codec_type": "video", "codec_name": "png", "width": 64, "height": 64, "r_frame_rate": "25/1" }],
+			"format": { "format_name": "png_pipe" }
+		}"#;
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(still));
+
+		let err = Conversion::new(options.clone()).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::StillImage(ref p) if p == &dir.join("input.mp4")), "{err:?}");
+
+		options.still_duration = Some(3.0);
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(still));
+		fake_ffmpeg(&mock, 3);
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+		let extract = mock.calls_to("ffmpeg")[2].args_lossy();
+		assert_eq!(extract[..4], ["-loop", "1", "-i", &dir.join("input.mp4").display().to_string()]);
+		assert_eq!(extract[4..8], ["-t", "3", "-vf", "fps=1"]);
+		assert_eq!((report.fps.to_string(), report.frame_count), ("1".to_string(), 3));
+	}
+
+	#[test]
+	fn long_inputs_need_force_or_a_max_duration() {
+		let (mut options, _dir) = options("too-long");
+		let long = || probe::TEST_PROBE.replace("\"2.000000\"", "\"120.000000\"");
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(long()));
+
+		let err = Conversion::new(options.clone()).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::TooLong { seconds, .. } if seconds.to_string() == "120"), "{err:?}");
+		assert!(err.to_string().contains("--force"), "{err}");
+		assert_eq!(mock.calls_to("ffmpeg").len(), 1, "nothing is extracted");
+
+		options.start = Some(30.0);
+		options.max_duration = Some(10.0);
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0"));
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(long()));
+		fake_ffmpeg(&mock, 1);
+		let report = Conversion::new(options).runner(&mock).run().unwrap();
+		let extract = mock.calls_to("ffmpeg")[2].args_lossy();
+		assert!(extract.windows(2).any(|a| a == ["-t", "10"]), "{extract:?}");
+		assert_eq!(report.truncated_from.map(|s| s.to_string()).as_deref(), Some("90"));
+	}
+
+	#[test]
+	fn missing_input_fails_without_running_anything() {
+		let (mut options, dir) = options("missing-input");
+		options.input = dir.join("nope.mp4");
+		let mock = MockRunner::new();
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::InputNotFound(ref p) if p == &dir.join("nope.mp4")), "{err:?}");
+		assert!(mock.calls().is_empty());
+	}
+
+	#[test]
+	fn extracting_nothing_fails_before_gifski() {
+		let (mut options, _dir) = options("no-frames");
+		options.start = Some(5.0);
+		options.width = Some(320);
+		let runner = mock();
+		runner.respond("ffmpeg", CommandOutput::ok_with_stderr(FFMPEG_STDERR));
+
+		let err = Conversion::new(options.clone()).runner(&runner).run().unwrap_err();
+
+		assert_eq!(err.id(), "no-frames-extracted");
+		assert!(err.to_string().starts_with("Extraction produced 0 frames, check your --start/--end/filters. It was 5.00s to the end of the 2.00s input, with the filters scale=320:"), "{err}");
+		assert_eq!(runner.calls_to("gifski").len(), 1, "only asked for its version");
+
+		options.start = None;
+		let runner = mock();
+		fake_ffmpeg(&runner, 1);
+		let report = Conversion::new(options).runner(&runner).run().unwrap();
+		let warning = report.warnings.iter().find(|w| w.kind == WarningKind::FewFrames).unwrap();
+		assert_eq!(warning.message, "Only 1 frames came out of 2.0s of video, check --start/--end and the filters. It was 0.00s to the end of the 2.00s input");
+	}
+
+	#[test]
+	fn undetectable_fps_is_its_own_error() {
+		let (options, _dir) = options("no-fps");
+		let mock = mock();
+		mock.respond_with("ffmpeg", |command: &runner::CommandLine| {
+			fs::write(PathBuf::from(command.args.last().unwrap()).with_file_name("frame0001.png"), b"")?;
+			Ok(CommandOutput::ok_with_stderr("Stream #0:0: Video: rawvideo"))
+		});
+
+		let err = Conversion::new(options).runner(&mock).run().unwrap_err();
+		assert!(matches!(err, ConvertError::FpsDetectionFailed), "{err:?}");
+	}
+
+	#[test]
+	fn a_recent_input_without_a_duration_looks_still_written() {
+		let (options, dir) = options("still-written");
+		let half_a_minute_ago = std::time::SystemTime::now() - Duration::from_secs(30);
+		fs::File::options().write(true).open(dir.join("input.mp4")).unwrap().set_modified(half_a_minute_ago).unwrap();
+		let runner = MockRunner::new();
+		runner.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright (c) 2000-2023 the FFmpeg developers"));
+		runner.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+		runner.respond("ffprobe", CommandOutput::ok_with_stdout(probe::TEST_PROBE.replace(r#", "duration": "2.000000""#, "")));
+		fake_ffmpeg(&runner, 10);
+
+		let report = Conversion::new(options.clone()).runner(&runner).run().unwrap();
+		let kinds = report.warnings.iter().map(|w| w.kind).collect::<Vec<_>>();
+		assert_eq!(kinds, [WarningKind::InputStillWritten]);
+		assert!(report.warnings[0].message.contains("has no duration and was modified 30s ago"), "{}", report.warnings[0].message);
+
+		growing::written_an_hour_ago(&dir.join("input.mp4"));
+		let runner = mock();
+		fake_ffmpeg(&runner, 10);
+		assert!(Conversion::new(options).runner(&runner).run().unwrap().warnings.is_empty(), "an hour old, with a duration");
+	}
+}
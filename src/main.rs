@@ -0,0 +1,1286 @@
+#![warn(
+clippy::all,
+clippy::pedantic,
+)]
+
+use std::{
+	cell::Cell,
+	env,
+	ffi::OsString,
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time::Instant,
+};
+mod argfile;
+mod capture;
+mod clipboard;
+mod config;
+mod events;
+mod notify;
+mod serve;
+mod style;
+
+use structopt::{clap::{AppSettings, ArgGroup, ArgMatches}, StructOpt};
+use simple_logger::SimpleLogger;
+use anyhow::{Context, Result};
+use config::Config;
+use events::Event;
+use notify::Notification;
+use gifski_ffmpeg::{
+	batch::{self, BatchResult, Session, Status},
+	benchmark::{self, BenchmarkReport},
+	disk, doctor, estimate,
+	explain::{self, Origin, Origins, Plan},
+	runner::{BackgroundRunner, CommandRunner, SystemRunner},
+	split::{self, Part},
+	tools::{self, Tool},
+	parse_timestamp, Aspect, Comment, Conversion, ConvertError, ConvertOptions, ConvertReport, Dither, Encoder, Fit, Focus, FrameFilter, FrameFilterMode, FramePattern, Gravity, Grid, Poster, PosterFormat, Progress, QualityRegion, QualityRun, RegionRun, Scale, SeekMode, Stage, Stages, Warning, WaveformStyle, Zoom, DEFAULT_CONTACT_SHEET, DEFAULT_FLASH_THRESHOLD, DEFAULT_INPUT_WAIT, DEFAULT_LOOP_SMOOTH_FRAMES, DEFAULT_WAVEFORM_HEIGHT,
+};
+
+/// Converts a video to frames using your local ffmpeg, then, runs gifski to convert the frames to a gif.
+///
+/// This runs
+///
+/// ffmpeg -i <INPUT> frame%04d.png <TEMP>/gifski-ffmpeg/run-<id>
+///
+/// followed by
+///
+/// gifski --fps <fps> --quality <quality> -o <OUTPUT> <TEMP>/gifski-ffmpeg/run-<id>/frame*.png
+///
+/// then, deletes the run-<id> directory
+#[derive(StructOpt, Debug)]
+#[structopt(name = "gifski-ffmpeg", global_settings = &[AppSettings::DisableVersion], group = ArgGroup::with_name("report").conflicts_with_all(&["stage", "progress-format"]))]
+#[allow(clippy::doc_markdown)] // These are the --help texts, not rustdoc.
+#[allow(clippy::struct_excessive_bools)] // Flags.
+struct Opt {
+	/// File to process.
+	///
+	/// A path to the video e.g. "./input.mp4" or "C:/videos/input.mp4", or the frames directory with --stage encode
+	#[structopt(name = "INPUT", parse(from_os_str), required_unless_one = &["version", "doctor", "batch", "from-clipboard", "capture"], conflicts_with = "batch")]
+	input: Option<PathBuf>,
+
+	/// Name or location of output file
+	///
+	/// "C:/videos/output.gif" will create output.gif in the specified directory where as
+	/// "output" will create output.gif in the same directory as <INPUT>
+	#[structopt(name = "OUTPUT", parse(from_os_str), conflicts_with = "batch")]
+	output: Option<OsString>,
+
+	/// Puts the output next to the file a symlinked <INPUT> points to, instead of next to the symlink.
+	#[structopt(long)]
+	resolve_symlinks: bool,
+
+	/// Converts the video file on the clipboard instead of <INPUT>.
+	///
+	/// The first video of the files copied in Finder or Explorer, or on Linux of the files or paths copied with wl-paste
+	/// or xclip around. The gif goes next to it unless <OUTPUT> says otherwise.
+	#[structopt(long, conflicts_with_all = &["INPUT", "batch"])]
+	from_clipboard: bool,
+
+	/// Records the screen with ffmpeg and converts the recording instead of <INPUT>: all of it, a region of it as
+	/// --capture=WxH+X+Y in pixels from the top left, or --capture=interactive to drag one out with slop, or slurp on
+	/// Wayland.
+	///
+	/// Records until Ctrl+C or for --capture-duration, at the --fps or 30. The gif goes in the current directory as
+	/// capture.gif, or where a lone <INPUT> says, which is taken as <OUTPUT>. The recording is deleted with the frames.
+	#[structopt(
+		long, value_name = "WxH+X+Y|interactive", min_values = 0, max_values = 1, require_equals = true,
+		conflicts_with_all = &["batch", "from-clipboard", "stage", "estimate", "benchmark", "split-every", "explain"],
+	)]
+	#[allow(clippy::option_option)] // Without a value it's the whole screen.
+	capture: Option<Option<capture::Region>>,
+
+	/// Stops --capture after this many seconds, instead of at Ctrl+C.
+	#[structopt(long, value_name = "seconds", requires = "capture")]
+	capture_duration: Option<f64>,
+
+	/// Which half of the conversion to run: "extract", "encode" or "all".
+	///
+	/// "extract" only extracts the frames, and prints where they are instead of deleting them. "encode" only encodes the
+	/// frame*.png, or --frame-pattern, in the directory given as <INPUT>, at the --fps it needs, then deletes them unless
+	/// --keep-frames. The directory and anything else in it are left alone.
+	#[structopt(long, default_value = "all", value_name = "extract|encode|all")]
+	stage: Stages,
+
+	/// Extracts the frames to this directory instead of a new one in the temp directory. It's emptied first, and after
+	/// encoding unless --keep-frames, but never deleted. It has to be new, empty, or one an earlier run extracted to,
+	/// and can't have the input or output in it.
+	///
+	/// A network share or a removable drive is warned about, writing thousands of frames there is a lot slower. Without
+	/// it, a temp directory on one is swapped for a local one.
+	#[structopt(long, parse(from_os_str), value_name = "dir", conflicts_with = "in-memory")]
+	frames_dir: Option<PathBuf>,
+
+	/// Names the frames after this pattern instead of frame%04d.png, e.g. img_%06d.png for scripts that expect
+	/// img_000001.png on.
+	///
+	/// It takes one %d, or %0Nd to pad the number with zeros, %% for a %, and ends in .png. --stage encode finds the
+	/// frames by it too, and a --frames-dir with files it would take for frames in it is refused.
+	#[structopt(long, value_name = "pattern")]
+	frame_pattern: Option<FramePattern>,
+
+	/// Leaves the frames directory behind instead of deleting it after encoding.
+	#[structopt(long)]
+	keep_frames: bool,
+
+	/// Extracts the frames to /dev/shm, which is much faster for short clips, if they're estimated to fit.
+	///
+	/// Otherwise says why not and uses the temp directory. Set ramdisk in the config file to use another one.
+	#[structopt(long)]
+	in_memory: bool,
+
+	/// The ramdisk --in-memory extracts to, instead of the config file's ramdisk.
+	#[structopt(long, parse(from_os_str), value_name = "dir")]
+	ramdisk: Option<PathBuf>,
+
+	/// Leaves alone the frames directories that crashed or killed runs left in the temp directory, instead of
+	/// deleting the ones untouched for a day.
+	#[structopt(long)]
+	no_gc: bool,
+
+	/// Stops extracting once the drive the frames are on has fewer MiB than this free, and makes the gif of the frames
+	/// extracted until then, warning that it was cut short. 0 never stops. [default: 256]
+	#[structopt(long, value_name = "MiB")]
+	reserve_space: Option<u64>,
+
+	/// Fails instead of making a shorter gif when extracting stops for --reserve-space.
+	#[structopt(long)]
+	strict_space: bool,
+
+	/// Encodes the frames ffmpeg extracted before failing, if there are at least ten, instead of failing, for videos
+	/// broken part way through. The summary says the gif is cut short, and how ffmpeg failed.
+	#[structopt(long, conflicts_with_all = &["overlap", "chunk-seconds"])]
+	tolerate_decode_errors: bool,
+
+	/// Waits for an input that's still being written, like a recording that hasn't been stopped, to go two seconds
+	/// without changing before converting it, for up to this many seconds [default: 60]. Without it, an input that is
+	/// still being written is warned about.
+	#[structopt(long, require_equals = true, value_name = "seconds")]
+	#[allow(clippy::option_option)]
+	wait_for_input: Option<Option<f64>>,
+
+	/// Runs ffmpeg and gifski at a lower priority, so the machine stays usable while they work, slower.
+	///
+	/// nice 10, and the idle I/O class on Linux, or the below normal priority class on Windows. ffmpeg gets half the
+	/// cores unless --threads says otherwise.
+	#[structopt(long)]
+	background: bool,
+
+	/// How many threads ffmpeg extracts the frames with. 0 lets ffmpeg pick, which is every core. [default: 0, or half
+	/// the cores with --background]
+	#[structopt(long, value_name = "n")]
+	threads: Option<usize>,
+
+	/// Appends a row about the conversion to this CSV file, creating it with a header if it doesn't exist.
+	///
+	/// Failed conversions get a row too, with the stage they failed in and the error's id. With --batch each input
+	/// gets its own row, and separate runs writing to the same file at once don't mix up theirs.
+	#[structopt(long, parse(from_os_str), value_name = "path.csv")]
+	stats_file: Option<PathBuf>,
+
+	/// Creates the directory of <OUTPUT> if it doesn't exist, instead of failing.
+	#[structopt(long)]
+	parents: bool,
+
+	/// Prints version information, including the ffmpeg and gifski that would be used.
+	#[structopt(short = "V", long)]
+	version: bool,
+
+	/// Checks that ffmpeg and gifski are installed and working, then exits without converting anything.
+	///
+	/// Exits with a non-zero code if any check fails.
+	#[structopt(long)]
+	doctor: bool,
+
+	/// Disables colored output. Color is also off when NO_COLOR is set or the output isn't a terminal.
+	#[structopt(long)]
+	no_color: bool,
+
+	/// Verbose mode.
+	#[structopt(short, long)]
+	verbose: bool,
+
+	/// Only prints the results, not the progress.
+	#[structopt(long, conflicts_with_all = &["verbose", "progress-format"])]
+	quiet: bool,
+
+	/// Exits with an error when there were any warnings, after writing the gif, and copying and serving it with --copy
+	/// and --serve. They're listed in the summary.
+	#[structopt(long)]
+	fail_on_warning: bool,
+
+	/// Doesn't look at the frames before encoding for noise or more colors than a gif keeps, or say which options
+	/// would help with them. -v shows what was measured.
+	#[structopt(long)]
+	no_advice: bool,
+
+	/// Quality passed to gifski.
+	#[structopt(short, long, default_value = "100")]
+	quality: u32,
+
+	/// Encodes once per quality from a single extraction, e.g. --compare-quality 60,80,100
+	///
+	/// Writes <OUTPUT>-q60.gif, <OUTPUT>-q80.gif, ... and prints their sizes and encode times.
+	#[structopt(long, use_delimiter = true, conflicts_with = "quality", value_name = "qualities")]
+	compare_quality: Vec<u32>,
+
+	/// Makes a gif per width from a single extraction, e.g. --sizes 480,960 or --size 480 --size 960
+	///
+	/// Writes <OUTPUT>-480.gif, <OUTPUT>-960.gif, ... and prints their sizes and encode times. The frames are
+	/// extracted at the widest and gifski scales them down for the others. One failing doesn't stop the rest.
+	#[structopt(long, alias = "size", use_delimiter = true, value_name = "widths", conflicts_with_all = &["compare-quality", "width", "height", "scale"])]
+	sizes: Vec<u32>,
+
+	/// Encodes stretches of the gif at their own quality and the rest at --quality, e.g. --quality-map 0-5:60,10-20:60
+	///
+	/// In seconds of the gif, in order and not overlapping. Each stretch is encoded on its own and they're joined into
+	/// the one gif, and the summary has the size of each.
+	#[structopt(long, use_delimiter = true, value_name = "start-end:quality", conflicts_with_all = &["compare-quality", "overlap", "chunk-seconds"])]
+	quality_map: Vec<QualityRegion>,
+
+	/// Starts gifski while ffmpeg is still extracting, instead of after it.
+	///
+	/// Faster on long videos, but the progress total is only an estimate until ffmpeg is done.
+	#[structopt(long, conflicts_with_all = &["compare-quality", "keyframes-only", "trim-idle"])]
+	overlap: bool,
+
+	/// Like --overlap, but extracts this many seconds at a time and deletes each chunk's frames once gifski has them.
+	///
+	/// Keeps at most one chunk of frames on disk, for videos too long to extract all at once.
+	#[structopt(long, value_name = "seconds", conflicts_with_all = &["overlap", "compare-quality", "keyframes-only", "trim-idle", "start-frame", "end-frame", "poster"])]
+	chunk_seconds: Option<f64>,
+
+	/// Fails when gifski runs out of memory, instead of trying again at a lower fps and then a smaller width.
+	#[structopt(long)]
+	no_retry: bool,
+
+	/// Encodes with ffmpeg's palettegen and paletteuse instead of gifski, for where gifski isn't installed.
+	///
+	/// The gifs are bigger and band more. Only ffmpeg takes --colors and --dither, they're an error with gifski.
+	#[structopt(long, default_value = "gifski", value_name = "gifski|ffmpeg")]
+	encoder: Encoder,
+
+	/// Colors in the palette of --encoder ffmpeg, from 2 to 256 [default: 256]
+	#[structopt(long, value_name = "2-256")]
+	colors: Option<u16>,
+
+	/// How --encoder ffmpeg dithers. bayer takes a scale from 0 to 5, e.g. bayer:3, higher is fainter but bands more.
+	#[structopt(long, value_name = "none|bayer[:scale]|floyd_steinberg|sierra2_4a")]
+	dither: Option<Dither>,
+
+	/// Picks the highest fps that keeps the gif within this many frames, given how long the (trimmed) video is.
+	#[structopt(long, conflicts_with_all = &["fps", "keyframes-only"], value_name = "N")]
+	max_frames: Option<u32>,
+
+	/// Fps passed to gifski. Clamps to a max of 50 [default: the fps of the input video, resampled down to 30 if it's
+	/// faster. Set max-auto-fps in ~/.config/gifski-ffmpeg/config, or the file $GIFSKI_FFMPEG_CONFIG, to change 30]
+	#[structopt(short, long)]
+	fps: Option<f32>,
+
+	/// The fps the input is resampled down to without --fps, instead of the config file's max-auto-fps.
+	#[structopt(long, parse(try_from_str = config::parse_max_auto_fps), value_name = "fps")]
+	max_auto_fps: Option<f32>,
+
+	/// Uses the fps the input says it has, even when the number of frames extracted from it says otherwise.
+	///
+	/// Without it, if the frames ffmpeg made over how long the extracted part is are more than 5% off that fps, the
+	/// gif plays at the fps they work out to instead, and says so.
+	#[structopt(long, conflicts_with = "fps")]
+	trust_metadata: bool,
+
+	/// Advanced: the ffmpeg format of <INPUT>, passed as its -f, for a raw stream or anything else ffmpeg can't tell
+	/// the format of by itself, e.g. h264 or rawvideo. See `ffmpeg -formats`.
+	#[structopt(long, value_name = "fmt")]
+	input_format: Option<String>,
+
+	/// Advanced: an ffmpeg option for <INPUT>, passed before its -i. Repeat it for each word, e.g.
+	/// --input-arg=-framerate --input-arg=30 for a raw stream, which doesn't say its fps.
+	#[structopt(long, value_name = "arg", number_of_values = 1, allow_hyphen_values = true)]
+	input_arg: Vec<String>,
+
+	/// Start converting at this timestamp, in seconds or as [hh:]mm:ss[.xxx]
+	#[structopt(long, parse(try_from_str = parse_timestamp))]
+	start: Option<f64>,
+
+	/// How ffmpeg seeks to --start.
+	///
+	/// "fast" jumps to the keyframe before it, which can be a couple of seconds early, "accurate" decodes everything
+	/// up to it, "smart" jumps to a few seconds before it and decodes the rest.
+	#[structopt(long, default_value = "smart", value_name = "fast|accurate|smart")]
+	seek_mode: SeekMode,
+
+	/// Stop converting at this timestamp, in seconds or as [hh:]mm:ss[.xxx]
+	#[structopt(long, parse(try_from_str = parse_timestamp), conflicts_with = "duration")]
+	end: Option<f64>,
+
+	/// Convert this many seconds, from --start or the beginning.
+	#[structopt(long, parse(try_from_str = parse_timestamp))]
+	duration: Option<f64>,
+
+	/// Converts at most this many seconds of whatever --start, --end or --duration pick. The rest isn't extracted.
+	#[structopt(long, parse(try_from_str = parse_timestamp), value_name = "seconds")]
+	max_duration: Option<f64>,
+
+	/// Deletes any frames ffmpeg extracted past --end, --duration or --max-duration before encoding.
+	///
+	/// ffmpeg can write a few while it finishes decoding, and one extra frame shows in a gif that loops.
+	#[structopt(long, conflicts_with_all = &["overlap", "chunk-seconds", "keyframes-only", "concat"])]
+	exact_end: bool,
+
+	/// Makes the gif exactly this long, e.g. 5 or 0:05, to the hundredth of a second, whatever the video's length.
+	///
+	/// The frames still play at --fps, and --start, --end and --duration still pick which part of the video they come
+	/// from. Frames past the length are left out; if the video ends sooner its last frame is held until it.
+	#[structopt(long, parse(try_from_str = parse_timestamp), value_name = "seconds", conflicts_with_all = &["overlap", "chunk-seconds", "source-timing"])]
+	exact_duration: Option<f64>,
+
+	/// Reduces each extracted frame to at most this many colors, 2 to 256, for a much smaller gif of noisy footage.
+	///
+	/// Each frame gets its own palette, without dithering, so there's banding instead of noise. Done after every
+	/// other filter. -v shows how many colors the first frame had and has left.
+	#[structopt(long, value_name = "colors")]
+	pre_quantize: Option<u32>,
+
+	/// Crossfades the last frames into the first ones, which are then dropped, so the gif doesn't jump when it loops.
+	///
+	/// The gif gets that many frames shorter. At most half of the frames, e.g. --loop-smooth=12 [default: 8]
+	#[structopt(long, require_equals = true, value_name = "frames", conflicts_with_all = &["overlap", "chunk-seconds"])]
+	#[allow(clippy::option_option)]
+	loop_smooth: Option<Option<usize>>,
+
+	/// Runs this command over each extracted frame before it's encoded, e.g. --frame-filter-cmd "redact --in {} -o {}"
+	///
+	/// {} is the frame's path, or the frames go at the end without one. The frames are changed in place. It's split
+	/// into arguments like a shell would, but isn't run by one unless --frame-filter-shell is given. The first one to
+	/// fail stops the conversion.
+	#[structopt(long, value_name = "command", conflicts_with_all = &["overlap", "chunk-seconds"])]
+	frame_filter_cmd: Option<String>,
+
+	/// Runs --frame-filter-cmd once per frame, or once with every frame as {}, in a few runs like xargs if they don't
+	/// fit in one command line [default: per-frame]
+	#[structopt(long, value_name = "per-frame|batch", requires = "frame-filter-cmd")]
+	frame_filter_mode: Option<FrameFilterMode>,
+
+	/// Runs --frame-filter-cmd with sh -c, or cmd /C on Windows, for pipes and the like. {} is "$@" to sh, the frame
+	/// paths quoted to cmd
+	#[structopt(long, requires = "frame-filter-cmd")]
+	frame_filter_shell: bool,
+
+	/// Draws --cursor-image on each frame where these events put the cursor, for recordings made without it
+	///
+	/// A JSON array of {"x": 10, "y": 20, "t": 1.5}, or one on each line, in the input's pixels and seconds. The
+	/// cursor moves in a line between them and stays put before the first and after the last.
+	#[structopt(long, value_name = "events.json", requires = "cursor-image", conflicts_with_all = &["overlap", "chunk-seconds", "keyframes-only"])]
+	cursor_overlay: Option<PathBuf>,
+
+	/// The cursor --cursor-overlay draws, a PNG with its tip in the top left corner
+	#[structopt(long, value_name = "png", requires = "cursor-overlay")]
+	cursor_image: Option<PathBuf>,
+
+	/// How many --frame-filter-cmd commands, or --cursor-overlay frames, run at once [default: one per CPU]
+	#[structopt(long, value_name = "n")]
+	jobs: Option<usize>,
+
+	/// Converts more than 60 seconds, which is refused without it in case it's an accident.
+	#[structopt(long, alias = "yes")]
+	force: bool,
+
+	/// Start converting at this frame. Frames are counted from 0.
+	///
+	/// Selects frames with ffmpeg's trim filter, so it's exact even where seeking by time isn't.
+	#[structopt(long, conflicts_with_all = &["start", "end", "duration"])]
+	start_frame: Option<u64>,
+
+	/// The last frame to convert. Inclusive: --start-frame 10 --end-frame 19 converts 10 frames.
+	#[structopt(long, conflicts_with_all = &["start", "end", "duration"])]
+	end_frame: Option<u64>,
+
+	/// Encodes only the extracted frames this file lists, in its order, so they can repeat or jump around.
+	///
+	/// One frame, 7, or inclusive range, 10-45, a line, numbered from 1 like the frame files, with # comments. A
+	/// range going down, 45-10, plays backwards. Works on the frames of --stage encode too.
+	#[structopt(long, parse(from_os_str), value_name = "path", conflicts_with_all = &["overlap", "chunk-seconds"])]
+	edit_list: Option<PathBuf>,
+
+	/// Decodes only the keyframes, for a quick rough storyboard of a long video.
+	///
+	/// Keyframes are irregularly spaced, so the gif plays at 3 fps unless --fps is given.
+	#[structopt(long)]
+	keyframes_only: bool,
+
+	/// Shows each frame for as long as it was in the video, instead of all of them for 1/fps seconds.
+	///
+	/// For gifs missing frames, like --keyframes-only ones, which otherwise play evenly spaced. Frames left out hold
+	/// the one before them. gifski is given one fps all the same and the delays are rewritten after; if it merged
+	/// frames that were the same, they're left as they are with a warning.
+	#[structopt(long, conflicts_with_all = &["overlap", "chunk-seconds", "edit-list", "loop-smooth"])]
+	source_timing: bool,
+
+	/// Drops black or frozen frames from the start and end, like the second before a screen recording gets going.
+	#[structopt(long)]
+	trim_idle: bool,
+
+	/// How dark a frame has to be to count as black for --trim-idle, from 0 to 1 [default: 0.1]
+	///
+	/// Higher values also count frames that barely change as frozen, so they trim more.
+	#[structopt(long, requires = "trim-idle")]
+	trim_idle_threshold: Option<f64>,
+
+	/// Replaces single frames much brighter or darker than the ones either side of them, like a white flash in a
+	/// stream, with the frame before, so they don't take over the palette.
+	///
+	/// The threshold is how far off from both they have to be, from 0 to 1 of the way from black to white, e.g.
+	/// --drop-flash-frames=0.3 to catch dimmer ones [default: 0.5]
+	#[structopt(long, require_equals = true, value_name = "threshold", conflicts_with_all = &["overlap", "chunk-seconds"])]
+	#[allow(clippy::option_option)]
+	drop_flash_frames: Option<Option<f64>>,
+
+	/// Undoes camera shake in handheld footage, with a first pass over the video to find it.
+	///
+	/// Needs an ffmpeg built with libvidstab, which is checked before starting.
+	#[structopt(long, conflicts_with_all = &["keyframes-only", "chunk-seconds", "concat", "grid"])]
+	stabilize: bool,
+
+	/// Slowly zooms in from the whole frame to this region of the video, e.g. 640x360+100+50 over the whole gif, or
+	/// 640x360+100+50:4 over the first 4 seconds.
+	///
+	/// The region is in pixels of the video, before --aspect, --width and the like. One of another shape than the
+	/// frame ends up centered, with some of what's around it.
+	#[structopt(long, value_name = "WxH+X+Y[:seconds]", conflicts_with_all = &["keyframes-only", "concat", "grid"])]
+	zoom_to: Option<Zoom>,
+
+	/// Crops to this region of the video, e.g. 1280x720@640,360, or with auto to the region the motion is in.
+	///
+	/// auto compares a few small frames spread over the gif, and prints the region it picked in the form that picks
+	/// it again. Either way --aspect, --width and the like then apply to the region rather than the whole frame.
+	#[structopt(long, value_name = "auto|WxH@X,Y", conflicts_with_all = &["zoom-to", "concat"])]
+	focus: Option<Focus>,
+
+	/// Crops to a square, same as --aspect 1:1
+	#[structopt(long, conflicts_with = "aspect")]
+	square: bool,
+
+	/// Crops to this aspect ratio, e.g. 16:9, keeping as much of the video as possible.
+	#[structopt(long, value_name = "W:H")]
+	aspect: Option<Aspect>,
+
+	/// Which part of the video --square and --aspect keep.
+	#[structopt(long, default_value = "center", value_name = "center|top|bottom|left|right")]
+	gravity: Gravity,
+
+	/// Scales the frames to this width, after cropping. Keeps the aspect ratio unless --height is also given.
+	#[structopt(long)]
+	width: Option<u32>,
+
+	/// Scales the frames to this height, after cropping. Keeps the aspect ratio unless --width is also given.
+	#[structopt(long)]
+	height: Option<u32>,
+
+	/// Scales the frames by a percentage or factor, e.g. 50% or 0.5, after cropping. Sizes are rounded to even numbers.
+	#[structopt(long, conflicts_with_all = &["width", "height"], value_name = "percent|factor")]
+	scale: Option<Scale>,
+
+	/// How the frames are made to fit when both --width and --height are given.
+	///
+	/// "stretch" distorts them, "crop" cuts off what sticks out, "pad" letterboxes them with --pad-color.
+	#[structopt(long, default_value = "pad", value_name = "stretch|crop|pad")]
+	fit: Fit,
+
+	/// Keeps odd widths and heights from --width, --height, --aspect or --square, instead of rounding them down to even.
+	#[structopt(long)]
+	allow_odd_dimensions: bool,
+
+	/// The color --fit pad pads with, e.g. black, white@0.5 or #1e1e1e [default: black]
+	#[structopt(long)]
+	pad_color: Option<String>,
+
+	/// Stacks a strip this many pixels high under the video, drawn from its audio, e.g. --waveform=48 [default: 64]
+	///
+	/// It's as wide as the frames after --width and the like, so the gif is only taller. Skipped with a warning if
+	/// the video has no audio.
+	#[structopt(long, require_equals = true, value_name = "height", conflicts_with_all = &["keyframes-only", "concat", "overlap", "chunk-seconds"])]
+	#[allow(clippy::option_option)]
+	waveform: Option<Option<u32>>,
+
+	/// What the --waveform strip shows: the sound wave, or the frequencies scrolling past [default: waves]
+	#[structopt(long, value_name = "waves|spectrum", requires = "waveform")]
+	waveform_style: Option<WaveformStyle>,
+
+	/// Writes this text into the gif's comment block [default: the gifski-ffmpeg and gifski versions and settings]
+	#[structopt(long, conflicts_with = "no-comment")]
+	comment: Option<String>,
+
+	/// Leaves the gif's comment block out.
+	#[structopt(long)]
+	no_comment: bool,
+
+	/// Makes the same gif, byte for byte, every time it's run on the same input with the same options and tools.
+	///
+	/// ffmpeg is asked for bit exact frames, and the automatic comment leaves out the versions. On when SOURCE_DATE_EPOCH is set, which ffmpeg and gifski are run with too.
+	#[structopt(long)]
+	deterministic: bool,
+
+	/// Shows a still image <INPUT> for this many seconds. Without it, images are refused as a likely wrong file.
+	#[structopt(long, value_name = "seconds")]
+	still_duration: Option<f64>,
+
+	/// Also write a still of one frame as <OUTPUT>.png, next to the gif.
+	///
+	/// Either "first", "middle", "interesting" for the first that isn't black or frozen, or a timestamp in seconds
+	/// into the video, e.g. --poster=2.5 [default: middle]
+	#[structopt(long, require_equals = true, value_name = "first|middle|interesting|timestamp")]
+	#[allow(clippy::option_option)] // How structopt spells a flag with an optional value.
+	poster: Option<Option<Poster>>,
+
+	/// Write the --poster as "png", or as "jpg" to <OUTPUT>.jpg [default: png]
+	#[structopt(long, value_name = "png|jpg")]
+	poster_format: Option<PosterFormat>,
+
+	/// Also write <OUTPUT>.mp4, H.264 of the same frames at most 720p and about 10 MB, for sites that preview video
+	#[structopt(long, conflicts_with = "chunk-seconds")]
+	also_mp4: bool,
+
+	/// For link previews: the gif, --also-mp4, and a JPEG --poster of the first interesting frame
+	///
+	/// Another --poster picks a different frame. The MP4 or poster failing is only a warning, the rest are still
+	/// written.
+	#[structopt(long, conflicts_with_all = &["chunk-seconds", "poster-format"])]
+	social: bool,
+
+	/// Also write <OUTPUT>-sheet.png, a grid of thumbnails of evenly spaced frames labelled with when they are.
+	///
+	/// Made from the extracted frames, skipped with a warning if the gif has fewer, e.g. --contact-sheet=6x3 [default: 4x4]
+	#[structopt(long, require_equals = true, value_name = "COLSxROWS", conflicts_with = "chunk-seconds")]
+	#[allow(clippy::option_option)]
+	contact_sheet: Option<Option<Grid>>,
+
+	/// Appends these videos after <INPUT> in the same gif, e.g. a.mp4 out.gif --concat b.mp4 c.mp4
+	///
+	/// They're extracted at <INPUT>'s fps, or --fps, and scaled to the size of its frames. Give it after <OUTPUT>,
+	/// everything following it is taken as another video.
+	#[structopt(long, parse(from_os_str), value_name = "INPUTS", min_values = 1, conflicts_with_all = &["batch", "estimate"])]
+	concat: Vec<PathBuf>,
+
+	/// Lays <INPUT> and the --grid-inputs out side by side, e.g. --grid 2x2 to compare four encodes.
+	///
+	/// Every cell is the size of <INPUT>, cells without an input are black, and the gif is as long as the shortest
+	/// input. Cropping and scaling apply to the whole grid.
+	#[structopt(long, value_name = "COLSxROWS", requires = "grid-inputs", conflicts_with_all = &["concat", "keyframes-only", "overlap", "chunk-seconds"])]
+	grid: Option<Grid>,
+
+	/// The other cells of the --grid, left to right then top to bottom. Give it after <OUTPUT>, like --concat.
+	#[structopt(long, parse(from_os_str), value_name = "INPUTS", min_values = 1, requires = "grid")]
+	grid_inputs: Vec<PathBuf>,
+
+	/// Writes each input's file name in the corner of its --grid cell.
+	#[structopt(long, requires = "grid")]
+	grid_labels: bool,
+
+	/// Converts <INPUT> once to warm up, then N more times, and reports how long each stage took [default: 3]
+	///
+	/// The gifs are written to a temporary directory and deleted, so <OUTPUT> can't be given.
+	#[structopt(long, require_equals = true, value_name = "N", group = "report")]
+	#[allow(clippy::option_option)]
+	benchmark: Option<Option<usize>>,
+
+	/// Converts each of these files, e.g. --batch *.mp4, and prints a table of the results.
+	///
+	/// Each gif is written next to its input. Carries on past inputs that fail, but exits with a non-zero code if any did.
+	/// How far it's got is saved to a session file in <TEMP>/gifski-ffmpeg/ as it goes, see --resume-session.
+	#[structopt(long, parse(from_os_str), value_name = "INPUTS", min_values = 1, group = "report")]
+	batch: Vec<PathBuf>,
+
+	/// Carries on with the --batch this session file was saved by, skipping the inputs it already converted.
+	///
+	/// Run the same command again with it added, the file says where it was saved when the batch starts. The inputs
+	/// and options have to be the same. Inputs whose gif is gone or broken are converted again, and so are ones that
+	/// failed. The file is deleted once every input is converted.
+	#[structopt(long, parse(from_os_str), value_name = "file", requires = "batch")]
+	resume_session: Option<PathBuf>,
+
+	/// Keeps the --batch session file once every input is converted.
+	#[structopt(long, requires = "batch")]
+	keep_session: bool,
+
+	/// Probes <INPUT> and prints roughly how big the gif and the extracted frames will be, without converting anything.
+	///
+	/// The gif size is a ballpark from the dimensions, frame count and quality, real ones vary with the content.
+	#[structopt(long, group = "report")]
+	estimate: bool,
+
+	/// Prints what converting would do, without doing it: the fps, size, quality and paths it comes to and why, and
+	/// the ffmpeg and gifski commands.
+	///
+	/// Each setting says where it came from: the command line, an args file, the config file, the default, or the
+	/// input, which is read with ffprobe.
+	#[structopt(long, group = "report")]
+	explain: bool,
+
+	/// Makes a gif of every this many seconds of <INPUT>, <name>-part01.gif, <name>-part02.gif and on, then prints
+	/// a table of them. A last part under 2 seconds is added to the one before it.
+	///
+	/// Splits what --start, --end and the other trims leave of the input.
+	#[structopt(
+		long,
+		parse(try_from_str = parse_timestamp),
+		value_name = "seconds",
+		group = "report",
+		conflicts_with_all = &["concat", "grid", "compare-quality", "stage", "max-duration"],
+	)]
+	split_every: Option<f64>,
+
+	/// How progress is printed: "human", or "json-lines" for programs wrapping this one.
+	///
+	/// json-lines prints one JSON object per line on stdout, and the summary on stderr. Every object has an "event":
+	///
+	///   {"event":"started","stage":"extract"}       stage is stabilize, extract, encode, poster or cleanup
+	///   {"event":"extracting","frame":12,"total":48,"percent":25.0}
+	///                                               total and percent are null if the video's length isn't known
+	///   {"event":"encoding","frame":12,"total":48,"percent":25.0,"eta_seconds":3}
+	///                                               eta_seconds is null until there's an estimate
+	///   {"event":"finished","stage":"extract","seconds":1.2}
+	///   {"event":"info","message":"..."}
+	///   {"event":"warning","id":"out-of-range","message":"..."}
+	///                                               id is stable, for picking out particular warnings
+	///   {"event":"result","output":"out.gif","poster":null,"mp4":null,"contact_sheet":null,"fps":24.0,
+	///    "quality":100,"frame_count":48,"duration":2.0,"size":123456,"decode_error":null,
+	///    "busyness":{"sampled":16,"unique_colors":0.42,"difference":0.013},"warnings":[{"id":"...","message":"..."}]}
+	///                                               on one line, the last of a conversion that worked, size is in bytes,
+	///                                               decode_error is how ffmpeg failed if --tolerate-decode-errors
+	///                                               made a gif cut short from what it extracted before, busyness is
+	///                                               what the advice was based on, null with --no-advice
+	///   {"event":"error","message":"..."}           the last line of one that didn't
+	///
+	/// New events and fields may be added, the ones above won't change.
+	#[structopt(long, default_value = "human", value_name = "human|json-lines", verbatim_doc_comment)]
+	progress_format: ProgressFormat,
+
+	/// Puts the gif on the clipboard when done, or its path where the clipboard can't hold files.
+	///
+	/// Uses osascript on macOS, PowerShell on Windows, and xclip or wl-copy on Linux. Skipped when stdout isn't
+	/// a terminal or CI is set.
+	#[structopt(long, conflicts_with = "report")]
+	copy: bool,
+
+	/// Shows a desktop notification when the gif is done, or which stage failed.
+	///
+	/// Uses notify-send on Linux, osascript on macOS and a toast on Windows, or rings the terminal bell without them.
+	/// Can be made the default with `notify = true` in the config file.
+	#[structopt(long, conflicts_with = "report")]
+	notify: bool,
+
+	/// Serves a page showing the gif on http://127.0.0.1:PORT/ once it's done, until Ctrl+C.
+	///
+	/// The page reloads the gif whenever it changes, so converting again to the same output, from another terminal,
+	/// shows the new one. Only listens on localhost.
+	#[structopt(long, conflicts_with_all = &["report", "stage"])]
+	serve: bool,
+
+	/// The port --serve listens on, instead of a free one.
+	#[structopt(long, requires = "serve")]
+	port: Option<u16>,
+
+	/// Doesn't notify, even if the config file says to.
+	#[structopt(long, conflicts_with = "notify")]
+	no_notify: bool,
+
+	/// Prints the --benchmark, --batch, --estimate, --explain or --split-every report as JSON.
+	#[structopt(long, requires = "report")]
+	json: bool,
+
+	/// Writes the options given, and the config file's settings, but not <INPUT> and <OUTPUT>, to a file to run with
+	/// again with --args-from.
+	#[structopt(long, parse(from_os_str), value_name = "file")]
+	save_args: Option<PathBuf>,
+
+	/// Loads the options in a --save-args file. "@file" does the same, where an option or <INPUT> could go but not
+	/// as an option's value. "@@" there stands for a "@", for an <INPUT> like "@@clip.mp4", and nothing after "--"
+	/// is a file.
+	///
+	/// Options on the command line are used instead of the same options in the file, but not instead of ones they
+	/// conflict with.
+	#[structopt(long, parse(from_os_str), value_name = "file")]
+	#[allow(dead_code)] // Loaded by argfile::expand before parsing, it's here for --help.
+	args_from: Option<PathBuf>,
+}
+
+impl Opt {
+	fn into_options(self, config: &Config) -> ConvertOptions {
+		// --batch replaces the input for each file.
+		let mut options = ConvertOptions::new(self.input.unwrap_or_default());
+		options.output = self.output;
+		options.stages = self.stage;
+		options.frames_dir = self.frames_dir;
+		if let Some(pattern) = self.frame_pattern { options.frame_pattern = pattern; }
+		options.keep_frames = self.keep_frames;
+		options.in_memory = self.in_memory;
+		options.ramdisk = self.ramdisk.or_else(|| config.ramdisk.clone());
+		options.gc = !self.no_gc;
+		options.advice = !self.no_advice;
+		if let Some(mib) = self.reserve_space { options.reserve_space = mib.saturating_mul(1024 * 1024); }
+		options.strict_space = self.strict_space;
+		options.tolerate_decode_errors = self.tolerate_decode_errors;
+		options.wait_for_input = self.wait_for_input.map(|s| s.unwrap_or(DEFAULT_INPUT_WAIT));
+		options.background = self.background;
+		options.threads = self.threads;
+		options.stats_file = self.stats_file;
+		options.resolve_symlinks = self.resolve_symlinks;
+		options.create_parents = self.parents;
+		options.concat = self.concat;
+		options.grid = self.grid;
+		options.grid_inputs = self.grid_inputs;
+		options.grid_labels = self.grid_labels;
+		options.quality = self.quality;
+		options.compare_quality = self.compare_quality;
+		options.sizes = self.sizes;
+		options.quality_map = self.quality_map;
+		options.overlap = self.overlap;
+		options.chunk_seconds = self.chunk_seconds;
+		options.retry = !self.no_retry;
+		options.encoder = self.encoder;
+		options.colors = self.colors;
+		options.dither = self.dither;
+		options.max_frames = self.max_frames;
+		options.fps = self.fps;
+		options.input_format = self.input_format;
+		options.input_args = self.input_arg;
+		options.trust_metadata = self.trust_metadata;
+		options.max_auto_fps = self.max_auto_fps.unwrap_or(config.max_auto_fps);
+		options.start = self.start;
+		options.seek_mode = self.seek_mode;
+		options.end = self.end;
+		options.duration = self.duration;
+		options.max_duration = self.max_duration;
+		options.exact_end = self.exact_end;
+		options.exact_duration = self.exact_duration;
+		options.pre_quantize = self.pre_quantize;
+		options.loop_smooth = self.loop_smooth.map(|n| n.unwrap_or(DEFAULT_LOOP_SMOOTH_FRAMES));
+		options.frame_filter = self.frame_filter_cmd.map(|command| FrameFilter {
+			mode: self.frame_filter_mode.unwrap_or_default(),
+			shell: self.frame_filter_shell,
+			..FrameFilter::new(command)
+		});
+		options.cursor_overlay = self.cursor_overlay;
+		options.cursor_image = self.cursor_image;
+		options.jobs = self.jobs;
+		options.force = self.force;
+		options.start_frame = self.start_frame;
+		options.end_frame = self.end_frame;
+		options.edit_list = self.edit_list;
+		options.keyframes_only = self.keyframes_only;
+		options.source_timing = self.source_timing;
+		options.trim_idle = self.trim_idle;
+		if let Some(threshold) = self.trim_idle_threshold { options.trim_idle_threshold = threshold; }
+		options.drop_flash_frames = self.drop_flash_frames.map(|t| t.unwrap_or(DEFAULT_FLASH_THRESHOLD));
+		options.stabilize = self.stabilize;
+		options.zoom_to = self.zoom_to;
+		options.focus = self.focus;
+		options.aspect = if self.square { Some(Aspect::SQUARE) } else { self.aspect };
+		options.gravity = self.gravity;
+		options.width = self.width;
+		options.height = self.height;
+		options.scale = self.scale;
+		options.fit = self.fit;
+		options.allow_odd_dimensions = self.allow_odd_dimensions;
+		options.pad_color = self.pad_color;
+		options.waveform = self.waveform.map(|height| height.unwrap_or(DEFAULT_WAVEFORM_HEIGHT));
+		options.waveform_style = self.waveform_style.unwrap_or_default();
+		options.comment = match (self.comment, self.no_comment) {
+			(_, true) => Comment::Off,
+			(Some(text), false) => Comment::Text(text),
+			(None, false) => Comment::Auto,
+		};
+		options.deterministic = self.deterministic || env::var_os("SOURCE_DATE_EPOCH").is_some();
+		options.still_duration = self.still_duration;
+		options.poster = self.poster.map(Option::unwrap_or_default);
+		options.poster_format = self.poster_format.unwrap_or_default();
+		options.also_mp4 = self.also_mp4;
+		options.social = self.social;
+		options.contact_sheet = self.contact_sheet.map(|grid| grid.unwrap_or(DEFAULT_CONTACT_SHEET));
+		options
+	}
+}
+
+/// The --progress-format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProgressFormat {
+	Human,
+	JsonLines,
+}
+
+impl std::str::FromStr for ProgressFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, String> {
+		match s {
+			"human" => Ok(ProgressFormat::Human),
+			"json-lines" => Ok(ProgressFormat::JsonLines),
+			_ => Err(format!("expected human or json-lines, got {s:?}")),
+		}
+	}
+}
+
+fn main() {
+	if let Err(e) = run() {
+		eprintln!("{}", style::error(&error_message(&e)));
+		std::process::exit(1);
+	}
+}
+
+/// `e` and the context it was given, stopping at a [`ConvertError`], whose message already says what caused it.
+fn error_message(e: &anyhow::Error) -> String {
+	let mut message = Vec::new();
+	for cause in e.chain() {
+		message.push(cause.to_string());
+		if cause.is::<ConvertError>() { break; }
+	}
+	message.join(": ")
+}
+
+fn run() -> Result<()> {
+	let expanded = argfile::expand(Opt::clap(), env::args_os().collect())?;
+	let matches = Opt::clap().get_matches_from(&expanded.args);
+	let mut opt = Opt::from_clap(&matches);
+	style::init(opt.no_color);
+	let level = if opt.verbose { log::LevelFilter::Debug } else { log::LevelFilter::Warn };
+	SimpleLogger::new().with_level(level).with_colors(style::stderr_colored()).init().unwrap();
+
+	if opt.version {
+		print_version();
+		return Ok(());
+	}
+
+	if opt.doctor {
+		let passed = print_doctor();
+		std::process::exit(i32::from(!passed));
+	}
+
+	if opt.from_clipboard {
+		let input = clipboard::paste_video(&SystemRunner).map_err(anyhow::Error::msg).context("--from-clipboard")?;
+		if !opt.quiet { println!("Converting {} from the clipboard", input.display()); }
+		opt.input = Some(input);
+	}
+
+	let config = config::load()?;
+
+	if let Some(path) = &opt.save_args {
+		argfile::save(&matches, &config, path)?;
+	}
+
+	if opt.explain {
+		let json = opt.json;
+		let origins = origins(&matches, &expanded.loaded, &config);
+		let runner: &dyn CommandRunner = if opt.background { &BackgroundRunner } else { &SystemRunner };
+		let plan = explain::explain(&opt.into_options(&config), runner, &origins)?;
+		if json { println!("{}", serde_json::to_string_pretty(&plan)?); } else { print_plan(&mut io::stdout(), &plan)?; }
+		return Ok(());
+	}
+
+	if opt.benchmark.is_some() || opt.estimate || !opt.batch.is_empty() || opt.split_every.is_some() {
+		return run_report(opt, &config);
+	}
+
+	let recording = match opt.capture {
+		Some(region) => Some(record_capture(&mut opt, region.unwrap_or(capture::Region::Screen)).context("--capture")?),
+		None => None,
+	};
+	let (keyframes_only, concatenated, quiet, copy, fail_on_warning) = (opt.keyframes_only, opt.concat.len(), opt.quiet, opt.copy, opt.fail_on_warning);
+	let server = opt.serve.then(|| serve::bind(opt.port)).transpose().context("couldn't start the --serve server")?;
+	let notify = (opt.notify || config.notify) && !opt.no_notify;
+	let json_lines = opt.progress_format == ProgressFormat::JsonLines;
+	// For which stage failed.
+	let (started, stage) = (Instant::now(), Cell::new(None));
+	let options = opt.into_options(&config);
+	let input = options.input.clone();
+	let result = Conversion::new(options)
+		.on_progress(|p| {
+			if let Progress::Started(s) = p { stage.set(Some(s)); }
+			if json_lines {
+				if let Some(event) = Event::from_progress(&p) { event.print(); }
+			} else if !quiet {
+				print_progress(p, None);
+			}
+		})
+		.run();
+	let report = match result {
+		Ok(report) => report,
+		Err(e) => {
+			if json_lines { Event::Error { message: &e.to_string() }.print(); }
+			if notify { Notification::failed(stage.get(), &input, started.elapsed()).show(); }
+			if let Some(recording) = &recording { log::warn!("The recording is kept at {}, to convert it again without recording it again", recording.display()); }
+			return Err(e.into());
+		}
+	};
+	if let Some(recording) = &recording {
+		// Kept along with the frames, with --keep-frames or --stage extract.
+		if report.frames_dir.is_none() { let _ = fs::remove_file(recording); } else if !quiet { println!("Recording: {}", recording.display()); }
+	}
+
+	if json_lines {
+		Event::from_report(&report).print();
+		print_summary(&mut io::stderr(), &report, keyframes_only, concatenated)?;
+	} else {
+		print_summary(&mut io::stdout(), &report, keyframes_only, concatenated)?;
+	}
+	if notify { Notification::finished(&report).show(); }
+	if copy && !json_lines && clipboard::interactive() {
+		match clipboard::copy(&report.output) {
+			Ok(clipboard::Copied::File) => println!("Copied the gif to the clipboard."),
+			Ok(clipboard::Copied::Path) => println!("{}", style::warning("The clipboard can't hold the gif itself here, so its path was copied instead.")),
+			Err(e) => println!("{}", style::warning(&format!("Couldn't copy the gif to the clipboard: {e}"))),
+		}
+	}
+	if let Some(server) = server {
+		let message = format!("Serving {} at {}, Ctrl+C to stop.", report.output.display(), serve::url(&server)?);
+		if json_lines { Event::Info { message: &message }.print(); } else { println!("{message}"); }
+		serve::serve(&server, &report.output)?;
+		if !json_lines { println!("Stopped serving."); }
+	}
+	// Once the gif is copied and served, which a warning doesn't stop.
+	if fail_on_warning && !report.warnings.is_empty() {
+		anyhow::bail!("there were {} warnings, and --fail-on-warning", report.warnings.len());
+	}
+	Ok(())
+}
+
+/// Records the screen for --capture, and makes the recording <INPUT>, and <INPUT> if one was given <OUTPUT>.
+fn record_capture(opt: &mut Opt, region: capture::Region) -> Result<PathBuf> {
+	if opt.input.is_some() && opt.output.is_some() { anyhow::bail!("the screen is the input, give only <OUTPUT>"); }
+	let (device, note) = capture::device().map_err(anyhow::Error::msg)?;
+	// Before anything is recorded, rather than after.
+	let encoder = (opt.encoder == Encoder::Gifski).then_some(Tool::Gifski);
+	for tool in [Tool::Ffmpeg].into_iter().chain(encoder) { tools::probe(&SystemRunner, tool)?; }
+	let region = capture::select(&SystemRunner, region).map_err(anyhow::Error::msg)?;
+	let recording = capture::recording_path();
+	let command = capture::command(&device, region, opt.fps.unwrap_or(capture::DEFAULT_FPS), opt.capture_duration, &recording);
+	let say = |message: &str| match opt.progress_format {
+		ProgressFormat::JsonLines => Event::Info { message }.print(),
+		ProgressFormat::Human if !opt.quiet => println!("{message}"),
+		ProgressFormat::Human => {}
+	};
+	if let Some(note) = note { say(note); }
+	match opt.capture_duration {
+		Some(seconds) => say(&format!("Recording {region} for {seconds}s.")),
+		None => say(&format!("Recording {region}, press Ctrl+C to stop.")),
+	}
+	capture::record(&SystemRunner, &command, &recording).map_err(anyhow::Error::msg)?;
+	// Relative to where it's run, not to the recording in the temp directory.
+	let cwd = env::current_dir()?;
+	opt.output = Some(match opt.input.take() {
+		Some(output) => cwd.join(output).into_os_string(),
+		None => opt.output.take().map_or_else(|| capture::default_output(&cwd), |output| cwd.join(output)).into_os_string(),
+	});
+	opt.input = Some(recording.clone());
+	Ok(recording)
+}
+
+/// --benchmark, --estimate, --batch and --split-every, which print a report instead of a summary.
+fn run_report(mut opt: Opt, config: &Config) -> Result<()> {
+	let runner: &dyn CommandRunner = if opt.background { &BackgroundRunner } else { &SystemRunner };
+	if let Some(runs) = opt.benchmark {
+		let json = opt.json;
+		let report = benchmark::run(opt.into_options(config), runner, runs.unwrap_or(benchmark::DEFAULT_RUNS))?;
+		if json {
+			println!("{}", serde_json::to_string_pretty(&report)?);
+		} else {
+			print_benchmark(&report);
+		}
+		return Ok(());
+	}
+
+	if opt.estimate {
+		let json = opt.json;
+		let estimate = estimate::estimate(&opt.into_options(config), runner)?;
+		if json {
+			println!("{}", serde_json::to_string_pretty(&estimate)?);
+		} else {
+			println!(
+				"Estimated gif size: {} ({}x{}, {} frames at {} fps, quality {})",
+				disk::human_size(estimate.gif_size), estimate.width, estimate.height, estimate.frame_count, estimate.fps, estimate.quality,
+			);
+			println!("Extracted frames: about {}", disk::human_size(estimate.frames_size));
+		}
+		return Ok(());
+	}
+
+	if !opt.batch.is_empty() {
+		let (inputs, json, quiet, fail_on_warning) = (std::mem::take(&mut opt.batch), opt.json, opt.quiet || opt.json, opt.fail_on_warning);
+		let mut session = opt.resume_session.take().map_or_else(|| Session::in_temp(&std::env::temp_dir()), Session::resume);
+		session.keep = opt.keep_session;
+		if !quiet && !session.resume {
+			println!("Saving how far it's got to {0}, carry on with --resume-session {0} if it's cut short.", session.path.display());
+		}
+		let results = batch::run(&opt.into_options(config), &inputs, Some(&session), runner, |i, progress| {
+			if !quiet { print_progress(progress, Some(&BatchPosition::new(i, &inputs))); }
+		})?;
+		if json {
+			println!("{}", serde_json::to_string_pretty(&results)?);
+		} else {
+			print_batch(&results);
+		}
+		let warned = fail_on_warning && results.iter().any(|r| !r.warnings.is_empty());
+		if warned || results.iter().any(|r| r.status == Status::Failed) { std::process::exit(1); }
+		return Ok(());
+	}
+
+	if let Some(every) = opt.split_every {
+		let (json, quiet) = (opt.json, opt.quiet || opt.json);
+		let name = opt.input.as_deref().and_then(Path::file_stem).unwrap_or_default().to_string_lossy().into_owned();
+		let parts = split::run(&opt.into_options(config), every, runner, |index, count, progress| {
+			if !quiet { print_progress(progress, Some(&BatchPosition { index, count, name: name.clone() })); }
+		})?;
+		if json {
+			println!("{}", serde_json::to_string_pretty(&parts)?);
+		} else {
+			print_parts(&parts);
+		}
+	}
+	Ok(())
+}
+
+/// What a finished conversion made. `concatenated` is the number of --concat inputs.
+fn print_summary(out: &mut dyn Write, report: &ConvertReport, keyframes_only: bool, concatenated: usize) -> io::Result<()> {
+	writeln!(out, "{}", style::header("Complete!"))?;
+	if let Some(error) = &report.decode_error {
+		writeln!(out, "{}", style::warning(&format!("Truncated at {:.1}s, where {error}", report.duration)))?;
+	}
+	if report.frames_dir.as_ref() == Some(&report.output) {
+		writeln!(out, "Frames: {} ({} at {} fps)", report.output.display(), report.frame_count, report.fps)?;
+		if let Some(location) = &report.frames_location { writeln!(out, "Extracted {location}")?; }
+		return print_warnings(out, &report.warnings);
+	}
+	if keyframes_only { writeln!(out, "Keyframes found: {}", report.frame_count)?; }
+	if report.comparisons.is_empty() {
+		writeln!(out, "Output: {}", &report.output.display())?;
+		if let Some((width, height)) = report.frame_size { writeln!(out, "Size: {width}x{height}")?; }
+		if concatenated > 0 { writeln!(out, "Duration: {:.1}s, {} inputs together", report.duration, concatenated + 1)?; }
+		if let Some(from) = report.truncated_from { writeln!(out, "Duration: {:.1}s, cut from {from:.1}s by --max-duration", report.duration)?; }
+		if let Some(n) = report.loop_smoothed {
+			#[allow(clippy::cast_precision_loss)]
+			writeln!(out, "Duration: {:.1}s, {n} frames ({:.2}s) shorter from --loop-smooth", report.duration, n as f64 / f64::from(report.fps))?;
+		}
+		if let Some(c) = &report.comment { writeln!(out, "Comment: {c}")?; }
+		if let Some(d) = &report.downgrade { writeln!(out, "{}", style::warning(&format!("gifski ran out of memory, so the gif has degraded settings: {d}")))?; }
+		if !report.quality_regions.is_empty() { print_regions(out, &report.quality_regions)?; }
+	} else {
+		print_comparisons(out, &report.comparisons)?;
+	}
+	if let Some(mp4) = &report.mp4 { writeln!(out, "MP4: {}", mp4.display())?; }
+	if let Some(p) = &report.poster { writeln!(out, "Poster: {}", p.display())?; }
+	if let Some(sheet) = &report.contact_sheet { writeln!(out, "Contact sheet: {}", sheet.display())?; }
+	if let Some(location) = &report.frames_location { writeln!(out, "Frames extracted {location}")?; }
+	if let Some(dir) = &report.frames_dir { writeln!(out, "Frames kept in: {}", dir.display())?; }
+	print_warnings(out, &report.warnings)
+}
+
+/// All the warnings again, so they aren't lost in the progress that scrolled past.
+fn print_warnings(out: &mut dyn Write, warnings: &[Warning]) -> io::Result<()> {
+	if warnings.is_empty() { return Ok(()); }
+	writeln!(out, "{}", style::header("Warnings"))?;
+	for warning in warnings {
+		writeln!(out, "{}", style::warning(&format!("{} ({})", warning.message, warning.kind)))?;
+	}
+	Ok(())
+}
+
+fn print_benchmark(report: &BenchmarkReport) {
+	println!("{} runs, {} frames, frames directory peaked at {}", report.runs, report.frame_count, disk::human_size(report.peak_frames_size));
+	println!("{:>8}  {:>8}  {:>8}  {:>8}", "stage", "min", "median", "max");
+	let row = |name: &str, t: &benchmark::Timings| {
+		println!("{name:>8}  {:>7.2}s  {:>7.2}s  {:>7.2}s", t.min.as_secs_f32(), t.median.as_secs_f32(), t.max.as_secs_f32());
+	};
+	for stage in &report.stages {
+		row(&format!("{:?}", stage.stage).to_lowercase(), &stage.timings);
+	}
+	row("total", &report.total);
+}
+
+/// Where each option in `matches` came from, `loaded` being the ones from args files.
+fn origins(matches: &ArgMatches, loaded: &[String], config: &Config) -> Origins {
+	let mut origins = Origins::default();
+	for key in &config.set { origins.set(key.as_str(), Origin::ConfigFile); }
+	for &name in matches.args.keys().filter(|&name| matches.occurrences_of(name) > 0) {
+		origins.set(name, if loaded.iter().any(|key| key == name) { Origin::ArgsFile } else { Origin::CommandLine });
+	}
+	origins
+}
+
+/// The --explain plan.
+fn print_plan(out: &mut dyn Write, plan: &Plan) -> io::Result<()> {
+	let command = |argv: &[String]| argv.iter().map(|arg| if arg.contains(char::is_whitespace) || arg.is_empty() { format!("{arg:?}") } else { arg.clone() }).collect::<Vec<_>>().join(" ");
+	writeln!(out, "Input: {}", plan.input.display())?;
+	let width = plan.decisions.iter().map(|d| d.setting.len()).max().unwrap_or_default();
+	let value_width = plan.decisions.iter().map(|d| d.value.len()).filter(|&len| len <= 24).max().unwrap_or_default();
+	for decision in &plan.decisions {
+		writeln!(out, "  {:<width$}  {:<value_width$}  {} [{}]", decision.setting, decision.value, decision.note, decision.origin)?;
+	}
+	if !plan.filters.is_empty() { writeln!(out, "Filters: {}", plan.filters.join(","))?; }
+	if !plan.ffmpeg.is_empty() { writeln!(out, "Extract: {}", command(&plan.ffmpeg))?; }
+	for gifski in &plan.gifski { writeln!(out, "Encode: {}", command(gifski))?; }
+	Ok(())
+}
+
+fn print_comparisons(out: &mut dyn Write, runs: &[QualityRun]) -> io::Result<()> {
+	let by_width = runs.iter().any(|run| run.width.is_some());
+	writeln!(out, "{:>7}  {:>10}  {:>8}  output", if by_width { "width" } else { "quality" }, "size", "encode")?;
+	for run in runs {
+		let size = run.size.map_or_else(|| "failed".to_string(), disk::human_size);
+		let detail = run.error.as_ref().map_or_else(|| run.output.display().to_string(), |e| e.lines().next().unwrap_or_default().to_string());
+		let variant = run.width.map_or(run.quality, |width| width);
+		let line = format!("{variant:>7}  {size:>10}  {:>7.1}s  {detail}", run.encode_time.as_secs_f32());
+		writeln!(out, "{}", if run.error.is_some() { style::failure(&line) } else { line })?;
+	}
+	Ok(())
+}
+
+fn print_regions(out: &mut dyn Write, regions: &[RegionRun]) -> io::Result<()> {
+	writeln!(out, "{:>13}  {:>7}  {:>6}  {:>10}", "region", "quality", "frames", "size")?;
+	for region in regions {
+		let range = format!("{:.1}-{:.1}s", region.start, region.end);
+		writeln!(out, "{range:>13}  {:>7}  {:>6}  {:>10}", region.quality, region.frame_count, disk::human_size(region.size))?;
+	}
+	Ok(())
+}
+
+fn print_batch(results: &[BatchResult]) {
+	let name = |p: &PathBuf| p.file_name().unwrap_or(p.as_os_str()).to_string_lossy().into_owned();
+	let input_width = results.iter().map(|r| name(&r.input).chars().count()).max().unwrap_or(0).max("input".len());
+	let output_width = results.iter().filter_map(|r| r.output.as_ref()).map(|o| name(o).chars().count()).max().unwrap_or(0).max("output".len());
+	println!("{:<input_width$}  {:<output_width$}  {:>10}  {:>8}  status", "input", "output", "size", "duration");
+	for result in results {
+		let output = result.output.as_ref().map_or_else(|| "-".to_string(), name);
+		let size = result.size.map_or_else(|| "-".to_string(), disk::human_size);
+		let duration = result.duration.map_or_else(|| "-".to_string(), |d| format!("{d:.1}s"));
+		let line = format!("{:<input_width$}  {output:<output_width$}  {size:>10}  {duration:>8}  ", name(&result.input));
+		match &result.reason {
+			None if result.resumed => println!("{line}{}", style::success("ok, earlier")),
+			None => println!("{line}{}", style::success("ok")),
+			Some(reason) => println!("{line}{}", style::failure(&format!("failed: {}", reason.lines().next().unwrap_or_default()))),
+		}
+	}
+	let warnings: Vec<_> = results.iter().flat_map(|r| r.warnings.iter().map(move |w| (name(&r.input), w))).collect();
+	if !warnings.is_empty() {
+		println!("{}", style::header("Warnings"));
+		for (input, warning) in warnings {
+			println!("{}", style::warning(&format!("{input}: {} ({})", warning.message, warning.kind)));
+		}
+	}
+}
+
+fn print_parts(parts: &[Part]) {
+	let name = |p: &Part| p.output.file_name().unwrap_or(p.output.as_os_str()).to_string_lossy().into_owned();
+	let output_width = parts.iter().map(|p| name(p).chars().count()).max().unwrap_or(0).max("output".len());
+	println!("part  {:<output_width$}  {:>8}  {:>8}  {:>10}", "output", "start", "end", "size");
+	for part in parts {
+		let size = part.size.map_or_else(|| "-".to_string(), disk::human_size);
+		println!("{:>4}  {:<output_width$}  {:>7.1}s  {:>7.1}s  {size:>10}", part.number, name(part), part.start, part.end);
+	}
+}
+
+/// Always succeeds, a missing tool is part of the answer.
+fn print_version() {
+	println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+	for tool in [Tool::Ffmpeg, Tool::Gifski] {
+		match tools::probe(&SystemRunner, tool) {
+			Ok(info) => println!(
+				"{tool} {} ({})",
+				info.version.as_deref().unwrap_or("(unknown version)"),
+				info.path.map_or_else(|| "not on the PATH".to_string(), |p| p.display().to_string()),
+			),
+			Err(_) => println!("{tool} not found"),
+		}
+	}
+}
+
+/// Returns whether all checks passed.
+fn print_doctor() -> bool {
+	let checks = doctor::run_checks(&SystemRunner);
+	for check in &checks {
+		let mut lines = check.detail.lines();
+		let status = if check.passed { style::success(" ok ") } else { style::failure("FAIL") };
+		println!("[{status}] {}: {}", check.name, lines.next().unwrap_or_default());
+		for line in lines { println!("       {line}"); }
+	}
+	checks.iter().all(|c| c.passed)
+}
+
+/// Which input of a --batch, or part of a --split-every, the progress is about.
+struct BatchPosition {
+	index: usize,
+	count: usize,
+	name: String,
+}
+
+impl BatchPosition {
+	fn new(index: usize, inputs: &[PathBuf]) -> Self {
+		let name = inputs[index].file_stem().unwrap_or_default().to_string_lossy().into_owned();
+		BatchPosition { index, count: inputs.len(), name }
+	}
+
+	/// `[3/12 clip-name] `
+	fn prefix(&self) -> String {
+		format!("[{}/{} {}] ", self.index + 1, self.count, self.name)
+	}
+
+	/// How far the whole batch is, counting `done` of the current input.
+	fn overall_percent(&self, done: f64) -> f64 {
+		#[allow(clippy::cast_precision_loss)]
+		let percent = (self.index as f64 + done) * 100.0 / self.count as f64;
+		percent
+	}
+}
+
+fn print_progress(progress: Progress, batch: Option<&BatchPosition>) {
+	let prefix = batch.map_or_else(String::new, BatchPosition::prefix);
+	match progress {
+		Progress::Started(Stage::Stabilize) => {
+			println!("{prefix}{}", style::header("ffmpeg"));
+			println!("{prefix}Finding the camera shake.");
+		}
+		Progress::Started(Stage::Extract) => {
+			if let Some(batch) = batch { println!("{prefix}{:.0}% of all inputs done", batch.overall_percent(0.0)); }
+			println!("{prefix}{}", style::header("ffmpeg"));
+			println!("{prefix}Splitting video into frames.");
+		}
+		Progress::Extracting { frame, total } if style::stdout_is_terminal() => {
+			let total = total.map_or_else(String::new, |t| format!(" of about {t}"));
+			print!("\r{prefix}{frame}{total} frames{:<20}", "");
+			let _ = io::stdout().flush();
+		}
+		Progress::Finished(Stage::Extract, _) => {
+			if style::stdout_is_terminal() { println!(); }
+			println!("{prefix}Frame conversion complete");
+		}
+		Progress::Started(Stage::Encode) => {
+			println!("{prefix}{}", style::header("gifski"));
+			println!("{prefix}Running gifski. This might take a while.");
+		}
+		Progress::Encoding { frame, total, eta } if style::stdout_is_terminal() => {
+			let eta = eta.map_or_else(String::new, |eta| format!(", about {}s left", eta.as_secs()));
+			#[allow(clippy::cast_precision_loss)]
+			let overall = batch.map_or_else(String::new, |b| format!(", {:.0}% overall", b.overall_percent(frame as f64 / total.max(1) as f64)));
+			// Padded so a shorter line fully covers the one before it.
+			print!("\r{prefix}{:>3}% ({frame}/{total}){:<40}", frame * 100 / total.max(1), eta + &overall);
+			let _ = io::stdout().flush();
+		}
+		Progress::Finished(Stage::Encode, _) => {
+			if style::stdout_is_terminal() { println!(); }
+			println!("{prefix}gifski complete");
+		}
+		Progress::Started(Stage::Poster) => println!("{prefix}{}", style::header("poster")),
+		Progress::Started(Stage::Cleanup) => println!("{prefix}{}", style::header("Cleaning Up")),
+		Progress::Info(message) => println!("{prefix}{message}"),
+		Progress::Warning(warning) => println!("{prefix}{}", style::warning(&warning.message)),
+		_ => {}
+	}
+}
+
+/// A new, empty `<TEMP>/gifski-ffmpeg-test-<name>` for one test's files, like the library's.
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+	let dir = env::temp_dir().join(format!("gifski-ffmpeg-test-{name}"));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
@@ -0,0 +1,113 @@
+//! [`ConvertOptions::also_mp4`](crate::ConvertOptions::also_mp4): an H.264 MP4 of the same frames as the gif, for
+//! sites that play video in link previews and not gifs.
+//!
+//! Made from the extracted frames, so it starts, ends and plays exactly like the gif. It's capped at 720p and its
+//! bitrate at what fits [`MP4_MAX_BYTES`] in the gif's length; short ones stay at the quality x264 picks anyway.
+
+use std::path::{Path, PathBuf};
+use crate::{
+	paths,
+	pattern::FramePattern,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Progress,
+	Result,
+	WarningKind,
+};
+
+/// About the most the MP4 gets to be, the limit of the sites that have one.
+pub const MP4_MAX_BYTES: u64 = 10_000_000;
+
+/// Longest side and shortest side it's scaled down to, 720p either way up.
+const MAX_SIDES: (u32, u32) = (1280, 720);
+
+/// `<output>.mp4`, next to the gif.
+pub(crate) fn mp4_path(output: &Path) -> PathBuf {
+	output.with_extension("mp4")
+}
+
+/// The most kbit/s that keeps `seconds` of video under [`MP4_MAX_BYTES`], with a tenth left for the container.
+fn max_kbps(seconds: f64) -> u64 {
+	#[allow(clippy::cast_precision_loss)]
+	let kbits = (MP4_MAX_BYTES * 8) as f64 * 0.9 / 1000.0;
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let kbps = (kbits / seconds.max(0.1)).floor() as u64;
+	kbps.max(100)
+}
+
+/// `ffmpeg -framerate fps -start_number N -i frame%04d.png -vf scale -c:v libx264 ... output.mp4`. `frames` are
+/// numbered one after another from the first by `pattern`.
+pub(crate) fn encode_command(fps: f32, (frames, pattern): (&[PathBuf], &FramePattern), mp4: &Path) -> CommandLine {
+	let (start_number, sequence) = pattern.sequence(frames.first().map(PathBuf::as_path));
+	#[allow(clippy::cast_precision_loss)]
+	let kbps = max_kbps(frames.len() as f64 / f64::from(fps));
+	let (long, short) = MAX_SIDES;
+	CommandLine::new("ffmpeg")
+		.args(["-v", "error", "-y"])
+		.arg("-framerate").arg(fps.to_string())
+		.arg("-start_number").arg(start_number.to_string())
+		.arg("-i").arg(paths::for_tool(&sequence))
+		.arg("-frames:v").arg(frames.len().to_string())
+		// Fit inside 1280x720 or 720x1280, then even sides for yuv420p.
+		.arg("-vf").arg(format!(
+			"scale='if(gte(iw,ih),min({long},iw),min({short},iw))':'if(gte(iw,ih),min({short},ih),min({long},ih))':force_original_aspect_ratio=decrease:flags=lanczos,scale=trunc(iw/2)*2:trunc(ih/2)*2",
+		))
+		.args(["-c:v", "libx264", "-preset", "slow", "-crf", "23", "-pix_fmt", "yuv420p"])
+		.arg("-maxrate").arg(format!("{kbps}k"))
+		.arg("-bufsize").arg(format!("{}k", kbps * 2))
+		.args(["-movflags", "+faststart", "-an"])
+		.arg(paths::for_tool(mp4))
+}
+
+/// Writes the MP4 of the gif at `output` from its `frames`, named by `pattern` and played at `fps`, and returns where it went. Warns if it
+/// came out bigger than [`MP4_MAX_BYTES`] all the same. Frames with gaps in their numbers are linked one after another first.
+///
+/// # Errors
+/// If ffmpeg fails.
+pub(crate) fn write_mp4(
+	runner: &dyn CommandRunner,
+	(frames, pattern): (&[PathBuf], &FramePattern),
+	fps: f32,
+	output: &Path,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<PathBuf> {
+	let mp4 = mp4_path(output);
+	let sequence = pattern.consecutive(frames)?;
+	let command = encode_command(fps, (&sequence.frames, pattern), &mp4);
+	log::debug!("Running: {command}");
+	let result = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	if !result.success() {
+		return Err(ConvertError::FfmpegFailed { code: result.code, stderr: String::from_utf8_lossy(&result.stderr).into_owned() });
+	}
+	if let Some(size) = std::fs::metadata(&mp4).ok().map(|m| m.len()).filter(|&size| size > MP4_MAX_BYTES) {
+		#[allow(clippy::cast_precision_loss)]
+		let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+		progress(Progress::warning(WarningKind::Mp4TooBig, format!(
+			"{} is {:.1} MB, over the {:.0} MB previews take", mp4.display(), megabytes(size), megabytes(MP4_MAX_BYTES),
+		)));
+	}
+	Ok(mp4)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn long_gifs_get_less_bitrate() {
+		assert_eq!(max_kbps(10.0), 7200);
+		assert_eq!(max_kbps(60.0), 1200);
+		assert_eq!(max_kbps(0.0), 720_000, "a single frame");
+		assert_eq!(max_kbps(1e6), 100);
+	}
+
+	#[test]
+	fn encodes_the_frames_left() {
+		let frames = [PathBuf::from("/tmp/frames/frame0003.png"), PathBuf::from("/tmp/frames/frame0004.png")];
+		let args = encode_command(2.0, (&frames, &FramePattern::default()), Path::new("/tmp/out.mp4")).args_lossy();
+		assert_eq!(args[..12], ["-v", "error", "-y", "-framerate", "2", "-start_number", "3", "-i", "/tmp/frames/frame%04d.png", "-frames:v", "2", "-vf"]);
+		assert!(args[12].starts_with("scale='if(gte(iw,ih),min(1280,iw),min(720,iw))'"), "{}", args[12]);
+		assert!(args.windows(2).any(|w| w == ["-maxrate", "72000k"]), "{args:?}");
+		assert_eq!(args.last().unwrap(), "/tmp/out.mp4");
+	}
+}
@@ -0,0 +1,102 @@
+//! `--notify`: a desktop notification when the conversion is done, for when it's been left running in the background.
+//!
+//! Uses notify-send on Linux, osascript on macOS and a PowerShell toast on Windows. Without any of them it rings
+//! the terminal bell and prints the notification instead.
+
+use std::{
+	path::Path,
+	process::{Command, Stdio},
+	time::Duration,
+};
+use gifski_ffmpeg::{disk, ConvertReport, Stage};
+
+/// A notification's title and text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+	pub title: String,
+	pub body: String,
+}
+
+impl Notification {
+	pub fn finished(report: &ConvertReport) -> Self {
+		let name = report.output.file_name().unwrap_or(report.output.as_os_str()).to_string_lossy();
+		let size = std::fs::metadata(&report.output).map_or_else(|_| "?".to_string(), |m| disk::human_size(m.len()));
+		Notification { title: "Gif done".to_string(), body: format!("{name}, {size}, in {}", elapsed(report.total_time)) }
+	}
+
+	/// `stage` is the last one that started, `None` if it failed before extracting.
+	pub fn failed(stage: Option<Stage>, input: &Path, took: Duration) -> Self {
+		let name = input.file_name().unwrap_or(input.as_os_str()).to_string_lossy();
+		Notification { title: "Gif failed".to_string(), body: format!("{name} failed {} after {}", stage_name(stage), elapsed(took)) }
+	}
+
+	/// Shows the notification, or rings the bell and prints it to stderr if there's no way to.
+	pub fn show(&self) {
+		if let Err(e) = self.command().ok_or_else(|| "no notifier for this platform".to_string()).and_then(run) {
+			log::debug!("Couldn't show the notification: {e}");
+			eprintln!("\x07{}: {}", self.title, self.body);
+		}
+	}
+
+	fn command(&self) -> Option<Command> {
+		let mut command;
+		if cfg!(target_os = "macos") {
+			let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+			command = Command::new("osascript");
+			command.args(["-e", &format!("display notification {} with title {}", quote(&self.body), quote(&self.title))]);
+		} else if cfg!(windows) {
+			let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+			let script = [
+				"$ErrorActionPreference = 'Stop'",
+				"$manager = [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime]",
+				"$toast = $manager::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)",
+				&format!("$toast.GetElementsByTagName('text')[0].AppendChild($toast.CreateTextNode({})) > $null", quote(&self.title)),
+				&format!("$toast.GetElementsByTagName('text')[1].AppendChild($toast.CreateTextNode({})) > $null", quote(&self.body)),
+				"$manager::CreateToastNotifier('gifski-ffmpeg').Show([Windows.UI.Notifications.ToastNotification]::new($toast))",
+			].join("; ");
+			command = Command::new("powershell");
+			command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+		} else if cfg!(unix) {
+			command = Command::new("notify-send");
+			command.args(["--app-name=gifski-ffmpeg", &self.title, &self.body]);
+		} else {
+			return None;
+		}
+		Some(command)
+	}
+}
+
+fn run(mut command: Command) -> Result<(), String> {
+	let status = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).status().map_err(|e| e.to_string())?;
+	if status.success() { Ok(()) } else { Err(format!("exited with {status}")) }
+}
+
+fn stage_name(stage: Option<Stage>) -> &'static str {
+	match stage {
+		None => "before extracting",
+		Some(Stage::Stabilize) => "finding the camera shake",
+		Some(Stage::Extract) => "extracting frames",
+		Some(Stage::Encode) => "encoding",
+		Some(Stage::Poster) => "writing the poster",
+		Some(Stage::Cleanup) => "cleaning up",
+		Some(_) => "converting",
+	}
+}
+
+/// `42s` or `3m 05s`.
+fn elapsed(time: Duration) -> String {
+	let secs = time.as_secs();
+	if secs < 60 { format!("{secs}s") } else { format!("{}m {:02}s", secs / 60, secs % 60) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn failures_name_the_stage() {
+		let failed = Notification::failed(Some(Stage::Encode), Path::new("videos/clip.mp4"), Duration::from_secs(185));
+		assert_eq!(failed.body, "clip.mp4 failed encoding after 3m 05s");
+		assert_eq!(Notification::failed(None, Path::new("clip.mp4"), Duration::from_millis(400)).body, "clip.mp4 failed before extracting after 0s");
+	}
+}
@@ -0,0 +1,1092 @@
+use std::{
+	ffi::OsString,
+	fmt,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+use crate::{paths, pattern::FramePattern, ConvertError};
+
+/// Everything a conversion can be configured with. Mirrors the command line flags of the `gifski-ffmpeg` binary.
+///
+/// Construct with [`ConvertOptions::new`] and set the fields you need; new fields may be added in minor releases.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+#[allow(clippy::struct_excessive_bools)] // Flags.
+pub struct ConvertOptions {
+	/// The video to convert, or the frames directory with [`Stages::Encode`].
+	pub input: PathBuf,
+
+	/// The ffmpeg format of [`input`](Self::input), its `-f`, for a raw stream or anything else ffmpeg can't tell
+	/// the format of by itself. Also given to ffprobe.
+	pub input_format: Option<String>,
+
+	/// More ffmpeg options for [`input`](Self::input), given before its `-i` in order, e.g. `-framerate 30` or
+	/// `-video_size 1280x720` for a raw stream. Also given to ffprobe.
+	pub input_args: Vec<String>,
+
+	/// Which half of the conversion to run.
+	pub stages: Stages,
+
+	/// More videos whose frames follow [`input`](Self::input)'s in the same gif, in order.
+	///
+	/// They're extracted at the first input's fps and scaled to the size of its frames. Can't be combined with
+	/// trimming, [`keyframes_only`](Self::keyframes_only), [`max_frames`](Self::max_frames), a poster at a timestamp,
+	/// or [`overlap`](Self::overlap) and [`chunk_seconds`](Self::chunk_seconds).
+	pub concat: Vec<PathBuf>,
+
+	/// Lay [`input`](Self::input) and the [`grid_inputs`](Self::grid_inputs) out side by side in a grid, e.g. to
+	/// compare encodes. Every cell is the size of `input`, cells without an input are black, and the gif is as long
+	/// as the shortest input. The other options apply to the whole grid, it's cropped and scaled as one video.
+	///
+	/// Can't be combined with [`concat`](Self::concat), [`keyframes_only`](Self::keyframes_only),
+	/// [`overlap`](Self::overlap) or [`chunk_seconds`](Self::chunk_seconds).
+	pub grid: Option<Grid>,
+
+	/// The cells of the [`grid`](Self::grid) after `input`'s, left to right then top to bottom.
+	pub grid_inputs: Vec<PathBuf>,
+
+	/// Write each input's file name in the corner of its [`grid`](Self::grid) cell.
+	pub grid_labels: bool,
+
+	/// Name or location of output file.
+	///
+	/// `"C:/videos/output.gif"` will create output.gif in the specified directory where as
+	/// "output" will create output.gif in the same directory as the input.
+	/// `None` creates `<input>-gif.gif` next to the input.
+	///
+	/// A name is always next to the input, wherever the conversion is run from. A relative path with a directory
+	/// in it, `gifs/output.gif`, is from the current directory.
+	pub output: Option<OsString>,
+
+	/// Put the default output next to the file a symlinked input points to, instead of next to the symlink.
+	pub resolve_symlinks: bool,
+
+	/// Create the directory of [`output`](Self::output) if it doesn't exist, instead of failing.
+	pub create_parents: bool,
+
+	/// Quality passed to gifski, clamped to 0-100.
+	pub quality: u32,
+
+	/// Encode once per quality instead of once with [`quality`](Self::quality), each into `<output>-q<quality>.gif`.
+	///
+	/// The frames are extracted only once and shared by all encodes.
+	pub compare_quality: Vec<u32>,
+
+	/// Encode once per width instead of once, each into `<output>-<width>.gif`, e.g. `[480, 960]` for chat and docs.
+	///
+	/// The frames are extracted once at the widest, and gifski scales them down for the others. Can't be combined
+	/// with [`width`](Self::width), [`height`](Self::height) or [`scale`](Self::scale), which it sets itself, or the
+	/// other options that encode their own way.
+	pub sizes: Vec<u32>,
+
+	/// Encode these stretches of the gif at their own quality, and the rest at [`quality`](Self::quality), each on its
+	/// own, then join them into the one gif. They're in seconds of the gif, in order and not overlapping.
+	///
+	/// Only does anything with gifski, and can't be combined with [`compare_quality`](Self::compare_quality). It's
+	/// never [`retry`](Self::retry)d.
+	pub quality_map: Vec<QualityRegion>,
+
+	/// What turns the frames into the gif. [`Encoder::Ffmpeg`] can't be combined with [`overlap`](Self::overlap),
+	/// [`chunk_seconds`](Self::chunk_seconds) or [`compare_quality`](Self::compare_quality), which are gifski's.
+	pub encoder: Encoder,
+
+	/// Colors in the palette [`Encoder::Ffmpeg`] makes, from 2 to 256. gifski picks its own, so it's an error with it.
+	pub colors: Option<u16>,
+
+	/// How [`Encoder::Ffmpeg`] dithers, ffmpeg's default [`Dither::Sierra`] if `None`. gifski always dithers its
+	/// own way, so it's an error with it.
+	pub dither: Option<Dither>,
+
+	/// Start gifski as soon as ffmpeg starts instead of after it's done, by piping a copy of the frames into it.
+	///
+	/// gifski drops frames to match [`fps`](Self::fps) when it reads video like this, where it otherwise plays every
+	/// extracted frame at that rate. The two only differ when the fps is lower than the input's.
+	/// Can't be combined with [`keyframes_only`](Self::keyframes_only), [`trim_idle`](Self::trim_idle) or
+	/// [`compare_quality`](Self::compare_quality), which need all frames before encoding.
+	pub overlap: bool,
+
+	/// Like [`overlap`](Self::overlap), but extract this many seconds at a time, deleting each chunk's frames once
+	/// they're piped into gifski. Caps the frames on disk at one chunk's worth, for long videos.
+	///
+	/// Has the same restrictions as `overlap`, and can't be combined with frame based trimming or a
+	/// [`poster`](Self::poster), which would need the frames after they're gone.
+	pub chunk_seconds: Option<f64>,
+
+	/// When gifski runs out of memory, encode again at half the fps, then at half the width too, instead of failing.
+	/// [`ConvertReport::downgrade`](crate::ConvertReport::downgrade) says what it settled on.
+	///
+	/// Only the usual single encode is retried, not [`overlap`](Self::overlap)ped or
+	/// [`compare_quality`](Self::compare_quality) ones.
+	pub retry: bool,
+
+	/// Extract at the highest fps that keeps the gif within this many frames, worked out from the length of the
+	/// (trimmed) input. Can't be combined with [`fps`](Self::fps), which it sets itself.
+	pub max_frames: Option<u32>,
+
+	/// Without an [`fps`](Self::fps), sources faster than this are resampled down to it, since the extra frames make a
+	/// gif a lot bigger and barely smoother.
+	pub max_auto_fps: f32,
+
+	/// Use the fps ffmpeg finds in the input even if the frames it extracts say otherwise. Without it, when the number
+	/// of frames over the length of what was extracted is more than a little off, that's the fps the gif plays at.
+	pub trust_metadata: bool,
+
+	/// Fps passed to gifski, clamped to a max of 50. `None` uses the fps of the input video.
+	pub fps: Option<f32>,
+
+	/// Start converting at this many seconds into the input.
+	pub start: Option<f64>,
+
+	/// How ffmpeg seeks to [`start`](Self::start). [`keyframes_only`](Self::keyframes_only) always seeks fast.
+	pub seek_mode: SeekMode,
+
+	/// Stop converting at this many seconds into the input. Can't be combined with [`duration`](Self::duration).
+	pub end: Option<f64>,
+
+	/// Convert this many seconds, from [`start`](Self::start) or the beginning.
+	pub duration: Option<f64>,
+
+	/// Start converting at this frame. Frames are counted from 0, like ffmpeg does.
+	///
+	/// Can't be combined with the time based [`start`](Self::start), [`end`](Self::end) and [`duration`](Self::duration).
+	pub start_frame: Option<u64>,
+
+	/// The last frame to convert. Inclusive, so `start_frame: Some(10), end_frame: Some(19)` converts 10 frames.
+	pub end_frame: Option<u64>,
+
+	/// Decode only the keyframes, for a rough storyboard of a long video in a fraction of the time.
+	///
+	/// Keyframes are irregularly spaced, so [`fps`](Self::fps) defaults to [`KEYFRAME_FPS`] instead of the input's.
+	pub keyframes_only: bool,
+
+	/// Show each frame for as long as it was in the input, instead of all of them for one [`fps`](Self::fps), so a
+	/// [`keyframes_only`](Self::keyframes_only) gif or one [`trim_idle`](Self::trim_idle) or
+	/// [`exact_end`](Self::exact_end) left frames out of keeps the input's timing.
+	///
+	/// Can't be combined with [`stages`](Self::stages) other than all, [`overlap`](Self::overlap),
+	/// [`chunk_seconds`](Self::chunk_seconds), [`concat`](Self::concat), [`edit_list`](Self::edit_list),
+	/// [`loop_smooth`](Self::loop_smooth), [`quality_map`](Self::quality_map) or
+	/// [`compare_quality`](Self::compare_quality).
+	pub source_timing: bool,
+
+	/// Drop black or frozen frames from the start and end, e.g. the second before a screen recording gets going.
+	pub trim_idle: bool,
+
+	/// How dark a frame has to be to count as black for [`trim_idle`](Self::trim_idle), from 0 to 1. Higher values
+	/// also count frames that change only a little as frozen, so they trim more.
+	pub trim_idle_threshold: f64,
+
+	/// Replace single frames much brighter or darker than the ones either side of them, like a white flash in a
+	/// stream, with the frame before, so they don't take over the palette. The threshold is how far off they have
+	/// to be, from 0 to 1 of the way from black to white, `None` to leave them in.
+	pub drop_flash_frames: Option<f64>,
+
+	/// Look at a few of the frames before encoding and say which options would help, when they're noisy or have
+	/// more colors than a gif keeps, e.g. grainy footage. What was measured is in
+	/// [`ConvertReport::busyness`](crate::ConvertReport::busyness).
+	pub advice: bool,
+
+	/// Undo camera shake with ffmpeg's vidstab filters, which take a first pass over the input to find it. Needs an
+	/// ffmpeg built with libvidstab, and can't be combined with [`keyframes_only`](Self::keyframes_only),
+	/// [`chunk_seconds`](Self::chunk_seconds), [`concat`](Self::concat) or [`grid`](Self::grid).
+	pub stabilize: bool,
+
+	/// Slowly zoom in from the whole frame to this region of the input, before any cropping or scaling. A region of
+	/// another shape than the frame ends up centered, with some of what's around it. Can't be combined with
+	/// [`keyframes_only`](Self::keyframes_only), [`concat`](Self::concat) or [`grid`](Self::grid).
+	pub zoom_to: Option<Zoom>,
+
+	/// Crop to this region of the input before the [`aspect`](Self::aspect) crop and the scaling, which then work
+	/// within it. [`Focus::Auto`] finds where the motion is.
+	pub focus: Option<Focus>,
+
+	/// Crop to this aspect ratio, keeping as much of the input as possible. See [`gravity`](Self::gravity) for which part is kept.
+	pub aspect: Option<Aspect>,
+
+	/// Which part of the input an [`aspect`](Self::aspect) crop keeps.
+	pub gravity: Gravity,
+
+	/// Scale the frames to this width, after cropping. Keeps the aspect ratio if [`height`](Self::height) isn't given.
+	pub width: Option<u32>,
+
+	/// Scale the frames to this height, after cropping. Keeps the aspect ratio if [`width`](Self::width) isn't given.
+	pub height: Option<u32>,
+
+	/// Scale the frames by this factor instead of to a [`width`](Self::width) or [`height`](Self::height), after
+	/// cropping. Both sides are rounded to even numbers.
+	pub scale: Option<Scale>,
+
+	/// How the frames are made to fit when both [`width`](Self::width) and [`height`](Self::height) are given.
+	pub fit: Fit,
+
+	/// Keep odd widths and heights the crop and scale options come out at, instead of rounding them down to even.
+	pub allow_odd_dimensions: bool,
+
+	/// The color [`Fit::Pad`] pads with, anything ffmpeg understands: `black`, `white@0.5`, `#1e1e1e`.
+	/// `None` is black.
+	pub pad_color: Option<String>,
+
+	/// Stack a strip this many pixels high under the frames, drawn from the input's audio the way
+	/// [`waveform_style`](Self::waveform_style) says. It's as wide as the frames after scaling, so the gif is that
+	/// much taller. Skipped with a warning if the input has no audio.
+	pub waveform: Option<u32>,
+
+	/// What the [`waveform`](Self::waveform) strip shows.
+	pub waveform_style: WaveformStyle,
+
+	/// The comment written into the gif.
+	pub comment: Comment,
+
+	/// Make the same gif byte for byte from the same input and options: ffmpeg is asked for bit exact frames, and
+	/// [`Comment::Auto`] leaves out the versions. gifski gets the frames in the order of their numbers either way.
+	pub deterministic: bool,
+
+	/// Convert at most this many seconds, from wherever the conversion starts. The rest is never extracted.
+	pub max_duration: Option<f64>,
+
+	/// Delete any frames ffmpeg wrote past the [`end`](Self::end), [`duration`](Self::duration) or
+	/// [`max_duration`](Self::max_duration) before encoding, so a looping gif doesn't get an extra frame or two.
+	pub exact_end: bool,
+
+	/// Make the gif exactly this many seconds long, to the centisecond GIFs time frames in, whatever the input's
+	/// length. Frames past it are left out, and if the frames end before it the last one is held until it.
+	///
+	/// The frames still play at [`fps`](Self::fps), and [`start`](Self::start), [`end`](Self::end) and
+	/// [`duration`](Self::duration) still pick what of the input they're from. Can't be combined with
+	/// [`source_timing`](Self::source_timing), [`overlap`](Self::overlap), [`chunk_seconds`](Self::chunk_seconds),
+	/// [`quality_map`](Self::quality_map) or [`compare_quality`](Self::compare_quality).
+	pub exact_duration: Option<f64>,
+
+	/// Reduce each extracted frame to at most this many colors, from 2 to 256, before gifski sees it. Makes noisy
+	/// footage much smaller, at the cost of banding. Done after every other filter.
+	pub pre_quantize: Option<u32>,
+
+	/// Crossfade the last this many frames into the first ones, which are then dropped, so the gif doesn't jump
+	/// when it loops. The gif gets this many frames shorter. At most half of the frames.
+	pub loop_smooth: Option<usize>,
+
+	/// A command run over the extracted frames before they're encoded, e.g. to blur or redact part of them. It
+	/// changes them in place.
+	pub frame_filter: Option<FrameFilter>,
+
+	/// A JSON file of `{"x", "y", "t"}` cursor positions, in the input's pixels and seconds, to draw
+	/// [`cursor_image`](Self::cursor_image) at on each frame, for screen recordings made without the cursor.
+	pub cursor_overlay: Option<PathBuf>,
+
+	/// The image drawn for [`cursor_overlay`](Self::cursor_overlay), with its top left corner at the position.
+	pub cursor_image: Option<PathBuf>,
+
+	/// How many [`FrameFilterMode::PerFrame`] commands, or [`cursor_overlay`](Self::cursor_overlay) frames, run at
+	/// once. `None` is one per CPU.
+	pub jobs: Option<usize>,
+
+	/// Convert more than [`SOFT_MAX_DURATION`] seconds, which is an error without it in case it's by accident.
+	pub force: bool,
+
+	/// Seconds to show the input for if it's a still image, which is an error without it.
+	pub still_duration: Option<f64>,
+
+	/// Also write a still of one frame next to the gif.
+	pub poster: Option<Poster>,
+
+	/// What the [`poster`](Self::poster) is written as, `<output>.png` or `<output>.jpg`.
+	pub poster_format: PosterFormat,
+
+	/// Also write `<output>.mp4`, H.264 made from the same frames, at most 720p and about
+	/// [`MP4_MAX_BYTES`](crate::MP4_MAX_BYTES).
+	pub also_mp4: bool,
+
+	/// For link previews: [`also_mp4`](Self::also_mp4), and a JPEG [`poster`](Self::poster), of the first
+	/// interesting frame unless another one is asked for. Either of them failing is a warning instead of an error,
+	/// so the gif and the other one are still written.
+	pub social: bool,
+
+	/// Also write `<output>-sheet.png`, this many columns and rows of thumbnails of evenly spaced frames, each with
+	/// when it is in the gif. Skipped with a warning if the gif has fewer frames than that.
+	pub contact_sheet: Option<Grid>,
+
+	/// Where the frames are extracted to. Emptied before and after the conversion, but not deleted, so it has to be
+	/// new, empty, or one an earlier run extracted to, and can't have the input or output in it.
+	/// `None` uses a new directory in `<TEMP>/gifski-ffmpeg/`.
+	pub frames_dir: Option<PathBuf>,
+
+	/// What the frames are named, e.g. `img_%06d.png` for scripts that expect `img_000001.png` on. Also how the
+	/// frames are found with [`Stages::Encode`].
+	pub frame_pattern: FramePattern,
+
+	/// Before extracting, delete the frames directories in `<TEMP>/gifski-ffmpeg/` that runs which crashed or were
+	/// killed left behind, once nothing has been written to them for a day.
+	pub gc: bool,
+
+	/// Put the default frames directory on the [`ramdisk`](Self::ramdisk) instead, if it's there and the frames are
+	/// estimated to fit, which is much faster for short clips. Otherwise it's logged why not, and they go in the
+	/// normal temp directory. Can't be combined with a [`frames_dir`](Self::frames_dir).
+	pub in_memory: bool,
+
+	/// The ramdisk for [`in_memory`](Self::in_memory), `None` is [`DEFAULT_RAMDISK`](crate::DEFAULT_RAMDISK).
+	pub ramdisk: Option<PathBuf>,
+
+	/// Stop extracting once the drive the frames are on has fewer than this many bytes free, and encode the frames
+	/// extracted until then, with a warning that the gif was cut short. It's checked every couple of seconds, and
+	/// not with [`overlap`](Self::overlap) or [`chunk_seconds`](Self::chunk_seconds). 0 never stops.
+	pub reserve_space: u64,
+
+	/// Fail with [`ConvertError::LowDiskSpace`](crate::ConvertError::LowDiskSpace) when extracting stops for the
+	/// [`reserve_space`](Self::reserve_space), instead of making a shorter gif.
+	pub strict_space: bool,
+
+	/// Encode the frames ffmpeg extracted before failing, if at least ten came out, instead of failing with
+	/// [`ConvertError::FfmpegFailed`](crate::ConvertError::FfmpegFailed), for inputs broken part way through. The
+	/// gif is cut short where ffmpeg stopped, which [`ConvertReport::decode_error`](crate::ConvertReport::decode_error)
+	/// says.
+	pub tolerate_decode_errors: bool,
+
+	/// Wait up to this many seconds, e.g. [`DEFAULT_INPUT_WAIT`](crate::DEFAULT_INPUT_WAIT), for an input that's still
+	/// being written, like a recording that hasn't been stopped, to go two seconds without changing before converting
+	/// it. `None` only looks, with a [`WarningKind::InputStillWritten`](crate::WarningKind::InputStillWritten) if it is.
+	pub wait_for_input: Option<f64>,
+
+	/// Leave the frames directory behind instead of deleting it after encoding, or the frames in it with
+	/// [`Stages::Encode`].
+	pub keep_frames: bool,
+
+	/// Run ffmpeg and gifski at a lower CPU priority, and I/O priority on Linux, so the machine stays usable while
+	/// they work, slower. Only when the conversion runs them itself, without a [`runner`](crate::Conversion::runner).
+	/// [`threads`](Self::threads) defaults to half the cores.
+	pub background: bool,
+
+	/// How many threads ffmpeg decodes and filters the frames it extracts with, `-threads` and `-filter_threads`.
+	/// `None` leaves it to ffmpeg, which uses every core, unless [`background`](Self::background).
+	pub threads: Option<usize>,
+
+	/// Encode only the extracted frames this file lists, in the order it lists them, repeats and all. It has a frame,
+	/// `7`, or an inclusive range, `10-45`, on each line, numbered from 1 like the frame files, and `#` comments. A
+	/// range that goes down, `45-10`, plays backwards.
+	pub edit_list: Option<PathBuf>,
+
+	/// Append a CSV row about the conversion to this file when it's done, or failed, with a header if it's new.
+	pub stats_file: Option<PathBuf>,
+}
+
+impl ConvertOptions {
+	/// Makes every path absolute against `cwd`, except an output that's only a name, which goes next to the input.
+	/// From there on nothing depends on the current directory, and ffmpeg and gifski get absolute paths.
+	pub(crate) fn make_absolute(&mut self, cwd: &Path) {
+		self.input = paths::absolute_in(cwd, &self.input);
+		for input in self.concat.iter_mut().chain(&mut self.grid_inputs) { *input = paths::absolute_in(cwd, input); }
+		for path in self.frames_dir.iter_mut().chain(&mut self.stats_file).chain(&mut self.edit_list) { *path = paths::absolute_in(cwd, path); }
+		if let Some(output) = self.output.as_mut().filter(|o| paths::has_dir(o)) {
+			*output = paths::absolute_in(cwd, Path::new(output)).into_os_string();
+		}
+	}
+
+	/// Turns [`social`](Self::social) into the options it's made of.
+	pub(crate) fn expand_social(&mut self) {
+		if !self.social { return; }
+		self.also_mp4 = true;
+		self.poster.get_or_insert(Poster::Interesting);
+		self.poster_format = PosterFormat::Jpeg;
+	}
+
+	/// Turns [`sizes`](Self::sizes) into extracting at the widest of them, and leaves out the ones given twice.
+	///
+	/// # Errors
+	/// With a width of 0, or options it can't be combined with.
+	pub(crate) fn expand_sizes(&mut self) -> Result<(), ConvertError> {
+		if self.sizes.is_empty() { return Ok(()); }
+		let invalid = |message: String| Err(ConvertError::InvalidOption { option: "sizes", message });
+		if self.sizes.contains(&0) { return invalid("a width can't be 0".to_string()); }
+		let conflict = [
+			(self.width.is_some() || self.height.is_some() || self.scale.is_some(), "--width, --height or --scale, the frames are extracted at the widest size"),
+			(!self.compare_quality.is_empty() || !self.quality_map.is_empty(), "--compare-quality or --quality-map"),
+			(self.overlap || self.chunk_seconds.is_some(), "--overlap or --chunk-seconds, which encode while extracting"),
+			(self.encoder == Encoder::Ffmpeg, "--encoder ffmpeg, gifski scales the smaller ones"),
+		].into_iter().find(|(set, _)| *set);
+		if let Some((_, what)) = conflict { return invalid(format!("can't be combined with {what}")); }
+		self.sizes.sort_unstable();
+		self.sizes.dedup();
+		// Already extracted at whatever size they are.
+		if self.stages != Stages::Encode { self.width = self.sizes.last().copied(); }
+		Ok(())
+	}
+
+	/// Options with the same defaults as the command line.
+	pub fn new(input: impl Into<PathBuf>) -> Self {
+		ConvertOptions {
+			input: input.into(),
+			input_format: None,
+			input_args: Vec::new(),
+			stages: Stages::All,
+			concat: Vec::new(),
+			grid: None,
+			grid_inputs: Vec::new(),
+			grid_labels: false,
+			output: None,
+			resolve_symlinks: false,
+			create_parents: false,
+			quality: 100,
+			compare_quality: Vec::new(),
+			sizes: Vec::new(),
+			quality_map: Vec::new(),
+			encoder: Encoder::default(),
+			colors: None,
+			dither: None,
+			overlap: false,
+			chunk_seconds: None,
+			retry: true,
+			max_frames: None,
+			max_auto_fps: DEFAULT_MAX_AUTO_FPS,
+			trust_metadata: false,
+			fps: None,
+			start: None,
+			seek_mode: SeekMode::default(),
+			end: None,
+			duration: None,
+			start_frame: None,
+			end_frame: None,
+			keyframes_only: false,
+			source_timing: false,
+			trim_idle: false,
+			trim_idle_threshold: crate::idle::DEFAULT_THRESHOLD,
+			drop_flash_frames: None,
+			advice: true,
+			stabilize: false,
+			zoom_to: None,
+			focus: None,
+			aspect: None,
+			gravity: Gravity::default(),
+			width: None,
+			height: None,
+			scale: None,
+			fit: Fit::default(),
+			allow_odd_dimensions: false,
+			pad_color: None,
+			waveform: None,
+			waveform_style: WaveformStyle::default(),
+			comment: Comment::default(),
+			deterministic: false,
+			max_duration: None,
+			exact_end: false,
+			exact_duration: None,
+			pre_quantize: None,
+			loop_smooth: None,
+			frame_filter: None,
+			cursor_overlay: None,
+			cursor_image: None,
+			jobs: None,
+			force: false,
+			still_duration: None,
+			poster: None,
+			poster_format: PosterFormat::default(),
+			also_mp4: false,
+			social: false,
+			contact_sheet: None,
+			frames_dir: None,
+			frame_pattern: FramePattern::default(),
+			gc: true,
+			in_memory: false,
+			ramdisk: None,
+			reserve_space: crate::disk::DEFAULT_RESERVE_SPACE,
+			background: false,
+			threads: None,
+			strict_space: false,
+			tolerate_decode_errors: false,
+			wait_for_input: None,
+			keep_frames: false,
+			edit_list: None,
+			stats_file: None,
+		}
+	}
+}
+
+/// An aspect ratio, `16:9`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aspect {
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Aspect {
+	pub const SQUARE: Aspect = Aspect { width: 1, height: 1 };
+}
+
+impl FromStr for Aspect {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|&n| n > 0);
+		s.split_once(':')
+			.and_then(|(w, h)| Some(Aspect { width: parse(w)?, height: parse(h)? }))
+			.ok_or_else(|| ConvertError::InvalidOption {
+				option: "aspect",
+				message: format!("expected two positive whole numbers like 16:9, got {s:?}"),
+			})
+	}
+}
+
+/// A factor to scale the frames by, `50%` or `0.5`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale(pub f64);
+
+impl Scale {
+	/// `size` scaled, rounded to the nearest even number of at least 2.
+	#[must_use]
+	pub fn apply(self, size: u32) -> u32 {
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		let half = (f64::from(size) * self.0 / 2.0).round() as u32;
+		half.max(1) * 2
+	}
+}
+
+impl FromStr for Scale {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		let factor = match s.trim().strip_suffix('%') {
+			Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+			None => s.trim().parse::<f64>(),
+		};
+		factor.ok().filter(|f| f.is_finite() && *f > 0.0).map(Scale).ok_or_else(|| ConvertError::InvalidOption {
+			option: "scale",
+			message: format!("expected a percentage like 50% or a factor like 0.5, got {s:?}"),
+		})
+	}
+}
+
+/// Columns and rows of a [`ConvertOptions::grid`], `2x2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+	pub columns: u32,
+	pub rows: u32,
+}
+
+impl FromStr for Grid {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|&n| n > 0);
+		s.split_once(['x', 'X'])
+			.and_then(|(c, r)| Some(Grid { columns: parse(c)?, rows: parse(r)? }))
+			.ok_or_else(|| ConvertError::InvalidOption {
+				option: "grid",
+				message: format!("expected columns and rows like 2x2, got {s:?}"),
+			})
+	}
+}
+
+/// A [`ConvertOptions::frame_filter`] command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameFilter {
+	/// The program and its arguments, split the way a shell would without running one. `{}` is where the frame
+	/// goes, or every frame with [`FrameFilterMode::Batch`]; without one they go at the end.
+	pub command: String,
+	pub mode: FrameFilterMode,
+	/// Run `command` with `sh -c`, or `cmd /C` on Windows, for pipes and the like. `sh` gets the frames as `"$@"`,
+	/// `cmd` gets them quoted.
+	pub shell: bool,
+}
+
+impl FrameFilter {
+	/// Run once per frame, without a shell.
+	pub fn new(command: impl Into<String>) -> Self {
+		FrameFilter { command: command.into(), mode: FrameFilterMode::default(), shell: false }
+	}
+}
+
+/// How often a [`FrameFilter`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameFilterMode {
+	/// Once per frame, up to [`ConvertOptions::jobs`] at a time.
+	#[default]
+	PerFrame,
+	/// Once with every frame, or like xargs, one run after another with as many as fit in a command line.
+	Batch,
+}
+
+impl FromStr for FrameFilterMode {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"per-frame" => Ok(FrameFilterMode::PerFrame),
+			"batch" => Ok(FrameFilterMode::Batch),
+			_ => Err(ConvertError::InvalidOption {
+				option: "frame filter mode",
+				message: format!("expected per-frame or batch, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// A stretch of the gif a [`ConvertOptions::quality_map`] encodes at `quality`, `5-10:100`, or with timestamps
+/// `1:05-1:10:100`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityRegion {
+	/// Seconds into the gif.
+	pub start: f64,
+	pub end: f64,
+	pub quality: u32,
+}
+
+impl FromStr for QualityRegion {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		let invalid = || ConvertError::InvalidOption { option: "quality map", message: format!("expected start-end:quality like 5-10:100, got {s:?}") };
+		let (range, quality) = s.rsplit_once(':').ok_or_else(invalid)?;
+		let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+		let region = QualityRegion {
+			start: parse_timestamp(start).map_err(|_| invalid())?,
+			end: parse_timestamp(end).map_err(|_| invalid())?,
+			quality: quality.trim().parse().ok().filter(|&q| q <= 100).ok_or_else(invalid)?,
+		};
+		if region.end <= region.start {
+			return Err(ConvertError::InvalidOption { option: "quality map", message: format!("{s:?} ends before it starts") });
+		}
+		Ok(region)
+	}
+}
+
+/// A region of the input a [`ConvertOptions::zoom_to`] ends on, `640x360+100+50`, optionally after so many seconds,
+/// `640x360+100+50:4`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zoom {
+	pub width: u32,
+	pub height: u32,
+	/// Left edge, in pixels of the input.
+	pub x: u32,
+	/// Top edge, in pixels of the input.
+	pub y: u32,
+	/// Seconds to get there in, `None` for the whole gif.
+	pub duration: Option<f64>,
+}
+
+impl FromStr for Zoom {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		let invalid = || ConvertError::InvalidOption { option: "zoom to", message: format!("expected WxH+X+Y[:seconds] like 640x360+100+50:4, got {s:?}") };
+		let (region, duration) = match s.split_once(':') {
+			Some((region, duration)) => (region, Some(parse_timestamp(duration).ok().filter(|&d| d > 0.0).ok_or_else(invalid)?)),
+			None => (s, None),
+		};
+		let parse = |n: &str| n.trim().parse::<u32>().ok();
+		let (size, offset) = region.split_once('+').ok_or_else(invalid)?;
+		let ((width, height), (x, y)) = size.split_once(['x', 'X']).zip(offset.split_once('+')).ok_or_else(invalid)?;
+		Ok(Zoom {
+			width: parse(width).filter(|&w| w > 0).ok_or_else(invalid)?,
+			height: parse(height).filter(|&h| h > 0).ok_or_else(invalid)?,
+			x: parse(x).ok_or_else(invalid)?,
+			y: parse(y).ok_or_else(invalid)?,
+			duration,
+		})
+	}
+}
+
+/// Where [`ConvertOptions::focus`] crops to: `auto`, or a region `1280x720@640,360`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+	/// The part of the frame with the motion in it, found by comparing a few frames spread over the gif.
+	Auto,
+	Region {
+		width: u32,
+		height: u32,
+		/// Left edge, in pixels of the input.
+		x: u32,
+		/// Top edge, in pixels of the input.
+		y: u32,
+	},
+}
+
+impl FromStr for Focus {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		if s.trim().eq_ignore_ascii_case("auto") { return Ok(Focus::Auto); }
+		let invalid = || ConvertError::InvalidOption { option: "focus", message: format!("expected auto or WxH@X,Y like 1280x720@640,360, got {s:?}") };
+		let parse = |n: &str| n.trim().parse::<u32>().ok();
+		let (size, offset) = s.split_once('@').ok_or_else(invalid)?;
+		let ((width, height), (x, y)) = size.split_once(['x', 'X']).zip(offset.split_once(',')).ok_or_else(invalid)?;
+		Ok(Focus::Region {
+			width: parse(width).filter(|&w| w > 0).ok_or_else(invalid)?,
+			height: parse(height).filter(|&h| h > 0).ok_or_else(invalid)?,
+			x: parse(x).ok_or_else(invalid)?,
+			y: parse(y).ok_or_else(invalid)?,
+		})
+	}
+}
+
+impl fmt::Display for Focus {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Focus::Auto => f.write_str("auto"),
+			Focus::Region { width, height, x, y } => write!(f, "{width}x{height}@{x},{y}"),
+		}
+	}
+}
+
+/// The default [`ConvertOptions::max_auto_fps`].
+pub const DEFAULT_MAX_AUTO_FPS: f32 = 30.0;
+
+/// Gifs longer than this many seconds need [`ConvertOptions::force`].
+pub const SOFT_MAX_DURATION: f64 = 60.0;
+
+/// The fps [`ConvertOptions::keyframes_only`] gifs play at unless one is given.
+pub const KEYFRAME_FPS: f32 = 3.0;
+
+/// Which stages [`ConvertOptions::stages`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stages {
+	/// Extract the frames and encode them.
+	#[default]
+	All,
+	/// Only extract the frames, and leave them in the frames directory.
+	Extract,
+	/// Only encode the frames already in [`ConvertOptions::input`], which is a frames directory.
+	/// Needs [`ConvertOptions::fps`]. Afterwards only the frames are deleted, not the directory or anything else in it.
+	Encode,
+}
+
+impl FromStr for Stages {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"all" => Ok(Stages::All),
+			"extract" => Ok(Stages::Extract),
+			"encode" => Ok(Stages::Encode),
+			_ => Err(ConvertError::InvalidOption {
+				option: "stage",
+				message: format!("expected extract, encode or all, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// What encodes the gif, [`ConvertOptions::encoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoder {
+	#[default]
+	Gifski,
+	/// ffmpeg's palettegen and paletteuse, for where gifski isn't installed. Larger and more banded gifs.
+	Ffmpeg,
+}
+
+impl FromStr for Encoder {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"gifski" => Ok(Encoder::Gifski),
+			"ffmpeg" => Ok(Encoder::Ffmpeg),
+			_ => Err(ConvertError::InvalidOption {
+				option: "encoder",
+				message: format!("expected gifski or ffmpeg, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// How [`Encoder::Ffmpeg`] dithers, [`ConvertOptions::dither`]. Parsed from paletteuse's names, with the bayer
+/// scale after a colon, `bayer:3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+	/// Nearest palette color only, flat areas band.
+	None,
+	/// A regular crosshatch, which compresses better than the others. `scale` is from 0 to 5, higher makes the
+	/// pattern fainter but the banding stronger.
+	Bayer { scale: u8 },
+	FloydSteinberg,
+	/// ffmpeg's `sierra2_4a`, its default.
+	Sierra,
+}
+
+impl Dither {
+	/// ffmpeg's default [`Dither::Bayer`] scale.
+	pub const DEFAULT_BAYER_SCALE: u8 = 2;
+}
+
+impl FromStr for Dither {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		let invalid = |message| Err(ConvertError::InvalidOption { option: "dither", message });
+		match s {
+			"none" => Ok(Dither::None),
+			"bayer" => Ok(Dither::Bayer { scale: Dither::DEFAULT_BAYER_SCALE }),
+			_ if s.starts_with("bayer:") => match s["bayer:".len()..].parse() {
+				Ok(scale @ 0..=5) => Ok(Dither::Bayer { scale }),
+				_ => invalid(format!("the bayer scale is from 0 to 5, got {s:?}")),
+			},
+			"floyd_steinberg" => Ok(Dither::FloydSteinberg),
+			"sierra2_4a" => Ok(Dither::Sierra),
+			_ => invalid(format!("expected none, bayer[:scale], floyd_steinberg or sierra2_4a, got {s:?}")),
+		}
+	}
+}
+
+/// How ffmpeg gets to [`ConvertOptions::start`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeekMode {
+	/// Jump to the keyframe before it, which can be a couple of seconds early in long-GOP video.
+	Fast,
+	/// Decode everything up to it. Exact, but slow far into a long video.
+	Accurate,
+	/// Jump to a few seconds before it, then decode the rest. Exact, and about as fast as `Fast`.
+	#[default]
+	Smart,
+}
+
+impl FromStr for SeekMode {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"fast" => Ok(SeekMode::Fast),
+			"accurate" => Ok(SeekMode::Accurate),
+			"smart" => Ok(SeekMode::Smart),
+			_ => Err(ConvertError::InvalidOption {
+				option: "seek mode",
+				message: format!("expected fast, accurate or smart, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// Which part of the input a crop keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gravity {
+	#[default]
+	Center,
+	Top,
+	Bottom,
+	Left,
+	Right,
+}
+
+impl FromStr for Gravity {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"center" => Ok(Gravity::Center),
+			"top" => Ok(Gravity::Top),
+			"bottom" => Ok(Gravity::Bottom),
+			"left" => Ok(Gravity::Left),
+			"right" => Ok(Gravity::Right),
+			_ => Err(ConvertError::InvalidOption {
+				option: "gravity",
+				message: format!("expected center, top, bottom, left or right, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// How frames are made to fit an exact width and height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fit {
+	/// Scale to exactly that size, distorting the frames if the aspect ratio differs.
+	Stretch,
+	/// Scale to cover the size, then cut off what sticks out on either side.
+	Crop,
+	/// Scale to fit inside the size, then pad the rest with [`ConvertOptions::pad_color`].
+	#[default]
+	Pad,
+}
+
+impl FromStr for Fit {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"stretch" => Ok(Fit::Stretch),
+			"crop" => Ok(Fit::Crop),
+			"pad" => Ok(Fit::Pad),
+			_ => Err(ConvertError::InvalidOption {
+				option: "fit",
+				message: format!("expected stretch, crop or pad, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// What the [`ConvertOptions::waveform`] strip shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WaveformStyle {
+	/// The sound wave, with ffmpeg's showwaves.
+	#[default]
+	Waves,
+	/// The frequencies scrolling past, with ffmpeg's showspectrum.
+	Spectrum,
+}
+
+impl FromStr for WaveformStyle {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"waves" => Ok(WaveformStyle::Waves),
+			"spectrum" => Ok(WaveformStyle::Spectrum),
+			_ => Err(ConvertError::InvalidOption {
+				option: "waveform style",
+				message: format!("expected waves or spectrum, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// What to write into the gif's comment extension.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Comment {
+	/// The versions of gifski-ffmpeg and gifski, and the settings they were run with.
+	#[default]
+	Auto,
+	Text(String),
+	/// Leave the gif as gifski wrote it.
+	Off,
+}
+
+/// Which of the extracted frames to use as the poster.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Poster {
+	First,
+	#[default]
+	Middle,
+	/// The first frame that isn't black or frozen, found the same way as with [`ConvertOptions::trim_idle`].
+	Interesting,
+	/// Seconds into the input video.
+	At(f64),
+}
+
+impl FromStr for Poster {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"first" => Ok(Poster::First),
+			"middle" => Ok(Poster::Middle),
+			"interesting" => Ok(Poster::Interesting),
+			_ => parse_timestamp(s).map(Poster::At).map_err(|_| ConvertError::InvalidOption {
+				option: "poster",
+				message: format!("expected \"first\", \"middle\", \"interesting\" or a timestamp, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// What [`ConvertOptions::poster`] is written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PosterFormat {
+	/// The frame as it was extracted.
+	#[default]
+	Png,
+	/// Converted by ffmpeg, for sites that don't take PNG previews.
+	Jpeg,
+}
+
+impl PosterFormat {
+	/// `png` or `jpg`.
+	#[must_use]
+	pub fn extension(self) -> &'static str {
+		match self {
+			PosterFormat::Png => "png",
+			PosterFormat::Jpeg => "jpg",
+		}
+	}
+}
+
+impl FromStr for PosterFormat {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self, ConvertError> {
+		match s {
+			"png" => Ok(PosterFormat::Png),
+			"jpg" | "jpeg" => Ok(PosterFormat::Jpeg),
+			_ => Err(ConvertError::InvalidOption {
+				option: "poster format",
+				message: format!("expected png or jpg, got {s:?}"),
+			}),
+		}
+	}
+}
+
+/// Parses a timestamp given as seconds (`90`, `2.5`, `15s`) or as `[hh:]mm:ss[.xxx]` (`1:30`, `01:02:03.5`).
+///
+/// # Errors
+/// [`ConvertError::InvalidOption`] if it's neither, or negative.
+pub fn parse_timestamp(s: &str) -> Result<f64, ConvertError> {
+	let invalid = || ConvertError::InvalidOption {
+		option: "timestamp",
+		message: format!("expected seconds or [hh:]mm:ss[.xxx], got {s:?}"),
+	};
+	let s = s.trim();
+	let parts: Vec<&str> = if s.contains(':') { s.split(':').collect() } else { vec![s.strip_suffix('s').unwrap_or(s)] };
+	if parts.len() > 3 { return Err(invalid()); }
+	let mut seconds = 0.0;
+	for (i, part) in parts.iter().enumerate() {
+		let value: f64 = part.parse().map_err(|_| invalid())?;
+		// Only the last part can have a fraction, and minutes/seconds before it must be < 60.
+		let is_last = i == parts.len() - 1;
+		if !value.is_finite() || value < 0.0 || (!is_last && value.fract() != 0.0) || (i > 0 && value >= 60.0) {
+			return Err(invalid());
+		}
+		seconds = seconds * 60.0 + value;
+	}
+	Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[allow(clippy::float_cmp)] // Exactly representable.
+	fn timestamps() {
+		assert_eq!(parse_timestamp("90").unwrap(), 90.0);
+		assert_eq!(parse_timestamp("2.5").unwrap(), 2.5);
+		assert_eq!(parse_timestamp("15s").unwrap(), 15.0);
+		assert_eq!(parse_timestamp("1:30").unwrap(), 90.0);
+		assert_eq!(parse_timestamp("01:02:03.5").unwrap(), 3723.5);
+		for bad in ["", "-1", "1:60", "1.5:00", "1:2:3:4", "abc", "inf", "s", "1:30s"] {
+			assert!(parse_timestamp(bad).is_err(), "{bad:?}");
+		}
+	}
+
+	#[test]
+	fn aspects() {
+		assert_eq!("16:9".parse::<Aspect>().unwrap(), Aspect { width: 16, height: 9 });
+		for bad in ["16/9", "16:", "0:1", "1.5:1", "-4:3", "square"] {
+			assert!(bad.parse::<Aspect>().is_err(), "{bad:?}");
+		}
+	}
+
+	#[test]
+	fn scales() {
+		assert_eq!("50%".parse::<Scale>().unwrap(), Scale(0.5));
+		assert_eq!("1.5".parse::<Scale>().unwrap(), Scale(1.5));
+		for bad in ["0", "-50%", "half", "%", "inf"] {
+			assert!(bad.parse::<Scale>().is_err(), "{bad:?}");
+		}
+		assert_eq!(Scale(0.5).apply(640), 320);
+		assert_eq!(Scale(0.5).apply(358), 180, "179 is odd");
+		assert_eq!(Scale(0.001).apply(640), 2);
+	}
+
+	#[test]
+	fn posters() {
+		assert_eq!("first".parse::<Poster>().unwrap(), Poster::First);
+		assert_eq!("0:01.5".parse::<Poster>().unwrap(), Poster::At(1.5));
+		assert_eq!("interesting".parse::<Poster>().unwrap(), Poster::Interesting);
+		assert_eq!("jpeg".parse::<PosterFormat>().unwrap().extension(), "jpg");
+		assert!("last".parse::<Poster>().is_err());
+	}
+
+	#[test]
+	fn quality_regions() {
+		assert_eq!("5-10:100".parse::<QualityRegion>().unwrap(), QualityRegion { start: 5.0, end: 10.0, quality: 100 });
+		assert_eq!("1:05-1:10.5:60".parse::<QualityRegion>().unwrap(), QualityRegion { start: 65.0, end: 70.5, quality: 60 });
+		for bad in ["5-10", "5:100", "5-10:101", "-10:50", "10-5:50", "5-5:50"] {
+			assert!(bad.parse::<QualityRegion>().is_err(), "{bad:?}");
+		}
+	}
+}
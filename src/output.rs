@@ -0,0 +1,364 @@
+use std::{
+	ffi::OsStr,
+	fs,
+	io,
+	path::{Path, PathBuf},
+};
+use crate::{
+	gif,
+	paths,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Poster,
+	PosterFormat,
+	Result,
+};
+
+/// Where the gif goes. A name, or no output, is put in `input`'s directory, a path with a directory in it is
+/// used as it is. Absolute with an input and output [made absolute](crate::ConvertOptions::make_absolute).
+pub(crate) fn parse_output(input: &Path, output: Option<&OsStr>, file_name: &OsStr) -> PathBuf {
+	let mut curr = input.parent().unwrap_or(input).to_owned();
+	if let Some(s) = output {
+		if paths::has_dir(s) {
+			// ./some/path.gif, C:\videos\path.gif
+			PathBuf::from(s)
+		} else if Path::new(s).extension().is_some() {
+			// output.gif
+			curr.push(s);
+			curr
+		} else {
+			// output
+			curr.push(s);
+			curr.set_extension("gif");
+			curr
+		}
+	} else {
+		// none
+		let mut name = file_name.to_os_string();
+		name.push("-gif");
+		curr.push(name);
+		curr.set_extension("gif");
+		curr
+	}
+}
+
+/// The path the output is placed next to: `input` as typed, or with `resolve_symlinks` the file it points to,
+/// through symlinked directories too.
+pub(crate) fn output_base(input: &Path, resolve_symlinks: bool) -> Result<PathBuf> {
+	// A dangling symlink doesn't exist either.
+	if !input.exists() { return Err(ConvertError::InputNotFound(input.to_owned())); }
+	if !resolve_symlinks { return Ok(input.to_owned()); }
+	input.canonicalize().map_err(ConvertError::io(input))
+}
+
+/// Checks the gif can be written to `output` before anything is extracted: its directory exists, or is created
+/// with `create_parents`, a file can be created in it, and it isn't `input`.
+pub(crate) fn check_output(input: &Path, output: &Path, create_parents: bool) -> Result<()> {
+	let dir = match output.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	};
+	if !dir.is_dir() {
+		if !create_parents || dir.exists() { return Err(ConvertError::OutputDirMissing(dir.to_owned())); }
+		fs::create_dir_all(dir).map_err(ConvertError::io(dir))?;
+		log::debug!("Created output directory {}", dir.display());
+	}
+	if output.is_dir() {
+		return Err(ConvertError::InvalidOption { option: "output", message: format!("{} is a directory", output.display()) });
+	}
+	// Canonical, so `./clip.mp4` and `clip.mp4` or a symlink to it are caught too. The output may not exist yet.
+	let canonical_output = dir.canonicalize().map(|d| d.join(output.file_name().unwrap_or_default()));
+	if output == input || canonical_output.is_ok_and(|o| input.canonicalize().is_ok_and(|i| i == o)) {
+		return Err(ConvertError::OutputIsInput(output.to_owned()));
+	}
+
+	let probe = dir.join(format!(".gifski-ffmpeg-write-test-{}", std::process::id()));
+	let written = fs::OpenOptions::new().write(true).create_new(true).open(&probe);
+	let _ = fs::remove_file(&probe);
+	written.map(drop).map_err(|source| ConvertError::OutputNotWritable { dir: dir.to_owned(), source })
+}
+
+/// `dir/clip.gif` -> `dir/clip-q80.gif`.
+pub(crate) fn quality_variant(output: &Path, quality: u32) -> PathBuf {
+	let mut name = output.file_stem().unwrap_or_default().to_os_string();
+	name.push(format!("-q{quality}.gif"));
+	output.with_file_name(name)
+}
+
+/// `dir/clip.gif` -> `dir/clip-480.gif`.
+pub(crate) fn size_variant(output: &Path, width: u32) -> PathBuf {
+	let mut name = output.file_stem().unwrap_or_default().to_os_string();
+	name.push(format!("-{width}.gif"));
+	output.with_file_name(name)
+}
+
+/// Where what goes to `output` is written before [`finalize_output`] puts it in place: `.<name>.partial.gif` next
+/// to it, where that's a rename, or in `fallback_dir` if nothing can be written next to it.
+pub(crate) fn staging_path(output: &Path, fallback_dir: &Path) -> PathBuf {
+	let mut name = std::ffi::OsString::from(".");
+	name.push(output.file_stem().unwrap_or_default());
+	name.push(".partial");
+	if let Some(extension) = output.extension() {
+		// Kept last, ffmpeg picks the format by it.
+		name.push(".");
+		name.push(extension);
+	}
+	let staged = output.with_file_name(name);
+	match fs::File::create(&staged) {
+		Ok(_) => staged,
+		Err(e) => {
+			log::debug!("Can't write next to {}, writing it in {} first: {e}", output.display(), fallback_dir.display());
+			fallback_dir.join(output.file_name().unwrap_or_default())
+		}
+	}
+}
+
+/// Moves the finished `staged` file to `output`, over whatever is there. A rename if they're on the same filesystem,
+/// otherwise a copy next to `output` that's synced and renamed over it, so `output` is only ever the old file or
+/// the whole new one. `staged` is gone either way.
+///
+/// # Errors
+/// If it can't be moved, or copied when it has to be.
+pub(crate) fn finalize_output(staged: &Path, output: &Path) -> Result<()> {
+	finalize_with(staged, output, |from, to| fs::rename(from, to))
+}
+
+/// [`finalize_output`], renaming with `rename`.
+fn finalize_with(staged: &Path, output: &Path, rename: impl Fn(&Path, &Path) -> io::Result<()>) -> Result<()> {
+	if staged == output { return Ok(()); }
+	match rename(staged, output) {
+		Ok(()) => return Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::CrossesDevices => log::debug!("{} is on another filesystem, copying it", staged.display()),
+		Err(e) => {
+			let _ = fs::remove_file(staged);
+			return Err(ConvertError::io(output)(e));
+		}
+	}
+	let mut name = std::ffi::OsString::from(".");
+	name.push(output.file_name().unwrap_or_default());
+	name.push(".copying");
+	let copy = output.with_file_name(name);
+	let copied = fs::copy(staged, &copy)
+		.and_then(|_| fs::File::open(&copy)?.sync_all())
+		.and_then(|()| rename(&copy, output));
+	if copied.is_err() { let _ = fs::remove_file(&copy); }
+	let _ = fs::remove_file(staged);
+	copied.map_err(ConvertError::io(output))
+}
+
+/// Has `write` write what goes to `output` to its [`staging_path`], then puts it in place with
+/// [`finalize_output`]. If `write` fails, what it wrote is deleted and `output` is left as it was.
+///
+/// # Errors
+/// What `write` returns, or what [`finalize_output`] does.
+pub(crate) fn staged<T>(output: &Path, fallback_dir: &Path, write: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+	let staging = staging_path(output, fallback_dir);
+	match write(&staging) {
+		Ok(value) => finalize_output(&staging, output).map(|()| value),
+		Err(e) => {
+			let _ = fs::remove_file(&staging);
+			Err(e)
+		}
+	}
+}
+
+/// Writes `comment` into the gif at `path`.
+pub(crate) fn write_comment(path: &Path, comment: &str) -> Result<()> {
+	let gif = fs::read(path).map_err(ConvertError::io(path))?;
+	let commented = gif::insert_comment(&gif, comment)
+		.ok_or_else(|| ConvertError::io(path)(io::Error::new(io::ErrorKind::InvalidData, "gifski didn't write a gif")))?;
+	fs::write(path, commented).map_err(ConvertError::io(path))
+}
+
+/// Copies one of the already extracted frames to <OUTPUT>.png, so the poster matches the gif exactly, or has ffmpeg
+/// convert it to <OUTPUT>.jpg. `frame_at` maps a [`Poster::At`] timestamp to a position in `frames`, `interesting`
+/// finds the [`Poster::Interesting`] one.
+pub(crate) fn write_poster(
+	runner: &dyn CommandRunner,
+	(poster, format): (Poster, PosterFormat),
+	frames: &[PathBuf],
+	output: &Path,
+	frame_at: impl FnOnce(f64) -> Result<f64>,
+	interesting: impl FnOnce() -> Result<usize>,
+) -> Result<PathBuf> {
+	let index = match poster {
+		Poster::First => 0,
+		Poster::Middle => frames.len() / 2,
+		Poster::Interesting => interesting()?,
+		#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+		Poster::At(t) => frame_at(t)?.round().max(0.0) as usize,
+	};
+	let frame = frames.get(index)
+		.ok_or(ConvertError::PosterOutOfRange { index, frame_count: frames.len() })?;
+	log::debug!("Poster frame: {}", &frame.display());
+
+	let poster_path = output.with_extension(format.extension());
+	match format {
+		PosterFormat::Png => { fs::copy(frame, &poster_path).map_err(ConvertError::io(&poster_path))?; }
+		PosterFormat::Jpeg => {
+			let command = jpeg_command(frame, &poster_path);
+			log::debug!("Running: {command}");
+			let result = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+			if !result.success() {
+				return Err(ConvertError::FfmpegFailed { code: result.code, stderr: String::from_utf8_lossy(&result.stderr).into_owned() });
+			}
+		}
+	}
+	Ok(poster_path)
+}
+
+/// `ffmpeg -i frame.png -q:v 2 poster.jpg`, about the best JPEG quality there is short of lossless.
+fn jpeg_command(frame: &Path, jpeg: &Path) -> CommandLine {
+	CommandLine::new("ffmpeg")
+		.args(["-v", "error", "-y", "-i"])
+		.arg(paths::for_tool(frame))
+		.args(["-frames:v", "1", "-q:v", "2"])
+		.arg(paths::for_tool(jpeg))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn output_is_checked_before_converting() {
+		let dir = crate::test_dir("check-output");
+		let input = dir.join("clip.mp4");
+		fs::write(&input, b"").unwrap();
+
+		check_output(&input, &dir.join("clip.gif"), false).unwrap();
+		assert!(matches!(check_output(&input, &dir.join("gifs/clip.gif"), false), Err(ConvertError::OutputDirMissing(d)) if d == dir.join("gifs")));
+		check_output(&input, &dir.join("gifs/clip.gif"), true).unwrap();
+		assert!(dir.join("gifs").is_dir());
+		assert!(matches!(check_output(&input, &dir.join("clip.mp4/clip.gif"), true), Err(ConvertError::OutputDirMissing(_))), "a file isn't a directory");
+		assert!(matches!(check_output(&input, &dir.join("gifs/../clip.mp4"), false), Err(ConvertError::OutputIsInput(_))));
+		assert!(matches!(check_output(&input, &dir.join("gifs"), false), Err(ConvertError::InvalidOption { .. })));
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "the write test is cleaned up");
+	}
+
+	fn finalize_dir(name: &str) -> PathBuf {
+		let dir = crate::test_dir(&format!("finalize-{name}"));
+		fs::create_dir(dir.join("temp")).unwrap();
+		dir
+	}
+
+	#[test]
+	fn outputs_are_staged_next_to_themselves() {
+		let dir = finalize_dir("staged");
+		let output = dir.join("clip.gif");
+		let staging = staging_path(&output, &dir.join("temp"));
+		assert_eq!(staging, dir.join(".clip.partial.gif"));
+		fs::write(&staging, b"GIF89a").unwrap();
+		finalize_output(&staging, &output).unwrap();
+		assert_eq!(fs::read(&output).unwrap(), b"GIF89a");
+		assert!(!staging.exists());
+
+		assert_eq!(staging_path(&dir.join("missing/clip.gif"), &dir.join("temp")), dir.join("temp/clip.gif"), "can't be written next to");
+	}
+
+	#[test]
+	fn across_filesystems_it_is_copied_then_renamed() {
+		let dir = finalize_dir("cross-device");
+		let (staged, output) = (dir.join("temp/clip.gif"), dir.join("clip.gif"));
+		fs::write(&staged, b"new").unwrap();
+		fs::write(&output, b"old").unwrap();
+		let renames = std::cell::RefCell::new(Vec::new());
+		// Only the copy next to the output is on the same filesystem.
+		let rename = |from: &Path, to: &Path| {
+			renames.borrow_mut().push(from.to_path_buf());
+			if from.parent() == to.parent() { fs::rename(from, to) } else { Err(io::ErrorKind::CrossesDevices.into()) }
+		};
+		finalize_with(&staged, &output, rename).unwrap();
+		assert_eq!(fs::read(&output).unwrap(), b"new");
+		assert_eq!(*renames.borrow(), [staged.clone(), dir.join(".clip.gif.copying")]);
+		assert!(!staged.exists());
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "the copy is renamed away: {:?}", fs::read_dir(&dir).unwrap().collect::<Vec<_>>());
+
+		fs::write(&staged, b"newer").unwrap();
+		let err = finalize_with(&staged, &output, |_, _| Err(io::ErrorKind::CrossesDevices.into())).unwrap_err();
+		assert!(matches!(err, ConvertError::Io { .. }), "{err:?}");
+		assert_eq!(fs::read(&output).unwrap(), b"new", "the old one is left as it was");
+		assert!(!dir.join(".clip.gif.copying").exists() && !staged.exists());
+	}
+
+	#[test]
+	fn a_failed_write_leaves_the_output_alone() {
+		let dir = finalize_dir("failed");
+		let output = dir.join("clip.gif");
+		fs::write(&output, b"old").unwrap();
+		let err = staged(&output, &dir.join("temp"), |staging| {
+			fs::write(staging, b"half").unwrap();
+			Err::<(), _>(ConvertError::GifskiFailed { code: Some(1), stderr: String::new() })
+		});
+		assert!(err.is_err());
+		assert_eq!(fs::read(&output).unwrap(), b"old");
+		assert!(!dir.join(".clip.partial.gif").exists());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn symlinked_inputs() {
+		use std::os::unix::fs::symlink;
+		let dir = crate::test_dir("symlinked-inputs");
+		fs::create_dir(dir.join("videos")).unwrap();
+		fs::create_dir(dir.join("by-date")).unwrap();
+		fs::write(dir.join("videos/clip.mp4"), b"").unwrap();
+		symlink(dir.join("videos/clip.mp4"), dir.join("by-date/clip.mp4")).unwrap();
+		symlink(dir.join("videos"), dir.join("linked")).unwrap();
+		symlink(dir.join("videos/gone.mp4"), dir.join("by-date/gone.mp4")).unwrap();
+		let output = |input: &str, resolve_symlinks| {
+			let input = dir.join(input);
+			output_base(&input, resolve_symlinks).map(|base| parse_output(&base, None, input.file_stem().unwrap()))
+		};
+		let real = dir.canonicalize().unwrap().join("videos/clip-gif.gif");
+
+		assert_eq!(output("by-date/clip.mp4", false).unwrap(), dir.join("by-date/clip-gif.gif"), "symlinked file");
+		assert_eq!(output("by-date/clip.mp4", true).unwrap(), real);
+		assert_eq!(output("linked/clip.mp4", false).unwrap(), dir.join("linked/clip-gif.gif"), "symlinked directory");
+		assert_eq!(output("linked/clip.mp4", true).unwrap(), real);
+		for resolve_symlinks in [false, true] {
+			let err = output("by-date/gone.mp4", resolve_symlinks).unwrap_err();
+			assert!(matches!(err, ConvertError::InputNotFound(ref p) if p == &dir.join("by-date/gone.mp4")), "dangling symlink: {err:?}");
+		}
+		assert!(matches!(check_output(&dir.join("by-date/clip.mp4"), &dir.join("linked/clip.mp4"), false), Err(ConvertError::OutputIsInput(_))));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn outputs_are_absolute_wherever_it_runs_from() {
+		let output = |cwd: &str, input: &str, output: Option<&str>| {
+			let mut options = crate::ConvertOptions::new(input);
+			options.output = output.map(Into::into);
+			options.make_absolute(Path::new(cwd));
+			parse_output(&options.input, options.output.as_deref(), options.input.file_stem().unwrap())
+		};
+		for cwd in ["/home/me", "/tmp"] {
+			assert_eq!(output(cwd, "/videos/clip.mp4", None), Path::new("/videos/clip-gif.gif"), "from {cwd}");
+			assert_eq!(output(cwd, "/videos/clip.mp4", Some("out")), Path::new("/videos/out.gif"), "names go next to the input");
+			assert_eq!(output(cwd, "/videos/clip.mp4", Some("/gifs/out.gif")), Path::new("/gifs/out.gif"));
+		}
+		assert_eq!(output("/home/me", "videos/clip.mp4", None), Path::new("/home/me/videos/clip-gif.gif"));
+		assert_eq!(output("/home/me", "clip.mp4", Some("out.gif")), Path::new("/home/me/out.gif"));
+		assert_eq!(output("/home/me", "videos/clip.mp4", Some("out.gif")), Path::new("/home/me/videos/out.gif"));
+		assert_eq!(output("/home/me", "videos/clip.mp4", Some("gifs/out.gif")), Path::new("/home/me/gifs/out.gif"), "paths are from the cwd");
+		assert_eq!(output("/home/me", "videos/clip.mp4", Some("./out.gif")), Path::new("/home/me/out.gif"));
+		assert_eq!(output("/home/me", "../clip.mp4", Some("../gifs/out")), Path::new("/home/me/../gifs/out"));
+	}
+
+	#[cfg(windows)]
+	#[test]
+	fn windows_outputs() {
+		let output = |input: &str, output: Option<&str>| {
+			let input = Path::new(input);
+			parse_output(input, output.map(OsStr::new), input.file_stem().unwrap())
+		};
+		assert_eq!(output(r"\\server\media\clip.mp4", None), Path::new(r"\\server\media\clip-gif.gif"));
+		assert_eq!(output(r"\\server\media\clip.mp4", Some("out")), Path::new(r"\\server\media\out.gif"));
+		assert_eq!(output(r"\\?\UNC\server\media\clip.mp4", None), Path::new(r"\\?\UNC\server\media\clip-gif.gif"));
+		assert_eq!(output(r"C:\videos\clip.mp4", Some(r"D:\gifs\out.gif")), Path::new(r"D:\gifs\out.gif"));
+		assert_eq!(output(r"C:\videos\clip.mp4", Some(r"gifs\out.gif")), Path::new(r"gifs\out.gif"));
+		assert_eq!(output(r"C:\videos\clip.mp4", Some("D:out.gif")), Path::new("D:out.gif"), "drive-relative");
+		let deep = format!(r"C:\{}clip.mp4", "nested\\".repeat(40));
+		assert_eq!(output(&deep, None), Path::new(&deep.replace("clip.mp4", "clip-gif.gif")));
+	}
+}
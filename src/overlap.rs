@@ -0,0 +1,185 @@
+//! Running ffmpeg and gifski at the same time, for `--overlap`.
+
+use std::{
+	fs,
+	io::{self, BufRead, BufReader, Read, Write},
+	path::Path,
+	thread,
+	time::{Duration, Instant},
+};
+use crate::{
+	gifski::{self, Watch},
+	pattern::FramePattern,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Progress,
+	Result,
+	Stage,
+};
+
+/// How long each of the overlapping stages took, and ffmpeg's stderr.
+pub(crate) struct Overlapped {
+	pub ffmpeg_stderr: String,
+	pub extract_time: Duration,
+	pub encode_time: Duration,
+	/// How many frames went through, for [`extract_and_encode_chunks`] which deletes them as it goes.
+	pub chunked_frames: Option<usize>,
+}
+
+/// How often gifski's progress is checked while a chunk is being piped into it.
+const CHUNK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs ffmpeg's [`overlap_command`](crate::ffmpeg::overlap_command) while gifski's [`stdin_command`](gifski::stdin_command)
+/// encodes the piped copy of the frames into `output`.
+///
+/// gifski reads yuv4mpeg from stdin rather than the PNGs, so it never sees a half-written frame. When either
+/// side fails the pipe between them breaks, which stops the other: gifski sees the end of its input, ffmpeg
+/// can't write to its output. ffmpeg failing is reported over gifski failing, since it's the likelier cause.
+pub(crate) fn extract_and_encode(
+	runner: &dyn CommandRunner,
+	ffmpeg_command: &CommandLine,
+	gifski_command: &CommandLine,
+	output: &Path,
+	frame_estimate: usize,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Overlapped> {
+	let started = Instant::now();
+	log::debug!("Running: {gifski_command}");
+	let mut gifski_child = runner.spawn(gifski_command).map_err(ConvertError::GifskiNotInstalled)?;
+	let mut gifski_stdin = gifski_child.take_stdin();
+
+	log::debug!("Running: {ffmpeg_command}");
+	let mut ffmpeg_child = runner.spawn(ffmpeg_command).map_err(ConvertError::FfmpegNotInstalled)?;
+	let ffmpeg_stderr = ffmpeg_child.take_stderr().map(|stderr| thread::spawn(move || read_all(stderr)));
+	let pump = ffmpeg_child.take_stdout().map(|mut stdout| thread::spawn(move || {
+		if let Some(stdin) = &mut gifski_stdin {
+			if let Err(e) = io::copy(&mut stdout, stdin) { log::debug!("Piping frames to gifski stopped: {e}"); }
+		}
+		// Dropping both ends here is what stops the other side.
+		started.elapsed()
+	}));
+
+	let gifski_result = gifski::watch(gifski_child, output, frame_estimate, progress);
+	let extract_time = pump.and_then(|p| p.join().ok()).unwrap_or_else(|| started.elapsed());
+	let encode_time = started.elapsed();
+	let ffmpeg_output = ffmpeg_child.wait().map_err(ConvertError::FfmpegNotInstalled)?;
+	let ffmpeg_stderr = ffmpeg_stderr.and_then(|r| r.join().ok()).unwrap_or_default() + &String::from_utf8_lossy(&ffmpeg_output.stderr);
+	log::debug!("ffmpeg stderr: {}", &ffmpeg_stderr);
+
+	if !ffmpeg_output.success() {
+		// Whatever gifski made of the frames it got isn't the whole video.
+		let _ = fs::remove_file(output);
+		return Err(ConvertError::FfmpegFailed { code: ffmpeg_output.code, stderr: ffmpeg_stderr });
+	}
+	gifski_result?;
+	progress(Progress::Finished(Stage::Extract, extract_time));
+	progress(Progress::Finished(Stage::Encode, encode_time));
+	Ok(Overlapped { ffmpeg_stderr, extract_time, encode_time, chunked_frames: None })
+}
+
+/// [`extract_and_encode`] a chunk at a time, deleting each chunk's frames before extracting the next.
+/// `next_chunk` gets the number the chunk's first frame file should have, so the numbers keep counting up
+/// across chunks, and returns ffmpeg's command for it, or `None` after the last chunk. The frames are found in
+/// `frames_dir` by the `pattern` they're written with.
+///
+/// The one gifski reads every chunk, so the gif comes out as continuous as an unchunked one. Each ffmpeg
+/// starts its stream with a yuv4mpeg header, which is skipped after the first since gifski expects only one.
+pub(crate) fn extract_and_encode_chunks(
+	runner: &dyn CommandRunner,
+	next_chunk: &mut dyn FnMut(usize) -> Option<CommandLine>,
+	(frames_dir, pattern): (&Path, &FramePattern),
+	gifski_command: &CommandLine,
+	output: &Path,
+	frame_estimate: usize,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Overlapped> {
+	let started = Instant::now();
+	log::debug!("Running: {gifski_command}");
+	let mut gifski_child = runner.spawn(gifski_command).map_err(ConvertError::GifskiNotInstalled)?;
+	let mut gifski_stdin = gifski_child.take_stdin();
+	let mut watch = Watch::new(&mut *gifski_child, frame_estimate);
+
+	let mut first_stderr = None;
+	let mut extract_time = Duration::ZERO;
+	let mut frame_count = 0;
+	while let Some(command) = next_chunk(frame_count + 1) {
+		let chunk_started = Instant::now();
+		log::debug!("Running: {command}");
+		let mut ffmpeg_child = runner.spawn(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+		let stderr = ffmpeg_child.take_stderr().map(|stderr| thread::spawn(move || read_all(stderr)));
+		let stdout = ffmpeg_child.take_stdout();
+		let stdin = gifski_stdin.take();
+		let skip_header = first_stderr.is_some();
+		let pump = thread::spawn(move || {
+			let (Some(stdout), Some(mut stdin)) = (stdout, stdin) else { return None };
+			match pump(stdout, &mut stdin, skip_header) {
+				Ok(()) => Some(stdin),
+				Err(e) => {
+					log::debug!("Piping frames to gifski stopped: {e}");
+					None
+				}
+			}
+		});
+		while !pump.is_finished() && watch.poll(output, CHUNK_POLL_INTERVAL, progress) {}
+		gifski_stdin = pump.join().ok().flatten();
+
+		let ffmpeg_output = ffmpeg_child.wait().map_err(ConvertError::FfmpegNotInstalled)?;
+		let stderr = stderr.and_then(|r| r.join().ok()).unwrap_or_default() + &String::from_utf8_lossy(&ffmpeg_output.stderr);
+		log::debug!("ffmpeg stderr: {}", &stderr);
+		if !ffmpeg_output.success() {
+			drop(gifski_stdin);
+			let _ = watch.finish(gifski_child, output, &mut |_| {});
+			let _ = fs::remove_file(output);
+			return Err(ConvertError::FfmpegFailed { code: ffmpeg_output.code, stderr });
+		}
+		extract_time += chunk_started.elapsed();
+
+		let frames = pattern.list(frames_dir)?;
+		log::debug!("Chunk of frames {} to {} piped.", frame_count + 1, frame_count + frames.len());
+		frame_count += frames.len();
+		for frame in frames {
+			fs::remove_file(&frame).map_err(ConvertError::io(frame))?;
+		}
+		first_stderr.get_or_insert(stderr);
+		// gifski stopped reading, finishing it below says why.
+		if gifski_stdin.is_none() { break; }
+	}
+
+	// Closing stdin is gifski's cue that there are no more frames.
+	drop(gifski_stdin);
+	watch.finish(gifski_child, output, progress)?;
+	let encode_time = started.elapsed();
+	progress(Progress::Finished(Stage::Extract, extract_time));
+	progress(Progress::Finished(Stage::Encode, encode_time));
+	Ok(Overlapped { ffmpeg_stderr: first_stderr.unwrap_or_default(), extract_time, encode_time, chunked_frames: Some(frame_count) })
+}
+
+/// Copies one ffmpeg's yuv4mpeg stream into gifski, without its header line if `skip_header`.
+fn pump(stdout: impl Read, stdin: &mut impl Write, skip_header: bool) -> io::Result<()> {
+	let mut stdout = BufReader::new(stdout);
+	if skip_header { stdout.read_until(b'\n', &mut Vec::new())?; }
+	io::copy(&mut stdout, stdin)?;
+	Ok(())
+}
+
+fn read_all(mut stderr: impl Read) -> String {
+	let mut text = Vec::new();
+	let _ = stderr.read_to_end(&mut text);
+	String::from_utf8_lossy(&text).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn later_chunks_lose_their_header() {
+		let chunk = b"YUV4MPEG2 W2 H2 F24:1 C444\nFRAME\nabcdefghijkl".as_slice();
+		let mut piped = Vec::new();
+
+		pump(chunk, &mut piped, false).unwrap();
+		pump(chunk, &mut piped, true).unwrap();
+
+		assert_eq!(piped, b"YUV4MPEG2 W2 H2 F24:1 C444\nFRAME\nabcdefghijklFRAME\nabcdefghijkl");
+	}
+}
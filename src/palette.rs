@@ -0,0 +1,120 @@
+//! [`Encoder::Ffmpeg`]: encoding with ffmpeg's palettegen and paletteuse instead of gifski.
+//!
+//! Both passes run in one ffmpeg: `split` hands the frames to palettegen and, once it has made the palette, to
+//! paletteuse, so they're only read once.
+
+use std::path::{Path, PathBuf};
+use crate::{
+	paths,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	ConvertOptions,
+	Dither,
+	Encoder,
+	Result,
+};
+
+/// Rejects the options only one of the encoders has.
+pub(crate) fn check(opt: &ConvertOptions) -> Result<()> {
+	let invalid = |option, message: &str| Err(ConvertError::InvalidOption { option, message: message.to_string() });
+	match opt.encoder {
+		Encoder::Gifski if opt.colors.is_some() => invalid("colors", "needs --encoder ffmpeg, gifski picks its own palette"),
+		Encoder::Gifski if opt.dither.is_some() => invalid("dither", "needs --encoder ffmpeg, gifski always dithers its own way"),
+		Encoder::Gifski => Ok(()),
+		Encoder::Ffmpeg => {
+			if let Some(colors) = opt.colors.filter(|c| !(2..=256).contains(c)) {
+				return invalid("colors", &format!("{colors} is not between 2 and 256"));
+			}
+			if let Some(Dither::Bayer { scale }) = opt.dither {
+				if scale > 5 { return invalid("bayer scale", &format!("{scale} is not between 0 and 5")); }
+			}
+			let conflict = [
+				(opt.overlap || opt.chunk_seconds.is_some(), "ffmpeg can't be combined with --overlap or --chunk-seconds, which pipe into gifski"),
+				(!opt.compare_quality.is_empty(), "ffmpeg can't be combined with --compare-quality, quality is a gifski setting"),
+			].into_iter().find(|(set, _)| *set);
+			conflict.map_or(Ok(()), |(_, message)| invalid("encoder", message))
+		}
+	}
+}
+
+/// `ffmpeg -framerate fps -start_number N -i frame%04d.png -filter_complex split,palettegen,paletteuse output.gif`,
+/// scaling the frames down to `width` if given. `frames` are the ones left to encode, which are numbered one after
+/// another from the first.
+pub(crate) fn encode_command(fps: f32, width: Option<u32>, opt: &ConvertOptions, frames: &[PathBuf], output: &Path) -> CommandLine {
+	let (start_number, sequence) = opt.frame_pattern.sequence(frames.first().map(PathBuf::as_path));
+	let scale = width.map(|w| format!("scale={w}:-1:flags=lanczos,")).unwrap_or_default();
+	let max_colors = opt.colors.map(|c| format!("=max_colors={c}")).unwrap_or_default();
+	let dither = match opt.dither {
+		None => String::new(),
+		Some(Dither::None) => "=dither=none".to_string(),
+		Some(Dither::Bayer { scale }) => format!("=dither=bayer:bayer_scale={scale}"),
+		Some(Dither::FloydSteinberg) => "=dither=floyd_steinberg".to_string(),
+		Some(Dither::Sierra) => "=dither=sierra2_4a".to_string(),
+	};
+	CommandLine::new("ffmpeg")
+		.args(["-v", "error", "-y"])
+		.arg("-framerate").arg(fps.to_string())
+		.arg("-start_number").arg(start_number.to_string())
+		.arg("-i").arg(paths::for_tool(&sequence))
+		.arg("-filter_complex").arg(format!("[0:v]{scale}split[a][b];[a]palettegen{max_colors}[p];[b][p]paletteuse{dither}"))
+		.args(["-loop", "0"])
+		.arg(paths::for_tool(output))
+}
+
+/// Runs an [`encode_command`] for `frames`, linked one after another first if their numbers have gaps.
+pub(crate) fn encode(runner: &dyn CommandRunner, fps: f32, width: Option<u32>, opt: &ConvertOptions, frames: &[PathBuf], output: &Path) -> Result<()> {
+	let sequence = opt.frame_pattern.consecutive(frames)?;
+	let command = encode_command(fps, width, opt, &sequence.frames, output);
+	log::debug!("Running: {command}");
+	let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	if output.success() { Ok(()) } else {
+		Err(ConvertError::FfmpegFailed { code: output.code, stderr: String::from_utf8_lossy(&output.stderr).into_owned() })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(opt: &ConvertOptions, width: Option<u32>) -> Vec<String> {
+		let frames = [PathBuf::from("/tmp/frames/frame0003.png"), PathBuf::from("/tmp/frames/frame0004.png")];
+		encode_command(12.5, width, opt, &frames, Path::new("/tmp/out.gif")).args_lossy()
+	}
+
+	#[test]
+	fn one_filter_graph_makes_and_uses_the_palette() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.encoder = Encoder::Ffmpeg;
+		assert_eq!(args(&opt, None), [
+			"-v", "error", "-y", "-framerate", "12.5", "-start_number", "3", "-i", "/tmp/frames/frame%04d.png",
+			"-filter_complex", "[0:v]split[a][b];[a]palettegen[p];[b][p]paletteuse", "-loop", "0", "/tmp/out.gif",
+		]);
+		opt.colors = Some(64);
+		opt.dither = Some(Dither::Bayer { scale: 4 });
+		assert_eq!(
+			args(&opt, Some(320))[10],
+			"[0:v]scale=320:-1:flags=lanczos,split[a][b];[a]palettegen=max_colors=64[p];[b][p]paletteuse=dither=bayer:bayer_scale=4",
+		);
+		opt.dither = Some(Dither::None);
+		assert!(args(&opt, None)[10].ends_with("paletteuse=dither=none"));
+	}
+
+	#[test]
+	fn palette_options_need_the_ffmpeg_encoder() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.colors = Some(64);
+		assert_eq!(check(&opt).unwrap_err().to_string(), "Invalid colors: needs --encoder ffmpeg, gifski picks its own palette");
+		opt.encoder = Encoder::Ffmpeg;
+		assert!(check(&opt).is_ok());
+		opt.colors = Some(300);
+		assert_eq!(check(&opt).unwrap_err().to_string(), "Invalid colors: 300 is not between 2 and 256");
+		opt.colors = None;
+		opt.dither = Some(Dither::Bayer { scale: 6 });
+		assert_eq!(check(&opt).unwrap_err().to_string(), "Invalid bayer scale: 6 is not between 0 and 5");
+		assert_eq!("bayer:3".parse::<Dither>().unwrap(), Dither::Bayer { scale: 3 });
+		assert!("bayer:6".parse::<Dither>().is_err());
+		opt.dither = Some(Dither::FloydSteinberg);
+		opt.compare_quality = vec![50, 90];
+		assert!(check(&opt).unwrap_err().to_string().contains("--compare-quality"));
+	}
+}
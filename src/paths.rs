@@ -0,0 +1,128 @@
+//! Paths handed to ffmpeg and gifski, which don't get the Windows long path handling std does for itself.
+
+use std::{
+	ffi::{OsStr, OsString},
+	path::{Component, Path, PathBuf},
+};
+#[cfg(windows)]
+use std::path::Prefix;
+
+/// Windows programs can't open paths this long without the `\\?\` prefix.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// `path` as an argument for ffmpeg or gifski. On Windows, absolute paths too long to open otherwise get the
+/// `\\?\` prefix, or `\\?\UNC\` for network shares.
+pub(crate) fn for_tool(path: &Path) -> OsString {
+	#[cfg(windows)]
+	if path.as_os_str().len() >= MAX_PATH {
+		if let Some(long) = extended(path) { return long.into_os_string(); }
+	}
+	path.as_os_str().to_owned()
+}
+
+/// Whether an output name has a directory in it, in either of the platform's separators, instead of being a
+/// file name to put next to the input. A drive-relative `C:out.gif` counts as one on Windows.
+pub(crate) fn has_dir(name: &OsStr) -> bool {
+	Path::new(name).parent().is_some_and(|p| !p.as_os_str().is_empty())
+}
+
+/// `path` made absolute against `cwd`, without looking at the file system, so a symlink stays a symlink. `./` is
+/// dropped, `..` is kept since it can't be resolved without it.
+pub(crate) fn absolute_in(cwd: &Path, path: &Path) -> PathBuf {
+	if path.is_absolute() { return path.to_owned(); }
+	// `C:out.gif` and `\out.gif` are relative to a drive, which only Windows knows the current directory of.
+	if matches!(path.components().next(), Some(Component::Prefix(_) | Component::RootDir)) {
+		return std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+	}
+	cwd.join(path).components().collect()
+}
+
+/// `C:\long\path` as `\\?\C:\long\path`, `\\server\share\path` as `\\?\UNC\server\share\path`. `None` for paths
+/// that already have the prefix or aren't absolute, which it can't be added to.
+///
+/// The prefix turns off Windows' own clean up of the path, so `/`, `.` and `..` are resolved here.
+#[cfg(windows)]
+fn extended(path: &Path) -> Option<PathBuf> {
+	let mut components = path.components();
+	let Some(Component::Prefix(prefix)) = components.next() else { return None };
+	let mut long = match prefix.kind() {
+		Prefix::Disk(_) if path.has_root() => {
+			let mut long = OsString::from(r"\\?\");
+			long.push(prefix.as_os_str());
+			long.push(r"\");
+			PathBuf::from(long)
+		}
+		Prefix::UNC(server, share) => {
+			let mut long = OsString::from(r"\\?\UNC\");
+			long.push(server);
+			long.push(r"\");
+			long.push(share);
+			long.push(r"\");
+			PathBuf::from(long)
+		}
+		_ => return None,
+	};
+	let root = long.as_os_str().len();
+	for component in components {
+		match component {
+			Component::Normal(name) => long.push(name),
+			Component::ParentDir if long.as_os_str().len() > root => { long.pop(); }
+			_ => {}
+		}
+	}
+	Some(long)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names_with_a_directory() {
+		assert!(has_dir(OsStr::new("./out.gif")));
+		assert!(has_dir(OsStr::new("videos/out.gif")));
+		assert!(!has_dir(OsStr::new("out.gif")));
+		assert!(!has_dir(OsStr::new("out")));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn relative_paths_are_made_absolute_against_the_cwd() {
+		let cwd = Path::new("/home/me/work");
+		assert_eq!(absolute_in(cwd, Path::new("clip.mp4")), Path::new("/home/me/work/clip.mp4"));
+		assert_eq!(absolute_in(cwd, Path::new("./videos/./clip.mp4")), Path::new("/home/me/work/videos/clip.mp4"));
+		assert_eq!(absolute_in(cwd, Path::new("../clip.mp4")), Path::new("/home/me/work/../clip.mp4"));
+		assert_eq!(absolute_in(cwd, Path::new("/tmp/clip.mp4")), Path::new("/tmp/clip.mp4"));
+	}
+
+	#[cfg(windows)]
+	#[test]
+	fn windows_names_with_a_directory() {
+		assert!(has_dir(OsStr::new(r"videos\out.gif")));
+		assert!(has_dir(OsStr::new(r"\\server\media\out.gif")));
+		assert!(has_dir(OsStr::new("C:out.gif")), "drive-relative");
+	}
+
+	#[cfg(windows)]
+	#[test]
+	fn long_paths_get_the_prefix() {
+		let deep = "nested\\".repeat(40);
+		let long = |path: String| for_tool(Path::new(&path)).into_string().unwrap();
+
+		assert_eq!(long(format!(r"C:\{deep}clip.mp4")), format!(r"\\?\C:\{deep}clip.mp4"));
+		assert_eq!(long(format!(r"\\server\media\{deep}clip.mp4")), format!(r"\\?\UNC\server\media\{deep}clip.mp4"));
+		assert_eq!(long(format!("C:/{}clip.mp4", deep.replace('\\', "/"))), format!(r"\\?\C:\{deep}clip.mp4"), "separators are Windows'");
+		assert_eq!(long(format!(r"C:\{deep}..\clip.mp4")), format!(r"\\?\C:\{}clip.mp4", "nested\\".repeat(39)));
+		// Already prefixed, or relative to a drive's current directory, which the prefix can't express.
+		assert_eq!(long(format!(r"\\?\C:\{deep}clip.mp4")), format!(r"\\?\C:\{deep}clip.mp4"));
+		assert_eq!(long(format!(r"C:{deep}clip.mp4")), format!(r"C:{deep}clip.mp4"));
+	}
+
+	#[cfg(windows)]
+	#[test]
+	fn short_paths_are_left_alone() {
+		assert_eq!(for_tool(Path::new(r"\\server\media\clip.mp4")), r"\\server\media\clip.mp4");
+		assert_eq!(for_tool(Path::new(r"C:\videos\clip.mp4")), r"C:\videos\clip.mp4");
+	}
+}
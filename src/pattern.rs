@@ -0,0 +1,294 @@
+//! How the frames are named, [`ConvertOptions::frame_pattern`](crate::ConvertOptions::frame_pattern), and
+//! everything that goes from the pattern to names and back: ffmpeg's printf pattern, gifski's glob, the number in a
+//! frame's name, and the order the frames go in.
+
+use std::{
+	fmt,
+	fs,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+use crate::{ConvertError, Result};
+
+/// The directory next to the frames that [`FramePattern::consecutive`] links them into when their numbers have gaps.
+const RENUMBERED_DIR: &str = "renumbered";
+
+/// Frames numbered one after another from the first, the way ffmpeg reads them: an image2 sequence stops at the
+/// first number missing, e.g. one deleted by hand before [`Stages::Encode`](crate::Stages::Encode). Links to frames
+/// that had gaps are deleted with it.
+#[derive(Debug)]
+pub(crate) struct Sequence {
+	pub frames: Vec<PathBuf>,
+	linked: Option<PathBuf>,
+}
+
+impl Drop for Sequence {
+	fn drop(&mut self) {
+		if let Some(dir) = &self.linked { let _ = fs::remove_dir_all(dir); }
+	}
+}
+
+/// A file name with one printf integer placeholder that the frames are named by, e.g. `img_%06d.png` for
+/// `img_000001.png` on. Parsed from the pattern, and displays as it. The default is `frame%04d.png`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramePattern {
+	prefix: String,
+	/// Digits the number is zero-padded to, 0 for none.
+	width: usize,
+	suffix: String,
+}
+
+impl Default for FramePattern {
+	fn default() -> Self {
+		FramePattern { prefix: "frame".to_string(), width: 4, suffix: ".png".to_string() }
+	}
+}
+
+impl FromStr for FramePattern {
+	type Err = ConvertError;
+
+	fn from_str(s: &str) -> Result<Self> {
+		let invalid = |message: &str| Err(ConvertError::InvalidOption { option: "frame pattern", message: format!("{message}, got {s:?}") });
+		if s.contains(['/', '\\']) { return invalid("is a file name, the directory goes in --frames-dir"); }
+		if s.contains(['*', '?', '[', ']']) { return invalid("can't have *, ?, [ or ], which gifski would take for a glob"); }
+		// Up to the placeholder, the placeholder's width, and what's after it, with %% unescaped.
+		let mut parts = (String::new(), None, String::new());
+		let mut chars = s.chars().peekable();
+		while let Some(c) = chars.next() {
+			let text = if parts.1.is_none() { &mut parts.0 } else { &mut parts.2 };
+			if c != '%' {
+				text.push(c);
+				continue;
+			}
+			if chars.next_if_eq(&'%').is_some() {
+				text.push('%');
+				continue;
+			}
+			let mut digits = String::new();
+			while let Some(d) = chars.next_if(char::is_ascii_digit) { digits.push(d); }
+			if chars.next() != Some('d') { return invalid("takes %d or %0Nd for the number, and %% for a %"); }
+			let width = match digits.strip_prefix('0') {
+				None if digits.is_empty() => 0,
+				Some(width) if !width.is_empty() && !width.starts_with('0') => width.parse().unwrap_or(usize::MAX),
+				_ => return invalid("pads the number with zeros, like %06d"),
+			};
+			if parts.1.is_some() { return invalid("has more than one number in it"); }
+			if width > 9 { return invalid("pads the number to at most 9 digits"); }
+			parts.1 = Some(width);
+		}
+		let (prefix, Some(width), suffix) = parts else { return invalid("needs a %d or %0Nd for the number, like img_%06d.png") };
+		if !suffix.to_ascii_lowercase().ends_with(".png") { return invalid("ends in .png, the frames are PNGs"); }
+		if suffix.starts_with(|c: char| c.is_ascii_digit()) { return invalid("can't have a digit right after the number"); }
+		Ok(FramePattern { prefix, width, suffix })
+	}
+}
+
+impl fmt::Display for FramePattern {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.printf())
+	}
+}
+
+impl FramePattern {
+	/// The pattern ffmpeg writes and reads the frames by, e.g. `frame%04d.png`.
+	pub(crate) fn printf(&self) -> String {
+		let number = if self.width == 0 { "%d".to_string() } else { format!("%0{}d", self.width) };
+		format!("{}{number}{}", self.prefix.replace('%', "%%"), self.suffix.replace('%', "%%"))
+	}
+
+	/// The glob gifski is given the frames by, e.g. `frame*.png`.
+	pub(crate) fn glob(&self) -> String {
+		format!("{}*{}", self.prefix, self.suffix)
+	}
+
+	/// Whether the frames sort by name, so gifski can take the [`glob`](Self::glob). Unpadded numbers put 10 before 2.
+	pub(crate) fn sorts_by_name(&self) -> bool {
+		self.width > 0
+	}
+
+	/// The name of frame `number`, e.g. `frame0012.png`.
+	pub(crate) fn name(&self, number: usize) -> String {
+		format!("{}{number:0width$}{}", self.prefix, self.suffix, width = self.width)
+	}
+
+	/// The number `path` was named with, `None` if it isn't a frame.
+	pub(crate) fn number(&self, path: &Path) -> Option<usize> {
+		let digits = path.file_name()?.to_str()?.strip_prefix(&self.prefix)?.strip_suffix(&self.suffix)?;
+		if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) { return None; }
+		digits.parse().ok()
+	}
+
+	/// Where ffmpeg reads the frames numbered one after another from `first` from: the number to start at, and the
+	/// [`printf`](Self::printf) pattern in its directory. Frame 1 in the current one without a first frame.
+	pub(crate) fn sequence(&self, first: Option<&Path>) -> (usize, PathBuf) {
+		let first = first.map_or_else(|| PathBuf::from(self.name(1)), Path::to_path_buf);
+		(self.number(&first).unwrap_or(1), first.with_file_name(self.printf()))
+	}
+
+	/// `frames` as a [`Sequence`]: themselves if they're already numbered one after another, otherwise linked into
+	/// [`RENUMBERED_DIR`] next to them, numbered from 1 like [`edit::apply`](crate::edit::apply) does.
+	///
+	/// # Errors
+	/// If the frames can't be linked or copied.
+	pub(crate) fn consecutive(&self, frames: &[PathBuf]) -> Result<Sequence> {
+		let first = frames.first().and_then(|f| self.number(f));
+		let numbered = first.is_some_and(|first| frames.iter().enumerate().all(|(i, f)| self.number(f) == Some(first + i)));
+		let Some(parent) = frames.first().and_then(|f| f.parent()).filter(|_| !numbered) else {
+			return Ok(Sequence { frames: frames.to_vec(), linked: None });
+		};
+		let dir = parent.join(RENUMBERED_DIR);
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).map_err(ConvertError::io(&dir))?;
+		// Deleted again if linking fails part way.
+		let mut sequence = Sequence { frames: Vec::with_capacity(frames.len()), linked: Some(dir.clone()) };
+		for (i, from) in frames.iter().enumerate() {
+			let to = dir.join(self.name(i + 1));
+			// Copied where links aren't a thing, e.g. FAT.
+			fs::hard_link(from, &to).or_else(|_| fs::copy(from, &to).map(drop)).map_err(ConvertError::io(&to))?;
+			sequence.frames.push(to);
+		}
+		log::debug!("The frames' numbers have gaps, linked them into {} numbered one after another", dir.display());
+		Ok(sequence)
+	}
+
+	/// Frames written to `dir` so far. Cheaper than [`list`](Self::list), which sorts them.
+	pub(crate) fn count(&self, dir: &Path) -> usize {
+		fs::read_dir(dir).map_or(0, |entries| {
+			entries.filter_map(std::result::Result::ok).filter(|e| self.number(&e.path()).is_some()).count()
+		})
+	}
+
+	/// The frames in `dir`, in the order of their numbers.
+	///
+	/// # Errors
+	/// If `dir` can't be read, or two files in it are the same frame, like `img_7.png` and `img_007.png` for `img_%d.png`.
+	pub(crate) fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+		let mut frames: Vec<(usize, PathBuf)> = fs::read_dir(dir).map_err(ConvertError::io(dir))?
+			.filter_map(|e| e.ok().map(|e| e.path()))
+			.filter_map(|p| Some((self.number(&p)?, p)))
+			.collect();
+		frames.sort();
+		if let Some(same) = frames.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+			let name = |p: &Path| p.file_name().unwrap_or_default().to_string_lossy().into_owned();
+			let message = format!("{} and {} in {} are both frame {}", name(&same[0].1), name(&same[1].1), dir.display(), same[0].0);
+			return Err(ConvertError::InvalidOption { option: "frame pattern", message });
+		}
+		Ok(frames.into_iter().map(|(_, p)| p).collect())
+	}
+
+	/// Whether [`glob`](Self::glob) would give gifski other files in `dir` than the frames, e.g. `frame-old.png`.
+	pub(crate) fn glob_catches_others(&self, dir: &Path) -> bool {
+		fs::read_dir(dir).is_ok_and(|entries| entries.filter_map(std::result::Result::ok).any(|e| {
+			let path = e.path();
+			self.globbed(&path) && self.number(&path).is_none()
+		}))
+	}
+
+	/// The names of the files in `dir` that would be taken for frames, as one or by the [`glob`](Self::glob), sorted.
+	pub(crate) fn clashes(&self, dir: &Path) -> Vec<String> {
+		let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+		let mut names: Vec<String> = entries.filter_map(std::result::Result::ok)
+			.map(|e| e.path())
+			.filter(|path| self.globbed(path) || self.number(path).is_some())
+			.filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+			.collect();
+		names.sort();
+		names
+	}
+
+	/// Whether `path`'s name matches the [`glob`](Self::glob).
+	fn globbed(&self, path: &Path) -> bool {
+		path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+			n.len() >= self.prefix.len() + self.suffix.len() && n.starts_with(&self.prefix) && n.ends_with(&self.suffix)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_patterns_with_one_number() {
+		let img: FramePattern = "img_%06d.png".parse().unwrap();
+		assert_eq!((img.printf(), img.glob(), img.name(12)), ("img_%06d.png".to_string(), "img_*.png".to_string(), "img_000012.png".to_string()));
+		let plain: FramePattern = "%d.png".parse().unwrap();
+		assert_eq!((plain.name(7), plain.sorts_by_name()), ("7.png".to_string(), false));
+		let percent: FramePattern = "100%%-%03d.PNG".parse().unwrap();
+		assert_eq!((percent.printf(), percent.name(5)), ("100%%-%03d.PNG".to_string(), "100%-005.PNG".to_string()));
+		assert_eq!(FramePattern::default().printf(), "frame%04d.png");
+		assert_eq!(FramePattern::default(), "frame%04d.png".parse().unwrap());
+	}
+
+	#[test]
+	fn rejects_patterns_without_exactly_one_number() {
+		for pattern in [
+			"frame.png", "frame%04d%04d.png", "frame%s.png", "frame%4d.png", "frame%00d.png", "frame%010d.png", "frame%04d.jpg",
+			"frames/frame%04d.png", "frame%04d1.png", "frame*%04d.png", "frame%", "",
+		] {
+			assert!(pattern.parse::<FramePattern>().is_err(), "{pattern}");
+		}
+		let error = "frame%04d%04d.png".parse::<FramePattern>().unwrap_err().to_string();
+		assert!(error.contains("more than one number"), "{error}");
+	}
+
+	#[test]
+	fn only_frame_files_are_frames() {
+		let pattern = FramePattern::default();
+		for frame in ["frame0001.png", "/tmp/frames/frame12345.png"] {
+			assert!(pattern.number(Path::new(frame)).is_some(), "{frame}");
+		}
+		for other in ["frame.png", "frame0001.jpg", "frame0001a.png", "poster.png", "transforms.trf", "xframe0001.png"] {
+			assert!(pattern.number(Path::new(other)).is_none(), "{other}");
+		}
+	}
+
+	#[test]
+	fn reads_frame_numbers_back() {
+		let img: FramePattern = "img_%06d.png".parse().unwrap();
+		assert_eq!(img.number(Path::new("/tmp/frames/img_000042.png")), Some(42));
+		assert_eq!(img.number(Path::new("img_1234567.png")), Some(1_234_567), "past the padding");
+		for other in ["img_.png", "img_000042.jpg", "img_00004a.png", "frame0001.png", "ximg_000001.png", "img_000001.png.bak"] {
+			assert_eq!(img.number(Path::new(other)), None, "{other}");
+		}
+		assert_eq!(img.sequence(Some(Path::new("/tmp/frames/img_000003.png"))), (3, PathBuf::from("/tmp/frames/img_%06d.png")));
+		assert_eq!(img.sequence(None), (1, PathBuf::from("img_%06d.png")));
+	}
+
+	#[test]
+	fn lists_frames_in_number_order_and_finds_collisions() {
+		let dir = crate::test_dir("pattern");
+		for name in ["2.png", "10.png", "1.png", "poster.png", "notes.txt"] { fs::write(dir.join(name), b"").unwrap(); }
+		let plain: FramePattern = "%d.png".parse().unwrap();
+		assert_eq!(plain.list(&dir).unwrap(), ["1.png", "2.png", "10.png"].map(|n| dir.join(n)), "10 after 2");
+		assert_eq!(plain.count(&dir), 3);
+		assert!(plain.glob_catches_others(&dir), "*.png is the poster too");
+		assert!(!FramePattern::default().glob_catches_others(&dir));
+		assert_eq!(plain.clashes(&dir), ["1.png", "10.png", "2.png", "poster.png"]);
+		assert!(FramePattern::default().clashes(&dir).is_empty());
+
+		fs::write(dir.join("02.png"), b"").unwrap();
+		let error = plain.list(&dir).unwrap_err().to_string();
+		assert!(error.contains("02.png and 2.png") && error.contains("are both frame 2"), "{error}");
+	}
+
+	#[test]
+	fn frames_with_gaps_are_linked_one_after_another() {
+		let dir = crate::test_dir("pattern-gaps");
+		let pattern = FramePattern::default();
+		for i in [3, 4, 5, 9] { fs::write(dir.join(pattern.name(i)), format!("frame {i}")).unwrap(); }
+		let frames = pattern.list(&dir).unwrap();
+
+		let sequence = pattern.consecutive(&frames[..3]).unwrap();
+		assert_eq!(sequence.frames, frames[..3], "3, 4 and 5 are one after another already");
+		drop(sequence);
+
+		let sequence = pattern.consecutive(&frames).unwrap();
+		let renumbered = dir.join(RENUMBERED_DIR);
+		assert_eq!(sequence.frames, (1..=4).map(|i| renumbered.join(pattern.name(i))).collect::<Vec<_>>());
+		assert_eq!(fs::read_to_string(&sequence.frames[3]).unwrap(), "frame 9");
+		drop(sequence);
+		assert!(!renumbered.exists(), "the links go with it");
+		assert_eq!(pattern.count(&dir), 4, "the frames stay");
+	}
+}
@@ -0,0 +1,85 @@
+//! Starting commands at a lower priority, for [`BackgroundRunner`](crate::runner::BackgroundRunner).
+//!
+//! The child is lowered before it runs anything, so every thread ffmpeg and gifski start has the priority too: set
+//! between fork and exec on unix, and asked for when the process is created on Windows. It does nothing elsewhere.
+
+use std::process::Command;
+
+/// How much nicer than us the commands are on unix. 10 is what `nice` defaults to.
+#[cfg(unix)]
+const NICENESS: libc::c_int = 10;
+
+/// What [`lower`] does on this platform, for the log. `None` where it does nothing.
+pub(crate) fn applied() -> Option<&'static str> {
+	if cfg!(target_os = "linux") {
+		Some("nice 10 and the idle I/O class")
+	} else if cfg!(unix) {
+		Some("nice 10")
+	} else if cfg!(windows) {
+		Some("the below normal priority class")
+	} else {
+		None
+	}
+}
+
+/// Has `command` start at a lower CPU priority, and on Linux the idle I/O one, as [`applied`] says.
+#[cfg(unix)]
+pub(crate) fn lower(command: &mut Command) {
+	use std::os::unix::process::CommandExt;
+
+	// SAFETY: only async-signal-safe syscalls between fork and exec, and their errors are ignored, the command runs
+	// at our priority then.
+	unsafe {
+		command.pre_exec(|| {
+			libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS);
+			#[cfg(target_os = "linux")]
+			{
+				const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+				const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+				const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+				libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT);
+			}
+			Ok(())
+		});
+	}
+}
+
+#[cfg(windows)]
+pub(crate) fn lower(command: &mut Command) {
+	use std::os::windows::process::CommandExt;
+
+	const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+	command.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn lower(_: &mut Command) {}
+
+/// Half the cores, at least one, the ffmpeg threads [`ConvertOptions::background`](crate::ConvertOptions::background)
+/// defaults to.
+pub(crate) fn half_the_cores() -> usize {
+	std::thread::available_parallelism().map_or(1, |cores| (cores.get() / 2).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{BackgroundRunner, CommandLine, CommandRunner, SystemRunner};
+
+	#[test]
+	#[cfg(unix)]
+	fn commands_run_nicer_than_us() {
+		// `nice` on its own prints the niceness it runs at.
+		let niceness = |runner: &dyn CommandRunner| -> i32 {
+			String::from_utf8(runner.run(&CommandLine::new("nice")).unwrap().stdout).unwrap().trim().parse().unwrap()
+		};
+		let ours = niceness(&SystemRunner);
+		assert_eq!(niceness(&BackgroundRunner), (ours + NICENESS).min(19));
+		assert!(applied().is_some());
+	}
+
+	#[test]
+	fn at_least_one_thread() {
+		assert!(half_the_cores() >= 1);
+	}
+}
@@ -0,0 +1,268 @@
+//! Looking at the input with ffprobe before anything else is done with it.
+
+use std::{
+	fs,
+	path::Path,
+};
+use serde::Deserialize;
+use crate::{
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Result,
+};
+
+/// What ffprobe found out about the input.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct InputInfo {
+	/// ffprobe's name for the container, e.g. `mov,mp4,m4a,3gp,3g2,mj2`.
+	pub format: String,
+	/// Length in seconds, if the container knows it.
+	pub duration: Option<f64>,
+	/// The first video stream.
+	pub video: VideoStream,
+	/// Whether there's an audio stream too.
+	pub has_audio: bool,
+}
+
+/// The video stream of the input.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct VideoStream {
+	/// Index of the stream in the container.
+	pub index: usize,
+	pub codec: String,
+	pub width: u32,
+	pub height: u32,
+	/// Average fps over the whole stream.
+	pub avg_fps: Option<f32>,
+	/// The lowest fps all timestamps can be represented at, which is the real fps of constant frame rate video.
+	pub r_fps: Option<f32>,
+	/// Number of frames, if the container stores it.
+	pub frame_count: Option<u64>,
+}
+
+impl InputInfo {
+	/// Whether it's a single image rather than a video, e.g. a png or jpg.
+	#[must_use]
+	pub fn is_still(&self) -> bool {
+		// ffprobe reads images with the image2 demuxer, or a `png_pipe` like one for each format.
+		self.format == "image2" || self.format.ends_with("_pipe") || self.video.frame_count == Some(1)
+	}
+}
+
+impl VideoStream {
+	/// The fps to go by: the average, or ffmpeg's guess if there's no average.
+	#[must_use]
+	pub fn fps(&self) -> Option<f32> {
+		self.avg_fps.or(self.r_fps)
+	}
+}
+
+#[derive(Deserialize)]
+struct Output {
+	#[serde(default)]
+	streams: Vec<Stream>,
+	format: Option<Format>,
+}
+
+#[derive(Deserialize)]
+struct Stream {
+	index: usize,
+	codec_type: Option<String>,
+	codec_name: Option<String>,
+	width: Option<u32>,
+	height: Option<u32>,
+	avg_frame_rate: Option<String>,
+	r_frame_rate: Option<String>,
+	nb_frames: Option<String>,
+	#[serde(default)]
+	disposition: Disposition,
+}
+
+#[derive(Deserialize, Default)]
+struct Disposition {
+	#[serde(default)]
+	attached_pic: u8,
+}
+
+#[derive(Deserialize)]
+struct Format {
+	format_name: String,
+	duration: Option<String>,
+}
+
+/// `ffprobe -print_format json -show_format -show_streams [input options] input.mp4`
+pub(crate) fn probe_command(input: &Path, input_options: &[String]) -> CommandLine {
+	CommandLine::new("ffprobe")
+		.args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"])
+		.args(input_options)
+		.arg(input)
+}
+
+/// Checks that `input` is a readable video and finds out what's in it.
+///
+/// # Errors
+/// - [`ConvertError::InputNotFound`] if it doesn't exist, [`ConvertError::Io`] if it can't be read.
+/// - [`ConvertError::FfprobeNotInstalled`] if ffprobe can't be run.
+/// - [`ConvertError::UnrecognizedFormat`] if ffprobe can't make sense of it, e.g. a text file or a broken download.
+/// - [`ConvertError::NoVideoStream`] if there's no video in it, e.g. an mp3.
+pub fn probe_input(runner: &dyn CommandRunner, input: &Path) -> Result<InputInfo> {
+	probe_input_with(runner, input, &[])
+}
+
+/// [`probe_input`] with ffmpeg options for the input, e.g. its format, ahead of it.
+pub(crate) fn probe_input_with(runner: &dyn CommandRunner, input: &Path, input_options: &[String]) -> Result<InputInfo> {
+	if !input.is_file() { return Err(ConvertError::InputNotFound(input.to_path_buf())); }
+	fs::File::open(input).map_err(ConvertError::io(input))?;
+
+	let command = probe_command(input, input_options);
+	log::debug!("Running: {}", &command);
+	let output = runner.run(&command).map_err(ConvertError::FfprobeNotInstalled)?;
+	let unrecognized = || ConvertError::UnrecognizedFormat {
+		path: input.to_path_buf(),
+		stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+	};
+	if !output.success() { return Err(unrecognized()); }
+	let parsed: Output = serde_json::from_slice(&output.stdout).map_err(|_| unrecognized())?;
+	let format = parsed.format.ok_or_else(unrecognized)?;
+
+	let has_audio = parsed.streams.iter().any(|s| s.codec_type.as_deref() == Some("audio"));
+	// Cover art in audio files shows up as a single frame video stream.
+	let video = parsed.streams.into_iter()
+		.find(|s| s.codec_type.as_deref() == Some("video") && s.disposition.attached_pic == 0)
+		.ok_or_else(|| ConvertError::NoVideoStream(input.to_path_buf()))?;
+	let info = InputInfo {
+		format: format.format_name,
+		duration: format.duration.and_then(|d| d.parse().ok()),
+		video: VideoStream {
+			index: video.index,
+			codec: video.codec_name.unwrap_or_default(),
+			width: video.width.unwrap_or(0),
+			height: video.height.unwrap_or(0),
+			avg_fps: video.avg_frame_rate.as_deref().and_then(parse_rate),
+			r_fps: video.r_frame_rate.as_deref().and_then(parse_rate),
+			frame_count: video.nb_frames.and_then(|n| n.parse().ok()),
+		},
+		has_audio,
+	};
+	log::debug!(
+		"Input: {} {}x{}, {} seconds, {} fps",
+		info.video.codec, info.video.width, info.video.height,
+		info.duration.map_or_else(|| "?".to_string(), |d| d.to_string()),
+		info.video.fps().map_or_else(|| "?".to_string(), |f| f.to_string()),
+	);
+	Ok(info)
+}
+
+/// `30000/1001` -> `29.97`. ffprobe says `0/0` when it doesn't know.
+#[allow(clippy::cast_possible_truncation)] // fps are nowhere near f32's limits.
+fn parse_rate(rate: &str) -> Option<f32> {
+	let (num, den) = rate.split_once('/')?;
+	let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+	(num > 0.0 && den > 0.0).then(|| (num / den) as f32)
+}
+
+/// ffprobe's answer for a 2 second 640x360 24 fps h264 mp4.
+#[cfg(test)]
+pub(crate) const TEST_PROBE: &str = r#"{
+	"streams": [
+		{ "index": 0, "codec_type": "audio", "codec_name": "aac" },
+		{ "index": 1, "codec_type": "video", "codec_name": "h264", "width": 640, "height": 360,
+		  "avg_frame_rate": "24/1", "r_frame_rate": "24/1", "nb_frames": "48", "disposition": { "attached_pic": 0 } }
+	],
+	"format": { "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "2.000000" }
+}"#;
+
+/// A 24 fps h264 stream of the given size.
+#[cfg(test)]
+pub(crate) fn test_video(width: u32, height: u32) -> VideoStream {
+	VideoStream { index: 0, codec: "h264".to_string(), width, height, avg_fps: Some(24.0), r_fps: Some(24.0), frame_count: None }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	fn input(name: &str) -> std::path::PathBuf {
+		let path = crate::test_dir(&format!("probe-{name}")).join(name);
+		fs::write(&path, b"").unwrap();
+		path
+	}
+
+	#[test]
+	fn rates() {
+		assert_eq!(parse_rate("24/1"), Some(24.0));
+		assert!((parse_rate("30000/1001").unwrap() - 29.97).abs() < 0.01);
+		assert_eq!(parse_rate("0/0"), None);
+	}
+
+	#[test]
+	fn input_options_come_before_the_input() {
+		let options = ["-f", "rawvideo", "-video_size", "320x240"].map(String::from);
+		assert_eq!(probe_command(Path::new("in.yuv"), &options).args_lossy(), [
+			"-v", "error", "-print_format", "json", "-show_format", "-show_streams", "-f", "rawvideo", "-video_size", "320x240", "in.yuv",
+		]);
+	}
+
+	#[test]
+	fn finds_the_video_stream() {
+		let mock = MockRunner::new();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(TEST_PROBE));
+
+		let info = probe_input(&mock, &input("video.mp4")).unwrap();
+
+		assert_eq!(info.format, "mov,mp4,m4a,3gp,3g2,mj2");
+		assert_eq!(info.duration, Some(2.0));
+		assert_eq!((info.video.index, info.video.codec.as_str(), info.video.width, info.video.height), (1, "h264", 640, 360));
+		assert_eq!((info.video.fps(), info.video.frame_count), (Some(24.0), Some(48)));
+		assert!(info.has_audio);
+	}
+
+	#[test]
+	fn audio_with_cover_art_has_no_video() {
+		let mock = MockRunner::new();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(r#"{
+			"streams": [
+				{ "index": 0, "codec_type": "audio", "codec_name": "mp3" },
+				{ "index": 1, "codec_type": "video", "codec_name": "mjpeg", "disposition": { "attached_pic": 1 } }
+			],
+			"format": { "format_name": "mp3" }
+		}"#));
+
+		assert!(matches!(probe_input(&mock, &input("song.mp3")), Err(ConvertError::NoVideoStream(_))));
+	}
+
+	#[test]
+	fn images_are_stills() {
+		let mock = MockRunner::new();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(r#"{
+			"streams": [{ "index": 0, "codec_type": "video", "codec_name": "mjpeg", "width": 64, "height": 48, "r_frame_rate": "25/1" }],
+			"format": { "format_name": "image2", "duration": "0.040000" }
+		}"#));
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(TEST_PROBE));
+
+		let photo = probe_input(&mock, &input("photo.jpg")).unwrap();
+		assert!(photo.is_still() && !photo.has_audio);
+		assert!(!probe_input(&mock, &input("clip.mp4")).unwrap().is_still());
+	}
+
+	#[test]
+	fn garbage_is_an_unrecognized_format() {
+		let mock = MockRunner::new();
+		mock.respond("ffprobe", CommandOutput::failed(1, "notes.txt: Invalid data found when processing input"));
+
+		let err = probe_input(&mock, &input("notes.txt")).unwrap_err();
+
+		assert!(matches!(&err, ConvertError::UnrecognizedFormat { stderr, .. } if stderr.contains("Invalid data")), "{err:?}");
+	}
+
+	#[test]
+	fn missing_input_is_not_probed() {
+		let mock = MockRunner::new();
+
+		assert!(matches!(probe_input(&mock, Path::new("/no/such/video.mp4")), Err(ConvertError::InputNotFound(_))));
+		assert!(mock.calls().is_empty());
+	}
+}
@@ -0,0 +1,158 @@
+//! [`ConvertOptions::quality_map`]: the gif encoded a stretch at a time, each at its own quality, and the stretches
+//! joined with [`gif::concat`].
+
+use std::{
+	fs,
+	ops::Range,
+	path::{Path, PathBuf},
+};
+use crate::{
+	gif,
+	gifski,
+	options::QualityRegion,
+	pattern::FramePattern,
+	runner::CommandRunner,
+	ConvertError,
+	ConvertOptions,
+	Encoder,
+	Progress,
+	RegionRun,
+	Result,
+	Stages,
+};
+
+fn invalid(message: String) -> ConvertError {
+	ConvertError::InvalidOption { option: "quality map", message }
+}
+
+/// Checks the regions are in order without overlapping, and aren't combined with options they can't work with.
+///
+/// # Errors
+/// If they overlap or are out of order, or with [`Encoder::Ffmpeg`], [`ConvertOptions::compare_quality`],
+/// [`ConvertOptions::overlap`], [`ConvertOptions::chunk_seconds`] or [`Stages::Extract`].
+pub(crate) fn check(opt: &ConvertOptions) -> Result<()> {
+	let regions = &opt.quality_map;
+	if regions.is_empty() { return Ok(()); }
+	let conflict = [
+		(opt.encoder == Encoder::Ffmpeg, "--encoder ffmpeg, quality is a gifski setting"),
+		(!opt.compare_quality.is_empty(), "--compare-quality"),
+		(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds, which encode the whole gif as it's extracted"),
+		(opt.stages == Stages::Extract, "--stage extract, which doesn't encode"),
+	].into_iter().find(|(set, _)| *set);
+	if let Some((_, what)) = conflict { return Err(invalid(format!("can't be combined with {what}"))); }
+	for region in regions {
+		if region.end <= region.start { return Err(invalid(format!("{}-{} ends before it starts", region.start, region.end))); }
+		if region.quality > 100 { return Err(invalid(format!("quality {} is not between 0 and 100", region.quality))); }
+	}
+	if let Some(pair) = regions.windows(2).find(|pair| pair[1].start < pair[0].end) {
+		return Err(invalid(format!(
+			"{}-{} starts before {}-{} ends, the regions have to be in order and not overlap",
+			pair[1].start, pair[1].end, pair[0].start, pair[0].end,
+		)));
+	}
+	Ok(())
+}
+
+/// Frames encoded at one quality.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Segment {
+	pub frames: Range<usize>,
+	pub quality: u32,
+}
+
+/// The `regions` as ranges of `frame_count` frames at `fps`, with the frames between and around them at `quality`.
+///
+/// # Errors
+/// If a region ends past the last frame, or is too short to have one.
+pub(crate) fn segments(regions: &[QualityRegion], quality: u32, frame_count: usize, fps: f32) -> Result<Vec<Segment>> {
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Checked to be within the frames.
+	let frame = |seconds: f64| (seconds * f64::from(fps)).round() as usize;
+	let mut segments = Vec::new();
+	let mut next = 0;
+	for region in regions {
+		let (start, end) = (frame(region.start), frame(region.end));
+		if end > frame_count {
+			#[allow(clippy::cast_precision_loss)]
+			let duration = frame_count as f64 / f64::from(fps);
+			return Err(invalid(format!("{}-{} ends past the end of the gif, at {duration:.2}s", region.start, region.end)));
+		}
+		if start == end { return Err(invalid(format!("{}-{} is too short to have a frame at {fps} fps", region.start, region.end))); }
+		if start > next { segments.push(Segment { frames: next..start, quality }); }
+		segments.push(Segment { frames: start..end, quality: region.quality });
+		next = end;
+	}
+	if next < frame_count { segments.push(Segment { frames: next..frame_count, quality }); }
+	Ok(segments)
+}
+
+/// Encodes each of the `segments` of `frames` into a gif in `dir`, and joins them into `output`.
+///
+/// # Errors
+/// If gifski fails, or the gifs it made can't be read, joined or written.
+pub(crate) fn encode(
+	runner: &dyn CommandRunner,
+	segments: &[Segment],
+	frames: &[PathBuf],
+	fps: f32,
+	dir: &Path,
+	output: &Path,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Vec<RegionRun>> {
+	let mut runs = Vec::new();
+	let mut gifs = Vec::new();
+	for (i, segment) in segments.iter().enumerate() {
+		#[allow(clippy::cast_precision_loss)]
+		let seconds = |frame: usize| frame as f64 / f64::from(fps);
+		let (start, end) = (seconds(segment.frames.start), seconds(segment.frames.end));
+		progress(Progress::Info(format!("{start:.2}-{end:.2}s at quality {}", segment.quality)));
+		let path = dir.join(format!("region{:02}.gif", i + 1));
+		let part = &frames[segment.frames.clone()];
+		let command = gifski::listed(gifski::encode_command(segment.quality, fps, None, (dir, &FramePattern::default()), &path), dir, part);
+		gifski::encode(runner, &command, &path, part.len(), progress)?;
+		let gif = fs::read(&path).map_err(ConvertError::io(&path))?;
+		let _ = fs::remove_file(&path);
+		runs.push(RegionRun { start, end, quality: segment.quality, frame_count: part.len(), size: gif.len() as u64 });
+		gifs.push(gif);
+	}
+	let joined = gif::concat(&gifs).ok_or_else(|| invalid("gifski made gifs that couldn't be joined".to_string()))?;
+	fs::write(output, joined).map_err(ConvertError::io(output))?;
+	Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn region(start: f64, end: f64, quality: u32) -> QualityRegion {
+		QualityRegion { start, end, quality }
+	}
+
+	#[test]
+	fn the_gaps_get_the_quality() {
+		let segments = segments(&[region(1.0, 2.0, 60), region(2.0, 2.5, 100)], 80, 40, 10.0).unwrap();
+		let expected = [(0..10, 80), (10..20, 60), (20..25, 100), (25..40, 80)];
+		assert_eq!(segments, expected.map(|(frames, quality)| Segment { frames, quality }));
+		assert_eq!(super::segments(&[region(0.0, 4.0, 60)], 80, 40, 10.0).unwrap().len(), 1, "no empty gaps");
+	}
+
+	#[test]
+	fn regions_have_to_be_in_the_gif() {
+		let error = |regions: &[QualityRegion]| segments(regions, 80, 40, 10.0).unwrap_err().to_string();
+		assert_eq!(error(&[region(3.0, 5.0, 60)]), "Invalid quality map: 3-5 ends past the end of the gif, at 4.00s");
+		assert!(error(&[region(1.0, 1.01, 60)]).contains("too short to have a frame"));
+	}
+
+	#[test]
+	fn regions_are_in_order() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.quality_map = vec![region(0.0, 5.0, 60), region(5.0, 10.0, 100)];
+		assert!(check(&opt).is_ok());
+		opt.quality_map = vec![region(0.0, 5.0, 60), region(4.0, 10.0, 100)];
+		assert!(check(&opt).unwrap_err().to_string().contains("4-10 starts before 0-5 ends"));
+		opt.quality_map = vec![region(5.0, 10.0, 60), region(0.0, 5.0, 100)];
+		assert!(check(&opt).is_err());
+		opt.quality_map = vec![region(0.0, 5.0, 60)];
+		opt.encoder = Encoder::Ffmpeg;
+		assert!(check(&opt).unwrap_err().to_string().contains("--encoder ffmpeg"));
+	}
+}
@@ -0,0 +1,79 @@
+//! [`ConvertOptions::pre_quantize`](crate::ConvertOptions::pre_quantize): fewer colors in the extracted frames
+//! themselves, so noisy footage, where hardly two pixels are the same color, leaves gifski less to fit in its palettes
+//! and compresses much better.
+//!
+//! palettegen makes each frame a palette of its own and paletteuse maps the frame onto it without dithering, which
+//! would put the noise back. It's the last filter of the extraction, after the crop, scale and fps, so anything that
+//! smooths the frames, like a denoise, has done so first.
+
+use std::{collections::HashSet, path::Path};
+use crate::{
+	ffmpeg::{self, Extraction},
+	runner::{CommandLine, CommandRunner},
+};
+
+/// `split,palettegen,paletteuse` down to `colors`, a palette per frame.
+pub(crate) fn filter(colors: u32) -> String {
+	format!("split[q0][q1];[q0]palettegen=max_colors={colors}:stats_mode=single[qp];[q1][qp]paletteuse=new=1:dither=none")
+}
+
+/// `ffmpeg [seeks] -i video.mp4 -vf filters -frames:v 1 -pix_fmt rgb24 -f rawvideo -`, the first frame `extraction`
+/// extracts, pre-quantized or not.
+pub(crate) fn sample_command(input: &Path, extraction: &Extraction, quantized: bool) -> CommandLine {
+	let sample = Extraction {
+		pre_quantize: extraction.pre_quantize.filter(|_| quantized),
+		max_frames: Some(1),
+		stabilize: None,
+		start_number: None,
+		waveform: None,
+		..extraction.clone()
+	};
+	let mut command = ffmpeg::extract_command(input, Path::new(""), &sample);
+	// To stdout instead of the frames.
+	command.args.pop();
+	command.args(["-pix_fmt", "rgb24", "-f", "rawvideo", "-"])
+}
+
+/// How many different colors there are in `rgb24` pixels.
+fn count_colors(rgb24: &[u8]) -> usize {
+	rgb24.chunks_exact(3).collect::<HashSet<_>>().len()
+}
+
+/// Logs how many colors the first frame has before and after it's pre-quantized. Only worth its two extra ffmpeg runs
+/// when it's shown, so only with debug logging.
+pub(crate) fn log_colors(runner: &dyn CommandRunner, input: &Path, extraction: &Extraction) {
+	let Some(colors) = extraction.pre_quantize.filter(|_| log::log_enabled!(log::Level::Debug)) else { return };
+	let count = |quantized| {
+		let command = sample_command(input, extraction, quantized);
+		log::debug!("Running: {command}");
+		runner.run(&command).ok().filter(|o| o.success() && !o.stdout.is_empty()).map(|o| count_colors(&o.stdout))
+	};
+	match (count(false), count(true)) {
+		(Some(before), Some(after)) => log::debug!("--pre-quantize {colors}: the first frame has {before} colors, {after} once quantized"),
+		_ => log::debug!("Couldn't count the colors --pre-quantize leaves"),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ConvertOptions;
+
+	#[test]
+	fn counts_each_color_once() {
+		assert_eq!(count_colors(&[0, 0, 0, 255, 255, 255, 0, 0, 0, 0, 0, 1]), 3);
+		assert_eq!(count_colors(&[]), 0);
+	}
+
+	#[test]
+	fn samples_the_first_frame_either_way() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.pre_quantize = Some(32);
+		opt.start = Some(1.0);
+		let extraction = Extraction::new(&opt).unwrap();
+		let before = sample_command(&opt.input, &extraction, false).args_lossy();
+		assert_eq!(before, ["-i", "in.mp4", "-ss", "1", "-frames:v", "1", "-pix_fmt", "rgb24", "-f", "rawvideo", "-"]);
+		let after = sample_command(&opt.input, &extraction, true).args_lossy();
+		assert_eq!(after[4..8], ["-frames:v", "1", "-vf", &filter(32)]);
+	}
+}
@@ -0,0 +1,132 @@
+//! Encoding again with friendlier settings when gifski runs out of memory.
+
+use std::{
+	fmt,
+	fs,
+	io::Read,
+	path::{Path, PathBuf},
+};
+use crate::{ConvertError, Progress, Result, WarningKind};
+
+/// Settings gifski fell back to after running out of memory, see [`ConvertOptions::retry`](crate::ConvertOptions::retry).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Downgrade {
+	/// The fps the gif plays at, with every other frame left out to keep it the same speed.
+	pub fps: f32,
+	/// Frames in the gif.
+	pub frame_count: usize,
+	/// Width gifski scaled the frames down to, `None` if they kept theirs.
+	pub width: Option<u32>,
+}
+
+impl fmt::Display for Downgrade {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "fps {} ({} frames)", self.fps, self.frame_count)?;
+		if let Some(width) = self.width { write!(f, ", width {width}")?; }
+		Ok(())
+	}
+}
+
+/// Encodes `frames` at `fps`, at the given width.
+pub(crate) type EncodeAt<'a> = dyn FnMut(f32, Option<u32>, usize, &mut dyn FnMut(Progress)) -> Result<()> + 'a;
+
+/// Runs `encode`, and if gifski runs out of memory and `retry` is set, runs it again at half the fps, then
+/// at half the width too.
+///
+/// Halving the fps keeps every other frame, moving the rest to a subdirectory of `frames_dir` where gifski
+/// doesn't look. `frames` is updated to where they went, so it still has every extracted frame for the poster.
+pub(crate) fn encode(
+	frames_dir: &Path,
+	frames: &mut [PathBuf],
+	fps: f32,
+	retry: bool,
+	progress: &mut dyn FnMut(Progress),
+	encode: &mut EncodeAt,
+) -> Result<Option<Downgrade>> {
+	let mut result = encode(fps, None, frames.len(), progress);
+	if !retry { return result.map(|()| None); }
+
+	let width = frames.first().and_then(|f| png_width(f));
+	let mut downgrade = None;
+	for step in 0..2 {
+		if !matches!(result, Err(ConvertError::GifskiOutOfMemory { .. })) { break; }
+		let next = match (step, downgrade) {
+			(0, _) => Downgrade { fps: fps / 2.0, frame_count: drop_every_other(frames_dir, frames)?, width: None },
+			(_, Some(d)) if width.is_some() => Downgrade { width: width.map(|w| w / 2), ..d },
+			_ => break,
+		};
+		progress(Progress::warning(WarningKind::OutOfMemoryRetry, format!("gifski ran out of memory, trying again with {next}")));
+		result = encode(next.fps, next.width, next.frame_count, progress);
+		downgrade = Some(next);
+	}
+	result.map(|()| downgrade)
+}
+
+/// Moves every odd frame into `frames_dir/dropped`. Returns how many are left.
+fn drop_every_other(frames_dir: &Path, frames: &mut [PathBuf]) -> Result<usize> {
+	let dropped = frames_dir.join("dropped");
+	fs::create_dir_all(&dropped).map_err(ConvertError::io(&dropped))?;
+	for frame in frames.iter_mut().skip(1).step_by(2) {
+		let to = dropped.join(frame.file_name().unwrap_or_default());
+		fs::rename(&*frame, &to).map_err(ConvertError::io(&*frame))?;
+		*frame = to;
+	}
+	Ok(frames.len().div_ceil(2))
+}
+
+/// From a PNG's IHDR chunk, which always comes first.
+fn png_width(path: &Path) -> Option<u32> {
+	let mut header = [0; 24];
+	fs::File::open(path).ok()?.read_exact(&mut header).ok()?;
+	if &header[..8] != b"\x89PNG\r\n\x1a\n" || &header[12..16] != b"IHDR" { return None; }
+	Some(u32::from_be_bytes(header[16..20].try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn frames(name: &str, count: usize) -> (PathBuf, Vec<PathBuf>) {
+		let dir = crate::test_dir(name);
+		let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+		png.extend(640u32.to_be_bytes());
+		png.extend(360u32.to_be_bytes());
+		let frames = (1..=count).map(|i| dir.join(format!("frame{i:04}.png"))).collect::<Vec<_>>();
+		for frame in &frames { fs::write(frame, &png).unwrap(); }
+		(dir, frames)
+	}
+
+	#[test]
+	fn halves_the_fps_then_the_width() {
+		let (dir, mut frames) = frames("retry", 5);
+		let mut attempts = Vec::new();
+		let mut warnings = Vec::new();
+
+		let downgrade = encode(&dir, &mut frames, 24.0, true, &mut |p| if let Progress::Warning(w) = p { warnings.push(w.message); }, &mut |fps, width, count, _| {
+			attempts.push((fps, width, count));
+			if attempts.len() < 3 { Err(ConvertError::GifskiOutOfMemory { stderr: String::new() }) } else { Ok(()) }
+		}).unwrap();
+
+		assert_eq!(attempts, [(24.0, None, 5), (12.0, None, 3), (12.0, Some(320), 3)]);
+		assert_eq!(downgrade, Some(Downgrade { fps: 12.0, frame_count: 3, width: Some(320) }));
+		assert_eq!(warnings[1], "gifski ran out of memory, trying again with fps 12 (3 frames), width 320");
+		assert_eq!(fs::read_dir(&dir).unwrap().filter(|e| e.as_ref().unwrap().path().is_file()).count(), 3);
+		assert!(frames.iter().all(|f| f.exists()), "every frame is still somewhere: {frames:?}");
+	}
+
+	#[test]
+	fn no_retry_gives_up_at_once() {
+		let (dir, mut frames) = frames("no-retry", 2);
+		let mut attempts = 0;
+		let mut failing = |_, _, _, _: &mut dyn FnMut(Progress)| {
+			attempts += 1;
+			Err(ConvertError::GifskiOutOfMemory { stderr: String::new() })
+		};
+
+		let result = encode(&dir, &mut frames, 24.0, false, &mut |_| {}, &mut failing);
+
+		assert!(matches!(result, Err(ConvertError::GifskiOutOfMemory { .. })));
+		assert_eq!(attempts, 1);
+	}
+}
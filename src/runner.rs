@@ -0,0 +1,499 @@
+//! Running child processes.
+//!
+//! Every call to ffmpeg and gifski goes through a [`CommandRunner`], so the pipeline can be driven by
+//! [`MockRunner`] in tests without either tool installed.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	ffi::{OsStr, OsString},
+	fmt,
+	io::{self, Read, Write},
+	path::PathBuf,
+	process::{Child, Command, ExitStatus, Stdio},
+	sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex, PoisonError},
+	thread,
+	time::Duration,
+};
+
+/// A program and its arguments, and the directory to run it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+	pub program: OsString,
+	pub args: Vec<OsString>,
+	/// Where it runs, the current directory if `None`.
+	pub dir: Option<PathBuf>,
+}
+
+impl CommandLine {
+	pub fn new(program: impl Into<OsString>) -> Self {
+		CommandLine { program: program.into(), args: Vec::new(), dir: None }
+	}
+
+	/// Runs it in `dir`, so relative paths in the arguments are relative to that.
+	#[must_use]
+	pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.dir = Some(dir.into());
+		self
+	}
+
+	#[must_use]
+	pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+		self.args.push(arg.into());
+		self
+	}
+
+	#[must_use]
+	pub fn args<I: IntoIterator<Item = S>, S: Into<OsString>>(mut self, args: I) -> Self {
+		self.args.extend(args.into_iter().map(Into::into));
+		self
+	}
+
+	/// The program name as a string, for matching and messages.
+	#[must_use]
+	pub fn program_name(&self) -> String {
+		self.program.to_string_lossy().into_owned()
+	}
+
+	/// The arguments as strings, lossily converted. Handy for assertions.
+	#[must_use]
+	pub fn args_lossy(&self) -> Vec<String> {
+		self.args.iter().map(|a| a.to_string_lossy().into_owned()).collect()
+	}
+}
+
+impl fmt::Display for CommandLine {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if let Some(dir) = &self.dir { write!(f, "cd {} && ", dir.display())?; }
+		write!(f, "{}", self.program.to_string_lossy())?;
+		for arg in &self.args {
+			write!(f, " {}", arg.to_string_lossy())?;
+		}
+		Ok(())
+	}
+}
+
+/// The result of a finished command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+	/// Exit code, `None` if the process was terminated by a signal.
+	pub code: Option<i32>,
+	/// The signal that terminated the process. Always `None` outside of unix.
+	pub signal: Option<i32>,
+	pub stdout: Vec<u8>,
+	pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+	/// A successful run with the given stderr, which is where ffmpeg and gifski print most things.
+	pub fn ok_with_stderr(stderr: impl Into<Vec<u8>>) -> Self {
+		CommandOutput { code: Some(0), stderr: stderr.into(), ..CommandOutput::default() }
+	}
+
+	/// A successful run with the given stdout.
+	pub fn ok_with_stdout(stdout: impl Into<Vec<u8>>) -> Self {
+		CommandOutput { code: Some(0), stdout: stdout.into(), ..CommandOutput::default() }
+	}
+
+	/// A run that exited with `code` and the given stderr.
+	pub fn failed(code: i32, stderr: impl Into<Vec<u8>>) -> Self {
+		CommandOutput { code: Some(code), stderr: stderr.into(), ..CommandOutput::default() }
+	}
+
+	/// A run that was terminated by `signal`.
+	#[must_use]
+	pub fn killed(signal: i32) -> Self {
+		CommandOutput { signal: Some(signal), ..CommandOutput::default() }
+	}
+
+	#[must_use]
+	pub fn success(&self) -> bool {
+		self.code == Some(0)
+	}
+}
+
+/// Runs commands, either to completion or streamed.
+pub trait CommandRunner {
+	/// Runs the command to completion, capturing stdout and stderr.
+	///
+	/// # Errors
+	/// If the command couldn't be started at all, e.g. the program isn't installed.
+	fn run(&self, command: &CommandLine) -> io::Result<CommandOutput>;
+
+	/// Starts the command and returns a handle that streams its stdin, stdout and stderr while it runs.
+	///
+	/// # Errors
+	/// If the command couldn't be started at all, e.g. the program isn't installed.
+	fn spawn(&self, command: &CommandLine) -> io::Result<Box<dyn RunningCommand>>;
+}
+
+impl<T: CommandRunner + ?Sized> CommandRunner for &T {
+	fn run(&self, command: &CommandLine) -> io::Result<CommandOutput> { (**self).run(command) }
+	fn spawn(&self, command: &CommandLine) -> io::Result<Box<dyn RunningCommand>> { (**self).spawn(command) }
+}
+
+/// A command started with [`CommandRunner::spawn`], which may be waited on from another thread.
+pub trait RunningCommand: Send {
+	/// The child's stderr. Can only be taken once; whatever is read from it is not part of the final [`CommandOutput`].
+	fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>>;
+
+	/// The child's stdout, like [`take_stderr`](Self::take_stderr).
+	fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>>;
+
+	/// The child's stdin. Dropping it closes the child's stdin; if it isn't taken, [`wait`](Self::wait) closes it.
+	fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>>;
+
+	/// Waits for the command to exit, collecting whatever output wasn't streamed.
+	///
+	/// # Errors
+	/// If waiting on the process failed.
+	fn wait(self: Box<Self>) -> io::Result<CommandOutput>;
+
+	/// Something that kills the command from another thread, `None` if it can't be. Killing a command that has
+	/// already exited does nothing.
+	fn kill_handle(&self) -> Option<KillHandle> {
+		None
+	}
+}
+
+/// Kills a [`RunningCommand`], see [`RunningCommand::kill_handle`].
+pub type KillHandle = Box<dyn Fn() + Send>;
+
+/// Runs commands with [`std::process::Command`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+	fn run(&self, command: &CommandLine) -> io::Result<CommandOutput> {
+		run_command(&mut system_command(command))
+	}
+
+	fn spawn(&self, command: &CommandLine) -> io::Result<Box<dyn RunningCommand>> {
+		spawn_command(&mut system_command(command))
+	}
+}
+
+/// A [`SystemRunner`] that runs commands at a lower priority, so the machine stays usable while they work, see
+/// [`ConvertOptions::background`](crate::ConvertOptions::background). Runs them like [`SystemRunner`] on platforms
+/// where the priority can't be lowered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackgroundRunner;
+
+impl BackgroundRunner {
+	fn command(command: &CommandLine) -> Command {
+		let mut lowered = system_command(command);
+		crate::priority::lower(&mut lowered);
+		lowered
+	}
+}
+
+impl CommandRunner for BackgroundRunner {
+	fn run(&self, command: &CommandLine) -> io::Result<CommandOutput> {
+		run_command(&mut BackgroundRunner::command(command))
+	}
+
+	fn spawn(&self, command: &CommandLine) -> io::Result<Box<dyn RunningCommand>> {
+		spawn_command(&mut BackgroundRunner::command(command))
+	}
+}
+
+/// `command` as a [`Command`] to run.
+fn system_command(command: &CommandLine) -> Command {
+	let mut system = Command::new(&command.program);
+	system.args(&command.args);
+	if let Some(dir) = &command.dir { system.current_dir(dir); }
+	system
+}
+
+fn run_command(command: &mut Command) -> io::Result<CommandOutput> {
+	let output = command.output()?;
+	Ok(CommandOutput { code: output.status.code(), signal: exit_signal(output.status), stdout: output.stdout, stderr: output.stderr })
+}
+
+fn spawn_command(command: &mut Command) -> io::Result<Box<dyn RunningCommand>> {
+	let child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+	Ok(Box::new(SystemChild(Arc::new(Mutex::new(child)))))
+}
+
+/// Shared with its [`KillHandle`]s, so it can be killed while it's waited on.
+struct SystemChild(Arc<Mutex<Child>>);
+
+/// How often [`SystemChild::wait`] checks whether the child has exited.
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+impl SystemChild {
+	fn child(&self) -> std::sync::MutexGuard<'_, Child> {
+		self.0.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl RunningCommand for SystemChild {
+	fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+		self.child().stderr.take().map(|s| Box::new(s) as Box<dyn Read + Send>)
+	}
+
+	fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+		self.child().stdout.take().map(|s| Box::new(s) as Box<dyn Read + Send>)
+	}
+
+	fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+		self.child().stdin.take().map(|s| Box::new(s) as Box<dyn Write + Send>)
+	}
+
+	fn wait(mut self: Box<Self>) -> io::Result<CommandOutput> {
+		// Like `Child::wait_with_output`, but without holding the lock until it exits, so it can still be killed.
+		drop(self.take_stdin());
+		let read = |pipe: Option<Box<dyn Read + Send>>| thread::spawn(move || {
+			let mut text = Vec::new();
+			if let Some(mut pipe) = pipe { let _ = pipe.read_to_end(&mut text); }
+			text
+		});
+		let (stdout, stderr) = (read(self.take_stdout()), read(self.take_stderr()));
+		let status = loop {
+			if let Some(status) = self.child().try_wait()? { break status; }
+			thread::sleep(WAIT_INTERVAL);
+		};
+		Ok(CommandOutput {
+			code: status.code(),
+			signal: exit_signal(status),
+			stdout: stdout.join().unwrap_or_default(),
+			stderr: stderr.join().unwrap_or_default(),
+		})
+	}
+
+	fn kill_handle(&self) -> Option<KillHandle> {
+		let child = Arc::clone(&self.0);
+		Some(Box::new(move || {
+			let _ = child.lock().unwrap_or_else(PoisonError::into_inner).kill();
+		}))
+	}
+}
+
+#[cfg(unix)]
+fn exit_signal(status: ExitStatus) -> Option<i32> {
+	std::os::unix::process::ExitStatusExt::signal(&status)
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_: ExitStatus) -> Option<i32> {
+	None
+}
+
+/// Cancels a conversion from another thread, see [`Conversion::cancel_token`](crate::Conversion::cancel_token).
+///
+/// Clones share the same state, so one can be kept to cancel with and another given to the conversion.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+#[derive(Default)]
+struct CancelState {
+	cancelled: AtomicBool,
+	next_id: AtomicU64,
+	/// The commands running through a [`Cancellable`] with this token, to kill when it's cancelled.
+	running: Mutex<HashMap<u64, KillHandle>>,
+}
+
+impl CancelToken {
+	#[must_use]
+	pub fn new() -> Self {
+		CancelToken::default()
+	}
+
+	/// Kills the commands running with this token, and stops any more from starting.
+	pub fn cancel(&self) {
+		let running = self.running();
+		self.0.cancelled.store(true, Ordering::SeqCst);
+		for kill in running.values() { kill(); }
+	}
+
+	#[must_use]
+	pub fn is_cancelled(&self) -> bool {
+		self.0.cancelled.load(Ordering::SeqCst)
+	}
+
+	fn running(&self) -> std::sync::MutexGuard<'_, HashMap<u64, KillHandle>> {
+		self.0.running.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Keeps `kill` to call on [`cancel`](Self::cancel). Calls it straight away if that already happened.
+	fn register(&self, kill: Option<KillHandle>) -> Option<u64> {
+		let mut running = self.running();
+		if self.is_cancelled() {
+			if let Some(kill) = kill { kill(); }
+			return None;
+		}
+		let id = self.0.next_id.fetch_add(1, Ordering::SeqCst);
+		if let Some(kill) = kill { running.insert(id, kill); }
+		Some(id)
+	}
+}
+
+impl fmt::Debug for CancelToken {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("CancelToken").field(&self.is_cancelled()).finish()
+	}
+}
+
+/// The error a [`Cancellable`] command fails with once its token is cancelled.
+fn cancelled() -> io::Error {
+	io::Error::new(io::ErrorKind::Interrupted, "cancelled")
+}
+
+/// A [`CommandRunner`] whose commands are killed when `token` is cancelled. After that nothing starts, and every
+/// command fails with [`io::ErrorKind::Interrupted`].
+///
+/// Commands it [`run`](CommandRunner::run)s are spawned, so they can be killed too.
+pub struct Cancellable<R> {
+	pub inner: R,
+	pub token: CancelToken,
+}
+
+impl<R: CommandRunner> CommandRunner for Cancellable<R> {
+	fn run(&self, command: &CommandLine) -> io::Result<CommandOutput> {
+		self.spawn(command)?.wait()
+	}
+
+	fn spawn(&self, command: &CommandLine) -> io::Result<Box<dyn RunningCommand>> {
+		if self.token.is_cancelled() { return Err(cancelled()); }
+		let child = self.inner.spawn(command)?;
+		let id = self.token.register(child.kill_handle());
+		Ok(Box::new(CancellableChild { child, id, token: self.token.clone() }))
+	}
+}
+
+struct CancellableChild {
+	child: Box<dyn RunningCommand>,
+	/// `None` if the token was cancelled before it was registered.
+	id: Option<u64>,
+	token: CancelToken,
+}
+
+impl RunningCommand for CancellableChild {
+	fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+		self.child.take_stderr()
+	}
+
+	fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+		self.child.take_stdout()
+	}
+
+	fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+		self.child.take_stdin()
+	}
+
+	fn wait(self: Box<Self>) -> io::Result<CommandOutput> {
+		let CancellableChild { child, id, token } = *self;
+		let output = child.wait();
+		if let Some(id) = id { token.running().remove(&id); }
+		if token.is_cancelled() { Err(cancelled()) } else { output }
+	}
+
+	fn kill_handle(&self) -> Option<KillHandle> {
+		self.child.kill_handle()
+	}
+}
+
+type Response = Box<dyn FnMut(&CommandLine) -> io::Result<CommandOutput> + Send>;
+
+/// A [`CommandRunner`] that records every command and answers with canned output instead of running anything.
+///
+/// Responses are queued per program name and used in order; a program without a queued response
+/// "succeeds" with empty output.
+///
+/// ```
+/// use gifski_ffmpeg::runner::{CommandLine, CommandOutput, CommandRunner, MockRunner};
+///
+/// let mock = MockRunner::new();
+/// mock.respond("ffmpeg", CommandOutput::failed(1, "no such file"));
+/// let output = mock.run(&CommandLine::new("ffmpeg").arg("-i").arg("missing.mp4")).unwrap();
+/// assert_eq!(output.code, Some(1));
+/// assert_eq!(mock.calls()[0].args_lossy(), ["-i", "missing.mp4"]);
+/// ```
+#[derive(Default)]
+pub struct MockRunner {
+	calls: Mutex<Vec<CommandLine>>,
+	responses: Mutex<HashMap<String, VecDeque<Response>>>,
+}
+
+impl MockRunner {
+	#[must_use]
+	pub fn new() -> Self {
+		MockRunner::default()
+	}
+
+	/// Queues `output` as the answer to the next call of `program`.
+	pub fn respond(&self, program: &str, output: CommandOutput) {
+		self.respond_with(program, move |_| Ok(output.clone()));
+	}
+
+	/// Queues `f` as the answer to the next call of `program`. It gets the command line, so it can
+	/// fake side effects like writing frames.
+	pub fn respond_with(&self, program: &str, f: impl FnMut(&CommandLine) -> io::Result<CommandOutput> + Send + 'static) {
+		self.responses.lock().unwrap_or_else(PoisonError::into_inner)
+			.entry(program.to_string())
+			.or_default()
+			.push_back(Box::new(f));
+	}
+
+	/// Every command run so far, in order.
+	pub fn calls(&self) -> Vec<CommandLine> {
+		self.calls.lock().unwrap_or_else(PoisonError::into_inner).clone()
+	}
+
+	/// The commands run so far for one program.
+	pub fn calls_to(&self, program: &str) -> Vec<CommandLine> {
+		self.calls().into_iter().filter(|c| c.program == OsStr::new(program)).collect()
+	}
+
+	fn answer(&self, command: &CommandLine) -> io::Result<CommandOutput> {
+		self.calls.lock().unwrap_or_else(PoisonError::into_inner).push(command.clone());
+		let response = self.responses.lock().unwrap_or_else(PoisonError::into_inner)
+			.get_mut(&command.program_name())
+			.and_then(VecDeque::pop_front);
+		match response {
+			Some(mut f) => f(command),
+			None => Ok(CommandOutput { code: Some(0), ..CommandOutput::default() }),
+		}
+	}
+}
+
+impl CommandRunner for MockRunner {
+	fn run(&self, command: &CommandLine) -> io::Result<CommandOutput> {
+		self.answer(command)
+	}
+
+	fn spawn(&self, command: &CommandLine) -> io::Result<Box<dyn RunningCommand>> {
+		let mut output = self.answer(command)?;
+		let stderr = std::mem::take(&mut output.stderr);
+		let stdout = std::mem::take(&mut output.stdout);
+		Ok(Box::new(MockChild { stderr: Some(stderr), stdout: Some(stdout), output }))
+	}
+}
+
+/// A finished mock command; its stdout and stderr are streamed from the canned output, its stdin is discarded.
+struct MockChild {
+	stderr: Option<Vec<u8>>,
+	stdout: Option<Vec<u8>>,
+	output: CommandOutput,
+}
+
+impl RunningCommand for MockChild {
+	fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+		self.stderr.take().map(|s| Box::new(io::Cursor::new(s)) as Box<dyn Read + Send>)
+	}
+
+	fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+		self.stdout.take().map(|s| Box::new(io::Cursor::new(s)) as Box<dyn Read + Send>)
+	}
+
+	fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+		Some(Box::new(io::sink()))
+	}
+
+	fn wait(self: Box<Self>) -> io::Result<CommandOutput> {
+		let MockChild { stderr, stdout, mut output } = *self;
+		// Not streamed, so it's part of the output like with a real child.
+		output.stderr = stderr.unwrap_or_default();
+		output.stdout = stdout.unwrap_or_default();
+		Ok(output)
+	}
+}
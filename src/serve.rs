@@ -0,0 +1,182 @@
+//! `--serve`: a page on localhost showing the gif, which reloads it whenever it changes.
+//!
+//! It keeps serving the same file after the conversion, so running again with other options in another terminal,
+//! to the same output, updates the page. Only one request is handled at a time, it's for one browser tab.
+
+use std::{
+	fs,
+	io::{self, BufRead, BufReader, Write},
+	net::{Ipv4Addr, TcpListener, TcpStream},
+	path::Path,
+	sync::atomic::{AtomicBool, Ordering},
+	thread,
+	time::{Duration, UNIX_EPOCH},
+};
+
+/// Set by Ctrl+C, to stop serving.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// How often Ctrl+C is checked for while there are no connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often the page asks whether the gif changed.
+const PAGE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a connection gets to send its request, so a stuck one doesn't hold up the rest.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Listens on `port` of localhost only, or a free port if `None`. Done before converting, so a port that's taken
+/// is reported before the wait.
+///
+/// # Errors
+/// If the port can't be listened on.
+pub fn bind(port: Option<u16>) -> io::Result<TcpListener> {
+	TcpListener::bind((Ipv4Addr::LOCALHOST, port.unwrap_or(0)))
+}
+
+/// `http://127.0.0.1:PORT/`
+pub fn url(listener: &TcpListener) -> io::Result<String> {
+	Ok(format!("http://{}/", listener.local_addr()?))
+}
+
+/// Serves `gif` until Ctrl+C.
+///
+/// # Errors
+/// If the listener stops working. Failed connections are only logged.
+pub fn serve(listener: &TcpListener, gif: &Path) -> io::Result<()> {
+	#[cfg(unix)]
+	{
+		extern "C" fn interrupted(_: libc::c_int) { INTERRUPTED.store(true, Ordering::SeqCst); }
+		// SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+		unsafe { libc::signal(libc::SIGINT, interrupted as *const () as libc::sighandler_t) };
+	}
+	// Elsewhere Ctrl+C ends the process, which has nothing to clean up.
+	serve_until(listener, gif, &INTERRUPTED)
+}
+
+fn serve_until(listener: &TcpListener, gif: &Path, stop: &AtomicBool) -> io::Result<()> {
+	// Not blocking, so `stop` is seen between connections.
+	listener.set_nonblocking(true)?;
+	while !stop.load(Ordering::SeqCst) {
+		match listener.accept() {
+			Ok((stream, _)) => {
+				if let Err(e) = respond(stream, gif) { log::debug!("Couldn't answer a request: {e}"); }
+			}
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(())
+}
+
+fn respond(mut stream: TcpStream, gif: &Path) -> io::Result<()> {
+	// Accepted sockets take after the listener on some platforms.
+	stream.set_nonblocking(false)?;
+	stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+	let mut reader = BufReader::new(&stream);
+	let mut request = String::new();
+	reader.read_line(&mut request)?;
+	// The headers don't change the answer, but are read so the browser doesn't see the connection reset.
+	let mut header = String::new();
+	while reader.read_line(&mut header)? > 2 { header.clear(); }
+
+	let path = request.split_whitespace().nth(1).unwrap_or("/");
+	let path = path.split('?').next().unwrap_or(path);
+	let (status, content_type, body) = match path {
+		"/" => ("200 OK", "text/html; charset=utf-8", page(gif).into_bytes()),
+		"/gif" => match fs::read(gif) {
+			Ok(bytes) => ("200 OK", "image/gif", bytes),
+			Err(_) => ("404 Not Found", "text/plain", b"no gif yet".to_vec()),
+		},
+		"/version" => ("200 OK", "text/plain", version(gif).into_bytes()),
+		_ => ("404 Not Found", "text/plain", b"not found".to_vec()),
+	};
+	write!(
+		stream,
+		"HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+		body.len(),
+	)?;
+	stream.write_all(&body)?;
+	stream.flush()
+}
+
+/// Changes whenever the gif is written again.
+fn version(gif: &Path) -> String {
+	fs::metadata(gif)
+		.and_then(|m| Ok((m.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos(), m.len())))
+		.map_or_else(|_| "none".to_string(), |(modified, len)| format!("{modified}-{len}"))
+}
+
+/// The gif on a dark background, reloaded when `/version` changes.
+fn page(gif: &Path) -> String {
+	let name = gif.file_name().unwrap_or(gif.as_os_str()).to_string_lossy();
+	let name = name.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+	format!(
+		r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{name}</title>
+<style>body {{ margin: 0; min-height: 100vh; display: grid; place-items: center; background: #1e1e1e; color: #ccc; font: 14px sans-serif; }} img {{ max-width: 100vw; max-height: 90vh; }}</style>
+</head>
+<body>
+<figure><img id="gif" src="/gif?v={version}" alt="{name}"><figcaption id="caption">{name}</figcaption></figure>
+<script>
+let version = "{version}";
+setInterval(async () => {{
+	try {{
+		const latest = await (await fetch("/version", {{ cache: "no-store" }})).text();
+		if (latest !== version) {{
+			version = latest;
+			document.getElementById("gif").src = "/gif?v=" + encodeURIComponent(latest);
+			document.getElementById("caption").textContent = document.getElementById("gif").alt + ", updated " + new Date().toLocaleTimeString();
+		}}
+	}} catch (e) {{}}
+}}, {poll});
+</script>
+</body>
+</html>
+"#,
+		version = version(gif),
+		poll = PAGE_POLL_INTERVAL.as_millis(),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Read;
+	use super::*;
+
+	fn get(url: &str, path: &str) -> String {
+		let mut stream = TcpStream::connect(url.trim_start_matches("http://").trim_end_matches('/')).unwrap();
+		write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+		let mut response = Vec::new();
+		stream.read_to_end(&mut response).unwrap();
+		String::from_utf8_lossy(&response).into_owned()
+	}
+
+	#[test]
+	fn serves_the_page_and_the_gif_until_stopped() {
+		let gif = crate::test_dir("serve").join("serve.gif");
+		fs::write(&gif, b"GIF89a first").unwrap();
+		let listener = bind(None).unwrap();
+		let url = url(&listener).unwrap();
+		assert!(url.starts_with("http://127.0.0.1:"), "{url}");
+		let stop = AtomicBool::new(false);
+
+		thread::scope(|s| {
+			let server = s.spawn(|| serve_until(&listener, &gif, &stop));
+			let page = get(&url, "/");
+			assert!(page.starts_with("HTTP/1.1 200 OK\r\n") && page.contains("text/html") && page.contains("<img id=\"gif\""), "{page}");
+			assert!(get(&url, "/gif?v=1").ends_with("\r\n\r\nGIF89a first"));
+			let before = get(&url, "/version");
+			fs::write(&gif, b"GIF89a second, longer").unwrap();
+			assert_ne!(get(&url, "/version"), before, "a new gif has a new version");
+			assert!(get(&url, "/favicon.ico").starts_with("HTTP/1.1 404"));
+			stop.store(true, Ordering::SeqCst);
+			server.join().unwrap().unwrap();
+		});
+		fs::remove_file(gif).unwrap();
+	}
+}
@@ -0,0 +1,133 @@
+//! `--contact-sheet`: one PNG of evenly spaced thumbnails of the gif, each labelled with when it is, for finding a
+//! clip without opening every gif.
+//!
+//! Made from the extracted frames, so the video isn't decoded again: ffmpeg scales and labels each, concatenates
+//! them into a stream of frames and tiles that into one image.
+
+use std::path::{Path, PathBuf};
+use crate::{
+	ffmpeg,
+	options::Grid,
+	paths,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Progress,
+	Result,
+	WarningKind,
+};
+
+/// The [`ConvertOptions::contact_sheet`](crate::ConvertOptions::contact_sheet) grid when none is given.
+pub const DEFAULT_CONTACT_SHEET: Grid = Grid { columns: 4, rows: 4 };
+
+/// Widest a thumbnail gets, smaller frames are left as they are.
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// `<output stem>-sheet.png`, next to the gif.
+pub(crate) fn sheet_path(output: &Path) -> PathBuf {
+	let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+	output.with_file_name(format!("{stem}-sheet.png"))
+}
+
+/// One frame from the middle of each of `cells` equal parts of `count` frames.
+fn sample(count: usize, cells: usize) -> Vec<usize> {
+	(0..cells).map(|i| (2 * i + 1) * count / (2 * cells)).collect()
+}
+
+/// `m:ss.s` into the gif.
+fn timestamp(seconds: f64) -> String {
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+	let minutes = (seconds / 60.0).floor() as u64;
+	#[allow(clippy::cast_precision_loss)]
+	let seconds = seconds - (minutes * 60) as f64;
+	format!("{minutes}:{seconds:04.1}")
+}
+
+/// `ffmpeg -i frame.png ... -filter_complex scale,drawtext,...,concat,tile -frames:v 1 sheet.png`, `frames` labelled
+/// with the times they're at.
+pub(crate) fn sheet_command(grid: Grid, frames: &[(&Path, f64)], sheet: &Path) -> CommandLine {
+	let mut command = CommandLine::new("ffmpeg").args(["-v", "error", "-y"]);
+	let mut chains = Vec::new();
+	for (i, (frame, at)) in frames.iter().enumerate() {
+		command = command.arg("-i").arg(paths::for_tool(frame));
+		chains.push(format!(
+			"[{i}:v]scale='min({THUMBNAIL_WIDTH},iw)':-2:flags=lanczos,drawtext=text={}:expansion=none:x=6:y=h-th-6:fontsize=14:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=3[t{i}]",
+			ffmpeg::escape_option(&timestamp(*at)),
+		));
+	}
+	let pads = (0..frames.len()).map(|i| format!("[t{i}]")).collect::<Vec<_>>().concat();
+	chains.push(format!("{pads}concat=n={}:v=1:a=0,tile={}x{}:padding=4:margin=4", frames.len(), grid.columns, grid.rows));
+	command
+		.arg("-filter_complex").arg(chains.join(";"))
+		.args(["-frames:v", "1"])
+		.arg(paths::for_tool(sheet))
+}
+
+/// Writes the contact sheet for the gif at `output` from its `frames`, played at `fps`. Returns where it went, or
+/// `None` with a warning if there are fewer frames than cells.
+///
+/// # Errors
+/// If ffmpeg fails.
+pub(crate) fn write_sheet(
+	runner: &dyn CommandRunner,
+	grid: Grid,
+	frames: &[PathBuf],
+	fps: f32,
+	output: &Path,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Option<PathBuf>> {
+	let cells = grid.columns as usize * grid.rows as usize;
+	if frames.len() < cells {
+		progress(Progress::warning(WarningKind::ContactSheetSkipped, format!(
+			"Skipped the contact sheet, the gif has {} frames, fewer than the {cells} a {}x{} sheet shows",
+			frames.len(), grid.columns, grid.rows,
+		)));
+		return Ok(None);
+	}
+	#[allow(clippy::cast_precision_loss)]
+	let picked: Vec<(&Path, f64)> = sample(frames.len(), cells).into_iter()
+		.map(|i| (frames[i].as_path(), i as f64 / f64::from(fps)))
+		.collect();
+	let sheet = sheet_path(output);
+	let command = sheet_command(grid, &picked, &sheet);
+	log::debug!("Running: {command}");
+	let result = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	if !result.success() {
+		return Err(ConvertError::FfmpegFailed { code: result.code, stderr: String::from_utf8_lossy(&result.stderr).into_owned() });
+	}
+	Ok(Some(sheet))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::MockRunner;
+
+	#[test]
+	fn samples_the_middle_of_each_part() {
+		assert_eq!(sample(16, 4), [2, 6, 10, 14]);
+		assert_eq!(sample(4, 4), [0, 1, 2, 3]);
+		assert_eq!(timestamp(75.25), "1:15.2");
+		assert_eq!(timestamp(2.0), "0:02.0");
+	}
+
+	#[test]
+	fn labels_and_tiles_the_frames() {
+		let frames = [(Path::new("/tmp/frames/frame0002.png"), 0.5), (Path::new("/tmp/frames/frame0006.png"), 1.5)];
+		let args = sheet_command(Grid { columns: 2, rows: 1 }, &frames, Path::new("/tmp/out-sheet.png")).args_lossy();
+		assert_eq!(args[..7], ["-v", "error", "-y", "-i", "/tmp/frames/frame0002.png", "-i", "/tmp/frames/frame0006.png"]);
+		let graph: Vec<&str> = args[8].split(';').collect();
+		assert!(graph[1].starts_with("[1:v]scale='min(320,iw)':-2:flags=lanczos,drawtext=text=0\\\\:01.5:"), "{}", graph[1]);
+		assert_eq!(graph[2], "[t0][t1]concat=n=2:v=1:a=0,tile=2x1:padding=4:margin=4");
+		assert_eq!(args[9..], ["-frames:v", "1", "/tmp/out-sheet.png"]);
+	}
+
+	#[test]
+	fn too_few_frames_is_a_warning() {
+		let frames = vec![PathBuf::from("frame0001.png"); 3];
+		let mut warnings = Vec::new();
+		let sheet = write_sheet(&MockRunner::new(), DEFAULT_CONTACT_SHEET, &frames, 10.0, Path::new("out.gif"), &mut |p| warnings.push(p)).unwrap();
+		assert!(sheet.is_none());
+		assert!(matches!(&warnings[..], [Progress::Warning(w)] if w.kind == WarningKind::ContactSheetSkipped && w.message.contains("3 frames, fewer than the 16")));
+		assert_eq!(sheet_path(Path::new("/gifs/clip.gif")), Path::new("/gifs/clip-sheet.png"));
+	}
+}
@@ -0,0 +1,119 @@
+//! Crossfading the end of the gif into its start, for `--loop-smooth`.
+//!
+//! Each of the last frames is blended with one of the first, more of the first the closer it is to the end, and
+//! the first ones are then dropped: the crossfade leads into the frame after them, which is where the gif starts
+//! again.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+use crate::{
+	paths,
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Progress,
+	Result,
+};
+
+/// Frames crossfaded when `--loop-smooth` is given without a number.
+pub const DEFAULT_LOOP_SMOOTH_FRAMES: usize = 8;
+
+/// `ffmpeg -i last.png -i first.png -filter_complex blend output.png`, `weight` of the way from `last` to `first`.
+pub(crate) fn blend_command(last: &Path, first: &Path, weight: f64, output: &Path) -> CommandLine {
+	CommandLine::new("ffmpeg")
+		.args(["-v", "error", "-y"])
+		.arg("-i").arg(paths::for_tool(last))
+		.arg("-i").arg(paths::for_tool(first))
+		.arg("-filter_complex").arg(format!("[0:v][1:v]blend=all_expr=A*(1-{weight})+B*{weight}"))
+		.args(["-frames:v", "1"])
+		.arg(paths::for_tool(output))
+}
+
+/// Crossfades the last `count` of `frames` into the first `count`, then deletes the first `count`. Returns the
+/// frames left, `count` fewer.
+///
+/// # Errors
+/// [`ConvertError::InvalidOption`] if `count` is more than half the frames, there wouldn't be enough frames between
+/// the two ends. Otherwise if ffmpeg fails. A `count` of 0 is refused with the other options, before extracting.
+pub(crate) fn smooth_loop(
+	runner: &dyn CommandRunner,
+	mut frames: Vec<PathBuf>,
+	count: usize,
+	progress: &mut dyn FnMut(Progress),
+) -> Result<Vec<PathBuf>> {
+	if count * 2 > frames.len() {
+		let message = format!("can crossfade 1 to {} of the {} frames, not {count}", frames.len() / 2, frames.len());
+		return Err(ConvertError::InvalidOption { option: "loop smooth", message });
+	}
+	let tail = frames.len() - count;
+	for i in 0..count {
+		#[allow(clippy::cast_precision_loss)]
+		let weight = (i + 1) as f64 / (count + 1) as f64;
+		let (last, first) = (&frames[tail + i], &frames[i]);
+		let blended = last.with_file_name("blended.png");
+		let command = blend_command(last, first, (weight * 1000.0).round() / 1000.0, &blended);
+		log::debug!("Running: {command}");
+		let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+		if !output.success() {
+			return Err(ConvertError::FfmpegFailed { code: output.code, stderr: String::from_utf8_lossy(&output.stderr).into_owned() });
+		}
+		fs::rename(&blended, last).map_err(ConvertError::io(last))?;
+	}
+	for frame in frames.drain(..count) {
+		fs::remove_file(&frame).map_err(ConvertError::io(frame))?;
+	}
+	progress(Progress::Info(format!("Crossfaded the last {count} frames into the first {count}, the gif is {count} frames shorter")));
+	Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	fn frames(name: &str, count: usize) -> Vec<PathBuf> {
+		let dir = crate::test_dir(&format!("smooth-{name}"));
+		(1..=count).map(|i| {
+			let frame = dir.join(format!("frame{i:04}.png"));
+			fs::write(&frame, format!("frame {i}")).unwrap();
+			frame
+		}).collect()
+	}
+
+	#[test]
+	fn the_end_fades_into_the_start() {
+		let frames = frames("fade", 10);
+		let mock = MockRunner::new();
+		for _ in 0..3 {
+			mock.respond_with("ffmpeg", |command| {
+				let [.., last, _, first, _, _, _, _, output] = &command.args_lossy()[..] else { unreachable!() };
+				fs::write(output, format!("{} + {}", fs::read_to_string(last)?, fs::read_to_string(first)?))?;
+				Ok(CommandOutput::ok_with_stderr(""))
+			});
+		}
+		let mut events = Vec::new();
+
+		let left = smooth_loop(&mock, frames.clone(), 3, &mut |p| events.push(p)).unwrap();
+
+		assert_eq!(left, frames[3..]);
+		assert!(!frames[0].exists() && !frames[0].with_file_name("blended.png").exists());
+		assert_eq!(fs::read_to_string(&frames[7]).unwrap(), "frame 8 + frame 1");
+		assert_eq!(fs::read_to_string(&frames[9]).unwrap(), "frame 10 + frame 3");
+		let filters: Vec<String> = mock.calls().iter().map(|c| c.args_lossy()[8].clone()).collect();
+		assert_eq!(filters, [
+			"[0:v][1:v]blend=all_expr=A*(1-0.25)+B*0.25",
+			"[0:v][1:v]blend=all_expr=A*(1-0.5)+B*0.5",
+			"[0:v][1:v]blend=all_expr=A*(1-0.75)+B*0.75",
+		]);
+		assert!(matches!(&events[..], [Progress::Info(m)] if m.ends_with("the gif is 3 frames shorter")));
+	}
+
+	#[test]
+	fn at_most_half_the_frames() {
+		let frames = frames("half", 9);
+		let err = smooth_loop(&MockRunner::new(), frames.clone(), 5, &mut |_| {}).unwrap_err();
+		assert_eq!(err.to_string(), "Invalid loop smooth: can crossfade 1 to 4 of the 9 frames, not 5");
+		assert!(frames.iter().all(|f| f.exists()), "nothing is touched");
+	}
+}
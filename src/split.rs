@@ -0,0 +1,179 @@
+//! Cutting a long input into several gifs, one after the other, behind `--split-every`.
+
+use std::{
+	ffi::OsString,
+	fs,
+	path::PathBuf,
+};
+use serde::Serialize;
+use crate::{
+	ffmpeg, output, probe,
+	runner::CommandRunner,
+	Conversion, ConvertError, ConvertOptions, Progress, Result, Stages,
+};
+
+/// A last part shorter than this, in seconds, is added to the one before it instead.
+pub const MIN_PART_SECONDS: f64 = 2.0;
+
+/// One gif of a split. Serializes to what `--split-every --json` prints.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct Part {
+	/// From 1.
+	pub number: usize,
+	/// Seconds into the input the part starts and ends at.
+	pub start: f64,
+	pub end: f64,
+	pub output: PathBuf,
+	/// Size of the gif in bytes.
+	pub size: Option<u64>,
+}
+
+/// Converts consecutive `every` second parts of `options.input`, or of the part of it the trim options pick, to
+/// `<name>-part01.gif`, `<name>-part02.gif` and on. `<name>` is the input's name, or the output's if one is given.
+/// `on_progress` gets the index of the part each event is about and how many parts there are.
+///
+/// # Errors
+/// [`ConvertError::InvalidOption`] for options that make more than one gif or combine inputs, and if the input
+/// doesn't say how long it is. Otherwise whatever the first failing part returned, the parts before it are kept.
+pub fn run(
+	options: &ConvertOptions,
+	every: f64,
+	runner: &dyn CommandRunner,
+	mut on_progress: impl FnMut(usize, usize, Progress),
+) -> Result<Vec<Part>> {
+	let invalid = |message: String| ConvertError::InvalidOption { option: "split every", message };
+	if every <= 0.0 { return Err(invalid("needs a length of more than 0 seconds".to_string())); }
+	if let Some((_, name)) = [
+		(!options.concat.is_empty(), "--concat"),
+		(options.grid.is_some(), "--grid"),
+		(!options.compare_quality.is_empty(), "--compare-quality"),
+		(options.stages != Stages::All, "--stage"),
+		(options.max_duration.is_some(), "--max-duration"),
+	].into_iter().find(|(set, _)| *set) {
+		return Err(invalid(format!("can't be used with {name}")));
+	}
+	let mut options = options.clone();
+	options.make_absolute(&std::env::current_dir().map_err(ConvertError::io("."))?);
+	let options = &options;
+	if !options.input.is_file() { return Err(ConvertError::InputNotFound(options.input.clone())); }
+
+	let extraction = ffmpeg::Extraction::new(options)?;
+	let info = probe::probe_input_with(runner, &options.input, &extraction.input_options)?;
+	let start = info.video.fps().map_or(extraction.start.unwrap_or(0.0), |fps| extraction.start_seconds(options, f64::from(fps)));
+	let seconds = extraction.seconds(options, &info)
+		.ok_or_else(|| invalid(format!("{} doesn't say how long it is, so it can't be split", options.input.display())))?;
+	let windows = windows(start, seconds, every);
+	log::debug!("Splitting into {} parts: {windows:?}", windows.len());
+
+	let count = windows.len();
+	let names = names(options, count)?;
+	let mut parts = Vec::new();
+	for (i, ((start, end), output)) in windows.into_iter().zip(names).enumerate() {
+		let part = ConvertOptions {
+			start: Some(start).filter(|&s| s > 0.0),
+			end: None,
+			duration: Some(end - start),
+			start_frame: None,
+			end_frame: None,
+			output: Some(output.into_os_string()),
+			..options.clone()
+		};
+		let report = Conversion::new(part).runner(runner).on_progress(|p| on_progress(i, count, p)).run()?;
+		parts.push(Part { number: i + 1, start, end, size: fs::metadata(&report.output).ok().map(|m| m.len()), output: report.output });
+	}
+	Ok(parts)
+}
+
+/// `(start, end)` of each part of `seconds` from `start`, with a last part shorter than [`MIN_PART_SECONDS`]
+/// added to the one before it.
+fn windows(start: f64, seconds: f64, every: f64) -> Vec<(f64, f64)> {
+	let mut windows: Vec<(f64, f64)> = Vec::new();
+	for from in (0..).map(|i| f64::from(i) * every).take_while(|&from| from < seconds) {
+		windows.push((start + from, start + (from + every).min(seconds)));
+	}
+	if let [.., _, (from, to)] = windows[..] {
+		if to - from < MIN_PART_SECONDS {
+			windows.pop();
+			if let Some(last) = windows.last_mut() { last.1 = to; }
+		}
+	}
+	windows
+}
+
+/// Where each of `count` parts goes, numbered with at least two digits.
+fn names(options: &ConvertOptions, count: usize) -> Result<Vec<PathBuf>> {
+	let name = options.input.file_stem().ok_or_else(|| ConvertError::InputNotFound(options.input.clone()))?;
+	let whole = output::parse_output(&output::output_base(&options.input, options.resolve_symlinks)?, options.output.as_deref(), name);
+	let stem = if options.output.is_some() { whole.file_stem().unwrap_or(name) } else { name };
+	let width = count.to_string().len().max(2);
+	Ok((1..=count).map(|n| {
+		let mut file_name = OsString::from(stem);
+		file_name.push(format!("-part{n:0width$}."));
+		file_name.push(whole.extension().unwrap_or_default());
+		whole.with_file_name(file_name)
+	}).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	#[test]
+	fn a_short_last_part_joins_the_one_before() {
+		assert_eq!(windows(0.0, 45.0, 15.0), [(0.0, 15.0), (15.0, 30.0), (30.0, 45.0)]);
+		assert_eq!(windows(10.0, 31.5, 15.0), [(10.0, 25.0), (25.0, 41.5)]);
+		assert_eq!(windows(0.0, 32.0, 15.0), [(0.0, 15.0), (15.0, 30.0), (30.0, 32.0)]);
+		assert_eq!(windows(0.0, 1.0, 15.0), [(0.0, 1.0)], "a short input is still one part");
+	}
+
+	#[test]
+	fn parts_are_numbered_after_the_input_or_output() {
+		let dir = crate::test_dir("split-names");
+		fs::write(dir.join("talk.mp4"), b"").unwrap();
+		let mut options = ConvertOptions::new(dir.join("talk.mp4"));
+		assert_eq!(names(&options, 3).unwrap()[2], dir.join("talk-part03.gif"));
+		assert_eq!(names(&options, 120).unwrap()[0], dir.join("talk-part001.gif"));
+		options.output = Some("tutorial".into());
+		assert_eq!(names(&options, 2).unwrap()[1], dir.join("tutorial-part02.gif"));
+	}
+
+	#[test]
+	fn each_part_is_its_own_conversion() {
+		let dir = crate::test_dir("split");
+		fs::write(dir.join("talk.mp4"), b"").unwrap();
+		crate::growing::written_an_hour_ago(&dir.join("talk.mp4"));
+		let mut options = ConvertOptions::new(dir.join("talk.mp4"));
+		options.frames_dir = Some(dir.join("frames"));
+		options.comment = crate::Comment::Off;
+		// The fake frames are empty, there's nothing to advise on.
+		options.advice = false;
+		options.start = Some(1.0);
+		let probe = crate::probe::TEST_PROBE.replace("\"2.000000\"", "\"32.000000\"");
+		let mock = MockRunner::new();
+		mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe.clone()));
+		for _ in 0..2 {
+			mock.respond("ffmpeg", CommandOutput::ok_with_stdout("ffmpeg version 6.0 Copyright"));
+			mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.32.0"));
+			mock.respond("ffprobe", CommandOutput::ok_with_stdout(probe.clone()));
+			mock.respond_with("ffmpeg", |command| {
+				let pattern = PathBuf::from(command.args.last().unwrap());
+				for i in 1..=12 { fs::write(pattern.with_file_name(format!("frame{i:04}.png")), b"")?; }
+				Ok(CommandOutput::ok_with_stderr("Stream #0:0: Video: h264, 24 fps"))
+			});
+			mock.respond("gifski", CommandOutput::ok_with_stdout(""));
+		}
+		let mut events = Vec::new();
+
+		let parts = run(&options, 15.0, &mock, |i, count, _| events.push((i, count))).unwrap();
+
+		let ranges: Vec<_> = parts.iter().map(|p| (p.number, p.start, p.end)).collect();
+		assert_eq!(ranges, [(1, 1.0, 16.0), (2, 16.0, 32.0)], "the 1 second left at the end is in the last part");
+		assert_eq!(parts[1].output, dir.join("talk-part02.gif"));
+		let extracts: Vec<String> = mock.calls_to("ffmpeg").iter().map(ToString::to_string).filter(|c| c.contains("frame%04d")).collect();
+		assert_eq!(extracts.len(), 2);
+		assert!(extracts[0].contains("-t 15 ") && extracts[1].contains("-t 16 "), "{extracts:?}");
+		assert!(events.contains(&(1, 2)) && events.iter().all(|&(_, count)| count == 2));
+	}
+}
@@ -0,0 +1,107 @@
+//! `--stabilize`: ffmpeg's two pass vidstab. vidstabdetect works out how the camera shook into a transforms file,
+//! then the extraction undoes it with vidstabtransform before any of the other filters.
+//!
+//! Both passes decode the same part of the input, so the transforms line up with the frames by number.
+
+use std::path::Path;
+use crate::{
+	ffmpeg::{self, Extraction},
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Result,
+};
+
+/// Name of the transforms file in the frames directory.
+pub(crate) const TRANSFORMS_FILE: &str = "transforms.trf";
+
+/// Checks `ffmpeg -filters` lists both vidstab filters, which only builds with libvidstab have.
+///
+/// # Errors
+/// [`ConvertError::MissingFilter`] if one isn't there.
+pub(crate) fn check_support(runner: &dyn CommandRunner) -> Result<()> {
+	let command = CommandLine::new("ffmpeg").args(["-hide_banner", "-filters"]);
+	log::debug!("Running: {command}");
+	let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	let filters = String::from_utf8_lossy(&output.stdout);
+	// ` T.. vidstabdetect     V->V       Extract relative transformations, ...`
+	let listed = |name| filters.lines().any(|line| line.split_whitespace().nth(1) == Some(name));
+	match ["vidstabdetect", "vidstabtransform"].into_iter().find(|f| !listed(f)) {
+		Some(filter) => Err(ConvertError::MissingFilter { filter, needed_by: "--stabilize" }),
+		None => Ok(()),
+	}
+}
+
+/// `ffmpeg [seeks] -i video.mp4 [-t duration] -vf vidstabdetect=result=transforms.trf -f null -`, over the same
+/// part of `input` that `extraction` extracts.
+pub(crate) fn detect_command(input: &Path, extraction: &Extraction, transforms: &Path) -> CommandLine {
+	let detect = Extraction {
+		filters: vec![format!("vidstabdetect=result={}", filter_path(transforms))],
+		stabilize: None,
+		start_number: None,
+		max_frames: None,
+		waveform: None,
+		pre_quantize: None,
+		bitexact: false,
+		..extraction.clone()
+	};
+	let mut command = ffmpeg::extract_command(input, Path::new(""), &detect);
+	// Nothing is written but the transforms.
+	command.args.pop();
+	command.args(["-an", "-f", "null", "-"])
+}
+
+/// The filter that undoes the shake found by [`detect_command`].
+pub(crate) fn transform_filter(transforms: &Path) -> String {
+	format!("vidstabtransform=input={}", filter_path(transforms))
+}
+
+/// Runs [`detect_command`].
+pub(crate) fn detect(runner: &dyn CommandRunner, input: &Path, extraction: &Extraction, transforms: &Path) -> Result<()> {
+	let command = detect_command(input, extraction, transforms);
+	log::debug!("Running: {command}");
+	let output = runner.run(&command).map_err(ConvertError::FfmpegNotInstalled)?;
+	if output.success() { Ok(()) } else {
+		Err(ConvertError::FfmpegFailed { code: output.code, stderr: String::from_utf8_lossy(&output.stderr).into_owned() })
+	}
+}
+
+/// `path` escaped for a filter option in a filter graph.
+fn filter_path(path: &Path) -> String {
+	ffmpeg::escape_option(&path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{runner::{CommandOutput, MockRunner}, ConvertOptions};
+
+	#[test]
+	fn detects_over_the_same_part_as_the_extraction() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.start = Some(1.5);
+		opt.duration = Some(3.0);
+		let mut extraction = Extraction::new(&opt).unwrap();
+		extraction.resample(12.0);
+		let command = detect_command(Path::new("in.mp4"), &extraction, Path::new("/tmp/frames/transforms.trf"));
+		assert_eq!(command.args_lossy(), [
+			"-i", "in.mp4", "-ss", "1.5", "-t", "3", "-vf", "vidstabdetect=result=/tmp/frames/transforms.trf", "-an", "-f", "null", "-",
+		]);
+	}
+
+	#[test]
+	fn paths_are_escaped_for_the_filter_graph() {
+		assert_eq!(transform_filter(Path::new("C:\\frames,1\\t.trf")), "vidstabtransform=input=C\\\\:\\\\\\\\frames\\,1\\\\\\\\t.trf");
+	}
+
+	#[test]
+	fn missing_vidstab_is_reported() {
+		let mock = MockRunner::new();
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout(" ... scale             V->V       Scale the input video size.\n"));
+		assert_eq!(
+			check_support(&mock).unwrap_err().to_string(),
+			"--stabilize needs ffmpeg's vidstabdetect filter, which this ffmpeg doesn't have (see ffmpeg -filters). Install a build that has it.",
+		);
+		mock.respond("ffmpeg", CommandOutput::ok_with_stdout(" ... vidstabdetect     V->V       Extract\n ... vidstabtransform  V->V       Transform\n"));
+		assert!(check_support(&mock).is_ok());
+	}
+}
@@ -0,0 +1,147 @@
+//! [`ConvertOptions::stats_file`](crate::ConvertOptions::stats_file): a CSV file that every conversion appends a row
+//! to, whether it worked or not, to follow sizes and settings over time.
+//!
+//! The file is locked while a row is written, and each row is written in one go, so conversions running side by
+//! side in other processes never interleave theirs or both write the header.
+
+use std::{
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	path::Path,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use crate::{ConvertError, ConvertReport, Result, Stage};
+
+/// The first line of a new stats file.
+const HEADER: &str = "timestamp,input,output,duration,fps,quality,width,height,frames,bytes,extract_seconds,encode_seconds,total_seconds,status,failed_stage,error,message";
+
+/// What one conversion is, or was trying to do when it failed.
+pub(crate) struct Run<'r> {
+	pub input: &'r Path,
+	pub result: &'r Result<ConvertReport>,
+	/// The last stage that started, which is the one that failed if it did.
+	pub stage: Option<Stage>,
+	/// When it finished.
+	pub finished: SystemTime,
+	/// How long it took, for a failed run which has no report to say so.
+	pub elapsed: Duration,
+}
+
+/// The CSV row of `run`, with the line break.
+fn row(run: &Run) -> String {
+	let seconds = |d: Duration| format!("{:.3}", d.as_secs_f64());
+	let mut fields = vec![timestamp(run.finished), run.input.to_string_lossy().into_owned()];
+	match run.result {
+		Ok(report) => {
+			let bytes = fs::metadata(&report.output).ok().filter(fs::Metadata::is_file).map(|m| m.len().to_string());
+			let (width, height) = report.frame_size.map(|(w, h)| (w.to_string(), h.to_string())).unzip();
+			fields.extend([
+				report.output.to_string_lossy().into_owned(),
+				format!("{:.3}", report.duration),
+				report.fps.to_string(),
+				report.quality.to_string(),
+				width.unwrap_or_default(),
+				height.unwrap_or_default(),
+				report.frame_count.to_string(),
+				bytes.unwrap_or_default(),
+				seconds(report.extract_time),
+				seconds(report.encode_time),
+				seconds(report.total_time),
+				"ok".to_string(),
+			]);
+			fields.extend(["", "", ""].map(String::from));
+		}
+		Err(e) => {
+			fields.extend(std::iter::repeat_n(String::new(), 10));
+			fields.push(seconds(run.elapsed));
+			fields.push(if matches!(e, ConvertError::Cancelled) { "cancelled" } else { "failed" }.to_string());
+			fields.push(run.stage.map(|s| s.id().to_string()).unwrap_or_default());
+			fields.push(e.id().to_string());
+			// One row per line, whatever the tool printed.
+			fields.push(e.to_string().split_whitespace().collect::<Vec<_>>().join(" "));
+		}
+	}
+	let mut line = fields.iter().map(|f| quote(f)).collect::<Vec<_>>().join(",");
+	line.push('\n');
+	line
+}
+
+/// `field` in double quotes if it has anything that would end it early, with its quotes doubled.
+fn quote(field: &str) -> String {
+	if field.contains([',', '"', '\n', '\r']) { format!("\"{}\"", field.replace('"', "\"\"")) } else { field.to_string() }
+}
+
+/// `2024-03-09T14:05:00Z`.
+fn timestamp(time: SystemTime) -> String {
+	let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+	let (days, of_day) = (seconds / 86_400, seconds % 86_400);
+	// Howard Hinnant's days to civil date, for days since 1970 that can't be negative.
+	let era = (days + 719_468) / 146_097;
+	let of_era = (days + 719_468) % 146_097;
+	let year_of_era = (of_era - of_era / 1460 + of_era / 36_524 - of_era / 146_096) / 365;
+	let day_of_year = of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = year_of_era + era * 400 + u64::from(month <= 2);
+	format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", of_day / 3600, of_day % 3600 / 60, of_day % 60)
+}
+
+/// Appends the row of `run` to `path`, with the header first if the file is new or empty.
+///
+/// # Errors
+/// If the file can't be created, locked or written to.
+pub(crate) fn append(path: &Path, run: &Run) -> io::Result<()> {
+	let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+	lock(&file)?;
+	let mut text = String::new();
+	if file.metadata()?.len() == 0 { text.extend([HEADER, "\n"]); }
+	text.push_str(&row(run));
+	file.write_all(text.as_bytes())
+}
+
+/// Locks `file` for this process alone, until it's closed.
+#[cfg(unix)]
+fn lock(file: &fs::File) -> io::Result<()> {
+	use std::os::unix::io::AsRawFd;
+	// SAFETY: flock only takes the descriptor, which `file` keeps open.
+	if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Appending a row in one write is as close as it gets without flock.
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn lock(_: &fs::File) -> io::Result<()> {
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn timestamps_are_utc() {
+		let at = |seconds| timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+		assert_eq!(at(0), "1970-01-01T00:00:00Z");
+		assert_eq!(at(1_709_993_100), "2024-03-09T14:05:00Z");
+		assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+	}
+
+	#[test]
+	fn failures_have_their_stage_and_error() {
+		let path = crate::test_dir("stats").join("stats.csv");
+		let failed = Err(ConvertError::FfmpegFailed { code: Some(1), stderr: "bad, \"very\"\nbad".to_string() });
+		let run = Run { input: Path::new("in, 1.mp4"), result: &failed, stage: Some(Stage::Extract), finished: UNIX_EPOCH, elapsed: Duration::from_millis(1500) };
+		append(&path, &run).unwrap();
+		append(&path, &Run { result: &Err(ConvertError::Cancelled), stage: None, ..run }).unwrap();
+
+		let text = fs::read_to_string(&path).unwrap();
+		let lines: Vec<&str> = text.lines().collect();
+		assert_eq!(lines.len(), 3, "one header, then a row each");
+		assert_eq!(lines[0], HEADER);
+		assert!(lines[1].starts_with("1970-01-01T00:00:00Z,\"in, 1.mp4\",,,,,,,,,,,1.500,failed,extract,ffmpeg-failed,\"ffmpeg exited"), "{}", lines[1]);
+		assert!(lines[1].contains("bad, \"\"very\"\" bad"), "{}", lines[1]);
+		assert!(lines[2].contains(",cancelled,,cancelled,"), "{}", lines[2]);
+		assert_eq!(lines[1].matches(',').count() - 2, HEADER.matches(',').count(), "2 of the commas are in quotes");
+	}
+}
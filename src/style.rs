@@ -0,0 +1,57 @@
+//! Colors for the binary's output. Off when the stream isn't a terminal, with `--no-color` or when `NO_COLOR` is set.
+
+use std::{
+	env,
+	io::{self, IsTerminal},
+	sync::atomic::{AtomicBool, Ordering},
+};
+
+static TERMINAL: AtomicBool = AtomicBool::new(false);
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Decides once whether to color stdout and stderr.
+pub fn init(no_color: bool) {
+	// https://no-color.org: any non-empty value disables color.
+	let allowed = !no_color && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+	TERMINAL.store(io::stdout().is_terminal(), Ordering::Relaxed);
+	STDOUT.store(allowed && TERMINAL.load(Ordering::Relaxed), Ordering::Relaxed);
+	STDERR.store(allowed && io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+/// Whether stdout can redraw a line in place, for progress.
+pub fn stdout_is_terminal() -> bool {
+	TERMINAL.load(Ordering::Relaxed)
+}
+
+/// Whether anything on stderr, like the log, should be colored.
+pub fn stderr_colored() -> bool {
+	STDERR.load(Ordering::Relaxed)
+}
+
+fn paint(enabled: &AtomicBool, code: &str, text: &str) -> String {
+	if enabled.load(Ordering::Relaxed) { format!("\x1b[{code}m{text}\x1b[0m") } else { text.to_string() }
+}
+
+/// `============[ffmpeg]============`, the same width for every title.
+pub fn header(title: &str) -> String {
+	paint(&STDOUT, "1;36", &format!("{:=^32}", format!("[{title}]")))
+}
+
+pub fn success(text: &str) -> String {
+	paint(&STDOUT, "32", text)
+}
+
+pub fn failure(text: &str) -> String {
+	paint(&STDOUT, "31", text)
+}
+
+/// Printed to stdout, in between the stage output it's about.
+pub fn warning(message: &str) -> String {
+	format!("{} {message}", paint(&STDOUT, "1;33", "warning:"))
+}
+
+/// Printed to stderr.
+pub fn error(message: &str) -> String {
+	format!("{} {message}", paint(&STDERR, "1;31", "error:"))
+}
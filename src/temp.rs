@@ -0,0 +1,295 @@
+//! The default frames directories, one per run under `<TEMP>/gifski-ffmpeg/`, and deleting the ones runs that
+//! crashed or were killed left behind.
+//!
+//! With [`ConvertOptions::in_memory`](crate::ConvertOptions::in_memory) they go on a ramdisk instead, if the frames
+//! fit.
+//!
+//! Only directories named like [`run_dir`] makes them are ever deleted, so nothing else in the temp directory is
+//! touched, and only once both their name and everything in them say they're old: a clock that was wrong for either
+//! makes it look new, not stale.
+//!
+//! A [`ConvertOptions::frames_dir`](crate::ConvertOptions::frames_dir) is the user's, so it's only ever emptied, and
+//! only once it's known to hold nothing but frames: it has to be new or empty, or marked by an earlier run.
+
+use std::{
+	fmt, fs,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicU32, Ordering},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use crate::{disk, ConvertError, FramePattern, Result};
+
+/// The directory in the temp directory that all the runs go in.
+pub(crate) const PARENT: &str = "gifski-ffmpeg";
+
+/// Left in a run directory that's kept on purpose, with [`Stages::Extract`](crate::Stages::Extract) or
+/// [`keep_frames`](crate::ConvertOptions::keep_frames), so it isn't taken as left behind.
+const KEEP_MARKER: &str = ".keep";
+
+/// Left in a [`ConvertOptions::frames_dir`](crate::ConvertOptions::frames_dir) while the frames are in it, so the
+/// next run can empty it.
+const FRAMES_DIR_MARKER: &str = ".gifski-ffmpeg";
+
+/// How long since a run directory was last written to before it's taken as left behind.
+const STALE_AFTER: Duration = Duration::from_hours(24);
+
+/// The most that's deleted in one go, in bytes.
+const MAX_RECLAIM: u64 = 20 * 1024 * 1024 * 1024;
+
+/// The ramdisk [`ConvertOptions::in_memory`](crate::ConvertOptions::in_memory) uses without a
+/// [`ConvertOptions::ramdisk`](crate::ConvertOptions::ramdisk).
+pub const DEFAULT_RAMDISK: &str = "/dev/shm";
+
+/// Runs started by this process, so conversions on several threads don't share a directory.
+static RUNS: AtomicU32 = AtomicU32::new(0);
+
+/// A new `<temp>/gifski-ffmpeg/run-<seconds since 1970>-<pid>-<n>`.
+pub(crate) fn run_dir(temp: &Path) -> PathBuf {
+	let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+	let n = RUNS.fetch_add(1, Ordering::Relaxed);
+	temp.join(PARENT).join(format!("run-{started}-{}-{n}", std::process::id()))
+}
+
+/// When the run named `name` started, `None` if it isn't a [`run_dir`] name.
+fn started(name: &str) -> Option<SystemTime> {
+	let mut parts = name.strip_prefix("run-")?.split('-');
+	let seconds = parts.next()?.parse().ok()?;
+	let is_number = |part: Option<&str>| part.is_some_and(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()));
+	if !(is_number(parts.next()) && is_number(parts.next()) && parts.next().is_none()) { return None; }
+	UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// Marks `frames_dir` as kept on purpose, if it's a [`run_dir`].
+pub(crate) fn keep(frames_dir: &Path) {
+	if frames_dir.file_name().and_then(|n| started(&n.to_string_lossy())).is_some() {
+		let _ = fs::write(frames_dir.join(KEEP_MARKER), b"");
+	}
+}
+
+/// Checks `dir`, given as [`ConvertOptions::frames_dir`](crate::ConvertOptions::frames_dir), can be extracted to,
+/// before anything is touched: it's emptied before and after, so it can't have the `input` or `output` in it, or
+/// anything else unless an earlier run marked it.
+///
+/// # Errors
+/// If it has, naming what `pattern` would take for frames, or what's in it.
+pub(crate) fn check_frames_dir(dir: &Path, input: &Path, output: &Path, pattern: &FramePattern) -> Result<()> {
+	let invalid = |message: String| Err(ConvertError::InvalidOption { option: "frames dir", message });
+	// Canonical, so `.` or a symlink to where the input is are caught too. The output may not exist yet.
+	let canonical_dir = dir.canonicalize().ok();
+	let inside = |path: Option<PathBuf>| path.is_some_and(|p| canonical_dir.as_ref().is_some_and(|d| p.starts_with(d)));
+	let output_parent = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+	for (what, path, canonical) in [
+		("input", input, input.canonicalize().ok()),
+		("output", output, output_parent.canonicalize().ok().map(|p| p.join(output.file_name().unwrap_or_default()))),
+	] {
+		if path.starts_with(dir) || inside(canonical) {
+			return invalid(format!("{} has the {what} in it, which would be deleted with the frames", dir.display()));
+		}
+	}
+
+	if !dir.exists() || dir.join(FRAMES_DIR_MARKER).exists() { return Ok(()); }
+	if !dir.is_dir() { return invalid(format!("{} isn't a directory", dir.display())); }
+	let mut entries: Vec<String> = fs::read_dir(dir).map_err(ConvertError::io(dir))?
+		.filter_map(|e| e.ok().map(|e| e.file_name().to_string_lossy().into_owned()))
+		.collect();
+	if entries.is_empty() { return Ok(()); }
+	let named = |names: &[String]| {
+		let shown = names.iter().take(3).cloned().collect::<Vec<_>>().join(", ");
+		if names.len() > 3 { format!("{shown} and {} more", names.len() - 3) } else { shown }
+	};
+	let clashes = pattern.clashes(dir);
+	if !clashes.is_empty() {
+		return invalid(format!(
+			"{} already has {} in it, which would be taken for frames named {}. Give an empty or new directory",
+			dir.display(), named(&clashes), pattern.printf(),
+		));
+	}
+	entries.sort();
+	invalid(format!(
+		"{} already has {} in it, which would be deleted with the frames. Give an empty or new directory",
+		dir.display(), named(&entries),
+	))
+}
+
+/// Empties `dir`, given as [`ConvertOptions::frames_dir`](crate::ConvertOptions::frames_dir) and checked by
+/// [`check_frames_dir`], or creates it, and marks it as holding frames.
+pub(crate) fn prepare_frames_dir(dir: &Path) -> std::io::Result<()> {
+	if dir.exists() { clear_frames_dir(dir)?; }
+	fs::create_dir_all(dir)?;
+	fs::write(dir.join(FRAMES_DIR_MARKER), b"")
+}
+
+/// Deletes everything in `dir`, given as [`ConvertOptions::frames_dir`](crate::ConvertOptions::frames_dir), but not
+/// `dir` itself.
+pub(crate) fn clear_frames_dir(dir: &Path) -> std::io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		if entry.file_type()?.is_dir() { fs::remove_dir_all(entry.path())?; } else { fs::remove_file(entry.path())?; }
+	}
+	Ok(())
+}
+
+/// Deletes the frames `pattern` names in `dir`, the input of [`Stages::Encode`](crate::Stages::Encode), leaving
+/// `dir` and anything else in it to the user.
+pub(crate) fn remove_frames(dir: &Path, pattern: &FramePattern) -> std::io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_file() && pattern.number(&path).is_some() { fs::remove_file(path)?; }
+	}
+	Ok(())
+}
+
+/// The last time `dir` or anything in it was written to.
+fn last_written(dir: &Path) -> Option<SystemTime> {
+	let own = fs::symlink_metadata(dir).and_then(|m| m.modified()).ok();
+	let entries = fs::read_dir(dir).ok()?.flatten().filter_map(|entry| match entry.metadata() {
+		Ok(meta) if meta.is_dir() => last_written(&entry.path()),
+		Ok(meta) => meta.modified().ok(),
+		Err(_) => None,
+	});
+	entries.chain(own).max()
+}
+
+/// Which temp directory the frames went in, with [`ConvertOptions::in_memory`](crate::ConvertOptions::in_memory).
+/// Displays as `in memory, in /dev/shm`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramesLocation {
+	/// The ramdisk.
+	Memory(PathBuf),
+	/// The normal temp directory, the ramdisk couldn't be used.
+	Disk(PathBuf),
+}
+
+impl fmt::Display for FramesLocation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FramesLocation::Memory(dir) => write!(f, "in memory, in {}", dir.display()),
+			FramesLocation::Disk(dir) => write!(f, "on disk, in {}", dir.display()),
+		}
+	}
+}
+
+/// Checks `ramdisk` is there and has room for `needed` bytes of frames, with a quarter more to spare since that's
+/// only an estimate. The reason it can't be used otherwise.
+pub(crate) fn check_ramdisk(ramdisk: &Path, needed: Option<u64>) -> Result<(), String> {
+	if !ramdisk.is_dir() { return Err(format!("{} doesn't exist", ramdisk.display())); }
+	let needed = needed.ok_or("how much space the frames need isn't known")?;
+	let free = disk::free_space(ramdisk).ok_or_else(|| format!("couldn't find how much space {} has", ramdisk.display()))?;
+	if free < needed.saturating_add(needed / 4) {
+		return Err(format!(
+			"{} has {} free and the frames need about {}",
+			ramdisk.display(), disk::human_size(free), disk::human_size(needed),
+		));
+	}
+	Ok(())
+}
+
+/// What [`collect`] deleted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Reclaimed {
+	pub dirs: usize,
+	pub bytes: u64,
+}
+
+/// Deletes the run directories in `parent` that are stale at `now`, oldest first, until the next
+/// would take it past `max_bytes`.
+fn collect_in(parent: &Path, now: SystemTime, max_bytes: u64) -> Reclaimed {
+	let is_stale = |time: SystemTime| now.duration_since(time).is_ok_and(|age| age > STALE_AFTER);
+	let Ok(entries) = fs::read_dir(parent) else { return Reclaimed::default() };
+	let mut stale: Vec<(SystemTime, PathBuf)> = entries.flatten()
+		.filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+		.filter_map(|entry| {
+			let path = entry.path();
+			let started = started(&entry.file_name().to_string_lossy())?;
+			let written = last_written(&path)?;
+			(is_stale(started) && is_stale(written) && !path.join(KEEP_MARKER).exists()).then_some((written, path))
+		})
+		.collect();
+	stale.sort();
+
+	let mut reclaimed = Reclaimed::default();
+	for (_, dir) in stale {
+		let size = disk::dir_size(&dir);
+		if reclaimed.bytes + size > max_bytes { break; }
+		if fs::remove_dir_all(&dir).is_ok() {
+			reclaimed.dirs += 1;
+			reclaimed.bytes += size;
+		}
+	}
+	reclaimed
+}
+
+/// Deletes the run directories in `temp` that were left behind, see the [module docs](self).
+pub(crate) fn collect(temp: &Path) -> Reclaimed {
+	collect_in(&temp.join(PARENT), SystemTime::now(), MAX_RECLAIM)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn run(parent: &Path, name: &str, bytes: usize) -> PathBuf {
+		let dir = parent.join(name);
+		fs::create_dir(&dir).unwrap();
+		fs::write(dir.join("frame0001.png"), vec![0; bytes]).unwrap();
+		dir
+	}
+
+	#[test]
+	fn run_dirs_are_unique_and_recognised() {
+		let temp = Path::new("/tmp");
+		let (first, second) = (run_dir(temp), run_dir(temp));
+		assert_ne!(first, second);
+		assert_eq!(first.parent(), Some(Path::new("/tmp/gifski-ffmpeg")));
+		assert!(started(&first.file_name().unwrap().to_string_lossy()).is_some());
+		assert_eq!(started("run-60-123-0"), Some(UNIX_EPOCH + Duration::from_mins(1)));
+		for name in ["frames", "run-60-123", "run-60-123-0-1", "run-60--0", "run-x-1-0", "xrun-60-123-0"] {
+			assert_eq!(started(name), None, "{name}");
+		}
+	}
+
+	#[test]
+	fn only_stale_run_dirs_are_deleted() {
+		let parent = crate::test_dir("temp-stale");
+		let stale = run(&parent, "run-1000-1-0", 10);
+		let kept = run(&parent, "run-1000-2-0", 10);
+		keep(&kept);
+		let other = run(&parent, "mine", 10);
+		let now = SystemTime::now() + 2 * STALE_AFTER;
+
+		assert_eq!(collect_in(&parent, now, MAX_RECLAIM), Reclaimed { dirs: 1, bytes: 10 });
+		assert!(!stale.exists() && kept.exists() && other.exists());
+	}
+
+	#[test]
+	fn a_clock_behind_or_ahead_deletes_nothing() {
+		let parent = crate::test_dir("temp-skew");
+		// Named as started in the future, as a clock running ahead would.
+		let next_year = (SystemTime::now() + 365 * STALE_AFTER).duration_since(UNIX_EPOCH).unwrap().as_secs();
+		let ahead = run(&parent, &format!("run-{next_year}-1-0"), 10);
+		let written_recently = run(&parent, "run-1000-2-0", 10);
+		assert_eq!(collect_in(&parent, SystemTime::now(), MAX_RECLAIM), Reclaimed::default());
+		// The files look written in the future.
+		assert_eq!(collect_in(&parent, SystemTime::now() - STALE_AFTER, MAX_RECLAIM), Reclaimed::default());
+		assert!(ahead.exists() && written_recently.exists());
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn the_ramdisk_needs_room_for_the_frames() {
+		let ramdisk = crate::test_dir("temp-ramdisk");
+		assert_eq!(check_ramdisk(&ramdisk, Some(1024)), Ok(()));
+		assert!(check_ramdisk(&ramdisk, Some(u64::MAX / 2)).unwrap_err().contains("free and the frames need about"));
+		assert_eq!(check_ramdisk(&ramdisk, None).unwrap_err(), "how much space the frames need isn't known");
+		assert!(check_ramdisk(&ramdisk.join("missing"), Some(1)).unwrap_err().ends_with("missing doesn't exist"));
+	}
+
+	#[test]
+	fn stops_before_deleting_too_much() {
+		let parent = crate::test_dir("temp-bound");
+		run(&parent, "run-1000-1-0", 100);
+		run(&parent, "run-1000-2-0", 100);
+		let reclaimed = collect_in(&parent, SystemTime::now() + 2 * STALE_AFTER, 150);
+		assert_eq!(reclaimed, Reclaimed { dirs: 1, bytes: 100 });
+	}
+}
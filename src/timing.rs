@@ -0,0 +1,144 @@
+//! [`ConvertOptions::source_timing`]: each frame shown for as long as it was in the input, for gifs missing some of
+//! its frames, like [`keyframes_only`](ConvertOptions::keyframes_only) ones, which one fps plays evenly spaced.
+//!
+//! gifski only takes the one `--fps`, so the gif is encoded at it and each frame's delay rewritten afterwards, from
+//! the timestamps `showinfo` printed as the frames were extracted. Delays are whole centiseconds, rounded from the
+//! running total so the whole gif is off by less than one.
+
+use std::{fs, path::{Path, PathBuf}};
+use regex::Regex;
+use crate::{gif, pattern::FramePattern, ConvertError, ConvertOptions, Progress, Result, Stages, WarningKind};
+
+/// The shortest delay browsers keep, they slow anything under it down to 10 centiseconds.
+const MIN_DELAY: u16 = 2;
+
+/// Checks the frames keep the numbers they were extracted with up to the encode, so their timestamps can be found.
+///
+/// # Errors
+/// When combined with options that encode something else than the extracted frames, or not all of them at once.
+pub(crate) fn check(opt: &ConvertOptions) -> Result<()> {
+	if !opt.source_timing { return Ok(()); }
+	let conflict = [
+		(opt.stages != Stages::All, "--stage, the timestamps are only known while extracting"),
+		(opt.overlap || opt.chunk_seconds.is_some(), "--overlap or --chunk-seconds"),
+		(!opt.concat.is_empty(), "--concat"),
+		(opt.edit_list.is_some(), "--edit-list, which renumbers the frames"),
+		(opt.loop_smooth.is_some(), "--loop-smooth, which makes frames of its own"),
+		(!opt.quality_map.is_empty() || !opt.compare_quality.is_empty() || !opt.sizes.is_empty(), "--quality-map, --compare-quality or --sizes"),
+	].into_iter().find(|(set, _)| *set);
+	if let Some((_, what)) = conflict {
+		return Err(ConvertError::InvalidOption { option: "source timing", message: format!("can't be combined with {what}") });
+	}
+	Ok(())
+}
+
+/// The `pts_time` of every frame `showinfo` printed to ffmpeg's stderr, in the order they were written, so the
+/// first is `frame0001.png`'s.
+pub(crate) fn parse_timestamps(stderr: &str) -> Vec<f64> {
+	let frame = Regex::new(r"showinfo.*\bn:\s*\d+\s.*\bpts_time:\s*(-?[\d.]+)").unwrap();
+	stderr.lines().filter_map(|line| frame.captures(line)?[1].parse().ok()).collect()
+}
+
+/// Centisecond delays for frames shown from each of `starts` until the next one, the last one until `end`.
+///
+/// Each is what takes the running total nearest the time its frame ends, so rounding doesn't add up, and none is
+/// under [`MIN_DELAY`]; a frame that had to be held longer takes it out of the next ones.
+pub(crate) fn delays(starts: &[f64], end: f64) -> Vec<u16> {
+	let Some(&first) = starts.first() else { return Vec::new() };
+	let mut shown = 0u64;
+	starts.iter().skip(1).chain([&end])
+		.map(|&ends| {
+			#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+			let total = ((ends - first) * 100.0).round().max(0.0) as u64;
+			let delay = total.saturating_sub(shown).clamp(u64::from(MIN_DELAY), u64::from(u16::MAX));
+			shown += delay;
+			u16::try_from(delay).unwrap_or(u16::MAX)
+		})
+		.collect()
+}
+
+/// When each of `frames`, numbered from 1 by `pattern`, starts and when the last one ends, from the `timestamps` of every frame
+/// extracted. Frames left out show the one before them for longer. The last one ends where the next extracted one
+/// would have started, or for as long as extracted frames are on average after the last of them.
+pub(crate) fn frame_times(frames: &[PathBuf], pattern: &FramePattern, timestamps: &[f64]) -> Option<(Vec<f64>, f64)> {
+	let numbers = frames.iter().map(|f| pattern.number(f).filter(|&n| (1..=timestamps.len()).contains(&n))).collect::<Option<Vec<_>>>()?;
+	let last = *numbers.last()?;
+	let starts = numbers.iter().map(|&n| timestamps[n - 1]).collect();
+	#[allow(clippy::cast_precision_loss)]
+	let average = if timestamps.len() > 1 { (timestamps[timestamps.len() - 1] - timestamps[0]) / (timestamps.len() - 1) as f64 } else { 0.1 };
+	let end = timestamps.get(last).copied().unwrap_or(timestamps[last - 1] + average);
+	Some((starts, end))
+}
+
+/// Rewrites the delays of the `gif` made from `frames`, named by `pattern`, to how long each was in the input, from the `showinfo`
+/// timestamps in ffmpeg's `stderr`. Returns how long the gif plays for now.
+///
+/// Warns and leaves it at the one fps it was encoded at when the gif's frames don't line up with the extracted ones,
+/// which they don't if gifski merged identical frames into one.
+///
+/// # Errors
+/// If the gif can't be read or written.
+pub(crate) fn retime(gif: &Path, frames: &[PathBuf], pattern: &FramePattern, stderr: &str, progress: &mut dyn FnMut(Progress)) -> Result<Option<f64>> {
+	let timestamps = parse_timestamps(stderr);
+	let bytes = fs::read(gif).map_err(ConvertError::io(gif))?;
+	let delays = frame_times(frames, pattern, &timestamps).map(|(starts, end)| delays(&starts, end));
+	let Some((retimed, delays)) = delays.and_then(|delays| Some((gif::set_delays(&bytes, &delays)?, delays))) else {
+		progress(Progress::warning(WarningKind::SourceTimingUnavailable, format!(
+			"Couldn't give the {} frames the timing they had in the input, so they play at the one fps. ffmpeg printed {} timestamps, and the gif has {} frames",
+			frames.len(), timestamps.len(), gif::delays(&bytes).map_or(0, |d| d.len()),
+		)));
+		return Ok(None);
+	};
+	fs::write(gif, retimed).map_err(ConvertError::io(gif))?;
+	let seconds = delays.iter().map(|&d| f64::from(d)).sum::<f64>() / 100.0;
+	progress(Progress::Info(format!("Timed the frames like the input, {seconds:.2}s in all")));
+	Ok(Some(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reads_showinfo_timestamps() {
+		let stderr = "\
+[Parsed_showinfo_1 @ 0x55d] config in time_base: 1/90000, frame_rate: 30/1
+[Parsed_showinfo_1 @ 0x55d] n:   0 pts:      0 pts_time:0       duration:   3000 duration_time:0.0333333 fmt:yuv420p
+[Parsed_showinfo_1 @ 0x55d] n:   1 pts: 225000 pts_time:2.5     duration:   3000 duration_time:0.0333333 fmt:yuv420p
+frame=    2 fps=0.0 q=-0.0 Lsize=N/A time=00:00:02.53 bitrate=N/A speed=  10x
+[Parsed_showinfo_1 @ 0x55d] n:   2 pts: 279000 pts_time:3.1     duration:   3000 duration_time:0.0333333 fmt:yuv420p
+";
+		assert_eq!(parse_timestamps(stderr), [0.0, 2.5, 3.1]);
+		assert!(parse_timestamps("Stream #0:0: Video: h264, 30 fps").is_empty());
+	}
+
+	#[test]
+	fn delays_add_up_to_the_input() {
+		assert_eq!(delays(&[0.0, 2.5, 3.1], 4.0), [250, 60, 90]);
+		assert_eq!(delays(&[10.0, 10.5], 11.0), [50, 50], "from the first frame");
+		// 30fps is 3.33cs a frame, which rounding each on its own would make 3s and lose 1cs every third.
+		let starts = (0..30).map(|i| f64::from(i) / 30.0).collect::<Vec<_>>();
+		let thirtieths = delays(&starts, 1.0);
+		assert_eq!(thirtieths.iter().map(|&d| u32::from(d)).sum::<u32>(), 100);
+		assert!(thirtieths.iter().all(|&d| d == 3 || d == 4), "{thirtieths:?}");
+		assert!(delays(&[], 1.0).is_empty());
+	}
+
+	#[test]
+	fn short_frames_are_held_for_the_least_browsers_keep() {
+		let held = delays(&[0.0, 0.001, 0.002, 1.0], 2.0);
+		assert_eq!(held, [2, 2, 96, 100], "the third makes up for the first two");
+		let total = held.iter().map(|&d| f64::from(d)).sum::<f64>();
+		assert!((total - 200.0).abs() <= 1.0);
+	}
+
+	#[test]
+	fn frames_left_out_hold_the_one_before() {
+		let frames = |numbers: &[usize]| numbers.iter().map(|n| PathBuf::from(format!("/tmp/frames/frame{n:04}.png"))).collect::<Vec<_>>();
+		let timestamps = [0.0, 0.5, 1.0, 1.5, 2.0];
+		assert_eq!(frame_times(&frames(&[1, 2, 3, 4, 5]), &FramePattern::default(), &timestamps), Some((vec![0.0, 0.5, 1.0, 1.5, 2.0], 2.5)));
+		assert_eq!(frame_times(&frames(&[1, 4]), &FramePattern::default(), &timestamps), Some((vec![0.0, 1.5], 2.0)), "up to the next one extracted");
+		assert_eq!(frame_times(&frames(&[2, 6]), &FramePattern::default(), &timestamps), None, "a frame without a timestamp");
+		assert_eq!(frame_times(&[], &FramePattern::default(), &timestamps), None);
+	}
+}
@@ -0,0 +1,161 @@
+//! Finding ffmpeg and gifski and asking them for their versions.
+
+use std::{
+	env,
+	fmt,
+	path::PathBuf,
+};
+use crate::{
+	runner::{CommandLine, CommandRunner},
+	ConvertError,
+	Result,
+};
+
+/// One of the programs a conversion shells out to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Tool {
+	Ffmpeg,
+	Gifski,
+}
+
+impl Tool {
+	/// The name it's run by.
+	#[must_use]
+	pub fn program(self) -> &'static str {
+		match self {
+			Tool::Ffmpeg => "ffmpeg",
+			Tool::Gifski => "gifski",
+		}
+	}
+
+	/// The oldest `(major, minor)` version known to work.
+	#[must_use]
+	pub fn minimum_version(self) -> (u32, u32) {
+		match self {
+			Tool::Ffmpeg => (4, 0),
+			Tool::Gifski => (1, 0),
+		}
+	}
+
+	fn version_command(self) -> CommandLine {
+		match self {
+			Tool::Ffmpeg => CommandLine::new("ffmpeg").arg("-version"),
+			Tool::Gifski => CommandLine::new("gifski").arg("--version"),
+		}
+	}
+
+	fn not_installed(self, e: std::io::Error) -> ConvertError {
+		match self {
+			Tool::Ffmpeg => ConvertError::FfmpegNotInstalled(e),
+			Tool::Gifski => ConvertError::GifskiNotInstalled(e),
+		}
+	}
+}
+
+impl fmt::Display for Tool {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.program())
+	}
+}
+
+/// What was found out about an installed tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ToolInfo {
+	pub tool: Tool,
+	/// Where on the PATH it is, if it could be found there.
+	pub path: Option<PathBuf>,
+	/// The version it reported, `None` if its output didn't contain one.
+	pub version: Option<String>,
+}
+
+impl ToolInfo {
+	/// `(major, minor)` of the version, if it looks like a release number.
+	///
+	/// Distribution suffixes (`6.1.1-3ubuntu5`) and the `n` some ffmpeg builds prefix (`n4.4`) are ignored;
+	/// git builds (`N-112345-g...`) have no release number.
+	#[must_use]
+	pub fn major_minor(&self) -> Option<(u32, u32)> {
+		let version = self.version.as_deref()?;
+		let version = version.strip_prefix('n').unwrap_or(version);
+		let mut parts = version.split(|c: char| !c.is_ascii_digit());
+		let major = parts.next()?.parse().ok()?;
+		let minor = parts.next().and_then(|m| m.parse().ok()).unwrap_or(0);
+		Some((major, minor))
+	}
+
+	/// `Some(false)` if the version is older than [`Tool::minimum_version`], `None` if it's unknown.
+	#[must_use]
+	pub fn is_adequate(&self) -> Option<bool> {
+		self.major_minor().map(|v| v >= self.tool.minimum_version())
+	}
+}
+
+/// Runs `tool`'s version command.
+///
+/// # Errors
+/// [`ConvertError::FfmpegNotInstalled`] or [`ConvertError::GifskiNotInstalled`] if it can't be run.
+pub fn probe(runner: &dyn CommandRunner, tool: Tool) -> Result<ToolInfo> {
+	let output = runner.run(&tool.version_command()).map_err(|e| tool.not_installed(e))?;
+	let version = parse_version(tool, &String::from_utf8_lossy(&output.stdout));
+	log::debug!("{} version: {:?}", tool, &version);
+	Ok(ToolInfo { tool, path: find_in_path(tool.program()), version })
+}
+
+/// `ffmpeg version 6.1.1-3ubuntu5 Copyright...` or `gifski 1.32.0`.
+fn parse_version(tool: Tool, stdout: &str) -> Option<String> {
+	let first_line = stdout.lines().next()?;
+	let rest = match tool {
+		Tool::Ffmpeg => first_line.strip_prefix("ffmpeg version ")?,
+		Tool::Gifski => first_line.strip_prefix("gifski ")?,
+	};
+	rest.split_whitespace().next().map(str::to_string)
+}
+
+/// Looks for `program` in the directories on the PATH, the way the shell would.
+#[must_use]
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+	let extensions: &[&str] = if cfg!(windows) { &["exe", "cmd", "bat"] } else { &[""] };
+	env::split_paths(&env::var_os("PATH")?)
+		.flat_map(|dir| extensions.iter().map(move |ext| dir.join(program).with_extension(ext)))
+		.find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{CommandOutput, MockRunner};
+
+	#[test]
+	fn parses_versions() {
+		let ffmpeg = "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers\nbuilt with gcc 13";
+		assert_eq!(parse_version(Tool::Ffmpeg, ffmpeg).as_deref(), Some("6.1.1-3ubuntu5"));
+		assert_eq!(parse_version(Tool::Ffmpeg, "ffmpeg version n4.4 Copyright").as_deref(), Some("n4.4"));
+		assert_eq!(parse_version(Tool::Gifski, "gifski 1.32.0\n").as_deref(), Some("1.32.0"));
+		assert_eq!(parse_version(Tool::Gifski, "usage: gifski"), None);
+	}
+
+	#[test]
+	fn version_numbers() {
+		let info = |version: &str| ToolInfo { tool: Tool::Ffmpeg, path: None, version: Some(version.to_string()) };
+		assert_eq!(info("6.1.1-3ubuntu5").major_minor(), Some((6, 1)));
+		assert_eq!(info("n4.4").major_minor(), Some((4, 4)));
+		assert_eq!(info("7").major_minor(), Some((7, 0)));
+		assert_eq!(info("N-112345-gabcdef").major_minor(), None);
+		assert_eq!(info("3.4.8").is_adequate(), Some(false));
+		assert_eq!(info("4.0").is_adequate(), Some(true));
+		assert_eq!(info("N-112345-gabcdef").is_adequate(), None);
+	}
+
+	#[test]
+	fn probe_runs_the_version_command() {
+		let mock = MockRunner::new();
+		mock.respond("gifski", CommandOutput::ok_with_stdout("gifski 1.7.0"));
+
+		let info = probe(&mock, Tool::Gifski).unwrap();
+
+		assert_eq!(info.version.as_deref(), Some("1.7.0"));
+		assert_eq!(mock.calls()[0].args_lossy(), ["--version"]);
+	}
+}
@@ -0,0 +1,139 @@
+//! [`Warning`]s, the things that didn't go as asked but didn't stop the conversion.
+
+use std::fmt;
+use serde::Serialize;
+
+/// What a [`Warning`] is about. Its [`id`](Self::id) never changes, so scripts can pick out the ones they care about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum WarningKind {
+	/// The fps or quality was outside what gifski takes, so the nearest it does was used.
+	OutOfRange,
+	/// [`ConvertOptions::still_duration`](crate::ConvertOptions::still_duration) was given for a video.
+	StillDurationIgnored,
+	/// [`ConvertOptions::scale`](crate::ConvertOptions::scale) made the frames bigger than the input.
+	Upscaled,
+	/// [`ConvertOptions::concat`](crate::ConvertOptions::concat) inputs were resampled to the first one's fps.
+	ConcatResampled,
+	/// [`ConvertOptions::trim_idle`](crate::ConvertOptions::trim_idle) found every frame idle, so trimmed none.
+	AllFramesIdle,
+	/// gifski ran out of memory and the gif was encoded again with lower settings.
+	OutOfMemoryRetry,
+	/// One of the [`ConvertOptions::compare_quality`](crate::ConvertOptions::compare_quality) or
+	/// [`ConvertOptions::sizes`](crate::ConvertOptions::sizes) encodes failed.
+	ComparisonFailed,
+	/// [`Focus::Auto`](crate::Focus::Auto) found nothing moving, so the whole frame was kept.
+	NoMotion,
+	/// The input has no audio to draw the [`ConvertOptions::waveform`](crate::ConvertOptions::waveform) from.
+	NoAudio,
+	/// The drive the frames are on got below [`ConvertOptions::reserve_space`](crate::ConvertOptions::reserve_space),
+	/// so extracting stopped there and the gif is cut short.
+	LowDiskSpace,
+	/// The frames directory is on a network share or a removable drive, or the temp directory was and another one
+	/// was used.
+	SlowFramesDir,
+	/// Only one or two frames were extracted from seconds of video, which is most likely a trim or filter that
+	/// picked less than meant.
+	FewFrames,
+	/// The gif had too few frames for the [`ConvertOptions::contact_sheet`](crate::ConvertOptions::contact_sheet).
+	ContactSheetSkipped,
+	/// The [`ConvertOptions::also_mp4`](crate::ConvertOptions::also_mp4) came out over
+	/// [`MP4_MAX_BYTES`](crate::MP4_MAX_BYTES).
+	Mp4TooBig,
+	/// The MP4 or poster of [`ConvertOptions::social`](crate::ConvertOptions::social) couldn't be written.
+	SocialPieceFailed,
+	/// The gif's frames didn't line up with the extracted ones, so
+	/// [`ConvertOptions::source_timing`](crate::ConvertOptions::source_timing) left them at the one fps.
+	SourceTimingUnavailable,
+	/// None of the [`ConvertOptions::cursor_overlay`](crate::ConvertOptions::cursor_overlay) events were in the
+	/// time the frames cover, so the cursor doesn't move.
+	CursorEventsOutsideRange,
+	/// ffmpeg failed part way and
+	/// [`ConvertOptions::tolerate_decode_errors`](crate::ConvertOptions::tolerate_decode_errors) kept the frames it
+	/// extracted before that, so the gif is cut short.
+	DecodeErrorTolerated,
+	/// The input was still being written, like a recording that hasn't been stopped, so the gif may be missing its
+	/// end. Waited for with [`ConvertOptions::wait_for_input`](crate::ConvertOptions::wait_for_input).
+	InputStillWritten,
+	/// ffmpeg didn't measure every frame, so [`ConvertOptions::drop_flash_frames`](crate::ConvertOptions::drop_flash_frames)
+	/// left them all as they were.
+	FlashFramesUnmeasured,
+}
+
+impl WarningKind {
+	/// `out-of-range`, `still-duration-ignored`, ...
+	#[must_use]
+	pub fn id(self) -> &'static str {
+		match self {
+			WarningKind::OutOfRange => "out-of-range",
+			WarningKind::StillDurationIgnored => "still-duration-ignored",
+			WarningKind::Upscaled => "upscaled",
+			WarningKind::ConcatResampled => "concat-resampled",
+			WarningKind::AllFramesIdle => "all-frames-idle",
+			WarningKind::OutOfMemoryRetry => "out-of-memory-retry",
+			WarningKind::ComparisonFailed => "comparison-failed",
+			WarningKind::NoMotion => "no-motion",
+			WarningKind::NoAudio => "no-audio",
+			WarningKind::LowDiskSpace => "low-disk-space",
+			WarningKind::SlowFramesDir => "slow-frames-dir",
+			WarningKind::FewFrames => "few-frames",
+			WarningKind::ContactSheetSkipped => "contact-sheet-skipped",
+			WarningKind::Mp4TooBig => "mp4-too-big",
+			WarningKind::SocialPieceFailed => "social-piece-failed",
+			WarningKind::SourceTimingUnavailable => "source-timing-unavailable",
+			WarningKind::CursorEventsOutsideRange => "cursor-events-outside-range",
+			WarningKind::DecodeErrorTolerated => "decode-error-tolerated",
+			WarningKind::InputStillWritten => "input-still-written",
+			WarningKind::FlashFramesUnmeasured => "flash-frames-unmeasured",
+		}
+	}
+}
+
+impl fmt::Display for WarningKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.id())
+	}
+}
+
+/// Something that didn't go as asked but didn't stop the conversion. Displays as its message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+	#[serde(rename = "id")]
+	pub kind: WarningKind,
+	pub message: String,
+}
+
+impl Warning {
+	pub fn new(kind: WarningKind, message: impl Into<String>) -> Self {
+		Warning { kind, message: message.into() }
+	}
+}
+
+impl fmt::Display for Warning {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.message)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ids_are_what_it_serializes_to() {
+		let kinds = [
+			WarningKind::OutOfRange, WarningKind::StillDurationIgnored, WarningKind::Upscaled, WarningKind::ConcatResampled,
+			WarningKind::AllFramesIdle, WarningKind::OutOfMemoryRetry, WarningKind::ComparisonFailed, WarningKind::NoMotion,
+			WarningKind::NoAudio, WarningKind::LowDiskSpace, WarningKind::SlowFramesDir,
+			WarningKind::FewFrames, WarningKind::ContactSheetSkipped, WarningKind::Mp4TooBig, WarningKind::SocialPieceFailed,
+			WarningKind::SourceTimingUnavailable, WarningKind::CursorEventsOutsideRange, WarningKind::DecodeErrorTolerated,
+			WarningKind::InputStillWritten, WarningKind::FlashFramesUnmeasured,
+		];
+		for kind in kinds {
+			assert_eq!(serde_json::to_value(kind).unwrap(), kind.id());
+		}
+		let warning = Warning::new(WarningKind::Upscaled, "scaling up");
+		assert_eq!(serde_json::to_string(&warning).unwrap(), r#"{"id":"upscaled","message":"scaling up"}"#);
+	}
+}
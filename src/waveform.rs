@@ -0,0 +1,80 @@
+//! `--waveform`: a strip drawn from the input's audio, stacked under the frames in the same filter graph they're
+//! extracted with. It's drawn at the frames' size after scaling, so it's never scaled itself.
+
+use crate::{
+	ffmpeg::{self, Extraction},
+	options::WaveformStyle,
+	ConvertError,
+	ConvertOptions,
+	Result,
+};
+
+/// Height of the strip in pixels, if `--waveform` is given without one.
+pub const DEFAULT_WAVEFORM_HEIGHT: u32 = 64;
+
+/// The filter graph that draws `style` from the input's audio at `width`x`height` and `fps`, and stacks it under
+/// the video, which the graph before it labels `[video]`.
+fn filter_graph(style: WaveformStyle, (width, height): (u32, u32), fps: Option<f32>) -> String {
+	let strip = match style {
+		WaveformStyle::Waves => {
+			let rate = fps.map(|fps| format!(":rate={fps}")).unwrap_or_default();
+			format!("showwaves=s={width}x{height}:mode=cline:colors=white{rate}")
+		}
+		// showspectrum makes a frame for every column it scrolls by.
+		WaveformStyle::Spectrum => {
+			let rate = fps.map(|fps| format!(",fps={fps}")).unwrap_or_default();
+			format!("showspectrum=s={width}x{height}:slide=scroll:color=intensity{rate}")
+		}
+	};
+	// The audio can run on past the end of the video, or start after it.
+	format!("[0:a]{strip}[strip];[video][strip]vstack=shortest=1")
+}
+
+/// Stacks the [`ConvertOptions::waveform`] strip under what `extraction` extracts, which has to be resized
+/// already, and makes its [`frame_size`](Extraction::frame_size) that much taller. `fps` is what the frames are
+/// extracted at, if it's known.
+///
+/// # Errors
+/// If the height is 0, or the size of the frames isn't known.
+pub(crate) fn stack(extraction: &mut Extraction, opt: &ConvertOptions, fps: Option<f32>) -> Result<()> {
+	let Some(height) = opt.waveform else { return Ok(()) };
+	let invalid = |message: &str| Err(ConvertError::InvalidOption { option: "waveform", message: message.to_string() });
+	if height == 0 { return invalid("must be at least 1 pixel high"); }
+	let height = if opt.allow_odd_dimensions { height } else { ffmpeg::even(height) };
+	let Some((width, frames_height)) = extraction.frame_size.filter(|&(w, h)| w > 0 && h > 0) else {
+		return invalid("couldn't find the dimensions of the input to draw it as wide");
+	};
+	extraction.waveform = Some(filter_graph(opt.waveform_style, (width, height), fps));
+	extraction.frame_size = Some((width, frames_height + height));
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_strip_is_drawn_at_the_frames_width_and_fps() {
+		assert_eq!(
+			filter_graph(WaveformStyle::Waves, (320, 64), Some(12.0)),
+			"[0:a]showwaves=s=320x64:mode=cline:colors=white:rate=12[strip];[video][strip]vstack=shortest=1",
+		);
+		assert_eq!(
+			filter_graph(WaveformStyle::Spectrum, (320, 64), None),
+			"[0:a]showspectrum=s=320x64:slide=scroll:color=intensity[strip];[video][strip]vstack=shortest=1",
+		);
+	}
+
+	#[test]
+	fn makes_the_frames_taller() {
+		let mut opt = ConvertOptions::new("in.mp4");
+		opt.waveform = Some(33);
+		let mut extraction = Extraction { frame_size: Some((320, 180)), ..Extraction::default() };
+		stack(&mut extraction, &opt, Some(24.0)).unwrap();
+		assert_eq!(extraction.frame_size, Some((320, 212)), "rounded down to even");
+		assert!(extraction.waveform.unwrap().contains("s=320x32:"));
+
+		opt.waveform = Some(0);
+		assert!(stack(&mut Extraction::default(), &opt, None).is_err());
+	}
+}
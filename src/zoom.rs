@@ -0,0 +1,83 @@
+//! The zoompan filter for `--zoom-to`, a slow zoom from the whole frame into a region of it.
+//!
+//! zoompan shows a window of the input at the input's size. The window starts as the whole frame and shrinks,
+//! linearly in size and position, to the smallest one of the frame's shape around the region.
+
+use crate::{ConvertError, Result, Zoom};
+
+/// `zoompan` going from the whole `width`x`height` frame to `zoom`'s region, over `zoom`'s duration or `seconds`.
+///
+/// The frames it sees are timestamped from where the decoding started, which is `start` seconds before the first
+/// frame that's kept. `fps` is the rate it's fed at, which zoompan needs told to keep the timestamps.
+///
+/// # Errors
+/// [`ConvertError::InvalidOption`] if the region isn't inside the frame, or there's no duration to zoom over.
+pub(crate) fn zoompan_filter(zoom: Zoom, (width, height): (u32, u32), fps: f32, start: f64, seconds: Option<f64>) -> Result<String> {
+	let invalid = |message: String| Err(ConvertError::InvalidOption { option: "zoom to", message });
+	let region = format!("{}x{}+{}+{}", zoom.width, zoom.height, zoom.x, zoom.y);
+	if width == 0 || height == 0 { return invalid("couldn't find the dimensions of the input".to_string()); }
+	if u64::from(zoom.x) + u64::from(zoom.width) > u64::from(width) || u64::from(zoom.y) + u64::from(zoom.height) > u64::from(height) {
+		return invalid(format!("{region} isn't inside the {width}x{height} input"));
+	}
+	let Some(seconds) = zoom.duration.or(seconds).filter(|&s| s > 0.0) else {
+		return invalid(format!("the input doesn't say how long it is, give the zoom a duration, e.g. {region}:5"));
+	};
+
+	let (w, h) = (f64::from(width), f64::from(height));
+	// The smallest window of the frame's shape that the region fits in, centered on it but kept inside the frame.
+	let end_zoom = (w / f64::from(zoom.width)).min(h / f64::from(zoom.height));
+	let (end_w, end_h) = (w / end_zoom, h / end_zoom);
+	let end_x = (f64::from(zoom.x) + f64::from(zoom.width) / 2.0 - end_w / 2.0).clamp(0.0, w - end_w);
+	let end_y = (f64::from(zoom.y) + f64::from(zoom.height) / 2.0 - end_h / 2.0).clamp(0.0, h - end_h);
+
+	// How far along the zoom is, from 0 to 1. `it` is the timestamp of the frame coming in.
+	let along = format!("clip((it-{})/{},0,1)", number(start), number(seconds));
+	Ok(format!(
+		"zoompan=z='{w}/({w}-{}*{along})':x='{}*{along}':y='{}*{along}':d=1:s={width}x{height}:fps={fps}",
+		number(w - end_w),
+		number(end_x),
+		number(end_y),
+	))
+}
+
+/// Rounded to 3 decimals, so float noise doesn't end up in the filter.
+fn number(n: f64) -> f64 {
+	(n * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn zoom(s: &str) -> Zoom {
+		s.parse().unwrap()
+	}
+
+	#[test]
+	fn zooms_from_the_whole_frame_to_the_region() {
+		let filter = zoompan_filter(zoom("640x360+640+360"), (1280, 720), 30.0, 0.0, Some(4.0)).unwrap();
+		assert_eq!(
+			filter,
+			"zoompan=z='1280/(1280-640*clip((it-0)/4,0,1))':x='640*clip((it-0)/4,0,1)':y='360*clip((it-0)/4,0,1)':d=1:s=1280x720:fps=30",
+		);
+	}
+
+	#[test]
+	fn other_shapes_are_centered_and_kept_inside_the_frame() {
+		// A 200x400 region of a 16:9 frame needs a 711x400 window, which would stick out to the left.
+		let filter = zoompan_filter(zoom("200x400+0+100:2.5"), (1280, 720), 24.0, 5.0, Some(60.0)).unwrap();
+		assert_eq!(
+			filter,
+			"zoompan=z='1280/(1280-568.889*clip((it-5)/2.5,0,1))':x='0*clip((it-5)/2.5,0,1)':y='100*clip((it-5)/2.5,0,1)':d=1:s=1280x720:fps=24",
+		);
+	}
+
+	#[test]
+	fn regions_outside_the_input_are_rejected() {
+		let err = zoompan_filter(zoom("640x360+700+0"), (1280, 720), 30.0, 0.0, Some(4.0)).unwrap_err();
+		assert_eq!(err.to_string(), "Invalid zoom to: 640x360+700+0 isn't inside the 1280x720 input");
+		assert!(zoompan_filter(zoom("640x360+0+0"), (1280, 720), 30.0, 0.0, None).is_err(), "no duration");
+		assert_eq!(zoom("10x20+3+4:1:30").duration.map(|d| d.to_string()).as_deref(), Some("90"));
+		assert!("10x20+3".parse::<Zoom>().is_err() && "0x20+3+4".parse::<Zoom>().is_err() && "10x20+3+4:0".parse::<Zoom>().is_err());
+	}
+}
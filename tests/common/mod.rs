@@ -0,0 +1,90 @@
+//! Helpers for the integration tests: generating fixture videos and reading back gifs.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+use gifski_ffmpeg::ConvertOptions;
+
+/// A fresh, empty directory for one test.
+pub fn test_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("gifski-ffmpeg-it-{}", name));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// Default options converting `input`, with the frames kept in `dir` so tests running in parallel don't share them.
+pub fn options(dir: &Path, input: &Path) -> ConvertOptions {
+	let mut options = ConvertOptions::new(input);
+	options.frames_dir = Some(dir.join("frames"));
+	options
+}
+
+/// Synthesizes a `seconds` long `width`x`height` video at `fps` with ffmpeg's testsrc.
+pub fn testsrc(dir: &Path, seconds: u32, width: u32, height: u32, fps: u32) -> PathBuf {
+	lavfi(dir, "testsrc.mp4", &format!("testsrc=duration={}:size={}x{}:rate={}", seconds, width, height, fps), &["-pix_fmt", "yuv420p"])
+}
+
+/// Generates `name` from one of ffmpeg's lavfi `source`s, with `args` before the output.
+pub fn lavfi(dir: &Path, name: &str, source: &str, args: &[&str]) -> PathBuf {
+	let path = dir.join(name);
+	let status = Command::new("ffmpeg")
+		.args(["-y", "-loglevel", "error", "-f", "lavfi", "-i", source])
+		.args(args)
+		.arg(&path)
+		.status()
+		.expect("ffmpeg is needed for the integration tests");
+	assert!(status.success(), "generating the fixture failed");
+	path
+}
+
+/// The parts of a gif the tests care about.
+#[derive(Debug)]
+pub struct GifInfo {
+	pub width: u16,
+	pub height: u16,
+	/// Delay of every frame in centiseconds.
+	pub delays: Vec<u16>,
+}
+
+impl GifInfo {
+	pub fn frames(&self) -> usize {
+		self.delays.len()
+	}
+}
+
+/// Walks the gif's blocks, panicking if it isn't a well formed gif.
+pub fn read_gif(path: &Path) -> GifInfo {
+	let data = fs::read(path).unwrap_or_else(|e| panic!("couldn't read {}: {}", path.display(), e));
+	assert!(data.starts_with(b"GIF89a") || data.starts_with(b"GIF87a"), "not a gif: {}", path.display());
+	let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+	let color_table_len = |flags: u8| if flags & 0x80 != 0 { 3 * (1 << ((flags & 0x07) + 1)) } else { 0 };
+	let skip_sub_blocks = |mut i: usize| {
+		while data[i] != 0 { i += data[i] as usize + 1; }
+		i + 1
+	};
+
+	let (width, height) = (u16_at(6), u16_at(8));
+	let mut i = 13 + color_table_len(data[10]);
+	let mut delays = Vec::new();
+	let mut delay = 0;
+	loop {
+		match data[i] {
+			0x21 => {
+				if data[i + 1] == 0xF9 { delay = u16_at(i + 4); }
+				i = skip_sub_blocks(i + 2);
+			}
+			0x2C => {
+				delays.push(delay);
+				i += 10 + color_table_len(data[i + 9]);
+				// LZW minimum code size, then the image data.
+				i = skip_sub_blocks(i + 1);
+			}
+			0x3B => break,
+			b => panic!("unexpected block {:#x} at {} in {}", b, i, path.display()),
+		}
+	}
+	GifInfo { width, height, delays }
+}
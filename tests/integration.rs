@@ -0,0 +1,256 @@
+//! End to end conversions with the real ffmpeg and gifski.
+//!
+//! These need both tools on the PATH, so they're ignored by default. Run them with
+//! `cargo test -- --ignored`.
+
+mod common;
+
+use gifski_ffmpeg::convert;
+use gifski_ffmpeg::ConvertError;
+use common::{lavfi, options, read_gif, test_dir, testsrc};
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn default_output_is_named_after_the_input() {
+	let dir = test_dir("default");
+	let input = testsrc(&dir, 2, 320, 240, 10);
+
+	let report = convert(options(&dir, &input)).unwrap();
+
+	assert_eq!(report.output, dir.join("testsrc-gif.gif"));
+	assert_eq!(report.fps, 10.0);
+	let gif = read_gif(&report.output);
+	assert_eq!((gif.width, gif.height), (320, 240));
+	assert!((19..=21).contains(&gif.frames()), "{} frames", gif.frames());
+	assert!(gif.delays.iter().all(|&d| d == 10), "{:?}", gif.delays);
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn output_name_gets_a_gif_extension_next_to_the_input() {
+	let dir = test_dir("named");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.output = Some("named".into());
+
+	let report = convert(options).unwrap();
+
+	assert_eq!(report.output, dir.join("named.gif"));
+	read_gif(&report.output);
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn fps_changes_the_frame_delay() {
+	let dir = test_dir("fps");
+	let input = testsrc(&dir, 2, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.fps = Some(5.0);
+
+	let report = convert(options).unwrap();
+
+	let gif = read_gif(&report.output);
+	// gifski keeps every png it's given, the fps only sets the speed.
+	assert!((19..=21).contains(&gif.frames()), "{} frames", gif.frames());
+	assert!(gif.delays.iter().all(|&d| d == 20), "{:?}", gif.delays);
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn fps_and_quality_are_clamped() {
+	let dir = test_dir("clamps");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.fps = Some(120.0);
+	options.quality = 500;
+
+	let report = convert(options).unwrap();
+
+	assert_eq!(report.fps, 50.0);
+	assert_eq!(report.quality, 100);
+	assert!(read_gif(&report.output).delays.iter().all(|&d| d == 2));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn poster_is_written_next_to_the_gif() {
+	let dir = test_dir("poster");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.poster = Some(gifski_ffmpeg::Poster::First);
+
+	let report = convert(options).unwrap();
+
+	let poster = report.poster.unwrap();
+	assert_eq!(poster, dir.join("testsrc-gif.png"));
+	assert!(std::fs::read(poster).unwrap().starts_with(b"\x89PNG"));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn time_trim_converts_only_the_range() {
+	let dir = test_dir("time-trim");
+	let input = testsrc(&dir, 2, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.start = Some(0.5);
+	options.duration = Some(1.0);
+
+	let report = convert(options).unwrap();
+
+	let frames = read_gif(&report.output).frames();
+	assert!((9..=11).contains(&frames), "{frames} frames");
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn frame_trim_includes_the_end_frame() {
+	let dir = test_dir("frame-trim");
+	let input = testsrc(&dir, 2, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.start_frame = Some(5);
+	options.end_frame = Some(9);
+
+	let report = convert(options).unwrap();
+
+	assert_eq!(report.frame_count, 5);
+	assert_eq!(read_gif(&report.output).frames(), 5);
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn square_crop_then_scale() {
+	let dir = test_dir("square");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.aspect = Some(gifski_ffmpeg::Aspect::SQUARE);
+	options.width = Some(60);
+
+	let report = convert(options).unwrap();
+
+	let gif = read_gif(&report.output);
+	assert_eq!((gif.width, gif.height), (60, 60));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn exact_size_is_padded_by_default() {
+	let dir = test_dir("pad");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.width = Some(200);
+	options.height = Some(100);
+
+	let report = convert(options).unwrap();
+
+	let gif = read_gif(&report.output);
+	assert_eq!((gif.width, gif.height), (200, 100));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn json_lines_progress_is_only_json_on_stdout() {
+	let dir = test_dir("json-lines");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+
+	let output = std::process::Command::new(env!("CARGO_BIN_EXE_gifski-ffmpeg"))
+		.arg(&input)
+		.args(["--progress-format", "json-lines"])
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	let events: Vec<serde_json::Value> = String::from_utf8(output.stdout).unwrap()
+		.lines()
+		.map(|l| serde_json::from_str(l).unwrap_or_else(|e| panic!("{e}: {l}")))
+		.collect();
+	assert_eq!(events[0]["event"], "started");
+	assert!(events.iter().any(|e| e["event"] == "extracting"));
+	let result = events.last().unwrap();
+	assert_eq!(result["event"], "result");
+	assert_eq!(result["output"], dir.join("testsrc-gif.gif").display().to_string());
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Complete!"));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn audio_only_input_has_no_video_stream() {
+	let dir = test_dir("audio-only");
+	let input = lavfi(&dir, "tone.mp3", "sine=duration=1", &[]);
+
+	let err = convert(options(&dir, &input)).unwrap_err();
+
+	assert!(matches!(err, ConvertError::NoVideoStream(_)), "{err:?}");
+	assert!(!dir.join("frames").exists());
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn still_image_is_refused_unless_given_a_duration() {
+	let dir = test_dir("still");
+	let input = lavfi(&dir, "still.png", "color=c=red:s=64x48", &["-frames:v", "1"]);
+
+	let err = convert(options(&dir, &input)).unwrap_err();
+	assert!(matches!(err, ConvertError::StillImage(_)), "{err:?}");
+
+	let mut options = options(&dir, &input);
+	options.still_duration = Some(2.0);
+	let report = convert(options).unwrap();
+	let gif = read_gif(&report.output);
+	assert_eq!((gif.width, gif.height), (64, 48));
+	assert_eq!(gif.delays.iter().map(|&d| u32::from(d)).sum::<u32>(), 200, "{:?}", gif.delays);
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn split_every_makes_consecutive_parts() {
+	let dir = test_dir("split");
+	let input = testsrc(&dir, 7, 160, 120, 10);
+
+	let parts = gifski_ffmpeg::split::run(&options(&dir, &input), 3.0, &gifski_ffmpeg::runner::SystemRunner, |_, _, _| {}).unwrap();
+
+	assert_eq!(parts.len(), 2, "the 1 second left over is in the second part");
+	assert_eq!(parts[1].output, dir.join("testsrc-part02.gif"));
+	assert!((29..=31).contains(&read_gif(&parts[0].output).frames()));
+	assert!((39..=41).contains(&read_gif(&parts[1].output).frames()));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn deterministic_runs_make_identical_gifs() {
+	let dir = test_dir("deterministic");
+	let input = testsrc(&dir, 1, 160, 120, 10);
+	let mut options = options(&dir, &input);
+	options.deterministic = true;
+	options.comment = gifski_ffmpeg::Comment::Auto;
+
+	let first = std::fs::read(convert(options.clone()).unwrap().output).unwrap();
+	let second = std::fs::read(convert(options).unwrap().output).unwrap();
+
+	assert!(first == second, "the two gifs differ");
+}
+
+#[test]
+#[ignore = "needs ffmpeg and gifski"]
+fn cancelling_either_stage_leaves_nothing_behind() {
+	use gifski_ffmpeg::{CancelToken, Conversion, Progress, Stage};
+
+	for stage in [Stage::Extract, Stage::Encode] {
+		let dir = test_dir(&format!("cancel-{stage:?}"));
+		let input = testsrc(&dir, 30, 640, 480, 30);
+		let token = CancelToken::new();
+		let cancel = token.clone();
+
+		let result = Conversion::new(options(&dir, &input))
+			.cancel_token(token)
+			.on_progress(move |p| match p {
+				Progress::Extracting { .. } if stage == Stage::Extract => cancel.cancel(),
+				Progress::Encoding { .. } if stage == Stage::Encode => cancel.cancel(),
+				_ => {}
+			})
+			.run();
+
+		assert!(matches!(result, Err(ConvertError::Cancelled)), "{stage:?}: {result:?}");
+		assert!(!dir.join("frames").exists(), "{stage:?}: the frames are still there");
+		assert!(!dir.join("testsrc-gif.gif").exists(), "{stage:?}: the half written gif is still there");
+	}
+}